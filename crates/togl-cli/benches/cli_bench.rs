@@ -37,7 +37,9 @@ fn bench_cli_100_files(c: &mut Criterion) {
             },
             |()| {
                 let mut cmd = std::process::Command::new(binary);
-                cmd.args(&paths).args(["-l", "1:10"]);
+                cmd.args(&paths)
+                    .args(["-l", "1:10"])
+                    .env("TOGGLE_NO_HISTORY", "1");
                 let output = cmd.output().expect("Failed to run toggle");
                 assert!(output.status.success(), "toggle exited with error");
             },
@@ -3,6 +3,7 @@
 use clap::Parser;
 use clap_complete::Shell;
 use std::path::PathBuf;
+use std::time::Duration;
 
 /// Output detail level for `--list-sections` (P07).
 #[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
@@ -26,6 +27,32 @@ pub enum RemoveMode {
     All,
 }
 
+/// How `--atomic` backs up each file before replacing it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum BackupStrategy {
+    /// Hard link to the original (fails where hard links are unsupported).
+    Hardlink,
+    /// Full copy with its own inode.
+    Copy,
+    /// Copy-on-write clone where the filesystem supports it, else a copy.
+    ReflinkIfPossible,
+    /// Reflink, then hard link, then copy: whichever works first.
+    Auto,
+}
+
+/// How `--backup` names each new backup (GNU `--backup=CONTROL`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum BackupMode {
+    /// `<file><EXT>`, replacing the previous backup.
+    Simple,
+    /// `<file>.~N~`, never replacing an older one.
+    Numbered,
+    /// Numbered if the file already has numbered backups, else simple.
+    Existing,
+    /// `<file>.~YYYYMMDDTHHMMSSZ~` (UTC).
+    Timestamp,
+}
+
 #[derive(Parser)]
 #[command(author, version, about, args_conflicts_with_subcommands = true)]
 pub struct Cli {
@@ -39,10 +66,33 @@ pub struct Cli {
     /// File or directory paths to process
     pub paths: Vec<PathBuf>,
 
-    /// Line range in format <start_line>:<end_line> or <start_line>:+<count> (repeatable)
-    #[arg(short = 'l', long = "line", action = clap::ArgAction::Append)]
+    /// Lines to toggle: <start>:<end>, <start>:+<count> or a single line, where
+    /// an end may be `$` (last line), `-N` (Nth line from the end) or
+    /// `/regex/` (first matching line); comma-separate several, and end with
+    /// `:block` to take the whole code block starting there (repeatable)
+    #[arg(short = 'l', long = "line", action = clap::ArgAction::Append, group = "anchor")]
     pub lines: Vec<String>,
 
+    /// Toggle the code block starting at LINE (a number, `$`, `-N` or
+    /// `/regex/`): shorthand for `-l LINE:block` (repeatable)
+    #[arg(
+        long = "block-at",
+        value_name = "LINE",
+        action = clap::ArgAction::Append,
+        conflicts_with = "operation"
+    )]
+    pub block_at: Vec<String>,
+
+    /// Toggle the lines listed in FILE (`-` for stdin) as `path:line[:col]`
+    /// or `path:start-end` records, e.g. the output of `grep -n`,
+    /// `rg --vimgrep` or a compiler; other lines are ignored.
+    #[arg(
+        long = "locations",
+        value_name = "FILE",
+        conflicts_with_all = ["paths", "lines", "sections", "block_at", "recursive", "stdin", "stdout", "operation"]
+    )]
+    pub locations: Option<PathBuf>,
+
     /// Section ID to toggle. Use `group:variant` (e.g. `db:postgres`) for variant ops:
     /// `-S group` flips a 2-variant pair; `-S group:variant` activates one variant
     /// and comments siblings; `-S group --force on/off` applies to every variant.
@@ -69,6 +119,27 @@ pub struct Cli {
     #[arg(long = "remove-mode", default_value = "commented")]
     pub remove_mode: RemoveMode,
 
+    /// Rename the section or group given with -S to NEW_ID, rewriting its
+    /// start and end markers in every file. Always atomic unless --dry-run.
+    #[arg(long = "rename", value_name = "NEW_ID", group = "operation")]
+    pub rename: Option<String>,
+
+    /// Copy the section given with -S right after itself as the new,
+    /// commented-out variant NEW_ID (`group:name`). A solo is turned into a
+    /// variant of its group first. Always atomic unless --dry-run.
+    #[arg(long = "add-variant", value_name = "NEW_ID", group = "operation")]
+    pub add_variant: Option<String>,
+
+    /// With --add-variant on a solo: the variant name the solo becomes
+    /// [default: its own ID, or `original` when that is the group name].
+    #[arg(long = "variant-as", value_name = "NAME", requires = "add_variant")]
+    pub variant_as: Option<String>,
+
+    /// Delete the variant given with -S (`group:name`), body and markers,
+    /// then re-validate what is left of its group. Always atomic unless --dry-run.
+    #[arg(long = "drop-variant", group = "operation")]
+    pub drop_variant: bool,
+
     /// With --remove, exit non-zero if -S <ID> matched no sections.
     #[arg(long = "require-match", requires = "remove")]
     pub require_match: bool,
@@ -78,8 +149,33 @@ pub struct Cli {
     #[arg(long = "insert", group = "operation")]
     pub insert: bool,
 
-    /// Description for the inserted section marker (use with --insert).
-    #[arg(long = "desc", requires = "insert")]
+    /// With --insert: wrap every line matching REGEX, or contiguous run of
+    /// matching lines, instead of a -l range. Works on many files and with -R.
+    #[arg(
+        long = "match",
+        value_name = "REGEX",
+        requires = "insert",
+        group = "anchor"
+    )]
+    pub match_pattern: Option<String>,
+
+    /// With --insert: wrap from each line matching /REGEX/ through the next
+    /// line matching --to (or to the end of file with --to-end).
+    #[arg(
+        long = "from",
+        value_name = "/REGEX/",
+        requires = "insert",
+        group = "anchor"
+    )]
+    pub from_pattern: Option<String>,
+
+    /// End anchor for --from: the first line after it matching /REGEX/.
+    #[arg(long = "to", value_name = "/REGEX/", requires = "from_pattern")]
+    pub to_pattern: Option<String>,
+
+    /// Description for the inserted section marker (use with --insert or
+    /// --add-variant).
+    #[arg(long = "desc")]
     pub desc: Option<String>,
 
     /// Force toggle state (on/off/invert)
@@ -102,9 +198,33 @@ pub struct Cli {
     #[arg(short = 't', long = "temp-suffix")]
     pub temp_suffix: Option<String>,
 
-    /// Override file codec (UTF-8 only in Phase 0)
-    #[arg(short = 'e', long = "encoding", default_value = "utf-8")]
-    pub encoding: String,
+    /// File encoding (e.g. utf-8, latin-1, utf-16le), or `auto` to detect from BOM/content.
+    /// Overrides the config; defaults to utf-8
+    #[arg(short = 'e', long = "encoding")]
+    pub encoding: Option<String>,
+
+    /// Only process the file if its SHA-256 is this hex digest (single file).
+    /// A mismatch exits with code 5, as does a file changed during the run.
+    #[arg(long = "expect-sha256", value_name = "HEX", value_parser = parse_sha256)]
+    pub expect_sha256: Option<String>,
+
+    /// Process files that contain NUL bytes (skipped as binary by default).
+    #[arg(long = "allow-binary")]
+    pub allow_binary: bool,
+
+    /// Process files whose header marks them as generated (`@generated`,
+    /// `DO NOT EDIT`); skipped by default.
+    #[arg(long = "allow-generated")]
+    pub allow_generated: bool,
+
+    /// Process files containing git conflict markers (skipped by default).
+    #[arg(long = "allow-conflicts")]
+    pub allow_conflicts: bool,
+
+    /// Largest file to process (e.g. 10M, 512K; 0 for no limit). Default:
+    /// `[global] max_file_size` from the config, else 10M.
+    #[arg(long = "max-file-size", value_name = "SIZE", value_parser = parse_max_file_size)]
+    pub max_file_size: Option<u64>,
 
     /// Error if target is not .py
     #[arg(long = "strict-ext")]
@@ -114,7 +234,12 @@ pub struct Cli {
     #[arg(short = 'N', long = "no-dereference")]
     pub no_dereference: bool,
 
-    /// EOL normalization: preserve, lf, or crlf
+    /// Keep each modified file's modification time (e.g. so timestamp-based
+    /// build tools do not rebuild it)
+    #[arg(long = "preserve-mtime")]
+    pub preserve_mtime: bool,
+
+    /// Line endings: preserve (keep each line's original ending), lf, or crlf
     #[arg(long = "eol", default_value = "preserve")]
     pub eol: String,
 
@@ -138,6 +263,26 @@ pub struct Cli {
     #[arg(long = "backup")]
     pub backup: Option<String>,
 
+    /// How backups are named: simple (default), numbered, existing or
+    /// timestamp. Implies --backup (with `~` as EXT if not given).
+    #[arg(
+        long = "backup-mode",
+        value_name = "MODE",
+        value_enum,
+        conflicts_with = "atomic"
+    )]
+    pub backup_mode: Option<BackupMode>,
+
+    /// Put backups under DIR, mirroring each file's path relative to the
+    /// current directory. Implies --backup.
+    #[arg(long = "backup-dir", value_name = "DIR", conflicts_with = "atomic")]
+    pub backup_dir: Option<PathBuf>,
+
+    /// Keep at most N numbered/timestamped backups per file, deleting the
+    /// oldest. Implies --backup.
+    #[arg(long = "backup-keep", value_name = "N", conflicts_with = "atomic")]
+    pub backup_keep: Option<usize>,
+
     /// Read input from stdin instead of a file (filter mode; writes to stdout).
     /// Equivalent to passing `-` as the path. Only valid for toggle/insert/remove.
     #[arg(long = "stdin")]
@@ -149,14 +294,19 @@ pub struct Cli {
     #[arg(long = "stdout")]
     pub stdout: bool,
 
-    /// Extend the last --line range to the end of file
-    #[arg(long = "to-end", requires = "lines")]
+    /// Extend the last --line range (or the --from block) to the end of file
+    #[arg(long = "to-end", requires = "anchor")]
     pub to_end: bool,
 
     /// Scan for section IDs without modifying files
     #[arg(long = "scan", group = "operation")]
     pub scan: bool,
 
+    /// Browse sections in a terminal UI, stage toggles and variant
+    /// activations, and commit them as one atomic batch (implies --atomic)
+    #[arg(long = "ui", group = "operation")]
+    pub ui: bool,
+
     /// Validate section integrity without modifying files. Requires --scan.
     #[arg(long = "check", requires = "scan")]
     pub check: bool,
@@ -170,16 +320,60 @@ pub struct Cli {
     #[arg(long = "config")]
     pub config: Option<PathBuf>,
 
+    /// Refuse to write (targets, backups, temp and journal files) anywhere
+    /// outside DIR, following `..` and symlinks. Violations exit with code 6.
+    #[arg(long = "root", value_name = "DIR")]
+    pub root: Option<PathBuf>,
+
     /// Enable atomic multi-file mode: all files succeed or none are modified.
     /// Implies --backup unless --no-backup is explicitly passed.
     #[arg(long = "atomic")]
     pub atomic: bool,
 
+    /// Save the planned changes to FILE (`-` for stdout) instead of writing
+    /// them, with each file's SHA-256 before and after; `--apply-plan`
+    /// writes them later.
+    #[arg(
+        long = "plan",
+        value_name = "FILE",
+        conflicts_with_all = ["dry_run", "interactive", "ui", "scan", "list_sections", "recover", "stdin", "stdout"]
+    )]
+    pub plan: Option<PathBuf>,
+
+    /// Include each file's unified diff in the --plan file, for review.
+    #[arg(long = "plan-diff", requires = "plan")]
+    pub plan_diff: bool,
+
+    /// Write the changes saved by --plan as one atomic batch, refusing if any
+    /// file changed since the plan was made.
+    #[arg(
+        long = "apply-plan",
+        value_name = "FILE",
+        conflicts_with_all = ["paths", "lines", "sections", "block_at", "locations", "force", "recursive", "plan", "operation", "ui", "scan", "stdin", "stdout"]
+    )]
+    pub apply_plan: Option<PathBuf>,
+
     /// Disable backup creation in atomic mode. Only valid with --atomic.
     /// WARNING: Without backups, rollback is not possible if the rename phase fails.
     #[arg(long = "no-backup", requires = "atomic")]
     pub no_backup: bool,
 
+    /// How atomic mode backs up files. Default: `[global] backup_strategy`
+    /// from the config, else auto.
+    #[arg(
+        long = "backup-strategy",
+        value_name = "STRATEGY",
+        value_enum,
+        requires = "atomic",
+        conflicts_with = "no_backup"
+    )]
+    pub backup_strategy: Option<BackupStrategy>,
+
+    /// How long to wait for another atomic operation to release the lock
+    /// (e.g. 30s, 500ms, 2m; a bare number is seconds). Default: fail at once.
+    #[arg(long = "lock-timeout", value_name = "DUR", value_parser = parse_duration)]
+    pub lock_timeout: Option<Duration>,
+
     /// Recover from an interrupted atomic operation. Default: rollback.
     #[arg(long = "recover")]
     pub recover: bool,
@@ -189,6 +383,10 @@ pub struct Cli {
     #[arg(long = "recover-forward", requires = "recover")]
     pub recover_forward: bool,
 
+    /// Do not record this run in .toggle/history.jsonl (disables `togl undo` for it)
+    #[arg(long = "no-history", env = "TOGGLE_NO_HISTORY", value_parser = clap::builder::BoolishValueParser::new())]
+    pub no_history: bool,
+
     /// Generate shell completions for the given shell to stdout.
     /// Example: `toggle --completions bash > /etc/bash_completion.d/toggle`
     #[arg(long = "completions", value_name = "SHELL")]
@@ -228,11 +426,17 @@ pub struct GlobalArgs {
     #[arg(short = 'm', long = "mode", default_value = "auto")]
     pub mode: String,
 
-    /// Override file codec (UTF-8 only in Phase 0)
-    #[arg(short = 'e', long = "encoding", default_value = "utf-8")]
-    pub encoding: String,
+    /// File encoding (e.g. utf-8, latin-1, utf-16le), or `auto` to detect from BOM/content.
+    /// Overrides the config; defaults to utf-8
+    #[arg(short = 'e', long = "encoding")]
+    pub encoding: Option<String>,
+
+    /// Only process the file if its SHA-256 is this hex digest (single file).
+    /// A mismatch exits with code 5, as does a file changed during the run.
+    #[arg(long = "expect-sha256", value_name = "HEX", value_parser = parse_sha256)]
+    pub expect_sha256: Option<String>,
 
-    /// EOL normalization: preserve, lf, or crlf
+    /// Line endings: preserve (keep each line's original ending), lf, or crlf
     #[arg(long = "eol", default_value = "preserve")]
     pub eol: String,
 
@@ -244,6 +448,11 @@ pub struct GlobalArgs {
     #[arg(short = 'N', long = "no-dereference")]
     pub no_dereference: bool,
 
+    /// Keep each modified file's modification time (e.g. so timestamp-based
+    /// build tools do not rebuild it)
+    #[arg(long = "preserve-mtime")]
+    pub preserve_mtime: bool,
+
     /// Error if target is not .py
     #[arg(long = "strict-ext")]
     pub strict_ext: bool,
@@ -264,13 +473,34 @@ pub struct GlobalArgs {
     #[arg(long = "backup")]
     pub backup: Option<String>,
 
+    /// How backups are named: simple, numbered, existing or timestamp
+    #[arg(long = "backup-mode", value_name = "MODE", value_enum)]
+    pub backup_mode: Option<BackupMode>,
+
+    /// Put backups under DIR, mirroring the relative tree layout
+    #[arg(long = "backup-dir", value_name = "DIR")]
+    pub backup_dir: Option<PathBuf>,
+
+    /// Keep at most N numbered/timestamped backups per file
+    #[arg(long = "backup-keep", value_name = "N")]
+    pub backup_keep: Option<usize>,
+
     /// Path to .toggleConfig TOML file
     #[arg(long = "config")]
     pub config: Option<PathBuf>,
 
+    /// Refuse to write (targets, backups, temp and journal files) anywhere
+    /// outside DIR, following `..` and symlinks. Violations exit with code 6.
+    #[arg(long = "root", value_name = "DIR")]
+    pub root: Option<PathBuf>,
+
     /// Override comment style: SINGLE [MULTI_START MULTI_END]
     #[arg(long = "comment-style", num_args = 1..=3, value_names = ["SINGLE", "MULTI_START", "MULTI_END"])]
     pub comment_style: Vec<String>,
+
+    /// Do not record this run in .toggle/history.jsonl (disables `togl undo` for it)
+    #[arg(long = "no-history", env = "TOGGLE_NO_HISTORY", value_parser = clap::builder::BoolishValueParser::new())]
+    pub no_history: bool,
 }
 
 impl GlobalArgs {
@@ -288,9 +518,13 @@ impl GlobalArgs {
             out.push("--mode".into());
             out.push((&self.mode).into());
         }
-        if self.encoding != "utf-8" {
+        if let Some(encoding) = &self.encoding {
             out.push("--encoding".into());
-            out.push((&self.encoding).into());
+            out.push(encoding.into());
+        }
+        if let Some(hash) = &self.expect_sha256 {
+            out.push("--expect-sha256".into());
+            out.push(hash.into());
         }
         if self.eol != "preserve" {
             out.push("--eol".into());
@@ -302,6 +536,9 @@ impl GlobalArgs {
         if self.no_dereference {
             out.push("--no-dereference".into());
         }
+        if self.preserve_mtime {
+            out.push("--preserve-mtime".into());
+        }
         if self.strict_ext {
             out.push("--strict-ext".into());
         }
@@ -319,16 +556,35 @@ impl GlobalArgs {
             out.push("--backup".into());
             out.push(b.into());
         }
+        if let Some(m) = &self.backup_mode {
+            out.push("--backup-mode".into());
+            out.push(enum_name(m));
+        }
+        if let Some(d) = &self.backup_dir {
+            out.push("--backup-dir".into());
+            out.push(d.into());
+        }
+        if let Some(n) = self.backup_keep {
+            out.push("--backup-keep".into());
+            out.push(n.to_string().into());
+        }
         if let Some(c) = &self.config {
             out.push("--config".into());
             out.push(c.into());
         }
+        if let Some(r) = &self.root {
+            out.push("--root".into());
+            out.push(r.into());
+        }
         if !self.comment_style.is_empty() {
             out.push("--comment-style".into());
             for v in &self.comment_style {
                 out.push(v.into());
             }
         }
+        if self.no_history {
+            out.push("--no-history".into());
+        }
     }
 }
 
@@ -358,6 +614,136 @@ impl FilterArgs {
     }
 }
 
+/// Preflight overrides, shared by the writer subcommands (toggle/insert/remove).
+#[derive(clap::Args, Debug)]
+pub struct SafetyArgs {
+    /// Process files that contain NUL bytes.
+    #[arg(long = "allow-binary")]
+    pub allow_binary: bool,
+
+    /// Process files with an `@generated` / `DO NOT EDIT` header.
+    #[arg(long = "allow-generated")]
+    pub allow_generated: bool,
+
+    /// Process files containing git conflict markers.
+    #[arg(long = "allow-conflicts")]
+    pub allow_conflicts: bool,
+
+    /// Largest file to process (e.g. 10M, 512K; 0 for no limit).
+    #[arg(long = "max-file-size", value_name = "SIZE", value_parser = parse_max_file_size)]
+    pub max_file_size: Option<u64>,
+}
+
+impl SafetyArgs {
+    fn push_argv(&self, out: &mut Vec<OsString>) {
+        if self.allow_binary {
+            out.push("--allow-binary".into());
+        }
+        if self.allow_generated {
+            out.push("--allow-generated".into());
+        }
+        if self.allow_conflicts {
+            out.push("--allow-conflicts".into());
+        }
+        if let Some(size) = self.max_file_size {
+            out.push("--max-file-size".into());
+            out.push(size.to_string().into());
+        }
+    }
+}
+
+/// Atomic-mode flags, shared by the writer subcommands (toggle/insert/remove).
+#[derive(clap::Args, Debug)]
+pub struct AtomicArgs {
+    /// Enable atomic multi-file mode: all files succeed or none are modified.
+    #[arg(long = "atomic")]
+    pub atomic: bool,
+
+    /// How atomic mode backs up files (only valid with --atomic).
+    #[arg(long = "backup-strategy", value_name = "STRATEGY", value_enum)]
+    pub backup_strategy: Option<BackupStrategy>,
+
+    #[command(flatten)]
+    pub commit: CommitArgs,
+}
+
+impl AtomicArgs {
+    fn push_argv(&self, out: &mut Vec<OsString>) {
+        if self.atomic {
+            out.push("--atomic".into());
+        }
+        if let Some(s) = &self.backup_strategy {
+            out.push("--backup-strategy".into());
+            out.push(enum_name(s));
+        }
+        self.commit.push_argv(out);
+    }
+}
+
+/// How an atomic commit is made, shared by [`AtomicArgs`] and the
+/// subcommands that always commit one atomic batch (ui/rename/variant/apply).
+#[derive(clap::Args, Debug)]
+pub struct CommitArgs {
+    /// Disable journal backups for the atomic commit.
+    #[arg(long = "no-backup")]
+    pub no_backup: bool,
+
+    /// How long to wait for another atomic operation to release the lock
+    /// (e.g. 30s, 500ms, 2m; a bare number is seconds).
+    #[arg(long = "lock-timeout", value_name = "DUR", value_parser = parse_duration)]
+    pub lock_timeout: Option<Duration>,
+}
+
+impl CommitArgs {
+    fn push_argv(&self, out: &mut Vec<OsString>) {
+        if self.no_backup {
+            out.push("--no-backup".into());
+        }
+        if let Some(t) = self.lock_timeout {
+            out.push("--lock-timeout".into());
+            out.push(format!("{}ms", t.as_millis()).into());
+        }
+    }
+}
+
+/// Parse a `--max-file-size` value into bytes, with 0 meaning no limit.
+pub fn parse_max_file_size(s: &str) -> Result<u64, String> {
+    togl_lib::preflight::parse_size(s).map(|limit| limit.unwrap_or(0))
+}
+
+/// Parse a SHA-256 hex digest, normalized to lowercase.
+pub fn parse_sha256(s: &str) -> Result<String, String> {
+    if s.len() == 64 && s.chars().all(|c| c.is_ascii_hexdigit()) {
+        Ok(s.to_ascii_lowercase())
+    } else {
+        Err(format!(
+            "invalid SHA-256 '{}': expected 64 hexadecimal digits",
+            s
+        ))
+    }
+}
+
+/// Parse a duration such as `30s`, `500ms`, `2m` or `1h`; a bare number is
+/// seconds.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (num, unit) = s.split_at(split);
+    let value: u64 = num
+        .parse()
+        .map_err(|_| format!("invalid duration '{}': expected e.g. 30s, 500ms, 2m", s))?;
+    match unit {
+        "ms" => Ok(Duration::from_millis(value)),
+        "" | "s" => Ok(Duration::from_secs(value)),
+        "m" => Ok(Duration::from_secs(value * 60)),
+        "h" => Ok(Duration::from_secs(value * 3600)),
+        _ => Err(format!(
+            "invalid duration unit '{}' in '{}': use ms, s, m or h",
+            unit, s
+        )),
+    }
+}
+
 /// Ergonomic subcommand surface. Each maps to a legacy operation mode.
 #[derive(clap::Subcommand, Debug)]
 pub enum Commands {
@@ -368,9 +754,16 @@ pub enum Commands {
         /// Section ID(s) to toggle (repeatable). Use `group:variant` for variants.
         #[arg(short = 'S', long = "section", action = clap::ArgAction::Append)]
         sections: Vec<String>,
-        /// Line range <start>:<end> or <start>:+<count> (repeatable)
+        /// Lines <start>:<end> or <start>:+<count>; an end may be `$`, `-N`,
+        /// `/regex/` or `block`, and a comma list is allowed (repeatable)
         #[arg(short = 'l', long = "line", action = clap::ArgAction::Append)]
         lines: Vec<String>,
+        /// Toggle the code block starting at LINE (same as `-l LINE:block`)
+        #[arg(long = "block-at", value_name = "LINE", action = clap::ArgAction::Append)]
+        block_at: Vec<String>,
+        /// Toggle the `path:line[:col]` locations listed in FILE (`-` for stdin)
+        #[arg(long = "locations", value_name = "FILE")]
+        locations: Option<PathBuf>,
         /// Recursively walk directories
         #[arg(short = 'R', long = "recursive")]
         recursive: bool,
@@ -383,12 +776,10 @@ pub enum Commands {
         /// Require exactly 2 variants in the targeted group; error otherwise.
         #[arg(long = "pair")]
         pair: bool,
-        /// Enable atomic multi-file mode: all files succeed or none are modified.
-        #[arg(long = "atomic")]
-        atomic: bool,
-        /// Disable backup creation in atomic mode (only valid with --atomic).
-        #[arg(long = "no-backup")]
-        no_backup: bool,
+        #[command(flatten)]
+        atomic: AtomicArgs,
+        #[command(flatten)]
+        safety: SafetyArgs,
         #[command(flatten)]
         filter: FilterArgs,
         #[command(flatten)]
@@ -436,16 +827,47 @@ pub enum Commands {
         #[command(flatten)]
         global: GlobalArgs,
     },
-    /// Insert a toggle:start/end marker pair around a single line range.
+    /// Browse groups, variants and sections in a terminal UI; stage toggles
+    /// and activations with the keyboard and commit them in one atomic batch.
+    Ui {
+        /// File or directory paths to browse (walked recursively) [default: .]
+        paths: Vec<PathBuf>,
+        #[command(flatten)]
+        commit: CommitArgs,
+        #[command(flatten)]
+        safety: SafetyArgs,
+        #[command(flatten)]
+        global: GlobalArgs,
+    },
+    /// Insert a toggle:start/end marker pair around a line range, or around
+    /// the blocks a --match or --from/--to pattern selects in each file.
     Insert {
-        /// Single file to modify. Omit (with --stdin) or pass `-` to read stdin.
-        path: Option<PathBuf>,
+        /// File to modify (several, or directories, with a pattern). Omit
+        /// (with --stdin) or pass `-` to read stdin.
+        paths: Vec<PathBuf>,
         /// Section ID for the inserted marker (exactly one)
         #[arg(short = 'S', long = "section")]
         section: String,
-        /// Line range to wrap (exactly one)
-        #[arg(short = 'l', long = "line")]
-        line: String,
+        /// Line range to wrap (exactly one; single file)
+        #[arg(
+            short = 'l',
+            long = "line",
+            required_unless_present_any = ["match_pattern", "from_pattern"],
+            conflicts_with_all = ["match_pattern", "from_pattern"]
+        )]
+        line: Option<String>,
+        /// Wrap every line matching REGEX, or contiguous run of matching lines
+        #[arg(long = "match", value_name = "REGEX", conflicts_with = "from_pattern")]
+        match_pattern: Option<String>,
+        /// Wrap from each line matching /REGEX/ ...
+        #[arg(long = "from", value_name = "/REGEX/")]
+        from_pattern: Option<String>,
+        /// ... through the next line matching /REGEX/
+        #[arg(long = "to", value_name = "/REGEX/", requires = "from_pattern")]
+        to_pattern: Option<String>,
+        /// Recursively walk directories (with a pattern)
+        #[arg(short = 'R', long = "recursive")]
+        recursive: bool,
         /// Description for the inserted section marker
         #[arg(long = "desc")]
         desc: Option<String>,
@@ -453,6 +875,10 @@ pub enum Commands {
         #[arg(long = "to-end")]
         to_end: bool,
         #[command(flatten)]
+        atomic: AtomicArgs,
+        #[command(flatten)]
+        safety: SafetyArgs,
+        #[command(flatten)]
         filter: FilterArgs,
         #[command(flatten)]
         global: GlobalArgs,
@@ -474,10 +900,257 @@ pub enum Commands {
         #[arg(long = "require-match")]
         require_match: bool,
         #[command(flatten)]
+        atomic: AtomicArgs,
+        #[command(flatten)]
+        safety: SafetyArgs,
+        #[command(flatten)]
         filter: FilterArgs,
         #[command(flatten)]
         global: GlobalArgs,
     },
+    /// Rename a section ID, or a whole group keeping its variant suffixes,
+    /// in every file as one atomic batch.
+    Rename {
+        /// Current section ID, or a group name to rename all its variants
+        old: String,
+        /// New section ID (or group name)
+        new: String,
+        /// File or directory paths to process
+        paths: Vec<PathBuf>,
+        /// Recursively walk directories
+        #[arg(short = 'R', long = "recursive")]
+        recursive: bool,
+        #[command(flatten)]
+        commit: CommitArgs,
+        #[command(flatten)]
+        safety: SafetyArgs,
+        #[command(flatten)]
+        global: GlobalArgs,
+    },
+    /// Add a variant copied from an existing section, or drop one, in every
+    /// file as one atomic batch.
+    Variant {
+        #[command(subcommand)]
+        action: VariantAction,
+    },
+    /// Save what a writing subcommand would change as a reviewable plan file,
+    /// e.g. `togl plan -o plan.json toggle -S debug -R src/`.
+    Plan {
+        /// Where to save the plan (`-` for stdout)
+        #[arg(short = 'o', long = "output", value_name = "FILE")]
+        output: PathBuf,
+        /// Include each file's unified diff in the plan
+        #[arg(long = "diff")]
+        diff: bool,
+        /// The toggle, insert, remove, rename or variant command to plan,
+        /// or the equivalent flat flags
+        #[arg(
+            value_name = "COMMAND",
+            required = true,
+            trailing_var_arg = true,
+            allow_hyphen_values = true
+        )]
+        command: Vec<OsString>,
+    },
+    /// Write a plan saved by `togl plan` as one atomic batch, refusing if any
+    /// file changed since it was made.
+    Apply {
+        /// The plan file
+        plan: PathBuf,
+        #[command(flatten)]
+        commit: CommitArgs,
+        #[command(flatten)]
+        global: GlobalArgs,
+    },
+    /// Revert the last N recorded operations (from .toggle/history.jsonl).
+    // Handled directly by `main()`: there is no legacy flat-flag equivalent.
+    Undo {
+        /// Number of operations to revert, newest first
+        #[arg(default_value_t = 1)]
+        count: usize,
+        /// Show what would be restored without writing files
+        #[arg(long = "dry-run")]
+        dry_run: bool,
+        /// How long to wait for another atomic operation to release the lock
+        #[arg(long = "lock-timeout", value_name = "DUR", value_parser = parse_duration)]
+        lock_timeout: Option<Duration>,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// List recorded operations (from .toggle/history.jsonl).
+    History {
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Inspect a leftover atomic journal before choosing how to recover.
+    Journal {
+        #[command(subcommand)]
+        action: JournalAction,
+    },
+    /// List, restore and prune the backups written by `--backup`.
+    Backups {
+        #[command(subcommand)]
+        action: BackupsAction,
+    },
+    /// Serve the Language Server Protocol over stdio for editor integration:
+    /// diagnostics, code lenses and actions, folding, symbols and completion.
+    Lsp {
+        /// Path to .toggleConfig TOML file
+        #[arg(long = "config")]
+        config: Option<PathBuf>,
+    },
+}
+
+/// `togl variant` actions.
+#[derive(clap::Subcommand, Debug)]
+pub enum VariantAction {
+    /// Copy the section --from right after itself as the commented-out
+    /// variant NEW_ID (`group:name`), turning a solo into a variant first.
+    Add {
+        /// New variant ID (`group:name`)
+        new: String,
+        /// Section to copy: a variant of the group, or the solo to convert
+        #[arg(long = "from", value_name = "ID")]
+        from: String,
+        /// File or directory paths to process
+        paths: Vec<PathBuf>,
+        /// Variant name a solo --from becomes [default: its own ID, or
+        /// `original` when that is the group name]
+        #[arg(long = "as", value_name = "NAME")]
+        solo_as: Option<String>,
+        /// Description for the new variant's start marker
+        #[arg(long = "desc")]
+        desc: Option<String>,
+        #[command(flatten)]
+        batch: BatchArgs,
+    },
+    /// Delete a variant, body and markers, and re-validate its group.
+    Drop {
+        /// Variant ID to drop (`group:name`)
+        id: String,
+        /// File or directory paths to process
+        paths: Vec<PathBuf>,
+        #[command(flatten)]
+        batch: BatchArgs,
+    },
+}
+
+/// Shared flags of the `togl variant` actions, which always commit as one
+/// atomic batch.
+#[derive(clap::Args, Debug)]
+pub struct BatchArgs {
+    /// Recursively walk directories
+    #[arg(short = 'R', long = "recursive")]
+    pub recursive: bool,
+    #[command(flatten)]
+    pub commit: CommitArgs,
+    #[command(flatten)]
+    pub safety: SafetyArgs,
+    #[command(flatten)]
+    pub global: GlobalArgs,
+}
+
+impl BatchArgs {
+    fn push_argv(&self, out: &mut Vec<OsString>) {
+        if self.recursive {
+            out.push("--recursive".into());
+        }
+        // The batch is atomic; a dry run only prints diffs.
+        if !self.global.dry_run {
+            out.push("--atomic".into());
+        }
+        self.commit.push_argv(out);
+        self.safety.push_argv(out);
+        self.global.push_argv(out);
+    }
+}
+
+/// `togl journal` actions. Both read `.toggle-atomic.journal` in the CWD and
+/// never modify anything.
+#[derive(clap::Subcommand, Debug)]
+pub enum JournalAction {
+    /// Show the journal status and every entry's target, temp and backup paths.
+    Show {
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Check staged content and backups against disk and recommend
+    /// `--recover` (rollback) or `--recover --recover-forward`.
+    Verify {
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+}
+
+/// `togl backups` actions. Backups are found by name next to each file, or
+/// under `--backup-dir` when given.
+#[derive(clap::Subcommand, Debug)]
+pub enum BackupsAction {
+    /// List the backups of the given files, or of every file under the given
+    /// directories (default: the current directory).
+    List {
+        paths: Vec<PathBuf>,
+        #[command(flatten)]
+        location: BackupLocationArgs,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Restore a file from its newest backup, or from the one named by --from.
+    Restore {
+        path: PathBuf,
+        /// The backup file to restore from
+        #[arg(long = "from", value_name = "BACKUP")]
+        from: Option<PathBuf>,
+        /// Show what would be restored without writing files
+        #[arg(long = "dry-run")]
+        dry_run: bool,
+        #[command(flatten)]
+        location: BackupLocationArgs,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Delete all but the newest N numbered/timestamped backups of each file.
+    Prune {
+        paths: Vec<PathBuf>,
+        /// Number of backups to keep per file
+        #[arg(long = "keep", value_name = "N")]
+        keep: usize,
+        /// Show what would be deleted without deleting anything
+        #[arg(long = "dry-run")]
+        dry_run: bool,
+        #[command(flatten)]
+        location: BackupLocationArgs,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+}
+
+/// Where `togl backups` looks for backups; match the flags they were made with.
+#[derive(clap::Args, Debug)]
+pub struct BackupLocationArgs {
+    /// The --backup-dir the backups were written to
+    #[arg(long = "backup-dir", value_name = "DIR")]
+    pub backup_dir: Option<PathBuf>,
+
+    /// The --backup EXT of simple backups
+    #[arg(long = "suffix", value_name = "EXT", default_value = "~")]
+    pub suffix: String,
+}
+
+/// Output flags for the subcommands that bypass the legacy pipeline.
+#[derive(clap::Args, Debug)]
+pub struct OutputArgs {
+    /// Human-readable log lines to stderr
+    #[arg(short = 'v', long = "verbose")]
+    pub verbose: bool,
+
+    /// Machine-readable single-line JSON to stdout
+    #[arg(long = "json")]
+    pub json: bool,
+
+    /// Map exit codes to sysexits.h values
+    #[arg(short = 'x', long = "posix-exit")]
+    pub posix_exit: bool,
 }
 
 /// Canonical kebab-case name for a `ValueEnum` value (e.g. `Lines` -> "lines").
@@ -489,6 +1162,19 @@ fn enum_name<E: clap::ValueEnum>(v: &E) -> OsString {
 }
 
 impl Commands {
+    /// Whether `togl plan` can wrap this subcommand: the ones that write
+    /// files through the planning pipeline.
+    pub fn can_plan(&self) -> bool {
+        matches!(
+            self,
+            Commands::Toggle { .. }
+                | Commands::Insert { .. }
+                | Commands::Remove { .. }
+                | Commands::Rename { .. }
+                | Commands::Variant { .. }
+        )
+    }
+
     /// Translate this subcommand into the equivalent legacy argv, with `bin` as
     /// argv[0]. The result is fed back through `build_command()` so clap owns
     /// defaults/validation/aliasing — making subcommand/flat-flag drift
@@ -500,12 +1186,14 @@ impl Commands {
                 paths,
                 sections,
                 lines,
+                block_at,
+                locations,
                 recursive,
                 force,
                 to_end,
                 pair,
                 atomic,
-                no_backup,
+                safety,
                 filter,
                 global,
             } => {
@@ -517,6 +1205,14 @@ impl Commands {
                     out.push("-l".into());
                     out.push(l.into());
                 }
+                for b in block_at {
+                    out.push("--block-at".into());
+                    out.push(b.into());
+                }
+                if let Some(l) = locations {
+                    out.push("--locations".into());
+                    out.push(l.into());
+                }
                 if *recursive {
                     out.push("--recursive".into());
                 }
@@ -530,12 +1226,8 @@ impl Commands {
                 if *pair {
                     out.push("--pair".into());
                 }
-                if *atomic {
-                    out.push("--atomic".into());
-                }
-                if *no_backup {
-                    out.push("--no-backup".into());
-                }
+                atomic.push_argv(&mut out);
+                safety.push_argv(&mut out);
                 filter.push_argv(&mut out);
                 global.push_argv(&mut out);
                 push_paths(&mut out, paths);
@@ -596,20 +1288,59 @@ impl Commands {
                 global.push_argv(&mut out);
                 push_paths(&mut out, paths);
             }
+            Commands::Ui {
+                paths,
+                commit,
+                safety,
+                global,
+            } => {
+                // Every commit from the UI is one atomic batch.
+                out.push("--ui".into());
+                out.push("--atomic".into());
+                commit.push_argv(&mut out);
+                safety.push_argv(&mut out);
+                global.push_argv(&mut out);
+                if paths.is_empty() {
+                    out.push(".".into());
+                } else {
+                    push_paths(&mut out, paths);
+                }
+            }
             Commands::Insert {
-                path,
+                paths,
                 section,
                 line,
+                match_pattern,
+                from_pattern,
+                to_pattern,
+                recursive,
                 desc,
                 to_end,
+                atomic,
+                safety,
                 filter,
                 global,
             } => {
                 out.push("--insert".into());
                 out.push("-S".into());
                 out.push(section.into());
-                out.push("-l".into());
-                out.push(line.into());
+                if let Some(l) = line {
+                    out.push("-l".into());
+                    out.push(l.into());
+                }
+                for (flag, value) in [
+                    ("--match", match_pattern),
+                    ("--from", from_pattern),
+                    ("--to", to_pattern),
+                ] {
+                    if let Some(v) = value {
+                        out.push(flag.into());
+                        out.push(v.into());
+                    }
+                }
+                if *recursive {
+                    out.push("--recursive".into());
+                }
                 if let Some(d) = desc {
                     out.push("--desc".into());
                     out.push(d.into());
@@ -617,12 +1348,11 @@ impl Commands {
                 if *to_end {
                     out.push("--to-end".into());
                 }
+                atomic.push_argv(&mut out);
+                safety.push_argv(&mut out);
                 filter.push_argv(&mut out);
                 global.push_argv(&mut out);
-                if let Some(p) = path {
-                    out.push("--".into());
-                    out.push(p.into());
-                }
+                push_paths(&mut out, paths);
             }
             Commands::Remove {
                 paths,
@@ -630,6 +1360,8 @@ impl Commands {
                 recursive,
                 remove_mode,
                 require_match,
+                atomic,
+                safety,
                 filter,
                 global,
             } => {
@@ -646,10 +1378,97 @@ impl Commands {
                 if *require_match {
                     out.push("--require-match".into());
                 }
+                atomic.push_argv(&mut out);
+                safety.push_argv(&mut out);
                 filter.push_argv(&mut out);
                 global.push_argv(&mut out);
                 push_paths(&mut out, paths);
             }
+            Commands::Rename {
+                old,
+                new,
+                paths,
+                recursive,
+                commit,
+                safety,
+                global,
+            } => {
+                out.push("--rename".into());
+                out.push(new.into());
+                out.push("-S".into());
+                out.push(old.into());
+                if *recursive {
+                    out.push("--recursive".into());
+                }
+                // A rename is one atomic batch; a dry run only prints diffs.
+                if !global.dry_run {
+                    out.push("--atomic".into());
+                }
+                commit.push_argv(&mut out);
+                safety.push_argv(&mut out);
+                global.push_argv(&mut out);
+                push_paths(&mut out, paths);
+            }
+            Commands::Variant {
+                action:
+                    VariantAction::Add {
+                        new,
+                        from,
+                        paths,
+                        solo_as,
+                        desc,
+                        batch,
+                    },
+            } => {
+                out.push("--add-variant".into());
+                out.push(new.into());
+                out.push("-S".into());
+                out.push(from.into());
+                if let Some(name) = solo_as {
+                    out.push("--variant-as".into());
+                    out.push(name.into());
+                }
+                if let Some(d) = desc {
+                    out.push("--desc".into());
+                    out.push(d.into());
+                }
+                batch.push_argv(&mut out);
+                push_paths(&mut out, paths);
+            }
+            Commands::Variant {
+                action: VariantAction::Drop { id, paths, batch },
+            } => {
+                out.push("--drop-variant".into());
+                out.push("-S".into());
+                out.push(id.into());
+                batch.push_argv(&mut out);
+                push_paths(&mut out, paths);
+            }
+            Commands::Apply {
+                plan,
+                commit,
+                global,
+            } => {
+                out.push("--apply-plan".into());
+                out.push(plan.into());
+                // The plan is committed as one atomic batch; a dry run only
+                // prints diffs.
+                if !global.dry_run {
+                    out.push("--atomic".into());
+                }
+                commit.push_argv(&mut out);
+                global.push_argv(&mut out);
+            }
+            Commands::Plan { .. }
+            | Commands::Undo { .. }
+            | Commands::History { .. }
+            | Commands::Journal { .. }
+            | Commands::Backups { .. }
+            | Commands::Lsp { .. } => {
+                unreachable!(
+                    "plan/undo/history/journal/backups/lsp are dispatched before the legacy bridge"
+                )
+            }
         }
         out
     }
@@ -0,0 +1,498 @@
+// `togl lsp`: a Language Server Protocol server over stdio.
+//
+// Editors get section diagnostics, code lenses and actions, folding ranges,
+// document symbols and section-ID completion from one server instead of a
+// plugin each. Documents are synced in full; every edit the server proposes
+// is a minimal set of line replacements computed from the library transforms.
+
+use anyhow::{anyhow, Result};
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, RequestId, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+    Notification as LspNotification, PublishDiagnostics,
+};
+use lsp_types::request::{
+    ApplyWorkspaceEdit, CodeActionRequest, CodeLensRequest, Completion, DocumentSymbolRequest,
+    ExecuteCommand, FoldingRangeRequest, Request as LspRequest,
+};
+use lsp_types::{
+    ApplyWorkspaceEditParams, CodeAction, CodeActionKind, CodeActionOrCommand, CodeActionParams,
+    CodeActionProviderCapability, CodeLens, CodeLensOptions, CodeLensParams, Command,
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, CompletionResponse,
+    Diagnostic, DiagnosticSeverity, DocumentSymbol, DocumentSymbolParams, DocumentSymbolResponse,
+    ExecuteCommandOptions, ExecuteCommandParams, FoldingRange, FoldingRangeKind,
+    FoldingRangeParams, FoldingRangeProviderCapability, OneOf, Position, PublishDiagnosticsParams,
+    Range, ServerCapabilities, SymbolKind, TextDocumentSyncCapability, TextDocumentSyncKind,
+    TextEdit, Uri, WorkspaceEdit,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use togl_lib::core::{self, RemoveMode, ScanSectionInfo};
+use togl_lib::io;
+use togl_lib::session::{Operation, Toggler};
+
+const CMD_TOGGLE: &str = "togl.toggleSection";
+const CMD_ACTIVATE: &str = "togl.activateVariant";
+const CMD_REMOVE: &str = "togl.removeMarkers";
+
+/// Serve LSP on stdin/stdout until the client shuts the server down.
+pub fn run(toggler: Toggler) -> Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+    connection.initialize(serde_json::to_value(capabilities())?)?;
+    Server {
+        toggler,
+        docs: HashMap::new(),
+        next_id: 0,
+    }
+    .serve(&connection)?;
+    // The writer thread only finishes once every sender is gone.
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
+
+fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        code_lens_provider: Some(CodeLensOptions {
+            resolve_provider: Some(false),
+        }),
+        code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+        folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec!["=".into(), ":".into()]),
+            ..CompletionOptions::default()
+        }),
+        execute_command_provider: Some(ExecuteCommandOptions {
+            commands: vec![CMD_TOGGLE.into(), CMD_ACTIVATE.into(), CMD_REMOVE.into()],
+            ..ExecuteCommandOptions::default()
+        }),
+        ..ServerCapabilities::default()
+    }
+}
+
+struct Server {
+    toggler: Toggler,
+    /// Text of every open document.
+    docs: HashMap<Uri, String>,
+    /// ID for the next request sent to the client.
+    next_id: i32,
+}
+
+impl Server {
+    fn serve(&mut self, connection: &Connection) -> Result<()> {
+        for msg in &connection.receiver {
+            match msg {
+                Message::Request(req) => {
+                    if connection.handle_shutdown(&req)? {
+                        return Ok(());
+                    }
+                    let response = self.handle_request(req, connection)?;
+                    connection.sender.send(response.into())?;
+                }
+                Message::Notification(note) => self.handle_notification(note, connection)?,
+                // Replies to our `workspace/applyEdit` requests: nothing to do.
+                Message::Response(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_request(&mut self, req: Request, connection: &Connection) -> Result<Response> {
+        let id = req.id.clone();
+        let result = match req.method.as_str() {
+            CodeLensRequest::METHOD => reply::<CodeLensRequest>(req, |p| Ok(self.code_lenses(p))),
+            CodeActionRequest::METHOD => {
+                reply::<CodeActionRequest>(req, |p| Ok(self.code_actions(p)))
+            }
+            FoldingRangeRequest::METHOD => {
+                reply::<FoldingRangeRequest>(req, |p| Ok(self.folding_ranges(p)))
+            }
+            DocumentSymbolRequest::METHOD => {
+                reply::<DocumentSymbolRequest>(req, |p| Ok(self.symbols(p)))
+            }
+            Completion::METHOD => reply::<Completion>(req, |p| Ok(self.complete(p))),
+            ExecuteCommand::METHOD => {
+                reply::<ExecuteCommand>(req, |p| self.execute(p, connection).map(|()| None))
+            }
+            _ => Err((
+                ErrorCode::MethodNotFound,
+                format!("unhandled method '{}'", req.method),
+            )),
+        };
+        Ok(match result {
+            Ok(value) => Response::new_ok(id, value),
+            Err((code, message)) => Response::new_err(id, code as i32, message),
+        })
+    }
+
+    fn handle_notification(&mut self, note: Notification, connection: &Connection) -> Result<()> {
+        let uri = match note.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let p: <DidOpenTextDocument as LspNotification>::Params =
+                    serde_json::from_value(note.params)?;
+                self.docs
+                    .insert(p.text_document.uri.clone(), p.text_document.text);
+                p.text_document.uri
+            }
+            DidChangeTextDocument::METHOD => {
+                let p: <DidChangeTextDocument as LspNotification>::Params =
+                    serde_json::from_value(note.params)?;
+                // Full sync: the last change carries the whole text.
+                if let Some(change) = p.content_changes.into_iter().last() {
+                    self.docs.insert(p.text_document.uri.clone(), change.text);
+                }
+                p.text_document.uri
+            }
+            DidCloseTextDocument::METHOD => {
+                let p: <DidCloseTextDocument as LspNotification>::Params =
+                    serde_json::from_value(note.params)?;
+                self.docs.remove(&p.text_document.uri);
+                // Clear the closed document's diagnostics.
+                return publish(connection, p.text_document.uri, Vec::new());
+            }
+            _ => return Ok(()),
+        };
+        let diagnostics = self.diagnostics(&uri);
+        publish(connection, uri, diagnostics)
+    }
+
+    fn text(&self, uri: &Uri) -> &str {
+        self.docs.get(uri).map(String::as_str).unwrap_or("")
+    }
+
+    fn sections(&self, uri: &Uri) -> Vec<ScanSectionInfo> {
+        core::scan_sections(&uri_path(uri), self.text(uri))
+    }
+
+    /// `validate_sections` findings, placed on the marker at fault.
+    fn diagnostics(&self, uri: &Uri) -> Vec<Diagnostic> {
+        let path = uri_path(uri);
+        let issues = core::validate_sections(&[(path, self.sections(uri))], false);
+        issues
+            .into_iter()
+            .filter_map(|issue| {
+                let severity = match issue.level {
+                    core::CheckLevel::Err => DiagnosticSeverity::ERROR,
+                    core::CheckLevel::Warn => DiagnosticSeverity::WARNING,
+                    core::CheckLevel::Ok => return None,
+                };
+                let line = issue.line.map_or(0, |l| l - 1);
+                Some(Diagnostic {
+                    range: line_range(self.text(uri), line),
+                    severity: Some(severity),
+                    source: Some("togl".into()),
+                    message: issue.message,
+                    ..Diagnostic::default()
+                })
+            })
+            .collect()
+    }
+
+    /// The edit applying `op` to the document, or None if it changes nothing.
+    fn edit(&self, uri: &Uri, op: Operation) -> Result<Option<WorkspaceEdit>> {
+        let text = self.text(uri);
+        let (modified, _) = self.toggler.transform(&uri_path(uri), text, &[op])?;
+        let edits = text_edits(text, &modified);
+        if edits.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(WorkspaceEdit {
+            changes: Some(HashMap::from([(uri.clone(), edits)])),
+            ..WorkspaceEdit::default()
+        }))
+    }
+
+    /// A lens on each start marker: toggle a solo section or activate a variant.
+    fn code_lenses(&self, p: CodeLensParams) -> Option<Vec<CodeLens>> {
+        let uri = p.text_document.uri;
+        let lenses = self
+            .sections(&uri)
+            .into_iter()
+            .filter(|s| s.end_line.is_some())
+            .map(|s| {
+                let (title, command) = match &s.variant {
+                    Some(_) => (format!("Activate {}", s.id), CMD_ACTIVATE),
+                    None => ("Toggle section".to_string(), CMD_TOGGLE),
+                };
+                CodeLens {
+                    range: line_range(self.text(&uri), s.start_line - 1),
+                    command: Some(Command {
+                        title,
+                        command: command.into(),
+                        arguments: Some(vec![uri.as_str().into(), s.id.into()]),
+                    }),
+                    data: None,
+                }
+            })
+            .collect();
+        Some(lenses)
+    }
+
+    /// Toggle / activate / remove-markers actions for the sections around the
+    /// cursor, each carrying its edit.
+    fn code_actions(&self, p: CodeActionParams) -> Option<Vec<CodeActionOrCommand>> {
+        let uri = p.text_document.uri;
+        let line = p.range.start.line as usize + 1;
+        let mut actions = Vec::new();
+        for s in self.sections(&uri) {
+            let Some(end) = s.end_line else { continue };
+            if line < s.start_line || line > end {
+                continue;
+            }
+            let (title, op) = match &s.variant {
+                Some(_) => (format!("Activate {}", s.id), Operation::section(&s.id)),
+                None => (
+                    format!("Toggle section {}", s.id),
+                    Operation::ToggleSection { id: s.id.clone() },
+                ),
+            };
+            let remove = Operation::Remove {
+                id: s.id.clone(),
+                mode: RemoveMode::Markers,
+            };
+            for (title, op) in [(title, op), (format!("Remove markers of {}", s.id), remove)] {
+                if let Ok(Some(edit)) = self.edit(&uri, op) {
+                    actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                        title,
+                        kind: Some(CodeActionKind::REFACTOR_REWRITE),
+                        edit: Some(edit),
+                        ..CodeAction::default()
+                    }));
+                }
+            }
+        }
+        Some(actions)
+    }
+
+    fn folding_ranges(&self, p: FoldingRangeParams) -> Option<Vec<FoldingRange>> {
+        let ranges = self
+            .sections(&p.text_document.uri)
+            .into_iter()
+            .filter_map(|s| {
+                Some(FoldingRange {
+                    start_line: (s.start_line - 1) as u32,
+                    end_line: (s.end_line? - 1) as u32,
+                    kind: Some(FoldingRangeKind::Region),
+                    collapsed_text: Some(s.id),
+                    ..FoldingRange::default()
+                })
+            })
+            .collect();
+        Some(ranges)
+    }
+
+    /// One symbol per solo section; variants nest under their group.
+    fn symbols(&self, p: DocumentSymbolParams) -> Option<DocumentSymbolResponse> {
+        let uri = p.text_document.uri;
+        let text = self.text(&uri);
+        let mut solos = Vec::new();
+        let mut groups: BTreeMap<String, Vec<DocumentSymbol>> = BTreeMap::new();
+        for s in self.sections(&uri) {
+            let start = s.start_line - 1;
+            let end = s.end_line.map_or(start, |e| e - 1);
+            let symbol = symbol(
+                s.id.clone(),
+                Some(s.state_label()),
+                span(text, start, end),
+                line_range(text, start),
+                None,
+            );
+            match s.variant {
+                Some(_) => groups.entry(s.group).or_default().push(symbol),
+                None => solos.push(symbol),
+            }
+        }
+        for (group, variants) in groups {
+            let start = variants.iter().map(|v| v.range.start).min()?;
+            let end = variants.iter().map(|v| v.range.end).max()?;
+            solos.push(symbol(
+                group,
+                Some(format!("{} variants", variants.len())),
+                Range::new(start, end),
+                Range::new(start, start),
+                Some(variants),
+            ));
+        }
+        solos.sort_by_key(|s| s.range.start);
+        Some(DocumentSymbolResponse::Nested(solos))
+    }
+
+    /// Known section IDs, offered after `ID=` on a marker line.
+    fn complete(&self, p: CompletionParams) -> Option<CompletionResponse> {
+        let pos = p.text_document_position.position;
+        let uri = p.text_document_position.text_document.uri;
+        let line = self.text(&uri).lines().nth(pos.line as usize)?;
+        let cursor = byte_offset(line, pos.character);
+        if !(line.contains("toggle:start") || line.contains("toggle:end")) {
+            return None;
+        }
+        let id_start = line.find("ID=")? + "ID=".len();
+        if cursor < id_start || line[id_start..cursor].contains(char::is_whitespace) {
+            return None;
+        }
+        let replace = Range::new(Position::new(pos.line, utf16_len(&line[..id_start])), pos);
+
+        let mut known: BTreeMap<String, Option<String>> = BTreeMap::new();
+        for text in self.docs.values() {
+            for s in core::discover_sections(text) {
+                let desc = known.entry(s.id).or_default();
+                if desc.is_none() {
+                    *desc = s.desc;
+                }
+            }
+        }
+        let items = known
+            .into_iter()
+            .map(|(id, desc)| CompletionItem {
+                label: id.clone(),
+                kind: Some(CompletionItemKind::REFERENCE),
+                detail: desc,
+                text_edit: Some(lsp_types::CompletionTextEdit::Edit(TextEdit::new(
+                    replace, id,
+                ))),
+                ..CompletionItem::default()
+            })
+            .collect();
+        Some(CompletionResponse::Array(items))
+    }
+
+    /// Run a lens command: compute the edit and ask the client to apply it.
+    fn execute(&mut self, p: ExecuteCommandParams, connection: &Connection) -> Result<()> {
+        let (uri, id) = match p.arguments.as_slice() {
+            [uri, id] => (
+                serde_json::from_value::<Uri>(uri.clone())?,
+                serde_json::from_value::<String>(id.clone())?,
+            ),
+            _ => return Err(anyhow!("{} expects [uri, sectionId]", p.command)),
+        };
+        let op = match p.command.as_str() {
+            CMD_TOGGLE => Operation::ToggleSection { id: id.clone() },
+            CMD_ACTIVATE => Operation::section(&id),
+            CMD_REMOVE => Operation::Remove {
+                id: id.clone(),
+                mode: RemoveMode::Markers,
+            },
+            other => return Err(anyhow!("unknown command '{other}'")),
+        };
+        let Some(edit) = self.edit(&uri, op)? else {
+            return Ok(());
+        };
+        self.next_id += 1;
+        let params = ApplyWorkspaceEditParams {
+            label: Some(format!("togl: {id}")),
+            edit,
+        };
+        let req = Request::new(
+            RequestId::from(self.next_id),
+            ApplyWorkspaceEdit::METHOD.into(),
+            params,
+        );
+        connection.sender.send(req.into())?;
+        Ok(())
+    }
+}
+
+type Reply = std::result::Result<serde_json::Value, (ErrorCode, String)>;
+
+/// Decode `req`'s params as `R`'s, run `f`, and encode its result.
+fn reply<R: LspRequest>(req: Request, f: impl FnOnce(R::Params) -> Result<R::Result>) -> Reply {
+    let params: R::Params = serde_json::from_value(req.params)
+        .map_err(|e| (ErrorCode::InvalidParams, e.to_string()))?;
+    let result = f(params).map_err(|e| (ErrorCode::RequestFailed, format!("{e:#}")))?;
+    serde_json::to_value(result).map_err(|e| (ErrorCode::InternalError, e.to_string()))
+}
+
+fn publish(connection: &Connection, uri: Uri, diagnostics: Vec<Diagnostic>) -> Result<()> {
+    let params = PublishDiagnosticsParams {
+        uri,
+        diagnostics,
+        version: None,
+    };
+    connection
+        .sender
+        .send(Notification::new(PublishDiagnostics::METHOD.into(), params).into())?;
+    Ok(())
+}
+
+#[allow(deprecated)] // `DocumentSymbol::deprecated` must still be set.
+fn symbol(
+    name: String,
+    detail: Option<String>,
+    range: Range,
+    selection_range: Range,
+    children: Option<Vec<DocumentSymbol>>,
+) -> DocumentSymbol {
+    DocumentSymbol {
+        name,
+        detail,
+        kind: SymbolKind::NAMESPACE,
+        tags: None,
+        deprecated: None,
+        range,
+        selection_range,
+        children,
+    }
+}
+
+/// The path a document URI names; only its extension matters here.
+fn uri_path(uri: &Uri) -> PathBuf {
+    PathBuf::from(uri.path().as_str())
+}
+
+/// Line `line` (0-based) from its start to its end.
+fn line_range(text: &str, line: usize) -> Range {
+    span(text, line, line)
+}
+
+/// Lines `start..=end` (0-based), from the start of the first to the end of
+/// the last.
+fn span(text: &str, start: usize, end: usize) -> Range {
+    let width = text.lines().nth(end).map_or(0, utf16_len);
+    Range::new(
+        Position::new(start as u32, 0),
+        Position::new(end as u32, width),
+    )
+}
+
+/// Minimal `TextEdit`s turning `original` into `modified`.
+fn text_edits(original: &str, modified: &str) -> Vec<TextEdit> {
+    let line_count = original.lines().count();
+    let last_width = original.lines().last().map_or(0, utf16_len);
+    let open_last_line = !original.is_empty() && !original.ends_with('\n');
+    let position = |line: usize| {
+        // Past an unterminated last line there is no line start to point at.
+        if line >= line_count && open_last_line {
+            Position::new((line_count - 1) as u32, last_width)
+        } else {
+            Position::new(line as u32, 0)
+        }
+    };
+    io::line_edits(original, modified)
+        .into_iter()
+        .map(|edit| {
+            TextEdit::new(
+                Range::new(position(edit.start), position(edit.end)),
+                edit.new_text,
+            )
+        })
+        .collect()
+}
+
+/// Length of `s` in UTF-16 code units, the LSP default position encoding.
+fn utf16_len(s: &str) -> u32 {
+    s.encode_utf16().count() as u32
+}
+
+/// The byte offset of UTF-16 column `character` in `line`.
+fn byte_offset(line: &str, character: u32) -> usize {
+    let mut units = 0;
+    for (i, c) in line.char_indices() {
+        if units >= character {
+            return i;
+        }
+        units += c.len_utf16() as u32;
+    }
+    line.len()
+}
@@ -1,35 +1,48 @@
 use anyhow::{Context, Result};
+use std::cell::RefCell;
 use std::collections::BTreeMap;
 use std::io::IsTerminal;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
 mod cli;
-use cli::{Cli, ListFields, RemoveMode};
+mod lsp;
+mod ui;
+use cli::{BackupMode, BackupStrategy, Cli, ListFields, RemoveMode};
+use togl_lib::backup;
 use togl_lib::config::ToggleConfig;
+use togl_lib::confine;
 use togl_lib::core;
-use togl_lib::exit_codes::{ExitCode, UsageError};
+use togl_lib::exit_codes::{
+    ContentChangedError, ExitCode, OutsideRootError, ToglError, UsageError,
+};
+use togl_lib::history;
 use togl_lib::io;
 use togl_lib::journal;
+use togl_lib::planfile;
+use togl_lib::preflight;
+use togl_lib::select::{self, LineSpec};
+use togl_lib::session::{self, Operation, Toggler};
 use togl_lib::walk;
 
 /// Bundled options passed through the toggle pipeline.
 struct ToggleOptions<'a> {
-    force: &'a Option<String>,
-    mode: &'a str,
-    temp_suffix: Option<&'a str>,
+    /// How files are read, transformed and written.
+    toggler: Toggler,
     dry_run: bool,
-    backup: Option<&'a str>,
-    config: Option<&'a ToggleConfig>,
     verbose: bool,
-    eol: &'a str,
-    no_dereference: bool,
-    encoding: &'a str,
     json: bool,
-    to_end: bool,
-    comment_style_override: &'a [String],
     interactive: bool,
+    /// Collects every file write for the history log; None when not recording.
+    history: Option<&'a RefCell<Vec<history::FileChange>>>,
+    /// Set by SIGTERM/SIGINT in atomic runs.
+    interrupted: Option<Arc<AtomicBool>>,
+    /// Where `--plan` saves the batch instead of committing it.
+    plan: Option<&'a Path>,
+    /// Include unified diffs in the saved plan.
+    plan_diff: bool,
 }
 
 /// Result of processing a single toggle operation.
@@ -38,6 +51,8 @@ struct ProcessResult {
     lines_changed: usize,
     section_id: Option<String>,
     desc: Option<String>,
+    /// Encoding found by `--encoding auto` (reported in `--json`).
+    detected_encoding: Option<io::FileEncoding>,
 }
 
 /// JSON output entry for --json mode.
@@ -54,6 +69,18 @@ struct ToggleResult {
     section_id: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     desc: Option<String>,
+    /// Detected encoding name (only with `--encoding auto`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    encoding: Option<String>,
+    /// Whether the file carried a byte-order mark (only with `--encoding auto`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bom: Option<bool>,
+    /// Why preflight skipped the file (`binary`, `generated`, ...).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    skipped: Option<&'static str>,
+    /// What a `--root` violation tried to write (`target`, `backup`, ...).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    outside_root: Option<&'static str>,
 }
 
 /// JSON output for --list-sections mode.
@@ -125,6 +152,68 @@ fn main() {
     let raw: Vec<std::ffi::OsString> = std::env::args_os().collect();
     let parsed = parse_cli(raw.iter().cloned());
 
+    // ── History, journal, backups and lsp subcommands ──
+    // `undo`, `history`, `journal`, `backups` and `lsp` have no flat-flag equivalent, so
+    // they are handled here instead of going through the legacy bridge below.
+    match &parsed.command {
+        Some(cli::Commands::Undo {
+            count,
+            dry_run,
+            lock_timeout,
+            output,
+        }) => finish(
+            run_undo(*count, *dry_run, lock_timeout.unwrap_or_default(), output),
+            output.json,
+            output.posix_exit,
+        ),
+        Some(cli::Commands::History { output }) => {
+            finish(run_history(output), output.json, output.posix_exit)
+        }
+        Some(cli::Commands::Journal { action }) => match action {
+            cli::JournalAction::Show { output } => {
+                finish(run_journal_show(output), output.json, output.posix_exit)
+            }
+            cli::JournalAction::Verify { output } => {
+                finish(run_journal_verify(output), output.json, output.posix_exit)
+            }
+        },
+        Some(cli::Commands::Backups { action }) => match action {
+            cli::BackupsAction::List {
+                paths,
+                location,
+                output,
+            } => finish(
+                run_backups_list(paths, location, output),
+                output.json,
+                output.posix_exit,
+            ),
+            cli::BackupsAction::Restore {
+                path,
+                from,
+                dry_run,
+                location,
+                output,
+            } => finish(
+                run_backups_restore(path, from.as_deref(), *dry_run, location, output),
+                output.json,
+                output.posix_exit,
+            ),
+            cli::BackupsAction::Prune {
+                paths,
+                keep,
+                dry_run,
+                location,
+                output,
+            } => finish(
+                run_backups_prune(paths, *keep, *dry_run, location, output),
+                output.json,
+                output.posix_exit,
+            ),
+        },
+        Some(cli::Commands::Lsp { config }) => finish(run_lsp(config.as_deref()), false, false),
+        _ => {}
+    }
+
     // ── Subcommand bridge ──
     // A subcommand is an ergonomic front-end: translate it to the equivalent
     // legacy argv and re-parse through the same path, yielding a flat `Cli`
@@ -132,26 +221,67 @@ fn main() {
     // flat-flag form emits a one-line deprecation nudge (humans only).
     let cli = if let Some(command) = &parsed.command {
         let bin = raw.first().cloned().unwrap_or_else(|| "togl".into());
-        let legacy_argv = command.to_legacy_argv(bin);
+        let legacy_argv = match command {
+            cli::Commands::Plan {
+                output,
+                diff,
+                command,
+            } => plan_argv(bin, output, *diff, command),
+            command => command.to_legacy_argv(bin),
+        };
         parse_cli(legacy_argv)
     } else {
         maybe_warn_legacy(&parsed);
         parsed
     };
 
-    let result = run(&cli);
+    finish(run(&cli), cli.json, cli.posix_exit);
+}
+
+/// The legacy argv of `togl plan`: the wrapped command (a subcommand or flat
+/// flags, parsed on their own since clap cannot nest the subcommand enum in
+/// itself) with the plan flags ahead of its `--`-guarded paths.
+fn plan_argv(
+    bin: std::ffi::OsString,
+    output: &Path,
+    diff: bool,
+    command: &[std::ffi::OsString],
+) -> Vec<std::ffi::OsString> {
+    let argv = std::iter::once(bin.clone()).chain(command.iter().cloned());
+    let mut planned: Vec<std::ffi::OsString> = match parse_cli(argv.clone()).command {
+        None => argv.collect(),
+        Some(inner) if inner.can_plan() => inner.to_legacy_argv(bin),
+        Some(_) => finish(
+            Err(UsageError(
+                "togl plan takes a toggle, insert, remove, rename or variant command".into(),
+            )
+            .into()),
+            false,
+            false,
+        ),
+    };
+    let mut flags: Vec<std::ffi::OsString> = vec!["--plan".into(), output.into()];
+    if diff {
+        flags.push("--plan-diff".into());
+    }
+    planned.splice(1..1, flags);
+    planned
+}
+
+/// Report a run's error (unless in JSON mode) and exit with its mapped code.
+fn finish(result: Result<()>, json: bool, posix_exit: bool) -> ! {
     let code = match &result {
         Ok(_) => ExitCode::Success,
         Err(e) => classify_error(e),
     };
 
     if let Err(e) = &result {
-        if !cli.json {
+        if !json {
             eprintln!("Error: {:#}", e);
         }
     }
 
-    let exit_val = if cli.posix_exit {
+    let exit_val = if posix_exit {
         code.posix()
     } else {
         code.code()
@@ -175,8 +305,17 @@ fn maybe_warn_legacy(cli: &Cli) {
 }
 
 fn classify_error(err: &anyhow::Error) -> ExitCode {
+    if content_changed(err) {
+        return ExitCode::ContentChanged;
+    }
+    if outside_root(err).is_some() {
+        return ExitCode::OutsideRoot;
+    }
     // Walk the error chain looking for specific typed errors
     for cause in err.chain() {
+        if let Some(e) = cause.downcast_ref::<ToglError>() {
+            return e.exit_code();
+        }
         if cause.downcast_ref::<std::io::Error>().is_some() {
             return ExitCode::IoError;
         }
@@ -188,6 +327,29 @@ fn classify_error(err: &anyhow::Error) -> ExitCode {
     ExitCode::ToggleError
 }
 
+/// The `T` that `err` is (or wraps), possibly carried inside an `io::Error`
+/// from the write path.
+fn find_cause<T: std::error::Error + 'static>(err: &anyhow::Error) -> Option<&T> {
+    err.chain().find_map(|cause| {
+        cause.downcast_ref::<T>().or_else(|| {
+            cause
+                .downcast_ref::<std::io::Error>()
+                .and_then(|e| e.get_ref())
+                .and_then(|inner| inner.downcast_ref::<T>())
+        })
+    })
+}
+
+/// Whether `err` is (or wraps) a `ContentChangedError`.
+fn content_changed(err: &anyhow::Error) -> bool {
+    find_cause::<ContentChangedError>(err).is_some()
+}
+
+/// The `--root` violation `err` is (or wraps), if any.
+fn outside_root(err: &anyhow::Error) -> Option<&OutsideRootError> {
+    find_cause(err)
+}
+
 fn run(cli: &Cli) -> Result<()> {
     // ── Meta short-circuits: completions and man page ──
     if let Some(shell) = cli.completions {
@@ -210,13 +372,21 @@ fn run(cli: &Cli) -> Result<()> {
     let filter_mode = cli.stdin || cli.stdout || cli.paths.iter().any(|p| p.as_os_str() == "-");
 
     // Path is required for everything else
-    if !filter_mode && cli.paths.is_empty() {
+    if !filter_mode && cli.paths.is_empty() && cli.locations.is_none() && cli.apply_plan.is_none() {
         return Err(UsageError("at least one file or directory path is required".into()).into());
     }
 
     // ── Atomic mode: startup journal check ──
+    // A batch spanning several directories leaves pointers to its journal in
+    // each of them, so look in the CWD and every given path. Runs that only
+    // read (scans, listings, dry runs, plans, filter mode) are not blocked.
     let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
-    let journal_path = cwd.join(journal::JOURNAL_FILENAME);
+    let read_only =
+        cli.scan || cli.list_sections || cli.dry_run || cli.plan.is_some() || filter_mode;
+    let mut search = vec![cwd.clone()];
+    search.extend(cli.paths.iter().filter(|p| p.as_os_str() != "-").cloned());
+    let journal_path =
+        journal::locate_journal(&search).unwrap_or_else(|| cwd.join(journal::JOURNAL_FILENAME));
 
     // Handle --recover flag
     if cli.recover {
@@ -226,10 +396,11 @@ fn run(cli: &Cli) -> Result<()> {
     }
 
     // Check for leftover journal from a previous interrupted run
-    if journal_path.exists() && !cli.recover {
+    if journal_path.exists() && !read_only {
         return Err(UsageError(
             "A previous atomic operation was interrupted. \
-             Run with --recover to clean up, or --recover --recover-forward to complete it."
+             Inspect it with `togl journal verify`, then run with --recover to roll back, \
+             or --recover --recover-forward to complete it."
                 .into(),
         )
         .into());
@@ -244,6 +415,12 @@ fn run(cli: &Cli) -> Result<()> {
     // Note: --atomic --stdout is not applicable (no --stdout flag exists yet)
     // Note: --atomic --in-place is not applicable (no --in-place flag exists yet)
 
+    // A single hash can only describe a single file on disk.
+    if cli.expect_sha256.is_some() && (filter_mode || cli.paths.len() != 1 || cli.paths[0].is_dir())
+    {
+        return Err(UsageError("--expect-sha256 requires exactly one file path".into()).into());
+    }
+
     let config = if let Some(config_path) = &cli.config {
         Some(ToggleConfig::load(config_path)?)
     } else {
@@ -257,10 +434,21 @@ fn run(cli: &Cli) -> Result<()> {
             .and_then(|c| c.global.as_ref())
             .and_then(|g| g.default_mode.as_deref())
             .unwrap_or("auto")
-            .to_string()
     } else {
-        cli.mode.clone()
+        cli.mode.as_str()
     };
+    let mode = session::Mode::parse(effective_mode).ok_or_else(|| {
+        UsageError(format!(
+            "Invalid --mode value '{}': must be auto, single, or multi",
+            effective_mode
+        ))
+    })?;
+    let eol = session::Eol::parse(&cli.eol).ok_or_else(|| {
+        UsageError(format!(
+            "Invalid --eol value '{}': must be preserve, lf, or crlf",
+            cli.eol
+        ))
+    })?;
 
     let effective_force = if let Some(ref val) = cli.force {
         match val.as_str() {
@@ -283,26 +471,147 @@ fn run(cli: &Cli) -> Result<()> {
             .map(String::from)
     };
 
+    // A saved plan is replayed with the --force it was made with.
+    let saved_plan = cli
+        .apply_plan
+        .as_deref()
+        .map(planfile::PlanFile::load)
+        .transpose()?;
+    let effective_force = match &saved_plan {
+        Some(saved) => saved.force.map(|f| f.as_str().to_string()),
+        None => effective_force,
+    };
+
     // Validate --encoding value (before --scan so it applies to all modes)
-    if !io::is_valid_encoding(&cli.encoding) {
-        return Err(UsageError(format!("Unsupported encoding: '{}'", cli.encoding)).into());
+    if let Some(encoding) = cli.encoding.as_deref() {
+        if !io::is_valid_encoding(encoding) {
+            return Err(UsageError(format!("Unsupported encoding: '{}'", encoding)).into());
+        }
+    }
+
+    // --root wins over the config's `root`, which is relative to the config file.
+    let root_dir = cli.root.clone().or_else(|| {
+        let dir = config.as_ref()?.global.as_ref()?.root.as_ref()?;
+        let base = cli.config.as_deref().and_then(Path::parent);
+        Some(base.map_or_else(|| dir.clone(), |b| b.join(dir)))
+    });
+    let root = match &root_dir {
+        Some(dir) => Some(
+            confine::Root::new(dir)
+                .map_err(|e| UsageError(format!("invalid root '{}': {}", dir.display(), e)))?,
+        ),
+        None => None,
+    };
+
+    // --backup-mode, --backup-dir and --backup-keep each imply --backup.
+    let backup_opts = (cli.backup.is_some()
+        || cli.backup_mode.is_some()
+        || cli.backup_dir.is_some()
+        || cli.backup_keep.is_some())
+    .then(|| backup::BackupOptions {
+        mode: match cli.backup_mode {
+            None | Some(BackupMode::Simple) => backup::BackupMode::Simple,
+            Some(BackupMode::Numbered) => backup::BackupMode::Numbered,
+            Some(BackupMode::Existing) => backup::BackupMode::Existing,
+            Some(BackupMode::Timestamp) => backup::BackupMode::Timestamp,
+        },
+        suffix: cli
+            .backup
+            .clone()
+            .unwrap_or_else(|| backup::DEFAULT_SUFFIX.to_string()),
+        dir: cli.backup_dir.clone(),
+        base: cwd.clone(),
+        keep: cli.backup_keep,
+    });
+
+    let backup_strategy = match cli.backup_strategy {
+        Some(BackupStrategy::Hardlink) => io::BackupStrategy::Hardlink,
+        Some(BackupStrategy::Copy) => io::BackupStrategy::Copy,
+        Some(BackupStrategy::ReflinkIfPossible) => io::BackupStrategy::ReflinkIfPossible,
+        Some(BackupStrategy::Auto) => io::BackupStrategy::Auto,
+        None => config
+            .as_ref()
+            .and_then(|c| c.backup_strategy())
+            .unwrap_or_default(),
+    };
+
+    // The UI commits everything it stages as one atomic batch, and so do
+    // renames and variant edits (unless they are only a dry run). A --plan
+    // run stages nothing, but plans the same way.
+    let batch = cli.rename.is_some() || cli.add_variant.is_some() || cli.drop_variant;
+    let atomic = cli.atomic || cli.ui || cli.plan.is_some() || (batch && !cli.dry_run);
+    let mut toggler = Toggler::new()
+        .with_mode(mode)
+        .with_force(match effective_force.as_deref() {
+            Some("on") => Some(session::Force::On),
+            Some("off") => Some(session::Force::Off),
+            _ => None,
+        })
+        .with_eol(eol)
+        .with_no_dereference(cli.no_dereference)
+        .with_preserve_mtime(cli.preserve_mtime)
+        .with_preflight(preflight::PreflightOptions {
+            allow_binary: cli.allow_binary,
+            allow_generated: cli.allow_generated,
+            allow_conflicts: cli.allow_conflicts,
+            max_file_size: match cli.max_file_size {
+                Some(limit) => (limit > 0).then_some(limit),
+                None => config
+                    .as_ref()
+                    .and_then(|c| c.max_file_size())
+                    .unwrap_or(Some(preflight::DEFAULT_MAX_FILE_SIZE)),
+            },
+        })
+        // --atomic implies journal backups unless --no-backup
+        .with_atomic(atomic, !cli.no_backup)
+        .with_backup_strategy(backup_strategy)
+        .with_lock_timeout(cli.lock_timeout.unwrap_or_default())
+        // Dry runs never write, and filter mode writes stdout only, so
+        // neither is recorded in .toggle/history.jsonl.
+        .with_history(!cli.no_history && !cli.dry_run && !filter_mode);
+    // An explicit --encoding, even utf-8, wins over the config.
+    if let Some(encoding) = &cli.encoding {
+        toggler = toggler.with_encoding(encoding);
+    }
+    if let Some(single) = cli.comment_style.first() {
+        let multi = (cli.comment_style.len() == 3).then(|| &cli.comment_style[1..]);
+        toggler = toggler.with_comment_style(core::CommentStyle {
+            single_line: single.clone(),
+            multi_line_start: multi.map(|m| m[0].clone()),
+            multi_line_end: multi.map(|m| m[1].clone()),
+        });
+    }
+    if let Some(suffix) = &cli.temp_suffix {
+        toggler = toggler.with_temp_suffix(suffix);
+    }
+    if let Some(hash) = &cli.expect_sha256 {
+        toggler = toggler.with_expect_sha256(hash);
+    }
+    if let Some(backup_opts) = backup_opts {
+        toggler = toggler.with_backup(backup_opts);
+    }
+    if let Some(root) = root {
+        toggler = toggler.with_root(root);
+    }
+    let interrupted = atomic.then(interrupt_flag);
+    if let Some(flag) = &interrupted {
+        toggler = toggler.with_interrupt(Arc::clone(flag));
+    }
+    if let Some(config) = config {
+        toggler = toggler.with_config(config);
     }
 
+    let written = RefCell::new(Vec::new());
     let opts = ToggleOptions {
-        force: &effective_force,
-        mode: &effective_mode,
-        temp_suffix: cli.temp_suffix.as_deref(),
+        toggler,
         dry_run: cli.dry_run,
-        backup: cli.backup.as_deref(),
-        config: config.as_ref(),
         verbose: cli.verbose && !cli.json, // suppress verbose in JSON mode
-        eol: &cli.eol,
-        no_dereference: cli.no_dereference,
-        encoding: &cli.encoding,
         json: cli.json,
-        to_end: cli.to_end,
-        comment_style_override: &cli.comment_style,
         interactive: cli.interactive,
+        history: Some(&written),
+        interrupted,
+        plan: cli.plan.as_deref(),
+        plan_diff: cli.plan_diff,
     };
 
     // ── Filter mode (stdin → stdout) ──
@@ -313,6 +622,20 @@ fn run(cli: &Cli) -> Result<()> {
         return run_filter(cli, &opts);
     }
 
+    let result = match &saved_plan {
+        Some(saved) => run_apply_plan(saved, &opts),
+        None => run_operation(cli, &opts),
+    };
+    record_history(written.into_inner());
+    result
+}
+
+/// Dispatch to the selected operation mode once options are resolved.
+fn run_operation(cli: &Cli, opts: &ToggleOptions) -> Result<()> {
+    if cli.ui {
+        return ui::run(cli, opts);
+    }
+
     // Handle --scan mode early (read-only, no toggle options needed).
     // Per PRD §0.14.2, --scan -S <id> is the detailed group view, so --section is allowed here.
     if cli.scan {
@@ -323,7 +646,7 @@ fn run(cli: &Cli) -> Result<()> {
         if cli.force.is_some() {
             return Err(UsageError("--scan cannot be combined with --force".into()).into());
         }
-        return run_scan(cli);
+        return run_scan(cli, opts);
     }
 
     // --check requires --scan: enforced declaratively in cli.rs via clap `requires`.
@@ -346,7 +669,7 @@ fn run(cli: &Cli) -> Result<()> {
         if cli.sections.is_empty() {
             return Err(UsageError("--pair requires at least one -S <group>".into()).into());
         }
-        validate_pair_groups(cli)?;
+        validate_pair_groups(cli, opts)?;
     }
 
     // Validate --list-sections conflicts
@@ -366,21 +689,21 @@ fn run(cli: &Cli) -> Result<()> {
         if cli.sections.len() != 1 {
             return Err(UsageError("--remove requires exactly one -S <ID>".into()).into());
         }
-        if cli.atomic {
-            return Err(UsageError("--remove cannot be combined with --atomic".into()).into());
-        }
     }
 
-    // Validate --eol value
-    match cli.eol.as_str() {
-        "preserve" | "lf" | "crlf" => {}
-        other => {
-            return Err(UsageError(format!(
-                "Invalid --eol value '{}': must be preserve, lf, or crlf",
-                other
-            ))
-            .into());
-        }
+    if cli.rename.is_some() && cli.sections.len() != 1 {
+        return Err(UsageError("--rename requires exactly one -S <OLD_ID>".into()).into());
+    }
+    if cli.add_variant.is_some() && cli.sections.len() != 1 {
+        return Err(UsageError("--add-variant requires exactly one -S <FROM_ID>".into()).into());
+    }
+    if cli.drop_variant && cli.sections.len() != 1 {
+        return Err(
+            UsageError("--drop-variant requires exactly one -S <GROUP:VARIANT>".into()).into(),
+        );
+    }
+    if cli.desc.is_some() && !cli.insert && cli.add_variant.is_none() {
+        return Err(UsageError("--desc requires --insert or --add-variant".into()).into());
     }
 
     // ── --insert mode validation (P05) ──
@@ -393,38 +716,42 @@ fn run(cli: &Cli) -> Result<()> {
             )
             .into());
         }
-        if cli.atomic {
-            return Err(UsageError("--insert cannot be combined with --atomic".into()).into());
-        }
-        if cli.recursive {
-            return Err(
-                UsageError("--insert operates on a single file; -R is not allowed".into()).into(),
-            );
-        }
-        if cli.paths.len() != 1 {
-            return Err(UsageError("--insert requires exactly one file path".into()).into());
+        // A pattern is resolved per file, so only a -l range is single-file.
+        if insert_pattern(cli).is_none() {
+            if cli.recursive {
+                return Err(UsageError(
+                    "--insert operates on a single file; -R is not allowed".into(),
+                )
+                .into());
+            }
+            if cli.paths.len() != 1 {
+                return Err(UsageError("--insert requires exactly one file path".into()).into());
+            }
         }
         if cli.sections.len() != 1 {
             return Err(UsageError("--insert requires exactly one -S <ID>".into()).into());
         }
-        if cli.lines.len() != 1 {
-            return Err(UsageError("--insert requires exactly one -l <range>".into()).into());
-        }
+        check_insert_selector(cli)?;
     }
-    // --desc requires --insert: enforced declaratively in cli.rs via clap `requires`.
 
     if cli.insert {
-        run_insert(cli, &opts)
+        run_insert(cli, opts)
     } else if cli.remove {
-        run_remove(cli, &opts)
+        run_remove(cli, opts)
+    } else if let Some(to) = &cli.rename {
+        run_rename(cli, to, opts)
+    } else if let Some(to) = &cli.add_variant {
+        run_add_variant(cli, to, opts)
+    } else if cli.drop_variant {
+        run_drop_variant(cli, opts)
     } else if cli.list_sections {
-        run_list_sections(cli, &opts)
-    } else if cli.atomic {
-        run_atomic(cli, &opts)
+        run_list_sections(cli, opts)
+    } else if batched(cli) {
+        run_atomic(cli, opts)
     } else if cli.json {
-        run_json(cli, &opts)
+        run_json(cli, opts)
     } else {
-        run_normal(cli, &opts)
+        run_normal(cli, opts)
     }
 }
 
@@ -435,7 +762,13 @@ fn run(cli: &Cli) -> Result<()> {
 /// other languages with `--comment-style`.
 fn run_filter(cli: &Cli, opts: &ToggleOptions) -> Result<()> {
     // Filter mode is for the writer operations only.
-    if cli.scan || cli.list_sections {
+    if cli.scan
+        || cli.list_sections
+        || cli.ui
+        || cli.rename.is_some()
+        || cli.add_variant.is_some()
+        || cli.drop_variant
+    {
         return Err(UsageError(
             "stdin/stdout filter mode is only supported for toggle, insert, and remove".into(),
         )
@@ -453,7 +786,8 @@ fn run_filter(cli: &Cli, opts: &ToggleOptions) -> Result<()> {
     };
     reject(cli.json, "--json")?;
     reject(cli.atomic, "--atomic")?;
-    reject(cli.backup.is_some(), "--backup")?;
+    reject(cli.plan.is_some(), "--plan")?;
+    reject(opts.toggler.backs_up(), "--backup")?;
     reject(cli.interactive, "--interactive")?;
     reject(cli.recursive, "--recursive")?;
     if cli.dry_run {
@@ -489,7 +823,7 @@ fn run_filter(cli: &Cli, opts: &ToggleOptions) -> Result<()> {
         .into());
     }
 
-    let (input, vpath) = if let Some(path) = real_paths.first() {
+    let (input, encoding, vpath) = if let Some(path) = real_paths.first() {
         if path.is_dir() {
             return Err(UsageError(format!(
                 "filter mode operates on a single file; '{}' is a directory",
@@ -497,13 +831,14 @@ fn run_filter(cli: &Cli, opts: &ToggleOptions) -> Result<()> {
             ))
             .into());
         }
-        let content = io::read_file_encoded(path, opts.encoding)
-            .with_context(|| format!("Failed to read {}", path.display()))?;
-        (content, (*path).clone())
+        let source = opts.toggler.read(path)?;
+        (source.content, source.encoding, (*path).clone())
     } else {
-        let content =
-            io::read_stdin_encoded(opts.encoding).context("Failed to read input from stdin")?;
-        (content, PathBuf::from("<stdin>.py"))
+        let (content, encoding) = opts
+            .toggler
+            .read_stdin()
+            .context("Failed to read input from stdin")?;
+        (content, encoding, PathBuf::from("<stdin>.py"))
     };
 
     let output = if cli.insert {
@@ -516,35 +851,15 @@ fn run_filter(cli: &Cli, opts: &ToggleOptions) -> Result<()> {
         if cli.sections.len() != 1 {
             return Err(UsageError("--insert requires exactly one -S <ID>".into()).into());
         }
-        if cli.lines.len() != 1 {
-            return Err(UsageError("--insert requires exactly one -l <range>".into()).into());
-        }
-        let prefix = resolve_comment_style(&vpath, opts)?.single_line;
-        let (start, mut end) = core::parse_line_range(&cli.lines[0])?;
-        if opts.to_end {
-            end = input.lines().count();
-        }
-        let modified = core::insert_section(
-            &input,
-            &cli.sections[0],
-            cli.desc.as_deref(),
-            start,
-            end,
-            &prefix,
-        )?;
-        io::normalize_eol(&modified, opts.eol)
+        check_insert_selector(cli)?;
+        let insert = insert_op(cli)?;
+        opts.toggler.transform(&vpath, &input, &[insert])?.0
     } else if cli.remove {
         if cli.sections.len() != 1 {
             return Err(UsageError("--remove requires exactly one -S <ID>".into()).into());
         }
-        let mode = match cli.remove_mode {
-            RemoveMode::Markers => core::RemoveMode::Markers,
-            RemoveMode::Commented => core::RemoveMode::Commented,
-            RemoveMode::All => core::RemoveMode::All,
-        };
-        let style = resolve_comment_style(&vpath, opts)?;
-        let (modified, removed) = core::remove_section(&input, &cli.sections[0], mode, &style);
-        if removed == 0 {
+        let (modified, outcomes) = opts.toggler.transform(&vpath, &input, &[remove_op(cli)])?;
+        if outcomes[0].removed == 0 {
             eprintln!("Warning: -S {} matched no sections", cli.sections[0]);
             if cli.require_match {
                 return Err(UsageError(format!(
@@ -554,19 +869,20 @@ fn run_filter(cli: &Cli, opts: &ToggleOptions) -> Result<()> {
                 .into());
             }
         }
-        io::normalize_eol(&modified, opts.eol)
+        modified
     } else {
         // Default operation: toggle line ranges and/or sections.
-        compute_file_changes(&vpath, cli, opts, &input)?
+        check_strict_ext(&vpath, cli)?;
+        opts.toggler.transform(&vpath, &input, &toggle_ops(cli)?)?.0
     };
 
-    io::write_stdout_encoded(&output, opts.encoding).context("Failed to write to stdout")?;
+    io::write_stdout_as(&output, &encoding).context("Failed to write to stdout")?;
     Ok(())
 }
 
 /// Per PRD §0.13.4: error if any targeted group does not contain exactly 2 variants
 /// in any input file. Runs before file mutation; failure leaves all files untouched.
-fn validate_pair_groups(cli: &Cli) -> Result<()> {
+fn validate_pair_groups(cli: &Cli, opts: &ToggleOptions) -> Result<()> {
     let walk_opts = walk::WalkOptions {
         verbose: cli.verbose,
         ..walk::WalkOptions::default()
@@ -576,8 +892,8 @@ fn validate_pair_groups(cli: &Cli) -> Result<()> {
     for section in &cli.sections {
         let (group, _variant) = core::parse_id_parts(section);
         for file in &files {
-            let content = match io::read_file_encoded(file, &cli.encoding) {
-                Ok(c) => c,
+            let content = match opts.toggler.read(file) {
+                Ok(s) => s.content,
                 Err(_) => continue,
             };
             let count = core::discover_variants(&content, &group).len();
@@ -593,107 +909,248 @@ fn validate_pair_groups(cli: &Cli) -> Result<()> {
     Ok(())
 }
 
-/// Check if a file has any sections matching the requested IDs.
-/// Returns true if at least one section matches, or if no section filter is active.
-fn file_has_matching_sections(path: &Path, section_ids: &[String], encoding: &str) -> bool {
-    if section_ids.is_empty() {
-        return true;
+/// The toggle operations the flags ask for: the line ranges first, then each
+/// `-S` section in order.
+fn toggle_ops(cli: &Cli) -> Result<Vec<Operation>> {
+    let mut ops = Vec::new();
+    if !cli.lines.is_empty() || !cli.block_at.is_empty() {
+        // Parsed up front so a bad spec fails before any file is read.
+        let blocks = cli.block_at.iter().map(|line| format!("{line}:block"));
+        let specs = cli
+            .lines
+            .iter()
+            .cloned()
+            .chain(blocks)
+            .map(|spec| LineSpec::parse(&spec))
+            .collect::<std::result::Result<_, _>>()?;
+        ops.push(Operation::ToggleLineSpecs {
+            specs,
+            to_end: cli.to_end,
+        });
     }
-    let content = match io::read_file_encoded(path, encoding) {
-        Ok(c) => c,
-        Err(_) => return false,
-    };
-    let found = core::discover_sections(&content);
-    section_ids.iter().any(|id| {
-        let (group, variant) = core::parse_id_parts(id);
-        found.iter().any(|s| match &variant {
-            Some(v) => s.id == format!("{group}:{v}"),
-            None => {
-                // Solo or group: match exact id or any variant whose group equals our id.
-                s.id == *id || core::parse_id_parts(&s.id).0 == group
-            }
-        })
+    ops.extend(cli.sections.iter().map(|id| Operation::section(id)));
+    Ok(ops)
+}
+
+/// The lines `--insert` selects by `--match` or `--from`/`--to`, if any.
+fn insert_pattern(cli: &Cli) -> Option<core::LinePattern> {
+    if let Some(re) = &cli.match_pattern {
+        Some(core::LinePattern::Match(re.clone()))
+    } else {
+        cli.from_pattern
+            .as_ref()
+            .map(|from| core::LinePattern::Between {
+                from: from.clone(),
+                to: cli.to_pattern.clone(),
+            })
+    }
+}
+
+/// `--insert` takes exactly one -l range, or one pattern.
+fn check_insert_selector(cli: &Cli) -> Result<()> {
+    if cli.match_pattern.is_some() && cli.to_end {
+        return Err(UsageError("--to-end cannot be combined with --match".into()).into());
+    }
+    if cli.from_pattern.is_some() && cli.to_pattern.is_none() && !cli.to_end {
+        return Err(UsageError("--from requires --to </REGEX/> or --to-end".into()).into());
+    }
+    if cli.to_pattern.is_some() && cli.to_end {
+        return Err(UsageError("--to cannot be combined with --to-end".into()).into());
+    }
+    if insert_pattern(cli).is_none() && cli.lines.len() != 1 {
+        return Err(UsageError(
+            "--insert requires exactly one -l <range>, --match or --from".into(),
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// The `--insert` operation (`-S ID -l RANGE [--desc ..] [--to-end]`, or a
+/// pattern in place of the range).
+fn insert_op(cli: &Cli) -> Result<Operation> {
+    if let Some(pattern) = insert_pattern(cli) {
+        return Ok(Operation::InsertMatching {
+            id: cli.sections[0].clone(),
+            desc: cli.desc.clone(),
+            pattern,
+        });
+    }
+    let (start, end) = core::parse_line_range(&cli.lines[0])?;
+    Ok(Operation::Insert {
+        id: cli.sections[0].clone(),
+        desc: cli.desc.clone(),
+        range: core::LineRange::new(start, end),
+        to_end: cli.to_end,
     })
 }
 
-/// Collect files from CLI paths and apply recursive-mode filters
-/// (section matching + extension support).
-fn collect_and_filter_files(cli: &Cli, opts: &ToggleOptions) -> Result<Vec<PathBuf>> {
-    let walk_opts = walk::WalkOptions {
-        verbose: opts.verbose,
-        skip_unsupported_extensions: false,
-        ..walk::WalkOptions::default()
+/// The `--remove` operation (`-S ID [--remove-mode ..]`).
+fn remove_op(cli: &Cli) -> Operation {
+    Operation::Remove {
+        id: cli.sections[0].clone(),
+        mode: match cli.remove_mode {
+            RemoveMode::Markers => core::RemoveMode::Markers,
+            RemoveMode::Commented => core::RemoveMode::Commented,
+            RemoveMode::All => core::RemoveMode::All,
+        },
+    }
+}
+
+/// `--strict-ext`: reject non-.py files.
+fn check_strict_ext(path: &Path, cli: &Cli) -> Result<()> {
+    if cli.strict_ext {
+        let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        if ext != "py" {
+            return Err(UsageError(format!(
+                "File '{}' is not a .py file (rejected by --strict-ext)",
+                path.display()
+            ))
+            .into());
+        }
+    }
+    Ok(())
+}
+
+/// Report preflight skips on stderr; fail if any file was named explicitly.
+fn report_skipped(skipped: &[session::Skipped]) -> Result<()> {
+    for s in skipped.iter().filter(|s| !s.explicit) {
+        eprintln!(
+            "Skipped {}: {} (use {} to process it)",
+            s.path.display(),
+            s.reason,
+            s.reason.override_flag()
+        );
+    }
+    match skipped.iter().find(|s| s.explicit) {
+        Some(s) => Err(refusal(s)),
+        None => Ok(()),
+    }
+}
+
+fn refusal(s: &session::Skipped) -> anyhow::Error {
+    anyhow::anyhow!(
+        "Refusing to modify {}: {} (use {} to override)",
+        s.path.display(),
+        s.reason,
+        s.reason.override_flag()
+    )
+}
+
+/// A file to toggle and the operations to run on it.
+type Target = (PathBuf, Vec<Operation>);
+
+/// The files to toggle, each with its operations: the `-l`/`-S` operations
+/// on every collected file or, with `--locations`, each listed file's lines.
+fn toggle_targets(cli: &Cli, opts: &ToggleOptions) -> Result<(Vec<Target>, Vec<session::Skipped>)> {
+    let Some(source) = &cli.locations else {
+        let ops = toggle_ops(cli)?;
+        let (files, skipped) =
+            opts.toggler
+                .collect(&cli.paths, cli.recursive, opts.verbose, &ops)?;
+        let targets = files.into_iter().map(|f| (f, ops.clone())).collect();
+        return Ok((targets, skipped));
     };
-    let files = walk::collect_files(&cli.paths, cli.recursive, &walk_opts)?;
 
-    Ok(files
+    let text = if source.as_os_str() == "-" {
+        let mut text = String::new();
+        std::io::Read::read_to_string(&mut std::io::stdin(), &mut text)
+            .context("Failed to read locations from stdin")?;
+        text
+    } else {
+        std::fs::read_to_string(source)
+            .with_context(|| format!("Failed to read locations from {}", source.display()))?
+    };
+    let locations = select::Locations::parse(&text);
+    if opts.verbose {
+        for line in &locations.unparsed {
+            eprintln!("Ignoring line {line} of the locations: not a path:line record");
+        }
+    }
+    if locations.files.is_empty() {
+        return Err(UsageError(format!(
+            "no path:line records found in {}",
+            source.display()
+        ))
+        .into());
+    }
+
+    let named: Vec<PathBuf> = locations.files.iter().map(|(p, _)| p.clone()).collect();
+    let (kept, skipped) = opts.toggler.preflight(named.clone(), &named);
+    let targets = locations
+        .files
         .into_iter()
-        .filter(|path| {
-            // In recursive mode with sections, skip files without matching sections
-            if cli.recursive
-                && !cli.sections.is_empty()
-                && !file_has_matching_sections(path, &cli.sections, opts.encoding)
-            {
-                return false;
-            }
-            // In recursive mode, silently skip files with unsupported extensions
-            if cli.recursive
-                && opts.comment_style_override.is_empty()
-                && core::get_comment_style(path, opts.mode, opts.config).is_err()
-            {
-                return false;
-            }
-            true
+        .filter(|(path, _)| kept.contains(path))
+        .map(|(path, ranges)| {
+            let ops = vec![Operation::ToggleLines {
+                ranges,
+                to_end: false,
+            }];
+            (path, ops)
         })
-        .collect())
+        .collect();
+    Ok((targets, skipped))
 }
 
 fn run_normal(cli: &Cli, opts: &ToggleOptions) -> Result<()> {
-    let files = collect_and_filter_files(cli, opts)?;
-    for path in &files {
-        process_file(path, cli, opts)
+    let (targets, skipped) = toggle_targets(cli, opts)?;
+    report_skipped(&skipped)?;
+    for (path, ops) in &targets {
+        process_file(path, cli, ops, opts)
             .with_context(|| format!("Failed to process {}", path.display()))?;
     }
     Ok(())
 }
 
-/// Atomic multi-file mode: compute all changes, stage them, then commit atomically.
-fn run_atomic(cli: &Cli, opts: &ToggleOptions) -> Result<()> {
-    let files = collect_and_filter_files(cli, opts)?;
-
-    if files.is_empty() {
-        return Ok(());
-    }
-
-    // Register signal handlers for graceful interrupt
+/// Register SIGTERM/SIGINT handlers that set the returned flag, so an atomic
+/// batch can stop between renames and leave a recoverable journal.
+fn interrupt_flag() -> Arc<AtomicBool> {
     let interrupted = Arc::new(AtomicBool::new(false));
     let _ = signal_hook::flag::register(signal_hook::consts::SIGTERM, Arc::clone(&interrupted));
     let _ = signal_hook::flag::register(signal_hook::consts::SIGINT, Arc::clone(&interrupted));
+    interrupted
+}
 
-    // Determine backup behavior: --atomic implies --backup unless --no-backup
-    let backup_enabled = !cli.no_backup;
-
-    // Compute all changes first, collecting (path, original, modified) tuples
-    let mut changes: Vec<(PathBuf, String, String)> = Vec::new();
-
-    for path in &files {
-        if interrupted.load(Ordering::Relaxed) {
+/// Atomic multi-file mode: compute all changes, stage them, then commit atomically.
+fn run_atomic(cli: &Cli, opts: &ToggleOptions) -> Result<()> {
+    let (targets, skipped) = toggle_targets(cli, opts)?;
+    // All or nothing: a refused file aborts the batch before anything is staged.
+    report_skipped(&skipped)?;
+
+    // Compute all changes first, without writing anything
+    let mut planned = Vec::new();
+    for (path, ops) in &targets {
+        if opts
+            .interrupted
+            .as_ref()
+            .is_some_and(|flag| flag.load(Ordering::Relaxed))
+        {
             anyhow::bail!("Interrupted before staging. No files were modified.");
         }
 
-        // Process each file to compute the modification (without writing)
-        let original = io::read_file_encoded(path, opts.encoding)
-            .with_context(|| format!("Failed to read {}", path.display()))?;
-
-        let modified = compute_file_changes(path, cli, opts, &original)
+        let source = opts.toggler.read(path)?;
+        let file = check_strict_ext(path, cli)
+            .and_then(|()| opts.toggler.plan_source(path, source, ops))
             .with_context(|| format!("Failed to compute changes for {}", path.display()))?;
-
-        if original != modified {
-            changes.push((path.clone(), original, modified));
-        }
+        planned.push(file);
     }
 
-    if changes.is_empty() {
+    commit_atomic(planned, opts)
+}
+
+/// Commit every changed file as one unit (the toggler is in atomic mode).
+/// Shared by all writing operations run with `--atomic`; an interrupted
+/// commit leaves a journal that `--recover` can roll back or complete.
+fn commit_atomic(files: Vec<session::FilePlan>, opts: &ToggleOptions) -> Result<()> {
+    if let Some(out) = opts.plan {
+        return save_plan(&files, out, opts);
+    }
+    let plan = session::Plan {
+        files,
+        skipped: Vec::new(),
+    };
+    let changed: Vec<&session::FilePlan> = plan.changed().collect();
+    if changed.is_empty() {
         if opts.verbose {
             eprintln!("No changes to apply.");
         }
@@ -701,47 +1158,31 @@ fn run_atomic(cli: &Cli, opts: &ToggleOptions) -> Result<()> {
     }
 
     if opts.verbose {
-        eprintln!("Staging {} file(s) in atomic mode...", changes.len());
+        eprintln!("Staging {} file(s) in atomic mode...", changed.len());
     }
-
-    // Create atomic batch and stage all writes
-    let target_paths: Vec<PathBuf> = changes.iter().map(|(p, _, _)| p.clone()).collect();
-    let mut batch = io::AtomicBatch::new(&target_paths, backup_enabled, Arc::clone(&interrupted))
-        .map_err(|e| anyhow::anyhow!("Failed to initialize atomic batch: {}", e))?;
-
-    for (path, _original, modified) in &changes {
-        let encoded = io::encode_for_atomic(modified, opts.encoding)
-            .with_context(|| format!("Failed to encode content for {}", path.display()))?;
-
-        batch
-            .stage(path, &encoded, opts.encoding)
-            .map_err(|e| anyhow::anyhow!("Failed to stage '{}': {}", path.display(), e))?;
+    for file in &changed {
+        warn_if_hardlinked(&file.path, opts);
     }
-
+    let staged = opts.toggler.stage(&plan)?;
     if opts.verbose {
         eprintln!("All files staged. Committing...");
     }
-
-    // Execute the two-phase commit
-    batch
-        .commit()
-        .map_err(|e| anyhow::anyhow!("Atomic commit failed: {}", e))?;
+    record_written(staged.commit()?, opts);
 
     if opts.verbose {
         eprintln!(
             "Atomic commit successful. {} file(s) modified.",
-            changes.len()
+            changed.len()
         );
     }
 
     // Print results in normal mode
     if !opts.json {
-        for (path, original, modified) in &changes {
-            let lines_changed = count_changed_lines(original, modified);
+        for file in &changed {
             eprintln!(
                 "Modified {} ({} line(s) changed)",
-                path.display(),
-                lines_changed
+                file.path.display(),
+                file.lines_changed()
             );
         }
     }
@@ -749,150 +1190,89 @@ fn run_atomic(cli: &Cli, opts: &ToggleOptions) -> Result<()> {
     Ok(())
 }
 
-/// Compute the modified content for a file without writing it.
-/// This is the "dry" version of process_file used by atomic mode.
-fn compute_file_changes(
-    path: &Path,
-    cli: &Cli,
-    opts: &ToggleOptions,
-    original: &str,
-) -> Result<String> {
-    // --strict-ext: reject non-.py files
-    if cli.strict_ext {
-        let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
-        if ext != "py" {
-            return Err(UsageError(format!(
-                "File '{}' is not a .py file (rejected by --strict-ext)",
-                path.display()
-            ))
-            .into());
-        }
-    }
-
-    let mut content = original.to_string();
-
-    if !cli.lines.is_empty() {
-        content = compute_line_range_changes(path, &cli.lines, opts, &content)?;
-    }
-
-    for section in &cli.sections {
-        content = compute_section_changes(path, section, opts, &content)?;
-    }
-
-    Ok(content)
+/// Whether every change is planned before any is written: `--atomic`
+/// commits them as one batch, `--plan` saves them instead.
+fn batched(cli: &Cli) -> bool {
+    cli.atomic || cli.plan.is_some()
 }
 
-/// Compute line-range toggle changes without writing.
-fn compute_line_range_changes(
-    path: &Path,
-    line_range_specs: &[String],
-    opts: &ToggleOptions,
-    content: &str,
-) -> Result<String> {
-    let comment_style = resolve_comment_style(path, opts)?;
-    let line_count = content.lines().count();
-
-    let mut ranges = Vec::new();
-    for spec in line_range_specs {
-        let (start_line, end_line) = core::parse_line_range(spec)?;
-        if start_line > line_count {
-            return Err(UsageError(format!(
-                "Start line {} is out of range (file has {} lines)",
-                start_line, line_count
-            ))
-            .into());
-        }
-        ranges.push(core::LineRange::new(start_line, end_line));
+/// `--plan`: save the batch for `togl apply` instead of committing it.
+fn save_plan(files: &[session::FilePlan], out: &Path, opts: &ToggleOptions) -> Result<()> {
+    let saved = planfile::PlanFile::new(files, opts.toggler.force(), opts.plan_diff)?;
+    let json = saved.to_json();
+    if out.as_os_str() == "-" {
+        println!("{json}");
+    } else {
+        std::fs::write(out, format!("{json}\n"))
+            .with_context(|| format!("Failed to write plan {}", out.display()))?;
     }
-
-    if opts.to_end {
-        if let Some(last) = ranges.last_mut() {
-            last.end = line_count;
+    if !opts.json {
+        for file in files.iter().filter(|f| f.is_changed()) {
+            eprintln!(
+                "Planned {} ({} line(s) changed)",
+                file.path.display(),
+                file.lines_changed()
+            );
         }
-    }
-
-    for range in &ranges {
-        if range.end > line_count {
-            return Err(UsageError(format!(
-                "End line {} is out of range (file has {} lines)",
-                range.end, line_count
-            ))
-            .into());
+        if out.as_os_str() != "-" {
+            eprintln!(
+                "Saved a plan for {} file(s) to {}; nothing was written",
+                saved.files.len(),
+                out.display()
+            );
         }
     }
-
-    let merged = core::merge_ranges(&ranges);
-    let force_mode = opts.force.as_deref();
-    let toggled = if opts.mode == "multi" {
-        let (ms, me) = match (
-            &comment_style.multi_line_start,
-            &comment_style.multi_line_end,
-        ) {
-            (Some(s), Some(e)) => (s.as_str(), e.as_str()),
-            _ => {
-                return Err(UsageError(format!(
-                    "Multi-line comments not supported for {}",
-                    path.display()
-                ))
-                .into());
-            }
-        };
-        core::toggle_comments_multi(content, &merged, force_mode, ms, me)
-    } else {
-        core::toggle_comments_with_marker(content, &merged, force_mode, &comment_style.single_line)
-    };
-
-    Ok(io::normalize_eol(&toggled, opts.eol))
+    Ok(())
 }
 
-/// Compute section toggle changes without writing.
-/// Routes between solo, pair-flip, activate, force-all and 3+ error per PRD §0.13.3.
-fn compute_section_changes(
-    path: &Path,
-    section_id: &str,
-    opts: &ToggleOptions,
-    content: &str,
-) -> Result<String> {
-    let comment_style = resolve_comment_style(path, opts)?;
-    let (group, variant) = core::parse_id_parts(section_id);
-
-    let toggled = match variant {
-        Some(v) => core::activate_variant(content, &group, &v, &comment_style)?,
-        None => {
-            let variants = core::discover_variants(content, &group);
-            // Solo path preserves prior behavior (no error if section missing — caller handles).
-            if variants.len() <= 1 && opts.force.is_none() {
-                let mut lines: Vec<String> = content.lines().map(String::from).collect();
-                let result = core::find_and_toggle_section(
-                    &mut lines,
-                    section_id,
-                    opts.force,
-                    &comment_style,
-                )?;
-                if !result.modified {
-                    return Ok(content.to_string());
-                }
-                let mut joined = lines.join("\n");
-                if content.ends_with('\n') {
-                    joined.push('\n');
-                }
-                joined
-            } else {
-                core::toggle_variant_group(content, &group, opts.force, &comment_style)?
-            }
-        }
-    };
-
-    Ok(io::normalize_eol(&toggled, opts.eol))
+/// `togl apply`: replay a saved plan and commit it as one batch, refusing if
+/// any file no longer has the content the plan was made against.
+fn run_apply_plan(saved: &planfile::PlanFile, opts: &ToggleOptions) -> Result<()> {
+    let mut stale = saved.stale();
+    if !stale.is_empty() {
+        for changed in &stale {
+            eprintln!("Changed since the plan was made: {changed}");
+        }
+        let count = stale.len();
+        return Err(anyhow::Error::new(stale.swap_remove(0)).context(format!(
+            "{count} file(s) changed since the plan was made; nothing was written"
+        )));
+    }
+    let files = saved.replay(&opts.toggler)?;
+    finish_batch(files, opts)
 }
 
 fn run_json(cli: &Cli, opts: &ToggleOptions) -> Result<()> {
-    let files = collect_and_filter_files(cli, opts)?;
+    let (targets, skipped) = toggle_targets(cli, opts)?;
     let mut results: Vec<ToggleResult> = Vec::new();
     let mut had_error = false;
+    // The first --root violation, so the run exits with its dedicated code.
+    let mut violation = None;
+
+    for s in &skipped {
+        had_error |= s.explicit;
+        results.push(ToggleResult {
+            file: s.path.display().to_string(),
+            action: String::new(),
+            lines_changed: 0,
+            success: false,
+            error: Some(if s.explicit {
+                format!("{:#}", refusal(s))
+            } else {
+                s.reason.to_string()
+            }),
+            dry_run: opts.dry_run,
+            section_id: None,
+            desc: None,
+            encoding: None,
+            bom: None,
+            skipped: Some(s.reason.code()),
+            outside_root: None,
+        });
+    }
 
-    for path in &files {
-        match process_file(path, cli, opts) {
+    for (path, ops) in &targets {
+        match process_file(path, cli, ops, opts) {
             Ok(proc_results) => {
                 for pr in proc_results {
                     results.push(ToggleResult {
@@ -904,6 +1284,10 @@ fn run_json(cli: &Cli, opts: &ToggleOptions) -> Result<()> {
                         dry_run: opts.dry_run,
                         section_id: pr.section_id,
                         desc: pr.desc,
+                        encoding: pr.detected_encoding.map(|e| e.name.to_string()),
+                        bom: pr.detected_encoding.map(|e| e.bom),
+                        skipped: None,
+                        outside_root: None,
                     });
                 }
             }
@@ -918,7 +1302,14 @@ fn run_json(cli: &Cli, opts: &ToggleOptions) -> Result<()> {
                     dry_run: opts.dry_run,
                     section_id: None,
                     desc: None,
+                    encoding: None,
+                    bom: None,
+                    skipped: None,
+                    outside_root: outside_root(&e).map(|v| v.kind.code()),
                 });
+                if violation.is_none() && outside_root(&e).is_some() {
+                    violation = Some(e);
+                }
             }
         }
     }
@@ -928,6 +1319,9 @@ fn run_json(cli: &Cli, opts: &ToggleOptions) -> Result<()> {
         serde_json::to_string(&results).expect("Failed to serialize JSON")
     );
 
+    if let Some(e) = violation {
+        return Err(e);
+    }
     if had_error {
         // Return a generic error so main() sets a non-zero exit code
         anyhow::bail!("One or more files failed to process");
@@ -938,54 +1332,112 @@ fn run_json(cli: &Cli, opts: &ToggleOptions) -> Result<()> {
 type SectionAggregation = (Option<String>, Vec<(String, usize, usize)>);
 
 fn run_insert(cli: &Cli, opts: &ToggleOptions) -> Result<()> {
+    if let Some(pattern) = insert_pattern(cli) {
+        return run_insert_matching(cli, &pattern, opts);
+    }
     let path = &cli.paths[0];
-    let comment_prefix = resolve_comment_style(path, opts)?.single_line;
-    let content = io::read_file_encoded(path, opts.encoding)?;
-
-    let (start, mut end) = core::parse_line_range(&cli.lines[0])?;
-    if opts.to_end {
-        end = content.lines().count();
+    let (_, skipped) = opts.toggler.preflight(vec![path.clone()], &cli.paths);
+    report_skipped(&skipped)?;
+    let insert = insert_op(cli)?;
+    let file = opts
+        .toggler
+        .plan_file(path, std::slice::from_ref(&insert))?;
+
+    if batched(cli) {
+        commit_atomic(vec![file.clone()], opts)?;
+    } else {
+        apply_changes(&file, opts)?;
     }
 
-    let id = &cli.sections[0];
-    let modified = core::insert_section(
-        &content,
-        id,
-        cli.desc.as_deref(),
-        start,
-        end,
-        &comment_prefix,
-    )?;
-    let modified = io::normalize_eol(&modified, opts.eol);
-
-    apply_changes(path, &content, &modified, opts)?;
-
     if opts.verbose {
-        eprintln!(
-            "Inserted section '{}' into {} (lines {}-{})",
-            id,
-            path.display(),
-            start,
-            end
-        );
+        if let Operation::Insert {
+            id, range, to_end, ..
+        } = &insert
+        {
+            let end = if *to_end {
+                file.original.lines().count()
+            } else {
+                range.end
+            };
+            eprintln!(
+                "Inserted section '{}' into {} (lines {}-{})",
+                id,
+                path.display(),
+                range.start,
+                end
+            );
+        }
     }
     Ok(())
 }
 
-fn run_remove(cli: &Cli, opts: &ToggleOptions) -> Result<()> {
+/// Insert a section around every block `pattern` selects in every file it
+/// matches. Every file is planned first, so an invalid pattern or ID aborts
+/// before anything is written; files that already have the ID are left as-is.
+fn run_insert_matching(cli: &Cli, pattern: &core::LinePattern, opts: &ToggleOptions) -> Result<()> {
     let id = &cli.sections[0];
-    let mode = match cli.remove_mode {
-        RemoveMode::Markers => core::RemoveMode::Markers,
-        RemoveMode::Commented => core::RemoveMode::Commented,
-        RemoveMode::All => core::RemoveMode::All,
-    };
-
-    let walk_opts = walk::WalkOptions {
-        verbose: opts.verbose,
-        skip_unsupported_extensions: false,
-        ..walk::WalkOptions::default()
-    };
-    let files = walk::collect_files(&cli.paths, cli.recursive, &walk_opts)?;
+    let ops = [insert_op(cli)?];
+    let plans = plan_batch(cli, &ops, &format!("Failed to insert '{id}'"), opts)?;
+    let matched = plans.iter().any(|file| {
+        core::find_pattern_ranges(&file.original, pattern).is_ok_and(|r| !r.is_empty())
+    });
+    if !matched {
+        let spec = match pattern {
+            core::LinePattern::Match(re) => re.clone(),
+            core::LinePattern::Between { from, .. } => from.clone(),
+        };
+        return Err(ToglError::InvalidPattern {
+            pattern: spec,
+            reason: "no line matches it".into(),
+        }
+        .into());
+    }
+    let (pending, present): (Vec<_>, Vec<_>) = plans
+        .into_iter()
+        .partition(|file| file.outcomes[0].added > 0);
+
+    if opts.verbose {
+        let already = present.iter().filter(|file| {
+            core::find_pattern_ranges(&file.original, pattern).is_ok_and(|r| !r.is_empty())
+        });
+        for file in already {
+            eprintln!(
+                "Section '{}' is already in {}, leaving it as-is",
+                id,
+                file.path.display()
+            );
+        }
+        for file in &pending {
+            let ranges = core::find_pattern_ranges(&file.original, pattern).unwrap_or_default();
+            let lines: Vec<String> = ranges
+                .iter()
+                .map(|r| format!("{}-{}", r.start, r.end))
+                .collect();
+            eprintln!(
+                "Inserted section '{}' into {} (lines {})",
+                id,
+                file.path.display(),
+                lines.join(", ")
+            );
+        }
+    }
+    if batched(cli) {
+        commit_atomic(pending, opts)
+    } else {
+        for file in &pending {
+            apply_changes(file, opts)?;
+        }
+        Ok(())
+    }
+}
+
+fn run_remove(cli: &Cli, opts: &ToggleOptions) -> Result<()> {
+    let id = &cli.sections[0];
+    let ops = [remove_op(cli)];
+    let (files, skipped) = opts
+        .toggler
+        .collect(&cli.paths, cli.recursive, opts.verbose, &ops)?;
+    report_skipped(&skipped)?;
 
     // Refuse an ambiguous bare group: `-S db` where db:sqlite / db:postgres exist
     // and no exact `db` section. Mirrors the toggling group-ambiguity behavior.
@@ -993,8 +1445,8 @@ fn run_remove(cli: &Cli, opts: &ToggleOptions) -> Result<()> {
         let mut has_exact = false;
         let mut variants = std::collections::BTreeSet::new();
         for path in &files {
-            if let Ok(content) = io::read_file_encoded(path, opts.encoding) {
-                for s in core::discover_variants(&content, id) {
+            if let Ok(source) = opts.toggler.read(path) {
+                for s in core::discover_variants(&source.content, id) {
                     if &s.id == id {
                         has_exact = true;
                     } else {
@@ -1017,28 +1469,38 @@ fn run_remove(cli: &Cli, opts: &ToggleOptions) -> Result<()> {
     }
 
     let mut total_removed = 0usize;
+    let mut pending = Vec::new();
     for path in &files {
-        let content = match io::read_file_encoded(path, opts.encoding) {
-            Ok(c) => c,
+        let source = match opts.toggler.read(path) {
+            Ok(s) => s,
+            Err(e) if content_changed(&e) => return Err(e),
             Err(_) => continue,
         };
-        let style = resolve_comment_style(path, opts)?;
-        let (modified, removed) = core::remove_section(&content, id, mode, &style);
+        let file = opts.toggler.plan_source(path, source, &ops)?;
+        let removed = file.outcomes[0].removed;
         total_removed += removed;
         if removed > 0 {
-            let modified = io::normalize_eol(&modified, opts.eol);
-            apply_changes(path, &content, &modified, opts)?;
-            if opts.verbose {
-                eprintln!(
-                    "Removed {} section(s) '{}' from {}",
-                    removed,
-                    id,
-                    path.display()
-                );
+            if batched(cli) {
+                pending.push(file);
+            } else {
+                apply_changes(&file, opts)?;
+                report_removed(removed, id, path, opts);
             }
         }
     }
 
+    if batched(cli) {
+        let removed: Vec<_> = pending
+            .iter()
+            .map(|f| (f.outcomes[0].removed, f.path.clone()))
+            .collect();
+        commit_atomic(pending, opts)?;
+        // Only once the batch is on disk.
+        for (count, path) in &removed {
+            report_removed(*count, id, path, opts);
+        }
+    }
+
     if total_removed == 0 {
         eprintln!("Warning: -S {} matched no sections", id);
         if cli.require_match {
@@ -1050,6 +1512,205 @@ fn run_remove(cli: &Cli, opts: &ToggleOptions) -> Result<()> {
     Ok(())
 }
 
+/// With `-v`, note the sections removed from `path`.
+fn report_removed(count: usize, id: &str, path: &Path, opts: &ToggleOptions) {
+    if opts.verbose {
+        eprintln!(
+            "Removed {} section(s) '{}' from {}",
+            count,
+            id,
+            path.display()
+        );
+    }
+}
+
+/// Plan `ops` on every collected file, reporting skipped ones. A file that
+/// cannot be read or decoded, or any planning error, aborts before anything
+/// is written. Unchanged files are kept so callers can look at the whole
+/// batch.
+fn plan_batch(
+    cli: &Cli,
+    ops: &[Operation],
+    failed: &str,
+    opts: &ToggleOptions,
+) -> Result<Vec<session::FilePlan>> {
+    let (files, skipped) = opts
+        .toggler
+        .collect(&cli.paths, cli.recursive, opts.verbose, ops)?;
+    report_skipped(&skipped)?;
+    // A walk passes over files it cannot read or decode. A batch must not:
+    // their sections would be left behind while the run reports success.
+    if cli.recursive {
+        let kept: std::collections::HashSet<&PathBuf> = files.iter().collect();
+        let (walked, _) = opts.toggler.collect(&cli.paths, true, false, &[])?;
+        for path in walked.iter().filter(|p| !kept.contains(p)) {
+            opts.toggler.read(path)?;
+        }
+    }
+
+    let mut plans = Vec::new();
+    for path in &files {
+        let source = opts.toggler.read(path)?;
+        let file = opts
+            .toggler
+            .plan_source(path, source, ops)
+            .with_context(|| format!("{} in {}", failed, path.display()))?;
+        plans.push(file);
+    }
+    Ok(plans)
+}
+
+/// Print the diffs of a dry run, otherwise commit the batch atomically.
+fn finish_batch(pending: Vec<session::FilePlan>, opts: &ToggleOptions) -> Result<()> {
+    if opts.dry_run {
+        for file in &pending {
+            apply_changes(file, opts)?;
+        }
+        Ok(())
+    } else {
+        commit_atomic(pending, opts)
+    }
+}
+
+/// Rename a section or group in every file. Each file is planned first, so a
+/// collision anywhere aborts before anything is written; the writes are then
+/// committed as one atomic batch.
+fn run_rename(cli: &Cli, to: &str, opts: &ToggleOptions) -> Result<()> {
+    let from = &cli.sections[0];
+    let ops = [Operation::Rename {
+        from: from.clone(),
+        to: to.to_string(),
+    }];
+    let plans = plan_batch(cli, &ops, &format!("Failed to rename '{from}'"), opts)?;
+
+    let mut pending = Vec::new();
+    for file in plans {
+        let renamed = file.outcomes[0].renamed;
+        if renamed > 0 {
+            if opts.verbose {
+                eprintln!(
+                    "Renamed {} section(s) '{}' to '{}' in {}",
+                    renamed,
+                    from,
+                    to,
+                    file.path.display()
+                );
+            }
+            pending.push(file);
+        }
+    }
+    if pending.is_empty() {
+        return Err(ToglError::SectionNotFound { id: from.clone() }.into());
+    }
+    finish_batch(pending, opts)
+}
+
+/// Copy a section as a new variant in every file that has it, as one batch.
+fn run_add_variant(cli: &Cli, to: &str, opts: &ToggleOptions) -> Result<()> {
+    let from = &cli.sections[0];
+    let ops = [Operation::AddVariant {
+        from: from.clone(),
+        to: to.to_string(),
+        solo_as: cli.variant_as.clone(),
+        desc: cli.desc.clone(),
+    }];
+    let plans = plan_batch(cli, &ops, &format!("Failed to add '{to}'"), opts)?;
+
+    let pending: Vec<_> = plans
+        .into_iter()
+        .filter(|file| file.outcomes[0].added > 0)
+        .collect();
+    if pending.is_empty() {
+        return Err(ToglError::SectionNotFound { id: from.clone() }.into());
+    }
+    if opts.verbose {
+        for file in &pending {
+            eprintln!("Added variant '{}' in {}", to, file.path.display());
+        }
+    }
+    finish_batch(pending, opts)
+}
+
+/// Drop a variant in every file, then re-validate what is left of its group
+/// across the batch: errors abort before anything is written, warnings (a
+/// lone variant, nothing active) are reported on stderr.
+fn run_drop_variant(cli: &Cli, opts: &ToggleOptions) -> Result<()> {
+    let id = &cli.sections[0];
+    let group = match core::parse_id_parts(id) {
+        (group, Some(variant)) if !variant.is_empty() => group,
+        (group, _) => {
+            return Err(ToglError::NotAVariant {
+                id: id.clone(),
+                group,
+            }
+            .into())
+        }
+    };
+    let ops = [Operation::Remove {
+        id: id.clone(),
+        mode: core::RemoveMode::All,
+    }];
+    let plans = plan_batch(cli, &ops, &format!("Failed to drop '{id}'"), opts)?;
+    if plans.iter().all(|file| file.outcomes[0].removed == 0) {
+        return Err(ToglError::SectionNotFound { id: id.clone() }.into());
+    }
+
+    let per_file: Vec<(PathBuf, Vec<core::ScanSectionInfo>)> = plans
+        .iter()
+        .map(|file| {
+            let sections = core::scan_sections(&file.path, &file.modified)
+                .into_iter()
+                .filter(|s| s.group == group)
+                .collect::<Vec<_>>();
+            (file.path.clone(), sections)
+        })
+        .filter(|(_, sections)| !sections.is_empty())
+        .collect();
+    let mut issues = core::validate_sections(&per_file, false);
+    let left: Vec<&core::ScanSectionInfo> = per_file.iter().flat_map(|(_, v)| v).collect();
+    let mut variants: Vec<&str> = left.iter().filter_map(|s| s.variant.as_deref()).collect();
+    variants.sort_unstable();
+    variants.dedup();
+    let warn = |message: String| core::CheckIssue {
+        level: core::CheckLevel::Warn,
+        group: group.clone(),
+        file: None,
+        line: None,
+        message,
+    };
+    if variants.len() == 1 {
+        issues.push(warn(format!("only one variant left ({})", variants[0])));
+    }
+    let active = |s: &&core::ScanSectionInfo| {
+        matches!(
+            s.state,
+            core::SectionState::Uncommented | core::SectionState::Mixed
+        )
+    };
+    if !variants.is_empty() && !left.iter().any(active) {
+        issues.push(warn("no variant is active".into()));
+    }
+    for issue in &issues {
+        eprintln!("{}", format_check_issue(issue));
+    }
+    if check_has_errors(&issues) {
+        return Err(anyhow::anyhow!(
+            "group '{group}' would not validate after dropping '{id}'; nothing was written"
+        ));
+    }
+
+    let pending: Vec<_> = plans
+        .into_iter()
+        .filter(|file| file.outcomes[0].removed > 0)
+        .collect();
+    if opts.verbose {
+        for file in &pending {
+            eprintln!("Dropped variant '{}' in {}", id, file.path.display());
+        }
+    }
+    finish_batch(pending, opts)
+}
+
 fn run_list_sections(cli: &Cli, opts: &ToggleOptions) -> Result<()> {
     let walk_opts = walk::WalkOptions {
         verbose: opts.verbose,
@@ -1062,8 +1723,8 @@ fn run_list_sections(cli: &Cli, opts: &ToggleOptions) -> Result<()> {
     let mut sections_by_id: BTreeMap<String, SectionAggregation> = BTreeMap::new();
 
     for path in &files {
-        let content = match io::read_file_encoded(path, opts.encoding) {
-            Ok(c) => c,
+        let content = match opts.toggler.read(path) {
+            Ok(s) => s.content,
             Err(_) => continue,
         };
         let found = core::discover_sections(&content);
@@ -1132,255 +1793,698 @@ fn run_list_sections(cli: &Cli, opts: &ToggleOptions) -> Result<()> {
     Ok(())
 }
 
-fn process_file(path: &Path, cli: &Cli, opts: &ToggleOptions) -> Result<Vec<ProcessResult>> {
-    // --strict-ext: reject non-.py files
-    if cli.strict_ext {
-        let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
-        if ext != "py" {
-            return Err(UsageError(format!(
-                "File '{}' is not a .py file (rejected by --strict-ext)",
-                path.display()
-            ))
-            .into());
-        }
+/// The atomic journal for this working directory: one in the CWD, or one a
+/// journal pointer there refers to.
+fn journal_file() -> Result<PathBuf> {
+    let cwd = std::env::current_dir().context("Failed to determine current directory")?;
+    Ok(journal::locate_journal(std::slice::from_ref(&cwd))
+        .unwrap_or_else(|| cwd.join(journal::JOURNAL_FILENAME)))
+}
+
+/// JSON output for `togl journal show`.
+#[derive(serde::Serialize)]
+struct JournalShowReport<'a> {
+    path: String,
+    journal: Option<&'a journal::Journal>,
+}
+
+/// Print the leftover journal (if any): status, timestamps and every entry.
+fn run_journal_show(output: &cli::OutputArgs) -> Result<()> {
+    let path = journal_file()?;
+    let loaded = journal::read_journal(&path)?;
+
+    if output.json {
+        let report = JournalShowReport {
+            path: path.display().to_string(),
+            journal: loaded.as_ref(),
+        };
+        println!(
+            "{}",
+            serde_json::to_string(&report).expect("Failed to serialize JSON")
+        );
+        return Ok(());
     }
 
-    if opts.verbose {
-        eprintln!("Processing {}:", path.display());
+    let Some(j) = loaded else {
+        println!("No journal found at {}", path.display());
+        return Ok(());
+    };
+    println!("Journal: {}", path.display());
+    println!("Status: {:?}", j.status);
+    println!("Created: {}", j.created_at);
+    println!(
+        "Backups: {}",
+        if j.backup_enabled {
+            "enabled"
+        } else {
+            "disabled"
+        }
+    );
+    if j.groups.len() > 1 {
+        println!("Staging groups ({}):", j.groups.len());
+        for group in &j.groups {
+            let device = group
+                .device
+                .map_or_else(|| "?".to_string(), |d| d.to_string());
+            println!(
+                "  device {}: {} ({} file(s))",
+                device,
+                group.dir.display(),
+                group.entries.len()
+            );
+        }
+    }
+    println!("Entries ({}):", j.entries.len());
+    for entry in &j.entries {
+        let state = if entry.rename_completed {
+            "renamed"
+        } else {
+            "pending"
+        };
+        println!("  [{}] {}", state, entry.target_path.display());
+        println!("      temp:   {}", entry.temp_path.display());
+        match &entry.backup_path {
+            Some(b) => match entry.backup_method {
+                Some(m) => println!("      backup: {} ({})", b.display(), m.as_str()),
+                None => println!("      backup: {}", b.display()),
+            },
+            None => println!("      backup: (none)"),
+        }
+        println!("      sha256: {}", entry.content_sha256);
     }
+    Ok(())
+}
 
-    let mut results = Vec::new();
+/// JSON output for `togl journal verify`.
+#[derive(serde::Serialize)]
+struct JournalVerifyReport {
+    path: String,
+    #[serde(flatten)]
+    verification: journal::JournalVerification,
+    recommendation: &'static str,
+}
 
-    if !cli.lines.is_empty() {
-        if opts.verbose {
-            for lr in &cli.lines {
-                eprintln!("  Line range: {}", lr);
-            }
+/// Check the leftover journal against disk and recommend a recovery. Fails
+/// when neither rollback nor roll-forward can be done safely.
+fn run_journal_verify(output: &cli::OutputArgs) -> Result<()> {
+    let path = journal_file()?;
+    let Some(j) = journal::read_journal(&path)? else {
+        if output.json {
+            println!(
+                "{}",
+                serde_json::json!({ "path": path.display().to_string(), "journal": null })
+            );
+        } else {
+            println!("No journal found at {}", path.display());
         }
-        let pr = toggle_line_ranges(path, &cli.lines, opts)?;
-        results.push(pr);
-    }
+        return Ok(());
+    };
 
-    for section in &cli.sections {
-        if opts.verbose {
-            eprintln!("  Section: {}", section);
+    let verification = journal::verify_journal(&j);
+    let recommendation = verification.recommendation();
+
+    if output.json {
+        let report = JournalVerifyReport {
+            path: path.display().to_string(),
+            verification,
+            recommendation,
+        };
+        println!(
+            "{}",
+            serde_json::to_string(&report).expect("Failed to serialize JSON")
+        );
+    } else {
+        let yes_no = |b: bool| if b { "ok" } else { "MISSING" };
+        println!("Journal: {} ({:?})", path.display(), verification.status);
+        for e in &verification.entries {
+            let temp = if e.temp_ok {
+                "ok"
+            } else if e.temp_exists {
+                "CORRUPT"
+            } else {
+                "missing"
+            };
+            let backup = match (e.backup_exists, e.backup_ok) {
+                (None, _) => "disabled",
+                (Some(_), Some(false)) => "CORRUPT",
+                (Some(exists), _) => yes_no(exists),
+            };
+            println!(
+                "  {} [{}] temp: {}, target: {}, backup: {}",
+                e.target_path.display(),
+                if e.rename_completed {
+                    "renamed"
+                } else {
+                    "pending"
+                },
+                temp,
+                if e.target_updated {
+                    "updated"
+                } else {
+                    "original"
+                },
+                backup
+            );
         }
-        let pr = toggle_section(path, section, opts)?;
-        results.push(pr);
+        let safe = |b: bool| if b { "safe" } else { "NOT safe" };
+        println!("Rollback (--recover): {}", safe(verification.rollback_safe));
+        println!(
+            "Roll-forward (--recover --recover-forward): {}",
+            safe(verification.forward_safe)
+        );
+        println!("Recommendation: {}", recommendation);
     }
 
-    Ok(results)
+    if recommendation == "manual" {
+        anyhow::bail!(
+            "neither rollback nor roll-forward is safe; manual inspection of {} required",
+            path.display()
+        );
+    }
+    Ok(())
 }
 
-/// Count the number of lines that differ between two strings.
-fn count_changed_lines(original: &str, modified: &str) -> usize {
-    let orig_lines: Vec<&str> = original.lines().collect();
-    let mod_lines: Vec<&str> = modified.lines().collect();
-    let max_len = orig_lines.len().max(mod_lines.len());
-    let mut changed = 0;
-    for i in 0..max_len {
-        let a = orig_lines.get(i).copied().unwrap_or("");
-        let b = mod_lines.get(i).copied().unwrap_or("");
-        if a != b {
-            changed += 1;
-        }
+/// The history store for this working directory (`./.toggle/history.jsonl`).
+fn history_store() -> Result<PathBuf> {
+    let cwd = std::env::current_dir().context("Failed to determine current directory")?;
+    Ok(history::history_path(&cwd))
+}
+
+/// The invoking command line, as recorded in the history log.
+fn command_line() -> String {
+    let mut args = std::env::args();
+    let bin = args
+        .next()
+        .and_then(|a| {
+            Path::new(&a)
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+        })
+        .unwrap_or_else(|| env!("CARGO_BIN_NAME").to_string());
+    std::iter::once(bin)
+        .chain(args)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Append the files written by this run to the history log. Best effort: the
+/// writes already happened, so a failure here only costs the ability to undo.
+fn record_history(files: Vec<history::FileChange>) {
+    if files.is_empty() {
+        return;
+    }
+    let result = history_store()
+        .and_then(|store| Ok(history::append_record(&store, &command_line(), files)?));
+    if let Err(e) = result {
+        eprintln!("Warning: failed to record history: {:#}", e);
     }
-    changed
 }
 
-/// Apply changes to a file: handles dry-run, interactive prompt, backup, and write.
-/// Returns the number of lines changed.
-fn apply_changes(
-    path: &Path,
-    original: &str,
-    modified: &str,
-    opts: &ToggleOptions,
-) -> Result<usize> {
-    let lines_changed = count_changed_lines(original, modified);
+/// JSON output for `togl undo`.
+#[derive(serde::Serialize)]
+struct UndoReport {
+    undone: Vec<u64>,
+    files: Vec<String>,
+    dry_run: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    conflicts: Vec<history::UndoConflict>,
+}
 
-    if opts.dry_run {
-        if !opts.json {
-            io::print_diff(path, original, modified);
+/// Revert the `count` most recent operations recorded in the history log.
+/// All restored files are committed together through an `AtomicBatch`.
+fn run_undo(
+    count: usize,
+    dry_run: bool,
+    lock_timeout: Duration,
+    output: &cli::OutputArgs,
+) -> Result<()> {
+    if count == 0 {
+        return Err(UsageError("undo count must be at least 1".into()).into());
+    }
+    let cwd = std::env::current_dir().context("Failed to determine current directory")?;
+    if journal_file()?.exists() {
+        return Err(UsageError(
+            "A previous atomic operation was interrupted. \
+             Run with --recover to clean up before undoing."
+                .into(),
+        )
+        .into());
+    }
+
+    // Hold the lock from planning until the records are marked undone, so no
+    // other batch can change the files or the history in between.
+    let batch = if dry_run {
+        None
+    } else {
+        let batch = io::AtomicBatch::with_lock_timeout(&[], true, interrupt_flag(), lock_timeout)
+            .map_err(|e| anyhow::anyhow!("Failed to initialize atomic batch: {}", e))?;
+        Some(batch)
+    };
+
+    let store = history::history_path(&cwd);
+    let records = history::read_history(&store)?;
+    let plan = match history::plan_undo(&records, count) {
+        Ok(plan) => plan,
+        Err(e) => {
+            if output.json {
+                let conflicts = e
+                    .downcast_ref::<history::ConflictError>()
+                    .map(|c| c.0.clone())
+                    .unwrap_or_default();
+                let report = UndoReport {
+                    undone: Vec::new(),
+                    files: Vec::new(),
+                    dry_run,
+                    conflicts,
+                };
+                println!(
+                    "{}",
+                    serde_json::to_string(&report).expect("Failed to serialize JSON")
+                );
+            }
+            return Err(e);
         }
-        if opts.interactive && std::io::stdin().is_terminal() {
-            // In dry-run + interactive, just show the diff (already done above)
-            eprintln!("(dry-run mode, no changes will be written)");
+    };
+
+    match batch {
+        Some(mut batch) => {
+            for (path, restored) in &plan.restores {
+                batch
+                    .stage(path, restored, "")
+                    .map_err(|e| anyhow::anyhow!("Failed to stage '{}': {}", path.display(), e))?;
+            }
+            batch
+                .commit_then(|| history::mark_undone(&store, &plan.record_ids))
+                .map_err(|e| anyhow::anyhow!("Atomic commit failed: {}", e))?;
+        }
+        None if !output.json => {
+            for (path, restored) in &plan.restores {
+                let current = std::fs::read(path).unwrap_or_default();
+                io::print_diff(
+                    path,
+                    &String::from_utf8_lossy(&current),
+                    &String::from_utf8_lossy(restored),
+                );
+            }
         }
-        return Ok(lines_changed);
+        None => {}
     }
 
-    // Interactive prompt
-    if opts.interactive {
-        // Show diff preview before prompting (only on TTY to avoid polluting piped output)
-        if std::io::stdin().is_terminal() && !opts.json {
-            io::print_diff(path, original, modified);
+    if output.json {
+        let report = UndoReport {
+            undone: plan.record_ids.clone(),
+            files: plan
+                .restores
+                .iter()
+                .map(|(p, _)| p.display().to_string())
+                .collect(),
+            dry_run,
+            conflicts: Vec::new(),
+        };
+        println!(
+            "{}",
+            serde_json::to_string(&report).expect("Failed to serialize JSON")
+        );
+    } else {
+        let verb = if dry_run { "Would restore" } else { "Restored" };
+        for (path, _) in &plan.restores {
+            eprintln!("{} {}", verb, path.display());
         }
-        eprint!("Modify {}? [y/N] ", path.display());
-        use std::io::Write;
-        std::io::stderr().flush().ok();
-        let mut answer = String::new();
-        std::io::stdin()
-            .read_line(&mut answer)
-            .map_err(|e| anyhow::anyhow!("Failed to read interactive input: {}", e))?;
-        if !answer.trim().eq_ignore_ascii_case("y") {
-            if opts.verbose {
-                eprintln!("  Skipped {}", path.display());
-            }
-            return Ok(0);
+        if output.verbose {
+            let ids: Vec<String> = plan
+                .record_ids
+                .iter()
+                .map(|id| format!("#{}", id))
+                .collect();
+            eprintln!("Undid operation(s) {}", ids.join(", "));
         }
     }
+    Ok(())
+}
 
-    if let Some(ext) = opts.backup {
-        io::create_backup(path, ext)?;
+/// JSON output entry for `togl history`.
+#[derive(serde::Serialize)]
+struct HistoryEntry<'a> {
+    id: u64,
+    created_at: &'a str,
+    command: &'a str,
+    files: Vec<String>,
+    undone: bool,
+}
+
+/// `togl lsp`: serve editors until they shut the server down.
+fn run_lsp(config: Option<&Path>) -> Result<()> {
+    let mut toggler = Toggler::new();
+    if let Some(path) = config {
+        toggler = toggler.with_config(ToggleConfig::load(path)?);
     }
-    io::write_file_encoded(
-        path,
-        modified,
-        opts.temp_suffix,
-        opts.no_dereference,
-        opts.encoding,
-    )?;
-    Ok(lines_changed)
+    lsp::run(toggler)
 }
 
-/// Resolve comment style for a file, applying --comment-style override if present.
-fn resolve_comment_style(path: &Path, opts: &ToggleOptions) -> Result<core::CommentStyle> {
-    if !opts.comment_style_override.is_empty() {
-        let single = opts.comment_style_override[0].clone();
-        let (ms, me) = if opts.comment_style_override.len() == 3 {
-            (
-                Some(opts.comment_style_override[1].clone()),
-                Some(opts.comment_style_override[2].clone()),
-            )
+/// List recorded operations, newest first.
+fn run_history(output: &cli::OutputArgs) -> Result<()> {
+    let records = history::read_history(&history_store()?)?;
+
+    if output.json {
+        let entries: Vec<HistoryEntry> = records
+            .iter()
+            .rev()
+            .map(|r| HistoryEntry {
+                id: r.id,
+                created_at: &r.created_at,
+                command: &r.command,
+                files: r
+                    .files
+                    .iter()
+                    .map(|f| f.path.display().to_string())
+                    .collect(),
+                undone: r.undone,
+            })
+            .collect();
+        println!(
+            "{}",
+            serde_json::to_string(&entries).expect("Failed to serialize JSON")
+        );
+        return Ok(());
+    }
+
+    if records.is_empty() {
+        println!("No recorded operations.");
+        return Ok(());
+    }
+    for record in records.iter().rev() {
+        let status = if record.undone { " [undone]" } else { "" };
+        println!(
+            "#{} {} {}{}",
+            record.id, record.created_at, record.command, status
+        );
+        for file in &record.files {
+            println!("    {}", file.path.display());
+        }
+    }
+    Ok(())
+}
+
+/// Backup options matching the `togl backups` location flags.
+fn backup_location(location: &cli::BackupLocationArgs) -> Result<backup::BackupOptions> {
+    Ok(backup::BackupOptions {
+        suffix: location.suffix.clone(),
+        dir: location.backup_dir.clone(),
+        base: std::env::current_dir().context("Failed to determine current directory")?,
+        ..backup::BackupOptions::default()
+    })
+}
+
+/// Backups of the given files, or of every file under the given directories.
+fn find_backups(
+    paths: &[PathBuf],
+    opts: &backup::BackupOptions,
+) -> Result<Vec<backup::BackupFile>> {
+    let default = [PathBuf::from(".")];
+    let paths = if paths.is_empty() {
+        &default[..]
+    } else {
+        paths
+    };
+    let mut found = Vec::new();
+    for path in paths {
+        let backups = if path.is_dir() {
+            backup::list_tree(path, opts)
         } else {
-            (None, None)
+            backup::list(path, opts)
         };
-        return Ok(core::CommentStyle {
-            single_line: single,
-            multi_line_start: ms,
-            multi_line_end: me,
-        });
+        found.extend(
+            backups.with_context(|| format!("Failed to list backups of {}", path.display()))?,
+        );
     }
-    core::get_comment_style(path, opts.mode, opts.config)
+    Ok(found)
 }
 
-fn toggle_line_ranges(
-    path: &Path,
-    line_range_specs: &[String],
-    opts: &ToggleOptions,
-) -> Result<ProcessResult> {
-    let comment_style = resolve_comment_style(path, opts)?;
-    let content = io::read_file_encoded(path, opts.encoding)?;
-    let line_count = content.lines().count();
-
-    // Parse all range specs into LineRange values
-    let mut ranges = Vec::new();
-    for spec in line_range_specs {
-        let (start_line, end_line) = core::parse_line_range(spec)?;
-        if start_line > line_count {
-            return Err(UsageError(format!(
-                "Start line {} is out of range (file has {} lines)",
-                start_line, line_count
-            ))
-            .into());
-        }
-        ranges.push(core::LineRange::new(start_line, end_line));
+fn backup_label(kind: &backup::BackupKind) -> String {
+    match kind {
+        backup::BackupKind::Simple => "simple".to_string(),
+        backup::BackupKind::Numbered { number } => format!("#{}", number),
+        backup::BackupKind::Timestamp { stamp } => stamp.clone(),
     }
+}
 
-    // --to-end: extend the last range's end to the file's line count
-    if opts.to_end {
-        if let Some(last) = ranges.last_mut() {
-            last.end = line_count;
-        }
+fn run_backups_list(
+    paths: &[PathBuf],
+    location: &cli::BackupLocationArgs,
+    output: &cli::OutputArgs,
+) -> Result<()> {
+    let backups = find_backups(paths, &backup_location(location)?)?;
+    if output.json {
+        println!(
+            "{}",
+            serde_json::to_string(&backups).expect("Failed to serialize JSON")
+        );
+        return Ok(());
     }
-
-    // Validate end lines against file length (after --to-end extension)
-    for range in &ranges {
-        if range.end > line_count {
-            return Err(UsageError(format!(
-                "End line {} is out of range (file has {} lines)",
-                range.end, line_count
-            ))
-            .into());
+    if backups.is_empty() {
+        println!("No backups found.");
+        return Ok(());
+    }
+    let mut current: Option<&Path> = None;
+    for b in &backups {
+        if current != Some(b.original.as_path()) {
+            println!("{}", b.original.display());
+            current = Some(&b.original);
         }
+        println!(
+            "    {} ({}, {} bytes)",
+            b.path.display(),
+            backup_label(&b.kind),
+            b.size
+        );
     }
+    Ok(())
+}
 
-    let merged = core::merge_ranges(&ranges);
-    let force_mode = opts.force.as_deref();
-    let toggled = if opts.mode == "multi" {
-        let (ms, me) = match (
-            &comment_style.multi_line_start,
-            &comment_style.multi_line_end,
-        ) {
-            (Some(s), Some(e)) => (s.as_str(), e.as_str()),
-            _ => {
-                return Err(UsageError(format!(
-                    "Multi-line comments not supported for {}",
-                    path.display()
-                ))
-                .into());
-            }
+/// JSON output for `togl backups restore`.
+#[derive(serde::Serialize)]
+struct RestoreReport {
+    file: String,
+    from: String,
+    dry_run: bool,
+}
+
+fn run_backups_restore(
+    path: &Path,
+    from: Option<&Path>,
+    dry_run: bool,
+    location: &cli::BackupLocationArgs,
+    output: &cli::OutputArgs,
+) -> Result<()> {
+    let backups = backup::list(path, &backup_location(location)?)
+        .with_context(|| format!("Failed to list backups of {}", path.display()))?;
+    let chosen = match from {
+        Some(from) => {
+            let real = std::fs::canonicalize(from).ok();
+            backups
+                .into_iter()
+                .find(|b| {
+                    b.path == from || real.is_some() && std::fs::canonicalize(&b.path).ok() == real
+                })
+                .ok_or_else(|| {
+                    UsageError(format!(
+                        "'{}' is not a backup of {}",
+                        from.display(),
+                        path.display()
+                    ))
+                })?
+        }
+        None => backups
+            .into_iter()
+            .next_back()
+            .ok_or_else(|| UsageError(format!("no backups found for {}", path.display())))?,
+    };
+    if !dry_run {
+        backup::restore(&chosen).with_context(|| {
+            format!(
+                "Failed to restore {} from {}",
+                path.display(),
+                chosen.path.display()
+            )
+        })?;
+    }
+    if output.json {
+        let report = RestoreReport {
+            file: path.display().to_string(),
+            from: chosen.path.display().to_string(),
+            dry_run,
         };
-        core::toggle_comments_multi(&content, &merged, force_mode, ms, me)
+        println!(
+            "{}",
+            serde_json::to_string(&report).expect("Failed to serialize JSON")
+        );
     } else {
-        core::toggle_comments_with_marker(&content, &merged, force_mode, &comment_style.single_line)
-    };
-    let result = io::normalize_eol(&toggled, opts.eol);
-    let lines_changed = apply_changes(path, &content, &result, opts)?;
-
-    Ok(ProcessResult {
-        action: "toggle_line_range".to_string(),
-        lines_changed,
-        section_id: None,
-        desc: None,
-    })
+        let verb = if dry_run { "Would restore" } else { "Restored" };
+        eprintln!("{} {} from {}", verb, path.display(), chosen.path.display());
+    }
+    Ok(())
 }
 
-fn toggle_section(path: &Path, section_id: &str, opts: &ToggleOptions) -> Result<ProcessResult> {
-    if opts.verbose {
-        eprintln!("  Looking for section with ID={}", section_id);
+fn run_backups_prune(
+    paths: &[PathBuf],
+    keep: usize,
+    dry_run: bool,
+    location: &cli::BackupLocationArgs,
+    output: &cli::OutputArgs,
+) -> Result<()> {
+    let backups = find_backups(paths, &backup_location(location)?)?;
+    let doomed = backup::prunable(&backups, keep);
+    if !dry_run {
+        for b in &doomed {
+            std::fs::remove_file(&b.path)
+                .with_context(|| format!("Failed to remove {}", b.path.display()))?;
+        }
+    }
+    if output.json {
+        println!(
+            "{}",
+            serde_json::to_string(&doomed).expect("Failed to serialize JSON")
+        );
+    } else {
+        let verb = if dry_run { "Would remove" } else { "Removed" };
+        for b in &doomed {
+            eprintln!("{} {}", verb, b.path.display());
+        }
+        if doomed.is_empty() {
+            eprintln!("Nothing to prune.");
+        }
     }
+    Ok(())
+}
 
-    let original_content = io::read_file_encoded(path, opts.encoding)?;
-    let modified = compute_section_changes(path, section_id, opts, &original_content)?;
+fn process_file(
+    path: &Path,
+    cli: &Cli,
+    ops: &[Operation],
+    opts: &ToggleOptions,
+) -> Result<Vec<ProcessResult>> {
+    check_strict_ext(path, cli)?;
 
-    let lines_changed = if modified == original_content {
+    if opts.verbose {
+        eprintln!("Processing {}:", path.display());
+        for lr in &cli.lines {
+            eprintln!("  Line range: {}", lr);
+        }
+        for section in &cli.sections {
+            eprintln!("  Section: {}", section);
+        }
+    }
+
+    let file = opts.toggler.plan_file(path, ops)?;
+    let applied = if file.is_changed() {
         if opts.verbose {
-            eprintln!("  No changes made to file");
+            eprintln!("  File modified, writing changes back");
         }
-        0
+        apply_changes(&file, opts)?
     } else {
         if opts.verbose {
-            eprintln!("  File modified, writing changes back");
+            eprintln!("  No changes made to file");
         }
-        apply_changes(path, &original_content, &modified, opts)?
+        false
     };
 
-    // Resolve desc by looking up the matching section/variant.
-    let (group, variant) = core::parse_id_parts(section_id);
-    let desc = core::discover_variants(&original_content, &group)
-        .into_iter()
-        .find(|s| match &variant {
-            Some(v) => s.id == format!("{group}:{v}"),
-            None => s.id == section_id || core::parse_id_parts(&s.id).1.is_some(),
+    let results = file
+        .outcomes
+        .iter()
+        .map(|outcome| {
+            if opts.verbose {
+                if let Some(d) = &outcome.desc {
+                    eprintln!("  Section desc: {}", d);
+                }
+            }
+            ProcessResult {
+                action: match outcome.operation {
+                    Operation::ToggleLines { .. } | Operation::ToggleLineSpecs { .. } => {
+                        "toggle_line_range"
+                    }
+                    _ => "toggle_section",
+                }
+                .to_string(),
+                lines_changed: if applied { outcome.lines_changed } else { 0 },
+                section_id: outcome.operation.section_id(),
+                desc: outcome.desc.clone(),
+                detected_encoding: file.detected.then_some(file.encoding),
+            }
         })
-        .and_then(|s| s.desc);
+        .collect();
+    Ok(results)
+}
 
-    if opts.verbose {
-        if let Some(ref d) = desc {
-            eprintln!("  Section desc: {}", d);
+/// Show, confirm and write one planned change: handles dry-run, the
+/// interactive prompt and the history log. Returns false if the user
+/// declined the change.
+fn apply_changes(file: &session::FilePlan, opts: &ToggleOptions) -> Result<bool> {
+    let path = &file.path;
+    if opts.dry_run {
+        if !opts.json {
+            io::print_diff(path, &file.original, &file.modified);
+        }
+        if opts.interactive && std::io::stdin().is_terminal() {
+            // In dry-run + interactive, just show the diff (already done above)
+            eprintln!("(dry-run mode, no changes will be written)");
         }
+        return Ok(true);
     }
 
-    Ok(ProcessResult {
-        action: "toggle_section".to_string(),
-        lines_changed,
-        section_id: Some(section_id.to_string()),
-        desc,
-    })
+    // Interactive prompt
+    if opts.interactive {
+        // Show diff preview before prompting (only on TTY to avoid polluting piped output)
+        if std::io::stdin().is_terminal() && !opts.json {
+            io::print_diff(path, &file.original, &file.modified);
+        }
+        eprint!("Modify {}? [y/N] ", path.display());
+        use std::io::Write;
+        std::io::stderr().flush().ok();
+        let mut answer = String::new();
+        std::io::stdin()
+            .read_line(&mut answer)
+            .map_err(|e| anyhow::anyhow!("Failed to read interactive input: {}", e))?;
+        if !answer.trim().eq_ignore_ascii_case("y") {
+            if opts.verbose {
+                eprintln!("  Skipped {}", path.display());
+            }
+            return Ok(false);
+        }
+    }
+
+    warn_if_hardlinked(path, opts);
+    // Refuses to overwrite an edit made since the file was read.
+    let written = opts.toggler.write(file)?;
+    if let (Some(saved), true) = (&written.backup, opts.verbose) {
+        eprintln!("  Backed up {} to {}", path.display(), saved.display());
+    }
+    record_written(vec![written], opts);
+    Ok(true)
+}
+
+/// Queue the history entries of files just written for the history log.
+fn record_written(written: Vec<session::Written>, opts: &ToggleOptions) {
+    if let Some(log) = opts.history {
+        log.borrow_mut()
+            .extend(written.into_iter().filter_map(|w| w.history));
+    }
 }
 
-fn run_scan(cli: &Cli) -> Result<()> {
+/// Files are replaced by renaming a new file over them, so a file with other
+/// hard links stops sharing content with them. Say so rather than silently
+/// splitting the links.
+fn warn_if_hardlinked(path: &Path, opts: &ToggleOptions) {
+    if let Some(links) = opts.toggler.shared_links(path) {
+        eprintln!(
+            "Warning: '{}' has {} hard links; it will be replaced by a new file \
+             and the other links will keep the old content",
+            path.display(),
+            links
+        );
+    }
+}
+
+fn run_scan(cli: &Cli, opts: &ToggleOptions) -> Result<()> {
     let walk_opts = walk::WalkOptions {
         verbose: cli.verbose,
         ..walk::WalkOptions::default()
@@ -1397,9 +2501,9 @@ fn run_scan(cli: &Cli) -> Result<()> {
 
     let mut all_sections: Vec<core::ScanSectionInfo> = Vec::new();
     for file_path in &files {
-        match io::read_file_encoded(file_path, &cli.encoding) {
-            Ok(content) => {
-                let sections = core::scan_sections(file_path, &content);
+        match opts.toggler.read(file_path) {
+            Ok(source) => {
+                let sections = core::scan_sections(file_path, &source.content);
                 all_sections.extend(sections);
             }
             Err(e) => {
@@ -1458,20 +2562,24 @@ fn print_check_results(issues: &[core::CheckIssue]) {
         return;
     }
     for i in issues {
-        let tag = match i.level {
-            core::CheckLevel::Ok => "OK  ",
-            core::CheckLevel::Warn => "WARN",
-            core::CheckLevel::Err => "ERR ",
-        };
-        let file_part = i
-            .file
-            .as_deref()
-            .map(|f| format!(" ({f})"))
-            .unwrap_or_default();
-        println!("{tag}  {:<18} {}{file_part}", i.group, i.message);
+        println!("{}", format_check_issue(i));
     }
 }
 
+fn format_check_issue(i: &core::CheckIssue) -> String {
+    let tag = match i.level {
+        core::CheckLevel::Ok => "OK  ",
+        core::CheckLevel::Warn => "WARN",
+        core::CheckLevel::Err => "ERR ",
+    };
+    let file_part = match (&i.file, i.line) {
+        (Some(f), Some(line)) => format!(" ({f}:{line})"),
+        (Some(f), None) => format!(" ({f})"),
+        (None, _) => String::new(),
+    };
+    format!("{tag}  {:<18} {}{file_part}", i.group, i.message)
+}
+
 fn check_has_errors(issues: &[core::CheckIssue]) -> bool {
     issues
         .iter()
@@ -1540,7 +2648,7 @@ fn print_scan_detailed(sections: &[core::ScanSectionInfo], ids: &[String]) {
             by_id.entry(&s.id).or_default().push(s);
         }
         for (vid, items) in by_id {
-            let state = items[0].state.clone();
+            let state = items[0].state_label();
             println!("  {vid} [{state}]");
             for it in items {
                 let end = it.end_line.map_or("?".to_string(), |e| e.to_string());
@@ -1588,7 +2696,11 @@ fn print_scan_results(sections: &[core::ScanSectionInfo]) {
             let desc = s.description.as_deref().unwrap_or("");
             println!(
                 "{:<20} {:<7} {:<12} {:<14} {}",
-                s.id, type_label, s.state, lines, desc
+                s.id,
+                type_label,
+                s.state_label(),
+                lines,
+                desc
             );
         }
     }
@@ -0,0 +1,607 @@
+//! `togl ui`: a terminal UI over the scan tree.
+//!
+//! Groups, variants and the files they live in are listed with each section's
+//! live state and a body preview. Toggles and variant activations are staged
+//! with the keyboard (at most one per group) and previewed in memory; `c`
+//! commits every staged change as one atomic batch and rescans.
+
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+
+use togl_lib::core::{self, GroupSummary, LineCounts, ScanSectionInfo, SectionType};
+use togl_lib::exit_codes::UsageError;
+use togl_lib::session::{self, Operation, Source};
+
+use crate::{content_changed, record_history, section_type_label, Cli, ToggleOptions};
+
+const HELP: &str = "↑↓ move  ←→ fold  space stage/unstage  c commit  x clear  r rescan  q quit";
+
+/// Run the UI over `cli.paths` until the user quits.
+pub fn run(cli: &Cli, opts: &ToggleOptions) -> Result<()> {
+    if opts.dry_run || opts.json || opts.interactive {
+        return Err(UsageError(
+            "--ui cannot be combined with --dry-run, --json or --interactive".into(),
+        )
+        .into());
+    }
+    if !cli.sections.is_empty() || !cli.lines.is_empty() || cli.force.is_some() {
+        return Err(UsageError(
+            "--ui picks sections interactively; it does not take -S, -l or --force".into(),
+        )
+        .into());
+    }
+    if !std::io::stdin().is_terminal() || !std::io::stdout().is_terminal() {
+        return Err(UsageError("togl ui needs an interactive terminal".into()).into());
+    }
+
+    let mut app = App::new(cli.paths.clone(), opts)?;
+    let mut terminal = ratatui::try_init().context("Failed to set up the terminal")?;
+    let result = app.run(&mut terminal);
+    ratatui::restore();
+    result
+}
+
+/// One line of the tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Row {
+    /// Index into `App::groups`.
+    Group(usize),
+    /// Group index and variant name.
+    Variant(usize, String),
+    /// Index into `App::sections`: one section in one file.
+    Section(usize),
+}
+
+enum Status {
+    Info(String),
+    Error(String),
+}
+
+struct App<'a> {
+    opts: &'a ToggleOptions<'a>,
+    paths: Vec<PathBuf>,
+    /// Every file with sections, as read by the last scan. Commits plan from
+    /// these, so a file edited since the scan is refused rather than clobbered.
+    sources: BTreeMap<PathBuf, Source>,
+    sections: Vec<ScanSectionInfo>,
+    groups: Vec<GroupSummary>,
+    /// The staged operation of each group, applied in group order.
+    staged: BTreeMap<String, Operation>,
+    /// Text of every file the staged operations change.
+    after: BTreeMap<PathBuf, String>,
+    /// Names of the unfolded groups.
+    expanded: BTreeSet<String>,
+    rows: Vec<Row>,
+    list: ListState,
+    status: Status,
+    /// Set by a first `q` with changes staged; a second one quits.
+    quit_armed: bool,
+}
+
+impl<'a> App<'a> {
+    fn new(paths: Vec<PathBuf>, opts: &'a ToggleOptions<'a>) -> Result<Self> {
+        let mut app = App {
+            opts,
+            paths,
+            sources: BTreeMap::new(),
+            sections: Vec::new(),
+            groups: Vec::new(),
+            staged: BTreeMap::new(),
+            after: BTreeMap::new(),
+            expanded: BTreeSet::new(),
+            rows: Vec::new(),
+            list: ListState::default().with_selected(Some(0)),
+            status: Status::Info(String::new()),
+            quit_armed: false,
+        };
+        app.rescan()?;
+        app.status = Status::Info(format!(
+            "{} group(s) in {} file(s)",
+            app.groups.len(),
+            app.sources.len()
+        ));
+        Ok(app)
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            if let Event::Key(key) = event::read()? {
+                if self.handle_key(key) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Re-read every file and rebuild the tree, keeping the staged
+    /// operations that still apply.
+    fn rescan(&mut self) -> Result<()> {
+        let toggler = &self.opts.toggler;
+        // Same selection as a recursive run: preflight refusals and files
+        // without a comment style are left out.
+        let (files, _) = toggler.collect(&self.paths, true, false, &[])?;
+        self.sources.clear();
+        self.sections.clear();
+        for path in files {
+            let Ok(source) = toggler.read(&path) else {
+                continue;
+            };
+            let sections = core::scan_sections(&path, &source.content);
+            if !sections.is_empty() {
+                self.sections.extend(sections);
+                self.sources.insert(path, source);
+            }
+        }
+        self.groups = core::summarize_scan(&self.sections);
+        self.staged
+            .retain(|group, _| self.groups.iter().any(|g| g.group == *group));
+        match self.transform(&self.staged) {
+            Ok(after) => self.after = after,
+            Err(_) => {
+                self.staged.clear();
+                self.after.clear();
+            }
+        }
+        self.rebuild_rows();
+        Ok(())
+    }
+
+    fn rebuild_rows(&mut self) {
+        let mut rows = Vec::new();
+        for (g, group) in self.groups.iter().enumerate() {
+            rows.push(Row::Group(g));
+            if !self.expanded.contains(&group.group) {
+                continue;
+            }
+            rows.extend(self.sections_of(&group.group, None).map(Row::Section));
+            for variant in &group.variants {
+                rows.push(Row::Variant(g, variant.clone()));
+                rows.extend(
+                    self.sections_of(&group.group, Some(variant))
+                        .map(Row::Section),
+                );
+            }
+        }
+        self.rows = rows;
+        let last = self.rows.len().saturating_sub(1);
+        self.list
+            .select(Some(self.list.selected().unwrap_or(0).min(last)));
+    }
+
+    /// Indices of the sections of `group` with exactly this `variant`.
+    fn sections_of<'s>(
+        &'s self,
+        group: &'s str,
+        variant: Option<&'s str>,
+    ) -> impl Iterator<Item = usize> + 's {
+        self.sections
+            .iter()
+            .enumerate()
+            .filter(move |(_, s)| s.group == group && s.variant.as_deref() == variant)
+            .map(|(i, _)| i)
+    }
+
+    fn selected(&self) -> Option<&Row> {
+        self.rows.get(self.list.selected()?)
+    }
+
+    fn group_index(&self, row: &Row) -> usize {
+        match row {
+            Row::Group(g) | Row::Variant(g, _) => *g,
+            Row::Section(i) => self
+                .groups
+                .iter()
+                .position(|g| g.group == self.sections[*i].group)
+                .unwrap_or(0),
+        }
+    }
+
+    // ── Keys ──
+
+    /// Handle one key press; true when the UI should close.
+    fn handle_key(&mut self, key: KeyEvent) -> bool {
+        if key.kind != KeyEventKind::Press {
+            return false;
+        }
+        let quit_armed = std::mem::take(&mut self.quit_armed);
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return true,
+            KeyCode::Char('q') | KeyCode::Esc => {
+                if self.staged.is_empty() || quit_armed {
+                    return true;
+                }
+                self.quit_armed = true;
+                self.status = Status::Info(format!(
+                    "{} staged change(s) not committed; press q again to discard them",
+                    self.staged.len()
+                ));
+            }
+            KeyCode::Up | KeyCode::Char('k') => self.list.select_previous(),
+            KeyCode::Down | KeyCode::Char('j') => self.list.select_next(),
+            KeyCode::Home | KeyCode::Char('g') => self.list.select_first(),
+            KeyCode::End | KeyCode::Char('G') => self.list.select_last(),
+            KeyCode::Right | KeyCode::Char('l') | KeyCode::Enter => self.fold(true),
+            KeyCode::Left | KeyCode::Char('h') => self.fold(false),
+            KeyCode::Char(' ') => self.toggle_staged(),
+            KeyCode::Char('x') => {
+                self.staged.clear();
+                self.after.clear();
+                self.status = Status::Info("Cleared all staged changes".into());
+            }
+            KeyCode::Char('c') => self.commit(),
+            KeyCode::Char('r') => {
+                self.status = match self.rescan() {
+                    Ok(()) => Status::Info(format!(
+                        "Rescanned {} file(s); {} change(s) staged",
+                        self.sources.len(),
+                        self.staged.len()
+                    )),
+                    Err(e) => Status::Error(format!("{:#}", e)),
+                };
+            }
+            _ => {}
+        }
+        false
+    }
+
+    /// Unfold or fold the group of the selected row. Folding from a child
+    /// row moves the selection up to its group.
+    fn fold(&mut self, open: bool) {
+        let Some(row) = self.selected().cloned() else {
+            return;
+        };
+        let g = self.group_index(&row);
+        let name = self.groups[g].group.clone();
+        if open {
+            self.expanded.insert(name);
+        } else {
+            self.expanded.remove(&name);
+        }
+        self.rebuild_rows();
+        if !open {
+            let at = self.rows.iter().position(|r| *r == Row::Group(g));
+            self.list.select(at);
+        }
+    }
+
+    /// What space stages for `row`: a toggle for a solo section or a pair,
+    /// an activation for a variant.
+    fn operation_for(&self, row: &Row) -> Result<(String, Operation), String> {
+        match row {
+            Row::Group(g) => {
+                let group = &self.groups[*g];
+                if group.section_type == SectionType::Group {
+                    return Err(format!(
+                        "{} has {} variants; unfold it and pick one to activate",
+                        group.group, group.variant_count
+                    ));
+                }
+                let op = Operation::ToggleSection {
+                    id: group.group.clone(),
+                };
+                Ok((group.group.clone(), op))
+            }
+            Row::Variant(g, variant) => {
+                let group = self.groups[*g].group.clone();
+                let op = Operation::Activate {
+                    group: group.clone(),
+                    variant: variant.clone(),
+                };
+                Ok((group, op))
+            }
+            Row::Section(i) => {
+                let section = &self.sections[*i];
+                match &section.variant {
+                    Some(variant) => Ok((
+                        section.group.clone(),
+                        Operation::Activate {
+                            group: section.group.clone(),
+                            variant: variant.clone(),
+                        },
+                    )),
+                    None => self.operation_for(&Row::Group(self.group_index(row))),
+                }
+            }
+        }
+    }
+
+    /// Stage the selected row's operation, replacing any other change to the
+    /// same group, or unstage it when it is already staged.
+    fn toggle_staged(&mut self) {
+        let Some(row) = self.selected().cloned() else {
+            return;
+        };
+        let (group, op) = match self.operation_for(&row) {
+            Ok(staged) => staged,
+            Err(message) => {
+                self.status = Status::Error(message);
+                return;
+            }
+        };
+        let label = describe(&op);
+        let mut staged = self.staged.clone();
+        let message = if staged.get(&group) == Some(&op) {
+            staged.remove(&group);
+            format!("Unstaged {}", label)
+        } else {
+            staged.insert(group, op);
+            format!("Staged {}", label)
+        };
+        // Refuse a change that cannot be applied rather than failing at commit.
+        match self.transform(&staged) {
+            Ok(after) => {
+                self.staged = staged;
+                self.after = after;
+                self.status = Status::Info(message);
+            }
+            Err(e) => self.status = Status::Error(format!("Cannot stage {}: {:#}", label, e)),
+        }
+    }
+
+    /// The operations of `staged` whose group has a section in `path`.
+    fn ops_for(&self, staged: &BTreeMap<String, Operation>, path: &Path) -> Vec<Operation> {
+        staged
+            .iter()
+            .filter(|(group, _)| {
+                self.sections
+                    .iter()
+                    .any(|s| s.group == **group && Path::new(&s.file) == path)
+            })
+            .map(|(_, op)| op.clone())
+            .collect()
+    }
+
+    /// Apply `staged` in memory: the new text of every file it changes.
+    fn transform(&self, staged: &BTreeMap<String, Operation>) -> Result<BTreeMap<PathBuf, String>> {
+        let mut after = BTreeMap::new();
+        for (path, source) in &self.sources {
+            let ops = self.ops_for(staged, path);
+            if ops.is_empty() {
+                continue;
+            }
+            let (modified, _) = self
+                .opts
+                .toggler
+                .transform(path, &source.content, &ops)
+                .with_context(|| path.display().to_string())?;
+            if modified != source.content {
+                after.insert(path.clone(), modified);
+            }
+        }
+        Ok(after)
+    }
+
+    /// Write every staged change as one atomic batch, then rescan.
+    fn commit(&mut self) {
+        if self.after.is_empty() {
+            self.status = Status::Info("Nothing staged".into());
+            return;
+        }
+        match self.write_staged() {
+            Ok(count) => {
+                self.staged.clear();
+                self.status = match self.rescan() {
+                    Ok(()) => Status::Info(format!("Committed {} file(s)", count)),
+                    Err(e) => Status::Error(format!(
+                        "Committed {} file(s), but the rescan failed: {:#}",
+                        count, e
+                    )),
+                };
+            }
+            Err(e) => {
+                let hint = if content_changed(&e) {
+                    "; press r to rescan"
+                } else {
+                    ""
+                };
+                self.status =
+                    Status::Error(format!("Commit failed, nothing written: {:#}{}", e, hint));
+            }
+        }
+    }
+
+    fn write_staged(&self) -> Result<usize> {
+        let toggler = &self.opts.toggler;
+        let files = self
+            .after
+            .keys()
+            .map(|path| {
+                let ops = self.ops_for(&self.staged, path);
+                toggler.plan_source(path, self.sources[path].clone(), &ops)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let plan = session::Plan {
+            files,
+            skipped: Vec::new(),
+        };
+        // The toggler is in atomic mode: all files are replaced or none.
+        let written = toggler.apply(&plan)?;
+        let count = written.len();
+        // Each commit is its own history entry, undone on its own.
+        if self.opts.history.is_some() {
+            record_history(written.into_iter().filter_map(|w| w.history).collect());
+        }
+        Ok(count)
+    }
+
+    // ── Drawing ──
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, status, help] = Layout::vertical([
+            Constraint::Fill(1),
+            Constraint::Length(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [tree, preview] =
+            Layout::horizontal([Constraint::Percentage(55), Constraint::Fill(1)]).areas(main);
+
+        let items: Vec<ListItem> = self.rows.iter().map(|row| self.row_item(row)).collect();
+        let title = format!(
+            " {} group(s), {} file(s), {} staged ",
+            self.groups.len(),
+            self.sources.len(),
+            self.staged.len()
+        );
+        let list = List::new(items)
+            .block(Block::bordered().title(title))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, tree, &mut self.list);
+
+        frame.render_widget(self.preview(), preview);
+
+        let status_line = match &self.status {
+            Status::Info(message) => Line::from(message.as_str()),
+            Status::Error(message) => Line::from(message.as_str().red()),
+        };
+        frame.render_widget(Paragraph::new(status_line), status);
+        frame.render_widget(Paragraph::new(HELP.dim()), help);
+    }
+
+    fn row_item(&self, row: &Row) -> ListItem<'static> {
+        let mut spans = Vec::new();
+        let (before, after) = match row {
+            Row::Group(g) => {
+                let group = &self.groups[*g];
+                let fold = if self.expanded.contains(&group.group) {
+                    "▾"
+                } else {
+                    "▸"
+                };
+                spans.push(Span::raw(format!("{} ", fold)));
+                spans.push(Span::raw(format!("{:<22} ", group.group)).bold());
+                spans.push(
+                    Span::raw(format!("{:<5} ", section_type_label(&group.section_type))).dim(),
+                );
+                self.counts(|s| s.group == group.group)
+            }
+            Row::Variant(g, variant) => {
+                let group = &self.groups[*g].group;
+                spans.push(Span::raw(format!(
+                    "    {:<26} ",
+                    format!("{}:{}", group, variant)
+                )));
+                self.counts(|s| s.group == *group && s.variant.as_deref() == Some(variant))
+            }
+            Row::Section(i) => {
+                let section = &self.sections[*i];
+                let place = format!("{}:{}", section.file, section.start_line);
+                spans.push(Span::raw(format!("        {:<22} ", place)).dim());
+                self.counts(|s| std::ptr::eq(s, section))
+            }
+        };
+        spans.push(Span::raw(before.state().to_string()));
+        if let Some(after) = after.filter(|a| a.state() != before.state()) {
+            spans.push(Span::raw(format!(" → {}", after.state())).yellow());
+        }
+        if let Row::Group(g) = row {
+            if let Some(op) = self.staged.get(&self.groups[*g].group) {
+                spans.push(Span::raw(format!("  [{}]", describe(op))).yellow());
+            }
+        }
+        ListItem::new(Line::from(spans))
+    }
+
+    /// Line counts of the sections matching `pick`, now and (when the staged
+    /// changes touch any of them) after the commit.
+    fn counts(&self, pick: impl Fn(&ScanSectionInfo) -> bool) -> (LineCounts, Option<LineCounts>) {
+        let mut before = LineCounts::default();
+        let mut after = LineCounts::default();
+        let mut touched = false;
+        for section in self.sections.iter().filter(|s| pick(s)) {
+            before += section.line_counts;
+            match self.after.get(Path::new(&section.file)) {
+                Some(text) => {
+                    touched = true;
+                    let marker = self.marker(Path::new(&section.file));
+                    after += LineCounts::of(&body(text, section), &marker);
+                }
+                None => after += section.line_counts,
+            }
+        }
+        (before, touched.then_some(after))
+    }
+
+    fn marker(&self, path: &Path) -> String {
+        self.opts
+            .toggler
+            .comment_style(path)
+            .map(|style| style.single_line)
+            .unwrap_or_else(|_| "#".into())
+    }
+
+    /// The body of the selected row's first section, as it will be after the
+    /// commit, with the lines the staged changes touch highlighted.
+    fn preview(&self) -> Paragraph<'static> {
+        let section = self.selected().and_then(|row| match row {
+            Row::Section(i) => Some(*i),
+            Row::Variant(g, variant) => self
+                .sections_of(&self.groups[*g].group, Some(variant))
+                .next(),
+            Row::Group(g) => {
+                let name = &self.groups[*g].group;
+                self.sections.iter().position(|s| s.group == *name)
+            }
+        });
+        let Some(section) = section.map(|i| &self.sections[i]) else {
+            return Paragraph::new("No sections found").block(Block::bordered());
+        };
+
+        let path = Path::new(&section.file);
+        let current = &self.sources[path].content;
+        let now = body(current, section);
+        let next = self
+            .after
+            .get(path)
+            .map(|text| body(text, section))
+            .filter(|next| *next != now);
+        let mut title = format!(" {}:{} ", section.file, section.start_line);
+        if next.is_some() {
+            title.push_str("(staged) ");
+        }
+
+        let mut lines = Vec::new();
+        if let Some(desc) = &section.description {
+            lines.push(Line::from(desc.clone().italic()));
+        }
+        for (offset, line) in now.iter().enumerate() {
+            let number = Span::raw(format!("{:>5} ", section.start_line + 1 + offset)).dim();
+            let text = match next.as_ref().and_then(|n| n.get(offset)) {
+                Some(new) if new != line => Span::raw(new.to_string()).yellow(),
+                _ => Span::raw(line.to_string()),
+            };
+            lines.push(Line::from(vec![number, text]));
+        }
+        Paragraph::new(lines).block(Block::bordered().title(title))
+    }
+}
+
+/// The body lines of `section` in `text` (everything between its markers;
+/// to the end of the file when it is unclosed).
+fn body<'t>(text: &'t str, section: &ScanSectionInfo) -> Vec<&'t str> {
+    let end = section.end_line.map_or(usize::MAX, |end| end - 1);
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line))
+        .filter(|(n, _)| *n > section.start_line && *n <= end)
+        .map(|(_, line)| line)
+        .collect()
+}
+
+fn describe(op: &Operation) -> String {
+    match op {
+        Operation::Activate { group, variant } => format!("activate {}:{}", group, variant),
+        Operation::ToggleSection { id } => format!("toggle {}", id),
+        other => format!("{:?}", other),
+    }
+}
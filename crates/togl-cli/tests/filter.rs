@@ -18,7 +18,10 @@ use tempfile::TempDir;
 
 #[allow(deprecated)]
 fn cmd() -> Command {
-    Command::cargo_bin("toggle").unwrap()
+    let mut c = Command::cargo_bin("toggle").unwrap();
+    // Keep runs from writing .toggle/history.jsonl into the crate directory.
+    c.env("TOGGLE_NO_HISTORY", "1");
+    c
 }
 
 const SECTION_FILE: &str = "# toggle:start ID=feat\nprint(\"hi\")\n# toggle:end ID=feat\nafter\n";
@@ -146,6 +149,11 @@ fn rejects_backup_in_filter_mode() {
     assert_filter_rejected(&["toggle", "-", "-S", "feat", "--backup", ".bak"]);
 }
 
+#[test]
+fn rejects_backup_mode_in_filter_mode() {
+    assert_filter_rejected(&["toggle", "-", "-S", "feat", "--backup-mode", "numbered"]);
+}
+
 #[test]
 fn rejects_recursive_in_filter_mode() {
     assert_filter_rejected(&["toggle", "-", "-S", "feat", "-R"]);
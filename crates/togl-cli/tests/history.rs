@@ -0,0 +1,235 @@
+//! Tests for the operation history log and `togl undo` / `togl history`.
+//!
+//! Each test runs with the CWD set to its own temp dir, since the history
+//! store lives at `./.toggle/history.jsonl`.
+
+use assert_cmd::Command;
+use predicates::prelude::*;
+use std::fs;
+use tempfile::TempDir;
+
+const SECTION_FILE: &str = "# toggle:start ID=feat\nprint(\"hi\")\n# toggle:end ID=feat\n";
+const TOGGLED_FILE: &str = "# toggle:start ID=feat\n# print(\"hi\")\n# toggle:end ID=feat\n";
+
+#[allow(deprecated)]
+fn cmd_in(dir: &TempDir) -> Command {
+    let mut c = Command::cargo_bin("togl").unwrap();
+    c.current_dir(dir.path()).env_remove("TOGGLE_NO_HISTORY");
+    c
+}
+
+fn setup() -> (TempDir, std::path::PathBuf) {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("a.py");
+    fs::write(&path, SECTION_FILE).unwrap();
+    (dir, path)
+}
+
+#[test]
+fn test_toggle_is_recorded_and_undone() {
+    let (dir, path) = setup();
+    cmd_in(&dir)
+        .args(["toggle", "-S", "feat", "a.py"])
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(&path).unwrap(), TOGGLED_FILE);
+    assert!(dir.path().join(".toggle/history.jsonl").exists());
+
+    cmd_in(&dir)
+        .arg("undo")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Restored"));
+    assert_eq!(fs::read_to_string(&path).unwrap(), SECTION_FILE);
+
+    // Already undone: nothing left.
+    cmd_in(&dir)
+        .arg("undo")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("nothing to undo"));
+}
+
+#[test]
+fn test_undo_multiple_operations() {
+    let (dir, path) = setup();
+    for _ in 0..2 {
+        cmd_in(&dir)
+            .args(["toggle", "-S", "feat", "a.py"])
+            .assert()
+            .success();
+    }
+    cmd_in(&dir)
+        .args(["toggle", "-S", "feat", "--force", "off", "a.py"])
+        .assert()
+        .success();
+    cmd_in(&dir).args(["undo", "3"]).assert().success();
+    assert_eq!(fs::read_to_string(&path).unwrap(), SECTION_FILE);
+}
+
+#[test]
+fn test_undo_atomic_multi_file() {
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join("a.py"), SECTION_FILE).unwrap();
+    fs::write(dir.path().join("b.py"), SECTION_FILE).unwrap();
+    cmd_in(&dir)
+        .args(["toggle", "-S", "feat", "--atomic", "a.py", "b.py"])
+        .assert()
+        .success();
+
+    cmd_in(&dir).arg("undo").assert().success();
+    for name in ["a.py", "b.py"] {
+        assert_eq!(
+            fs::read_to_string(dir.path().join(name)).unwrap(),
+            SECTION_FILE
+        );
+    }
+}
+
+#[test]
+fn test_undo_waits_for_the_atomic_lock_before_planning() {
+    let (dir, path) = setup();
+    cmd_in(&dir)
+        .args(["toggle", "-S", "feat", "a.py"])
+        .assert()
+        .success();
+
+    // Another batch holds the lock and, before releasing it, changes the
+    // file. Undo must plan against that content, not what it saw first.
+    let lock_path = dir.path().join(".toggle-atomic.lock");
+    let file_path = path.clone();
+    let (ready_tx, ready_rx) = std::sync::mpsc::channel();
+    let holder = std::thread::spawn(move || {
+        let mut lock = fd_lock::RwLock::new(fs::File::create(&lock_path).unwrap());
+        let guard = lock.write().unwrap();
+        ready_tx.send(()).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(300));
+        fs::write(&file_path, "edited\n").unwrap();
+        drop(guard);
+    });
+    ready_rx.recv().unwrap();
+    cmd_in(&dir)
+        .args(["undo", "--lock-timeout", "10s"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("a.py"));
+    holder.join().unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "edited\n");
+}
+
+#[test]
+fn test_undo_refuses_on_conflict() {
+    let (dir, path) = setup();
+    fs::write(dir.path().join("b.py"), SECTION_FILE).unwrap();
+    cmd_in(&dir)
+        .args(["toggle", "-S", "feat", "a.py", "b.py"])
+        .assert()
+        .success();
+    fs::write(&path, "edited by hand\n").unwrap();
+
+    cmd_in(&dir)
+        .arg("undo")
+        .assert()
+        .code(3)
+        .stderr(predicate::str::contains("changed since"))
+        .stderr(predicate::str::contains("a.py"));
+    // Nothing was reverted, including the unchanged file.
+    assert_eq!(fs::read_to_string(&path).unwrap(), "edited by hand\n");
+    assert_eq!(
+        fs::read_to_string(dir.path().join("b.py")).unwrap(),
+        TOGGLED_FILE
+    );
+}
+
+#[test]
+fn test_undo_conflict_json() {
+    let (dir, path) = setup();
+    cmd_in(&dir)
+        .args(["toggle", "-S", "feat", "a.py"])
+        .assert()
+        .success();
+    fs::write(&path, "edited by hand\n").unwrap();
+
+    let out = cmd_in(&dir)
+        .args(["undo", "--json"])
+        .assert()
+        .failure()
+        .get_output()
+        .stdout
+        .clone();
+    let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+    assert_eq!(json["undone"].as_array().unwrap().len(), 0);
+    assert_eq!(json["conflicts"][0]["record_id"], 1);
+}
+
+#[test]
+fn test_undo_dry_run_does_not_write() {
+    let (dir, path) = setup();
+    cmd_in(&dir)
+        .args(["toggle", "-S", "feat", "a.py"])
+        .assert()
+        .success();
+    cmd_in(&dir)
+        .args(["undo", "--dry-run"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("-# print(\"hi\")"));
+    assert_eq!(fs::read_to_string(&path).unwrap(), TOGGLED_FILE);
+}
+
+#[test]
+fn test_undo_restores_latin1_bytes() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("a.py");
+    let original: &[u8] = b"# toggle:start ID=feat\nprint(\"caf\xe9\")\n# toggle:end ID=feat\n";
+    fs::write(&path, original).unwrap();
+    cmd_in(&dir)
+        .args(["toggle", "-S", "feat", "--encoding", "latin-1", "a.py"])
+        .assert()
+        .success();
+    assert_ne!(fs::read(&path).unwrap(), original);
+
+    cmd_in(&dir).arg("undo").assert().success();
+    assert_eq!(fs::read(&path).unwrap(), original);
+}
+
+#[test]
+fn test_history_lists_records() {
+    let (dir, _path) = setup();
+    cmd_in(&dir)
+        .args(["toggle", "-S", "feat", "a.py"])
+        .assert()
+        .success();
+    cmd_in(&dir)
+        .arg("history")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("#1"))
+        .stdout(predicate::str::contains("toggle -S feat a.py"));
+
+    cmd_in(&dir).arg("undo").assert().success();
+    let out = cmd_in(&dir)
+        .args(["history", "--json"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+    assert_eq!(json[0]["id"], 1);
+    assert_eq!(json[0]["undone"], true);
+}
+
+#[test]
+fn test_dry_run_and_no_history_are_not_recorded() {
+    let (dir, _path) = setup();
+    cmd_in(&dir)
+        .args(["toggle", "-S", "feat", "--dry-run", "a.py"])
+        .assert()
+        .success();
+    cmd_in(&dir)
+        .args(["toggle", "-S", "feat", "--no-history", "a.py"])
+        .assert()
+        .success();
+    assert!(!dir.path().join(".toggle/history.jsonl").exists());
+}
@@ -6,7 +6,10 @@ use tempfile::TempDir;
 
 #[allow(deprecated)]
 fn cmd() -> Command {
-    Command::cargo_bin("toggle").unwrap()
+    let mut c = Command::cargo_bin("toggle").unwrap();
+    // Keep runs from writing .toggle/history.jsonl into the crate directory.
+    c.env("TOGGLE_NO_HISTORY", "1");
+    c
 }
 
 fn setup_temp_file(content: &str, filename: &str) -> (TempDir, std::path::PathBuf) {
@@ -79,6 +82,101 @@ fn test_to_end_without_line_errors() {
         ));
 }
 
+// ── -l anchors, `$`, offsets from the end and lists ──
+
+#[test]
+fn test_line_spec_anchors_and_lists() {
+    let (_dir, path) = setup_temp_file("a\ndef main():\n    run()\nb\nc\nd\n", "test.py");
+    cmd()
+        .args([
+            path.to_str().unwrap(),
+            "-l",
+            "/def main/:+1,-2:$",
+            "--force",
+            "on",
+        ])
+        .assert()
+        .success();
+    let result = fs::read_to_string(&path).unwrap();
+    assert_eq!(result, "a\n# def main():\n    # run()\nb\n# c\n# d\n");
+}
+
+#[test]
+fn test_line_spec_unmatched_anchor_errors() {
+    let (_dir, path) = setup_temp_file("a\nb\n", "test.py");
+    cmd()
+        .args([path.to_str().unwrap(), "-l", "/BEGIN/:/END/"])
+        .assert()
+        .code(1)
+        .stderr(predicates::str::contains(
+            "Invalid pattern '/BEGIN/': no line matches it",
+        ));
+    assert_eq!(fs::read_to_string(&path).unwrap(), "a\nb\n");
+}
+
+#[test]
+fn test_locations_from_stdin_toggle_listed_lines() {
+    let (dir, path) = setup_temp_file("a\nprint(1)\nb\nprint(2)\n", "test.py");
+    fs::write(dir.path().join("other.py"), "x\ny\n").unwrap();
+    cmd()
+        .current_dir(dir.path())
+        .args(["--locations", "-", "--force", "on", "--json"])
+        .write_stdin("./test.py:2:print(1)\ntest.py:4:1: debug\nother.py:2-2\nnote: context\n")
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("\"lines_changed\":2"));
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "a\n# print(1)\nb\n# print(2)\n"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.path().join("other.py")).unwrap(),
+        "x\n# y\n"
+    );
+}
+
+#[test]
+fn test_locations_atomic_refuses_out_of_range_and_writes_nothing() {
+    let (dir, path) = setup_temp_file("a\nb\n", "test.py");
+    fs::write(dir.path().join("short.py"), "x\n").unwrap();
+    cmd()
+        .current_dir(dir.path())
+        .args(["--locations", "-", "--atomic"])
+        .write_stdin("test.py:1\nshort.py:5\n")
+        .assert()
+        .failure();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "a\nb\n");
+    assert_eq!(
+        fs::read_to_string(dir.path().join("short.py")).unwrap(),
+        "x\n"
+    );
+}
+
+#[test]
+fn test_block_at_toggles_indented_block() {
+    let (_dir, path) = setup_temp_file(
+        "import os\n\ndef main():\n    run()\n\n    stop()\n\nmain()\n",
+        "test.py",
+    );
+    cmd()
+        .args(["toggle", path.to_str().unwrap(), "--block-at", "/def main/"])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "import os\n\n# def main():\n    # run()\n\n    # stop()\n\nmain()\n"
+    );
+    // `-l N:block` is the same selection.
+    cmd()
+        .args([path.to_str().unwrap(), "-l", "3:block"])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "import os\n\ndef main():\n    run()\n\n    stop()\n\nmain()\n"
+    );
+}
+
 // ── Multi-line comment support (Phase 2) ──
 
 #[test]
@@ -202,6 +300,466 @@ fn test_interactive_no_skips_modification() {
     );
 }
 
+#[test]
+fn test_file_edited_during_interactive_prompt_is_not_overwritten() {
+    use std::io::{Read, Write};
+    use std::process::{Command as StdCommand, Stdio};
+
+    let (_dir, path) = setup_temp_file("hello\nworld\n", "test.py");
+    let mut child = StdCommand::new(env!("CARGO_BIN_EXE_toggle"))
+        .env("TOGGLE_NO_HISTORY", "1")
+        .args([path.to_str().unwrap(), "-l", "1:2", "--interactive"])
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // Wait until the file has been read and the prompt is showing.
+    let mut stderr = child.stderr.take().unwrap();
+    let mut seen = Vec::new();
+    let mut buf = [0u8; 256];
+    while !String::from_utf8_lossy(&seen).contains("[y/N]") {
+        let n = stderr.read(&mut buf).unwrap();
+        assert!(n > 0, "process exited before prompting");
+        seen.extend_from_slice(&buf[..n]);
+    }
+
+    // Another tool edits the file while toggle waits for an answer.
+    fs::write(&path, "edited elsewhere\n").unwrap();
+    child.stdin.take().unwrap().write_all(b"y\n").unwrap();
+    let status = child.wait().unwrap();
+    let mut rest = String::new();
+    stderr.read_to_string(&mut rest).unwrap();
+
+    assert_eq!(status.code(), Some(5), "stderr: {}", rest);
+    assert!(rest.contains("does not have the expected content"));
+    assert_eq!(fs::read_to_string(&path).unwrap(), "edited elsewhere\n");
+}
+
+// ── --expect-sha256 ──
+
+/// A well-formed digest that matches none of the test inputs.
+const STALE_SHA256: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+fn sha256_of(path: &std::path::Path) -> String {
+    togl_lib::journal::sha256_file(path).unwrap()
+}
+
+#[test]
+fn test_expect_sha256_matching_input_is_processed() {
+    let (_dir, path) = setup_temp_file("hello\nworld\n", "test.py");
+    let hash = sha256_of(&path).to_uppercase();
+    cmd()
+        .args([
+            path.to_str().unwrap(),
+            "-l",
+            "1:1",
+            "--expect-sha256",
+            &hash,
+        ])
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "# hello\nworld\n");
+}
+
+#[test]
+fn test_expect_sha256_mismatch_exits_5() {
+    let (_dir, path) = setup_temp_file("hello\nworld\n", "test.py");
+    for extra in [&[][..], &["--atomic"][..]] {
+        cmd()
+            .args([
+                path.to_str().unwrap(),
+                "-l",
+                "1:1",
+                "--expect-sha256",
+                STALE_SHA256,
+            ])
+            .args(extra)
+            .assert()
+            .code(5)
+            .stderr(predicates::str::contains(
+                "input does not match --expect-sha256",
+            ));
+    }
+    assert_eq!(fs::read_to_string(&path).unwrap(), "hello\nworld\n");
+}
+
+#[test]
+fn test_expect_sha256_mismatch_posix_exit() {
+    let (_dir, path) = setup_temp_file("hello\n", "test.py");
+    cmd()
+        .args([
+            path.to_str().unwrap(),
+            "-l",
+            "1:1",
+            "--expect-sha256",
+            STALE_SHA256,
+            "--posix-exit",
+        ])
+        .assert()
+        .code(75);
+}
+
+#[test]
+fn test_expect_sha256_requires_single_file() {
+    let dir = setup_temp_dir_with_files(&[("a.py", "a\n"), ("b.py", "b\n")]);
+    let hash = sha256_of(&dir.path().join("a.py"));
+    cmd()
+        .args([
+            dir.path().join("a.py").to_str().unwrap(),
+            dir.path().join("b.py").to_str().unwrap(),
+            "-l",
+            "1:1",
+            "--expect-sha256",
+            &hash,
+        ])
+        .assert()
+        .code(1)
+        .stderr(predicates::str::contains(
+            "--expect-sha256 requires exactly one file path",
+        ));
+}
+
+#[test]
+fn test_expect_sha256_rejects_malformed_digest() {
+    let (_dir, path) = setup_temp_file("hello\n", "test.py");
+    cmd()
+        .args([
+            path.to_str().unwrap(),
+            "-l",
+            "1:1",
+            "--expect-sha256",
+            "abc",
+        ])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("expected 64 hexadecimal digits"));
+}
+
+// ── Preflight safety checks ──
+
+const GENERATED_PY: &str = "# @generated by protoc\nx = 1\n";
+
+#[test]
+fn test_recursive_skips_generated_file_with_reason() {
+    let dir = setup_temp_dir_with_files(&[("gen.py", GENERATED_PY), ("src.py", "x = 1\n")]);
+    cmd()
+        .args([dir.path().to_str().unwrap(), "-l", "1:1", "-R"])
+        .assert()
+        .success()
+        .stderr(predicates::str::contains("Skipped"))
+        .stderr(predicates::str::contains("generated file"))
+        .stderr(predicates::str::contains("--allow-generated"));
+    assert_eq!(
+        fs::read_to_string(dir.path().join("gen.py")).unwrap(),
+        GENERATED_PY
+    );
+    assert_eq!(
+        fs::read_to_string(dir.path().join("src.py")).unwrap(),
+        "# x = 1\n"
+    );
+}
+
+#[test]
+fn test_explicit_generated_file_is_refused() {
+    let (_dir, path) = setup_temp_file(GENERATED_PY, "gen.py");
+    cmd()
+        .args([path.to_str().unwrap(), "-l", "2:2"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("Refusing to modify"))
+        .stderr(predicates::str::contains("--allow-generated"));
+    assert_eq!(fs::read_to_string(&path).unwrap(), GENERATED_PY);
+}
+
+#[test]
+fn test_allow_generated_processes_file() {
+    let (_dir, path) = setup_temp_file(GENERATED_PY, "gen.py");
+    cmd()
+        .args([path.to_str().unwrap(), "-l", "2:2", "--allow-generated"])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "# @generated by protoc\n# x = 1\n"
+    );
+}
+
+#[test]
+fn test_json_reports_skipped_reason() {
+    let dir = setup_temp_dir_with_files(&[("gen.py", GENERATED_PY), ("src.py", "x = 1\n")]);
+    let output = cmd()
+        .args([dir.path().to_str().unwrap(), "-l", "1:1", "-R", "--json"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let json: Vec<Value> = serde_json::from_slice(&output.stdout).unwrap();
+    let skipped: Vec<&Value> = json.iter().filter(|r| !r["skipped"].is_null()).collect();
+    assert_eq!(skipped.len(), 1);
+    assert_eq!(skipped[0]["skipped"], "generated");
+    assert_eq!(skipped[0]["success"], false);
+    assert!(skipped[0]["file"].as_str().unwrap().ends_with("gen.py"));
+}
+
+#[test]
+fn test_max_file_size_refuses_large_file() {
+    let (_dir, path) = setup_temp_file("hello\nworld\n", "test.py");
+    cmd()
+        .args([path.to_str().unwrap(), "-l", "1:1", "--max-file-size", "4"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("over the 4 byte limit"));
+    cmd()
+        .args([path.to_str().unwrap(), "-l", "1:1", "--max-file-size", "0"])
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "# hello\nworld\n");
+}
+
+#[test]
+fn test_conflict_markers_and_binary_are_refused() {
+    let (_dir, conflicted) = setup_temp_file(
+        "a = 1\n<<<<<<< HEAD\nb = 2\n=======\nb = 3\n>>>>>>> main\n",
+        "merge.py",
+    );
+    cmd()
+        .args([conflicted.to_str().unwrap(), "-l", "1:1"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("unresolved merge conflict"));
+    cmd()
+        .args([
+            conflicted.to_str().unwrap(),
+            "-l",
+            "1:1",
+            "--allow-conflicts",
+        ])
+        .assert()
+        .success();
+
+    let (_dir, binary) = setup_temp_file("x = 1\n\0\n", "blob.py");
+    cmd()
+        .args([binary.to_str().unwrap(), "-l", "1:1"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("binary file"));
+}
+
+#[test]
+fn test_config_invalid_max_file_size_errors() {
+    let dir = TempDir::new().unwrap();
+    let config_path = dir.path().join("toggle.toml");
+    fs::write(&config_path, "[global]\nmax_file_size = \"bogus\"\n").unwrap();
+    let file_path = dir.path().join("test.py");
+    fs::write(&file_path, "hello\n").unwrap();
+    cmd()
+        .args([
+            file_path.to_str().unwrap(),
+            "-l",
+            "1:1",
+            "--config",
+            config_path.to_str().unwrap(),
+        ])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("invalid size 'bogus'"));
+}
+
+// ── --root confinement ──
+
+/// A temp dir holding `proj/a.py` (inside the root) and `out.py` (outside).
+fn setup_root_dirs() -> (TempDir, std::path::PathBuf) {
+    let dir = TempDir::new().unwrap();
+    let proj = dir.path().join("proj");
+    fs::create_dir(&proj).unwrap();
+    fs::write(proj.join("a.py"), "x = 1\n").unwrap();
+    fs::write(dir.path().join("out.py"), "y = 1\n").unwrap();
+    (dir, proj)
+}
+
+#[test]
+fn test_root_allows_writes_inside() {
+    let (_dir, proj) = setup_root_dirs();
+    cmd()
+        .args(["--root", proj.to_str().unwrap()])
+        .arg(proj.join("a.py"))
+        .args(["-l", "1:1"])
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(proj.join("a.py")).unwrap(), "# x = 1\n");
+}
+
+#[test]
+fn test_root_refuses_dotdot_escape_with_exit_6() {
+    let (dir, proj) = setup_root_dirs();
+    cmd()
+        .args(["--root", proj.to_str().unwrap()])
+        .arg(proj.join("../out.py"))
+        .args(["-l", "1:1"])
+        .assert()
+        .code(6)
+        .stderr(predicates::str::contains("outside the root"));
+    assert_eq!(
+        fs::read_to_string(dir.path().join("out.py")).unwrap(),
+        "y = 1\n"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_root_refuses_symlink_escape() {
+    let (dir, proj) = setup_root_dirs();
+    std::os::unix::fs::symlink("../out.py", proj.join("link.py")).unwrap();
+    for extra in [&["--no-dereference"][..], &[][..]] {
+        cmd()
+            .args(["--root", proj.to_str().unwrap()])
+            .arg(proj.join("link.py"))
+            .args(["-l", "1:1", "--posix-exit"])
+            .args(extra)
+            .assert()
+            .code(77);
+    }
+    assert_eq!(
+        fs::read_to_string(dir.path().join("out.py")).unwrap(),
+        "y = 1\n"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_root_refuses_backup_outside() {
+    let (dir, proj) = setup_root_dirs();
+    std::os::unix::fs::symlink("../stolen", proj.join("a.py.bak")).unwrap();
+    cmd()
+        .args(["--root", proj.to_str().unwrap()])
+        .arg(proj.join("a.py"))
+        .args(["-l", "1:1", "--backup", ".bak"])
+        .assert()
+        .code(6)
+        .stderr(predicates::str::contains("refusing to write backup"));
+    assert!(!dir.path().join("stolen").exists());
+    assert_eq!(fs::read_to_string(proj.join("a.py")).unwrap(), "x = 1\n");
+}
+
+#[test]
+fn test_root_refuses_history_store_outside() {
+    let (dir, proj) = setup_root_dirs();
+    let outside = dir.path().join("outside");
+    fs::create_dir(&outside).unwrap();
+    // History goes in the CWD, which is outside the root.
+    cmd()
+        .env_remove("TOGGLE_NO_HISTORY")
+        .current_dir(&outside)
+        .args(["--root", "../proj", "../proj/a.py", "-l", "1:1"])
+        .assert()
+        .code(6)
+        .stderr(predicates::str::contains("refusing to write history store"));
+    assert!(!outside.join(".toggle").exists());
+    assert_eq!(fs::read_to_string(proj.join("a.py")).unwrap(), "x = 1\n");
+
+    cmd()
+        .env_remove("TOGGLE_NO_HISTORY")
+        .current_dir(&outside)
+        .args([
+            "--root",
+            "../proj",
+            "../proj/a.py",
+            "-l",
+            "1:1",
+            "--no-history",
+        ])
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(proj.join("a.py")).unwrap(), "# x = 1\n");
+}
+
+#[test]
+fn test_root_refuses_atomic_journal_outside() {
+    let (dir, proj) = setup_root_dirs();
+    // The journal goes in the CWD, which is outside the root.
+    cmd()
+        .current_dir(dir.path())
+        .args(["--root", "proj", "proj/a.py", "-l", "1:1", "--atomic"])
+        .assert()
+        .code(6)
+        .stderr(predicates::str::contains("refusing to write journal"));
+    assert_eq!(fs::read_to_string(proj.join("a.py")).unwrap(), "x = 1\n");
+    assert!(!dir.path().join(".toggle-atomic.lock").exists());
+
+    cmd()
+        .current_dir(&proj)
+        .args(["--root", ".", "a.py", "-l", "1:1", "--atomic"])
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(proj.join("a.py")).unwrap(), "# x = 1\n");
+}
+
+#[test]
+fn test_root_atomic_batch_with_escape_writes_nothing() {
+    let (dir, proj) = setup_root_dirs();
+    cmd()
+        .current_dir(&proj)
+        .args(["--root", ".", "a.py", "../out.py", "-l", "1:1", "--atomic"])
+        .assert()
+        .code(6);
+    assert_eq!(fs::read_to_string(proj.join("a.py")).unwrap(), "x = 1\n");
+    assert_eq!(
+        fs::read_to_string(dir.path().join("out.py")).unwrap(),
+        "y = 1\n"
+    );
+}
+
+#[test]
+fn test_root_violation_in_json() {
+    let (_dir, proj) = setup_root_dirs();
+    let output = cmd()
+        .args(["--root", proj.to_str().unwrap()])
+        .arg(proj.join("a.py"))
+        .arg(proj.join("../out.py"))
+        .args(["-l", "1:1", "--json"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(6));
+    let json: Vec<Value> = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json.len(), 2);
+    let refused = json
+        .iter()
+        .find(|r| r["file"].as_str().unwrap().ends_with("out.py"))
+        .unwrap();
+    assert_eq!(refused["success"], false);
+    assert_eq!(refused["outside_root"], "target");
+    assert!(json.iter().any(|r| r["success"] == true));
+}
+
+#[test]
+fn test_root_from_config_is_relative_to_config_file() {
+    let (dir, proj) = setup_root_dirs();
+    let config_path = proj.join("toggle.toml");
+    fs::write(&config_path, "[global]\nroot = \".\"\n").unwrap();
+    cmd()
+        .arg(dir.path().join("out.py"))
+        .args(["-l", "1:1", "--config", config_path.to_str().unwrap()])
+        .assert()
+        .code(6);
+    cmd()
+        .arg(proj.join("a.py"))
+        .args(["-l", "1:1", "--config", config_path.to_str().unwrap()])
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_root_must_exist() {
+    let (_dir, proj) = setup_root_dirs();
+    cmd()
+        .args(["--root", "/nonexistent/toggle/root"])
+        .arg(proj.join("a.py"))
+        .args(["-l", "1:1"])
+        .assert()
+        .code(1)
+        .stderr(predicates::str::contains("invalid root"));
+}
+
 // ── Line range toggling ──
 
 #[test]
@@ -388,32 +946,165 @@ fn test_backup_creates_backup_file() {
     assert!(backup_path.exists(), "backup file should exist");
     let backup_content = fs::read_to_string(&backup_path).unwrap();
     assert_eq!(
-        backup_content, original,
-        "backup should contain original content"
+        backup_content, original,
+        "backup should contain original content"
+    );
+    let modified = fs::read_to_string(&path).unwrap();
+    assert!(modified.contains("#"), "original file should be toggled");
+}
+
+#[test]
+fn test_backup_with_dry_run_skips_backup() {
+    let (_dir, path) = setup_temp_file("hello\nworld\n", "test.py");
+    cmd()
+        .args([
+            path.to_str().unwrap(),
+            "-l",
+            "1:2",
+            "--backup",
+            ".bak",
+            "--dry-run",
+        ])
+        .assert()
+        .success();
+    let backup_path = path.with_file_name("test.py.bak");
+    assert!(
+        !backup_path.exists(),
+        "backup should not be created in dry-run mode"
+    );
+}
+
+#[test]
+fn test_backup_mode_numbered_keeps_every_version() {
+    let (dir, path) = setup_temp_file("x = 1\n", "test.py");
+    for _ in 0..3 {
+        cmd()
+            .arg(&path)
+            .args(["-l", "1:1", "--backup-mode", "numbered"])
+            .assert()
+            .success();
+    }
+    for (n, content) in [(1, "x = 1\n"), (2, "# x = 1\n"), (3, "x = 1\n")] {
+        let backup = dir.path().join(format!("test.py.~{}~", n));
+        assert_eq!(fs::read_to_string(backup).unwrap(), content);
+    }
+}
+
+#[test]
+fn test_backup_keep_limits_numbered_backups() {
+    let (dir, path) = setup_temp_file("x = 1\n", "test.py");
+    for _ in 0..4 {
+        cmd()
+            .arg(&path)
+            .args([
+                "-l",
+                "1:1",
+                "--backup-mode",
+                "numbered",
+                "--backup-keep",
+                "2",
+            ])
+            .assert()
+            .success();
+    }
+    assert!(!dir.path().join("test.py.~2~").exists());
+    assert!(dir.path().join("test.py.~3~").exists());
+    assert!(dir.path().join("test.py.~4~").exists());
+}
+
+#[test]
+fn test_backup_dir_mirrors_tree_relative_to_cwd() {
+    let dir = setup_temp_dir_with_files(&[("src/a.py", "x = 1\n")]);
+    cmd()
+        .current_dir(dir.path())
+        .args(["src/a.py", "-l", "1:1", "--backup-dir", "bk"])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(dir.path().join("bk/src/a.py~")).unwrap(),
+        "x = 1\n"
+    );
+    assert!(!dir.path().join("src/a.py~").exists());
+}
+
+#[test]
+fn test_backup_mode_conflicts_with_atomic() {
+    let (_dir, path) = setup_temp_file("x = 1\n", "test.py");
+    cmd()
+        .arg(&path)
+        .args(["-l", "1:1", "--atomic", "--backup-mode", "numbered"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("cannot be used with"));
+}
+
+#[test]
+fn test_backups_list_restore_prune() {
+    let dir = setup_temp_dir_with_files(&[("src/a.py", "x = 1\n")]);
+    let run = |args: &[&str]| cmd().current_dir(dir.path()).args(args).assert().success();
+    for _ in 0..3 {
+        run(&[
+            "src/a.py",
+            "-l",
+            "1:1",
+            "--backup-mode",
+            "numbered",
+            "--backup-dir",
+            "bk",
+        ]);
+    }
+
+    let out = run(&["backups", "list", "--backup-dir", "bk", "--json"]);
+    let json: Vec<Value> = serde_json::from_slice(&out.get_output().stdout).unwrap();
+    let numbers: Vec<u64> = json.iter().map(|b| b["number"].as_u64().unwrap()).collect();
+    assert_eq!(numbers, [1, 2, 3]);
+    assert!(json.iter().all(|b| b["original"] == "src/a.py"));
+    assert_eq!(json[0]["kind"], "numbered");
+
+    // Newest backup (#3) holds the content before the third toggle.
+    run(&["backups", "restore", "src/a.py", "--backup-dir", "bk"]);
+    assert_eq!(
+        fs::read_to_string(dir.path().join("src/a.py")).unwrap(),
+        "x = 1\n"
     );
-    let modified = fs::read_to_string(&path).unwrap();
-    assert!(modified.contains("#"), "original file should be toggled");
+    run(&[
+        "backups",
+        "restore",
+        "src/a.py",
+        "--backup-dir",
+        "bk",
+        "--from",
+        "bk/src/a.py.~2~",
+    ]);
+    assert_eq!(
+        fs::read_to_string(dir.path().join("src/a.py")).unwrap(),
+        "# x = 1\n"
+    );
+
+    run(&[
+        "backups",
+        "prune",
+        "--keep",
+        "1",
+        "--backup-dir",
+        "bk",
+        "--dry-run",
+    ]);
+    assert!(dir.path().join("bk/src/a.py.~1~").exists());
+    run(&["backups", "prune", "--keep", "1", "--backup-dir", "bk"]);
+    assert!(!dir.path().join("bk/src/a.py.~1~").exists());
+    assert!(!dir.path().join("bk/src/a.py.~2~").exists());
+    assert!(dir.path().join("bk/src/a.py.~3~").exists());
 }
 
 #[test]
-fn test_backup_with_dry_run_skips_backup() {
-    let (_dir, path) = setup_temp_file("hello\nworld\n", "test.py");
+fn test_backups_restore_without_backups_fails() {
+    let (_dir, path) = setup_temp_file("x = 1\n", "test.py");
     cmd()
-        .args([
-            path.to_str().unwrap(),
-            "-l",
-            "1:2",
-            "--backup",
-            ".bak",
-            "--dry-run",
-        ])
+        .args(["backups", "restore", path.to_str().unwrap()])
         .assert()
-        .success();
-    let backup_path = path.with_file_name("test.py.bak");
-    assert!(
-        !backup_path.exists(),
-        "backup should not be created in dry-run mode"
-    );
+        .code(1)
+        .stderr(predicates::str::contains("no backups found"));
 }
 
 // ── --config ──
@@ -572,6 +1263,32 @@ fn test_eol_preserve_keeps_original() {
     assert!(!result.contains(&b'\r'), "preserve should not introduce CR");
 }
 
+#[test]
+fn test_eol_preserve_keeps_crlf_per_line() {
+    let (_dir, path) = setup_temp_file("line1\r\nline2\nline3\r\nline4", "test.py");
+    cmd()
+        .args([path.to_str().unwrap(), "-l", "2:3"])
+        .assert()
+        .success();
+    let result = fs::read(&path).unwrap();
+    assert_eq!(result, b"line1\r\n# line2\n# line3\r\nline4");
+}
+
+#[test]
+fn test_eol_preserve_section_toggle_crlf() {
+    let original = "# toggle:start ID=feat\r\nx = 1\r\n# toggle:end ID=feat\r\ntail\r\n";
+    let (_dir, path) = setup_temp_file(original, "test.py");
+    cmd()
+        .args([path.to_str().unwrap(), "-S", "feat"])
+        .assert()
+        .success();
+    let result = fs::read_to_string(&path).unwrap();
+    assert_eq!(
+        result,
+        "# toggle:start ID=feat\r\n# x = 1\r\n# toggle:end ID=feat\r\ntail\r\n"
+    );
+}
+
 // ── --no-dereference ──
 
 #[cfg(unix)]
@@ -621,6 +1338,109 @@ fn test_default_replaces_symlink() {
     );
 }
 
+// ── File metadata and --preserve-mtime ──
+
+fn set_old_mtime(path: &std::path::Path) -> std::time::SystemTime {
+    let old = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000_000);
+    let file = fs::File::options().write(true).open(path).unwrap();
+    file.set_modified(old).unwrap();
+    old
+}
+
+#[test]
+fn test_preserve_mtime_keeps_modification_time() {
+    let (_dir, path) = setup_temp_file("hello\nworld\n", "test.py");
+    let old = set_old_mtime(&path);
+
+    cmd()
+        .args([path.to_str().unwrap(), "-l", "1:2", "--preserve-mtime"])
+        .assert()
+        .success();
+
+    assert!(fs::read_to_string(&path).unwrap().contains("# hello"));
+    assert_eq!(fs::metadata(&path).unwrap().modified().unwrap(), old);
+}
+
+#[test]
+fn test_preserve_mtime_atomic() {
+    let dir = setup_temp_dir_with_files(&[("a.py", "a\n"), ("b.py", "b\n")]);
+    let a = dir.path().join("a.py");
+    let b = dir.path().join("b.py");
+    let old = set_old_mtime(&a);
+    set_old_mtime(&b);
+
+    cmd()
+        .args([a.to_str().unwrap(), b.to_str().unwrap()])
+        .args(["-l", "1:1", "--atomic", "--preserve-mtime"])
+        .assert()
+        .success();
+
+    for path in [&a, &b] {
+        assert!(fs::read_to_string(path).unwrap().starts_with('#'));
+        assert_eq!(fs::metadata(path).unwrap().modified().unwrap(), old);
+    }
+}
+
+#[test]
+fn test_mtime_updated_without_preserve_mtime() {
+    let (_dir, path) = setup_temp_file("hello\n", "test.py");
+    let old = set_old_mtime(&path);
+
+    cmd()
+        .args([path.to_str().unwrap(), "-l", "1:1"])
+        .assert()
+        .success();
+
+    assert_ne!(fs::metadata(&path).unwrap().modified().unwrap(), old);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_write_keeps_file_mode() {
+    use std::os::unix::fs::PermissionsExt;
+    for extra in [&[][..], &["-t", "tmp"][..], &["--atomic"][..]] {
+        let (_dir, path) = setup_temp_file("hello\n", "run.py");
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+
+        cmd()
+            .args([path.to_str().unwrap(), "-l", "1:1"])
+            .args(extra)
+            .assert()
+            .success();
+
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o755, "args {:?}", extra);
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_hardlinked_file_warns() {
+    let (dir, path) = setup_temp_file("hello\n", "test.py");
+    let other = dir.path().join("other.py");
+    fs::hard_link(&path, &other).unwrap();
+
+    cmd()
+        .args([path.to_str().unwrap(), "-l", "1:1"])
+        .assert()
+        .success()
+        .stderr(predicates::str::contains("has 2 hard links"));
+
+    // The other link is split off and keeps the old content.
+    assert_eq!(fs::read_to_string(&other).unwrap(), "hello\n");
+    assert!(fs::read_to_string(&path).unwrap().starts_with('#'));
+}
+
+#[test]
+fn test_single_link_does_not_warn() {
+    let (_dir, path) = setup_temp_file("hello\n", "test.py");
+    cmd()
+        .args([path.to_str().unwrap(), "-l", "1:1"])
+        .assert()
+        .success()
+        .stderr(predicates::str::contains("hard links").not());
+}
+
 #[test]
 fn test_eol_invalid_value_errors() {
     let (_dir, path) = setup_temp_file("hello\n", "test.py");
@@ -631,6 +1451,17 @@ fn test_eol_invalid_value_errors() {
         .stderr(predicates::str::contains("Invalid --eol value"));
 }
 
+#[test]
+fn test_mode_invalid_value_errors() {
+    let (_dir, path) = setup_temp_file("hello\n", "test.py");
+    cmd()
+        .args([path.to_str().unwrap(), "-l", "1:1", "--mode", "block"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("Invalid --mode value 'block'"));
+    assert_eq!(fs::read_to_string(&path).unwrap(), "hello\n");
+}
+
 // ── --encoding ──
 
 #[test]
@@ -674,6 +1505,112 @@ fn test_encoding_invalid_errors() {
         .stderr(predicates::str::contains("Unsupported encoding"));
 }
 
+#[test]
+fn test_encoding_utf8_bom_preserved_when_toggling_first_line() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("test.py");
+    fs::write(&path, b"\xEF\xBB\xBFhello\nworld\n").unwrap();
+    cmd()
+        .args([path.to_str().unwrap(), "-l", "1:1"])
+        .assert()
+        .success();
+    assert_eq!(fs::read(&path).unwrap(), b"\xEF\xBB\xBF# hello\nworld\n");
+}
+
+#[test]
+fn test_encoding_auto_utf16le_roundtrip_with_json() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("test.py");
+    let encode = |s: &str| {
+        let mut bytes = vec![0xFF, 0xFE];
+        bytes.extend(s.encode_utf16().flat_map(u16::to_le_bytes));
+        bytes
+    };
+    fs::write(&path, encode("a\nb\n")).unwrap();
+    let output = cmd()
+        .args([path.to_str().unwrap(), "-l", "1:1", "-e", "auto", "--json"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let json: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json[0]["encoding"], "UTF-16LE");
+    assert_eq!(json[0]["bom"], true);
+    assert_eq!(fs::read(&path).unwrap(), encode("# a\nb\n"));
+}
+
+#[test]
+fn test_encoding_auto_falls_back_to_legacy() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("test.py");
+    fs::write(&path, [0x63, 0x61, 0x66, 0xe9, 0x0a]).unwrap();
+    cmd()
+        .args([path.to_str().unwrap(), "-l", "1:1", "--encoding", "auto"])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read(&path).unwrap(),
+        [0x23, 0x20, 0x63, 0x61, 0x66, 0xe9, 0x0a]
+    );
+}
+
+#[test]
+fn test_recursive_toggle_skips_undecodable_files() {
+    let section = "# toggle:start ID=feat\nx = 1\n# toggle:end ID=feat\n";
+    let dir = setup_temp_dir_with_files(&[("a.py", section)]);
+    fs::write(dir.path().join("b.py"), b"name = '\xe9'\n").unwrap();
+    cmd()
+        .current_dir(dir.path())
+        .args(["-R", "-S", "feat", "."])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(dir.path().join("a.py")).unwrap(),
+        "# toggle:start ID=feat\n# x = 1\n# toggle:end ID=feat\n"
+    );
+}
+
+#[test]
+fn test_encoding_per_path_config_rule() {
+    let dir = TempDir::new().unwrap();
+    fs::create_dir(dir.path().join("legacy")).unwrap();
+    let path = dir.path().join("legacy").join("old.py");
+    fs::write(&path, [0x63, 0x61, 0x66, 0xe9, 0x0a]).unwrap();
+    let config_path = dir.path().join(".toggleConfig");
+    fs::write(
+        &config_path,
+        "[[encoding.path]]\nglob = \"**/legacy/*.py\"\nencoding = \"latin-1\"\n",
+    )
+    .unwrap();
+    // An explicit --encoding, even the default, overrides the rule.
+    cmd()
+        .args([
+            path.to_str().unwrap(),
+            "-l",
+            "1:1",
+            "--encoding",
+            "utf-8",
+            "--config",
+            config_path.to_str().unwrap(),
+        ])
+        .assert()
+        .code(2);
+    assert_eq!(fs::read(&path).unwrap(), [0x63, 0x61, 0x66, 0xe9, 0x0a]);
+    cmd()
+        .args([
+            path.to_str().unwrap(),
+            "-l",
+            "1:1",
+            "--config",
+            config_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read(&path).unwrap(),
+        [0x23, 0x20, 0x63, 0x61, 0x66, 0xe9, 0x0a]
+    );
+}
+
 #[test]
 fn test_config_global_delimiter_fallback() {
     let dir = TempDir::new().unwrap();
@@ -1517,6 +2454,32 @@ fn test_scan_json_output() {
     assert!(files[0]["end"].is_number());
 }
 
+#[test]
+fn test_scan_reports_line_counts() {
+    let dir = TempDir::new().unwrap();
+    let content = "# toggle:start ID=mix\n# a\nb\nc\n\n# toggle:end ID=mix\n";
+    let file = dir.path().join("file.py");
+    fs::write(&file, content).unwrap();
+
+    let output = cmd()
+        .args([file.to_str().unwrap(), "--scan", "--json"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let json: Value = serde_json::from_slice(&output.stdout).unwrap();
+    let f = &json["sections"][0]["files"][0];
+    assert_eq!(f["state"], "mixed");
+    assert_eq!(f["line_counts"]["commented"], 1);
+    assert_eq!(f["line_counts"]["live"], 2);
+    assert_eq!(f["line_counts"]["blank"], 1);
+
+    cmd()
+        .args([file.to_str().unwrap(), "--scan"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("mixed (2/3 lines live)"));
+}
+
 #[test]
 fn test_scan_empty_directory() {
     let dir = TempDir::new().unwrap();
@@ -1668,6 +2631,86 @@ fn test_atomic_implies_backup_by_default() {
     assert!(!dir.path().join("a.py.toggle-atomic-backup").exists());
 }
 
+#[test]
+fn test_atomic_backup_strategy_copy() {
+    let dir = setup_temp_dir_with_files(&[("a.py", "hello\n")]);
+    cmd()
+        .current_dir(dir.path())
+        .args([
+            dir.path().join("a.py").to_str().unwrap(),
+            "-l",
+            "1:1",
+            "--atomic",
+            "--backup-strategy",
+            "copy",
+        ])
+        .assert()
+        .success();
+    let a = fs::read_to_string(dir.path().join("a.py")).unwrap();
+    assert_eq!(a, "# hello\n");
+    assert!(!dir.path().join("a.py.toggle-atomic-backup").exists());
+}
+
+#[test]
+fn test_backup_strategy_requires_atomic() {
+    let dir = setup_temp_dir_with_files(&[("a.py", "hello\n")]);
+    cmd()
+        .current_dir(dir.path())
+        .args([
+            dir.path().join("a.py").to_str().unwrap(),
+            "-l",
+            "1:1",
+            "--backup-strategy",
+            "copy",
+        ])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "required arguments were not provided",
+        ));
+}
+
+#[test]
+fn test_backup_strategy_rejects_unknown_value() {
+    let dir = setup_temp_dir_with_files(&[("a.py", "hello\n")]);
+    cmd()
+        .current_dir(dir.path())
+        .args([
+            dir.path().join("a.py").to_str().unwrap(),
+            "-l",
+            "1:1",
+            "--atomic",
+            "--backup-strategy",
+            "symlink",
+        ])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("reflink-if-possible"));
+}
+
+#[test]
+fn test_config_backup_strategy_is_validated() {
+    let dir = setup_temp_dir_with_files(&[
+        ("a.py", "hello\n"),
+        ("toggle.toml", "[global]\nbackup_strategy = \"symlink\"\n"),
+    ]);
+    cmd()
+        .current_dir(dir.path())
+        .args([
+            dir.path().join("a.py").to_str().unwrap(),
+            "-l",
+            "1:1",
+            "--atomic",
+            "--config",
+            dir.path().join("toggle.toml").to_str().unwrap(),
+        ])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "unknown backup_strategy 'symlink'",
+        ));
+}
+
 #[test]
 fn test_atomic_no_backup_flag() {
     let dir = setup_temp_dir_with_files(&[("a.py", "hello\n")]);
@@ -1789,6 +2832,44 @@ fn test_recover_no_journal_is_noop() {
     assert!(stderr.contains("No journal found"));
 }
 
+#[test]
+fn test_leftover_journal_blocks_writes_only_where_it_lives() {
+    let dir = TempDir::new().unwrap();
+    let sub = dir.path().join("sub");
+    fs::create_dir(&sub).unwrap();
+    let target = sub.join("a.py");
+    fs::write(&target, "x\n").unwrap();
+    let journal = togl_lib::journal::Journal::new(Vec::new(), false);
+    togl_lib::journal::persist_journal(&journal, &dir.path().join(".toggle-atomic.journal"))
+        .unwrap();
+
+    // A journal in a parent directory belongs to someone else.
+    cmd()
+        .current_dir(&sub)
+        .args(["a.py", "-l", "1:1"])
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(&target).unwrap(), "# x\n");
+
+    // In its own directory it blocks writes, but not read-only runs.
+    cmd()
+        .current_dir(dir.path())
+        .args(["sub/a.py", "-l", "1:1"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("was interrupted"));
+    cmd()
+        .current_dir(dir.path())
+        .args(["--scan", "sub/a.py"])
+        .assert()
+        .success();
+    cmd()
+        .current_dir(dir.path())
+        .args(["sub/a.py", "-l", "1:1", "--dry-run"])
+        .assert()
+        .success();
+}
+
 #[test]
 fn test_recover_from_staged_journal() {
     // Manually create a journal in Staged state with temp files
@@ -1805,6 +2886,8 @@ fn test_recover_from_staged_journal() {
             backup_path: None,
             content_sha256: "xxx".to_string(),
             rename_completed: false,
+            original_sha256: None,
+            backup_method: None,
         }],
         false,
     );
@@ -1828,6 +2911,198 @@ fn test_recover_from_staged_journal() {
     );
 }
 
+#[test]
+fn test_atomic_across_directories_leaves_no_pointers() {
+    let dir = TempDir::new().unwrap();
+    let checkout = dir.path().join("checkout");
+    let config = dir.path().join("config");
+    fs::create_dir_all(&checkout).unwrap();
+    fs::create_dir_all(&config).unwrap();
+    fs::write(checkout.join("a.py"), "hello\n").unwrap();
+    fs::write(config.join("b.py"), "world\n").unwrap();
+
+    cmd()
+        .current_dir(&checkout)
+        .args([
+            "a.py",
+            config.join("b.py").to_str().unwrap(),
+            "-l",
+            "1:1",
+            "--atomic",
+        ])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(checkout.join("a.py")).unwrap(),
+        "# hello\n"
+    );
+    assert_eq!(
+        fs::read_to_string(config.join("b.py")).unwrap(),
+        "# world\n"
+    );
+    assert!(!checkout.join(".toggle-atomic.journal").exists());
+    assert!(!config.join(".toggle-atomic.journal-ref").exists());
+}
+
+#[test]
+fn test_recover_from_another_directory_of_the_batch() {
+    use togl_lib::journal::{persist_journal, write_journal_refs, Journal, JournalEntry};
+    let dir = TempDir::new().unwrap();
+    let checkout = dir.path().join("checkout");
+    let config = dir.path().join("config");
+    fs::create_dir_all(&checkout).unwrap();
+    fs::create_dir_all(&config).unwrap();
+    let entry = |target: std::path::PathBuf| {
+        let temp = target.with_extension("tmp");
+        fs::write(&target, "original\n").unwrap();
+        fs::write(&temp, "staged\n").unwrap();
+        JournalEntry {
+            target_path: target,
+            temp_path: temp,
+            backup_path: None,
+            backup_method: None,
+            content_sha256: "xxx".to_string(),
+            rename_completed: false,
+            original_sha256: None,
+        }
+    };
+    let journal = Journal::new(
+        vec![entry(checkout.join("a.py")), entry(config.join("b.py"))],
+        false,
+    );
+    let journal_path = checkout.join(".toggle-atomic.journal");
+    persist_journal(&journal, &journal_path).unwrap();
+    write_journal_refs(&journal, &journal_path).unwrap();
+
+    // A plain run in the other directory is blocked by the leftover journal.
+    cmd()
+        .current_dir(&config)
+        .args(["b.py", "-l", "1:1"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("interrupted"));
+
+    cmd()
+        .current_dir(&config)
+        .args(["b.py", "--recover"])
+        .assert()
+        .success();
+    assert!(!journal_path.exists());
+    assert!(!config.join(".toggle-atomic.journal-ref").exists());
+    assert!(!checkout.join("a.tmp").exists());
+    assert!(!config.join("b.tmp").exists());
+    assert_eq!(
+        fs::read_to_string(config.join("b.py")).unwrap(),
+        "original\n"
+    );
+}
+
+/// Persist a Committing journal for `a.py` in `dir` whose temp file holds the
+/// staged content and whose backup holds the original.
+fn write_committing_journal(dir: &std::path::Path, renamed: bool) {
+    use togl_lib::journal::{sha256_hex, Journal, JournalEntry};
+    let target = dir.join("a.py");
+    let temp = dir.join(".tmp-a");
+    let backup = dir.join("a.py.toggle-atomic-backup");
+    fs::write(&target, if renamed { "new\n" } else { "old\n" }).unwrap();
+    if !renamed {
+        fs::write(&temp, "new\n").unwrap();
+    }
+    fs::write(&backup, "old\n").unwrap();
+    let mut journal = Journal::new(
+        vec![JournalEntry {
+            target_path: target,
+            temp_path: temp,
+            backup_path: Some(backup),
+            content_sha256: sha256_hex(b"new\n"),
+            rename_completed: renamed,
+            original_sha256: Some(sha256_hex(b"old\n")),
+            backup_method: None,
+        }],
+        true,
+    );
+    journal.transition_to_committing();
+    togl_lib::journal::persist_journal(&journal, &dir.join(".toggle-atomic.journal")).unwrap();
+}
+
+#[test]
+fn test_journal_show_without_journal() {
+    let dir = TempDir::new().unwrap();
+    cmd()
+        .current_dir(dir.path())
+        .args(["journal", "show"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("No journal found"));
+}
+
+#[test]
+fn test_journal_show_lists_entries() {
+    let dir = TempDir::new().unwrap();
+    write_committing_journal(dir.path(), false);
+    cmd()
+        .current_dir(dir.path())
+        .args(["journal", "show"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("Status: Committing"))
+        .stdout(predicates::str::contains("[pending]"))
+        .stdout(predicates::str::contains("a.py.toggle-atomic-backup"));
+
+    let out = cmd()
+        .current_dir(dir.path())
+        .args(["journal", "show", "--json"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+    assert_eq!(json["journal"]["status"], "Committing");
+    assert_eq!(json["journal"]["entries"][0]["rename_completed"], false);
+}
+
+#[test]
+fn test_journal_verify_recommends_recovery() {
+    let dir = TempDir::new().unwrap();
+    write_committing_journal(dir.path(), true);
+    cmd()
+        .current_dir(dir.path())
+        .args(["journal", "verify"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("Recommendation: rollback"));
+
+    let out = cmd()
+        .current_dir(dir.path())
+        .args(["journal", "verify", "--json"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+    assert_eq!(json["rollback_safe"], true);
+    assert_eq!(json["forward_safe"], true);
+    assert_eq!(json["entries"][0]["backup_ok"], true);
+    // Verification is read-only.
+    assert!(dir.path().join(".toggle-atomic.journal").exists());
+}
+
+#[test]
+fn test_journal_verify_fails_when_no_recovery_is_safe() {
+    let dir = TempDir::new().unwrap();
+    write_committing_journal(dir.path(), true);
+    fs::remove_file(dir.path().join("a.py.toggle-atomic-backup")).unwrap();
+    fs::write(dir.path().join("a.py"), "edited\n").unwrap();
+    cmd()
+        .current_dir(dir.path())
+        .args(["journal", "verify"])
+        .assert()
+        .code(3)
+        .stdout(predicates::str::contains("Recommendation: manual"));
+}
+
 #[test]
 fn test_journal_blocks_new_operations() {
     // Create a leftover journal
@@ -1883,6 +3158,236 @@ fn test_atomic_single_file() {
     assert_eq!(a, "x\n# y\nz\n");
 }
 
+#[test]
+fn test_atomic_remove_recursive() {
+    let section = "keep\n# toggle:start ID=dbg\nprint(1)\n# toggle:end ID=dbg\n";
+    let dir = setup_temp_dir_with_files(&[("a.py", section), ("sub/b.py", section)]);
+    cmd()
+        .current_dir(dir.path())
+        .args([
+            "--remove",
+            "-S",
+            "dbg",
+            "--remove-mode",
+            "all",
+            "-R",
+            "--atomic",
+            dir.path().to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+    for name in ["a.py", "sub/b.py"] {
+        assert_eq!(fs::read_to_string(dir.path().join(name)).unwrap(), "keep\n");
+    }
+    assert!(!dir.path().join(".toggle-atomic.journal").exists());
+    assert!(!dir.path().join(".toggle-atomic.lock").exists());
+    assert!(!dir.path().join("a.py.toggle-atomic-backup").exists());
+}
+
+#[test]
+fn test_atomic_remove_blocked_by_leftover_journal() {
+    let section = "# toggle:start ID=dbg\nprint(1)\n# toggle:end ID=dbg\n";
+    let dir = setup_temp_dir_with_files(&[("a.py", section)]);
+    fs::write(dir.path().join(".toggle-atomic.journal"), "{}").unwrap();
+    cmd()
+        .current_dir(dir.path())
+        .args([
+            "--remove",
+            "-S",
+            "dbg",
+            "--atomic",
+            dir.path().join("a.py").to_str().unwrap(),
+        ])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("previous atomic operation"));
+    assert_eq!(
+        fs::read_to_string(dir.path().join("a.py")).unwrap(),
+        section
+    );
+}
+
+#[test]
+fn test_atomic_insert() {
+    let dir = setup_temp_dir_with_files(&[("a.py", "x\ny\nz\n")]);
+    let output = cmd()
+        .current_dir(dir.path())
+        .args([
+            "--insert",
+            "-S",
+            "feat",
+            "-l",
+            "2:2",
+            "--atomic",
+            "-v",
+            dir.path().join("a.py").to_str().unwrap(),
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Atomic commit successful"));
+    let a = fs::read_to_string(dir.path().join("a.py")).unwrap();
+    assert_eq!(a, "x\n# toggle:start ID=feat\ny\n# toggle:end ID=feat\nz\n");
+    assert!(!dir.path().join(".toggle-atomic.journal").exists());
+}
+
+/// Lock holder thread started by `hold_atomic_lock`.
+struct LockHolder {
+    release: std::sync::mpsc::Sender<()>,
+    handle: std::thread::JoinHandle<()>,
+}
+
+impl LockHolder {
+    fn release(self) {
+        let _ = self.release.send(());
+        self.handle.join().unwrap();
+    }
+}
+
+/// Hold the atomic lock in `dir` from this process for at most `hold`, with
+/// holder metadata like a real `togl` run would write.
+fn hold_atomic_lock(dir: &std::path::Path, hold: std::time::Duration) -> LockHolder {
+    let lock_path = dir.join(".toggle-atomic.lock");
+    let (ready_tx, ready_rx) = std::sync::mpsc::channel();
+    let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+    let handle = std::thread::spawn(move || {
+        let file = fs::File::create(&lock_path).unwrap();
+        let mut lock = fd_lock::RwLock::new(file);
+        let mut guard = lock.write().unwrap();
+        use std::io::Write;
+        write!(
+            guard,
+            r#"{{"pid":{},"started_at":"0s-since-epoch","command":"togl toggle --atomic"}}"#,
+            std::process::id()
+        )
+        .unwrap();
+        ready_tx.send(()).unwrap();
+        let _ = release_rx.recv_timeout(hold);
+        drop(guard);
+    });
+    ready_rx.recv().unwrap();
+    LockHolder {
+        release: release_tx,
+        handle,
+    }
+}
+
+#[test]
+fn test_atomic_lock_busy_reports_holder() {
+    let dir = setup_temp_dir_with_files(&[("a.py", "hello\n")]);
+    let holder = hold_atomic_lock(dir.path(), std::time::Duration::from_secs(30));
+    cmd()
+        .current_dir(dir.path())
+        .args(["a.py", "-l", "1:1", "--atomic"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(format!(
+            "Held by PID {}",
+            std::process::id()
+        )))
+        .stderr(predicates::str::contains("togl toggle --atomic"));
+    holder.release();
+    assert_eq!(
+        fs::read_to_string(dir.path().join("a.py")).unwrap(),
+        "hello\n"
+    );
+}
+
+#[test]
+fn test_atomic_lock_timeout_waits_for_holder() {
+    let dir = setup_temp_dir_with_files(&[("a.py", "hello\n")]);
+    let holder = hold_atomic_lock(dir.path(), std::time::Duration::from_millis(300));
+    cmd()
+        .current_dir(dir.path())
+        .args(["a.py", "-l", "1:1", "--atomic", "--lock-timeout", "10s"])
+        .assert()
+        .success();
+    holder.release();
+    assert_eq!(
+        fs::read_to_string(dir.path().join("a.py")).unwrap(),
+        "# hello\n"
+    );
+}
+
+#[test]
+fn test_atomic_lock_timeout_expires() {
+    let dir = setup_temp_dir_with_files(&[("a.py", "hello\n")]);
+    let holder = hold_atomic_lock(dir.path(), std::time::Duration::from_secs(30));
+    cmd()
+        .current_dir(dir.path())
+        .args(["a.py", "-l", "1:1", "--atomic", "--lock-timeout", "200ms"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("Gave up after waiting"));
+    holder.release();
+}
+
+#[test]
+fn test_atomic_remove_reports_only_after_commit() {
+    let section = "# toggle:start ID=dbg\nprint(1)\n# toggle:end ID=dbg\n";
+    let dir = setup_temp_dir_with_files(&[("a.py", section)]);
+    let args = ["--remove", "-S", "dbg", "--atomic", "-v", "a.py"];
+    let holder = hold_atomic_lock(dir.path(), std::time::Duration::from_secs(30));
+    cmd()
+        .current_dir(dir.path())
+        .args(args)
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("Removed").not());
+    holder.release();
+    assert_eq!(
+        fs::read_to_string(dir.path().join("a.py")).unwrap(),
+        section
+    );
+
+    cmd()
+        .current_dir(dir.path())
+        .args(args)
+        .assert()
+        .success()
+        .stderr(predicates::str::contains(
+            "Removed 1 section(s) 'dbg' from a.py",
+        ));
+}
+
+#[test]
+fn test_atomic_reclaims_stale_lock_file() {
+    let dir = setup_temp_dir_with_files(&[("a.py", "hello\n")]);
+    // Metadata left behind by a process that died without cleaning up; no
+    // process holds the OS lock any more.
+    fs::write(
+        dir.path().join(".toggle-atomic.lock"),
+        r#"{"pid":4000000,"started_at":"0s-since-epoch","command":"togl remove -S x"}"#,
+    )
+    .unwrap();
+    cmd()
+        .current_dir(dir.path())
+        .args(["a.py", "-l", "1:1", "--atomic"])
+        .assert()
+        .success()
+        .stderr(predicates::str::contains(
+            "reclaimed stale lock left by PID 4000000",
+        ));
+    assert!(!dir.path().join(".toggle-atomic.lock").exists());
+}
+
+#[test]
+fn test_lock_timeout_rejects_bad_duration() {
+    let (_dir, path) = setup_temp_file("hello\n", "a.py");
+    cmd()
+        .args([
+            path.to_str().unwrap(),
+            "-l",
+            "1:1",
+            "--lock-timeout",
+            "soon",
+        ])
+        .assert()
+        .code(1)
+        .stderr(predicates::str::contains("invalid duration"));
+}
+
 // ── Section variants (PRD §0.13) ──
 
 fn copy_variants_fixture() -> (TempDir, std::path::PathBuf) {
@@ -2267,23 +3772,6 @@ fn test_insert_rejects_scan_combo() {
         .failure();
 }
 
-#[test]
-fn test_insert_rejects_atomic_combo() {
-    let (_dir, path) = setup_temp_file("a\nb\n", "test.py");
-    cmd()
-        .args([
-            path.to_str().unwrap(),
-            "--insert",
-            "-S",
-            "feat",
-            "-l",
-            "1:2",
-            "--atomic",
-        ])
-        .assert()
-        .failure();
-}
-
 #[test]
 fn test_insert_to_end() {
     let (_dir, path) = setup_temp_file("a\nb\nc\n", "test.py");
@@ -0,0 +1,278 @@
+//! Tests for `togl lsp`: a client speaking JSON-RPC over the server's stdio.
+//!
+//! Each test opens one document and checks a single capability. Edits must
+//! come back as minimal line replacements, never the whole buffer.
+
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+const URI: &str = "file:///project/app.py";
+
+const DOC: &str = "\
+import os
+# toggle:start ID=debug desc=\"Debug output\"
+print('debug')
+# toggle:end ID=debug
+# toggle:start ID=db:sqlite
+db = 'sqlite'
+# toggle:end ID=db:sqlite
+# toggle:start ID=db:postgres
+# db = 'postgres'
+# toggle:end ID=db:postgres
+";
+
+struct Client {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    next_id: i64,
+}
+
+impl Client {
+    /// Start the server, initialize it and open `text` as [`URI`].
+    fn open(text: &str) -> Self {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_togl"))
+            .arg("lsp")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        let mut client = Client {
+            child,
+            stdin,
+            stdout,
+            next_id: 0,
+        };
+        client.request("initialize", json!({ "capabilities": {} }));
+        client.notify("initialized", json!({}));
+        client.notify(
+            "textDocument/didOpen",
+            json!({ "textDocument": {
+                "uri": URI, "languageId": "python", "version": 1, "text": text
+            }}),
+        );
+        client
+    }
+
+    fn send(&mut self, msg: Value) {
+        let body = msg.to_string();
+        write!(self.stdin, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+        self.stdin.flush().unwrap();
+    }
+
+    fn recv(&mut self) -> Value {
+        let mut len = 0;
+        loop {
+            let mut header = String::new();
+            self.stdout.read_line(&mut header).unwrap();
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some(n) = header.strip_prefix("Content-Length: ") {
+                len = n.parse().unwrap();
+            }
+        }
+        let mut body = vec![0; len];
+        self.stdout.read_exact(&mut body).unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    fn notify(&mut self, method: &str, params: Value) {
+        self.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+    }
+
+    /// Send a request and return its result, skipping other messages.
+    fn request(&mut self, method: &str, params: Value) -> Value {
+        self.next_id += 1;
+        let id = self.next_id;
+        self.send(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }));
+        loop {
+            let msg = self.recv();
+            if msg["id"] == id && msg.get("method").is_none() {
+                assert!(msg.get("error").is_none(), "error response: {msg}");
+                return msg["result"].clone();
+            }
+        }
+    }
+
+    /// Wait for the next message with `method` (a notification or a request
+    /// from the server).
+    fn expect(&mut self, method: &str) -> Value {
+        loop {
+            let msg = self.recv();
+            if msg["method"] == method {
+                return msg;
+            }
+        }
+    }
+
+    /// Send a positional request (completion, or a code action on an empty
+    /// range) for `line`:`character`.
+    fn at(&mut self, method: &str, line: u32, character: u32) -> Value {
+        let position = json!({ "line": line, "character": character });
+        let mut params = json!({ "textDocument": { "uri": URI }, "position": position });
+        if method == "textDocument/codeAction" {
+            params["range"] = json!({ "start": position, "end": position });
+            params["context"] = json!({ "diagnostics": [] });
+        }
+        self.request(method, params)
+    }
+
+    fn doc(&mut self, method: &str) -> Value {
+        self.request(method, json!({ "textDocument": { "uri": URI } }))
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        self.request("shutdown", Value::Null);
+        self.notify("exit", Value::Null);
+        let _ = self.child.wait();
+    }
+}
+
+fn titles(actions: &Value) -> Vec<String> {
+    actions
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|a| a["title"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn lsp_publishes_unclosed_marker_diagnostic() {
+    let mut client = Client::open("x = 1\n# toggle:start ID=orphan\ny = 2\n");
+    let diag = client.expect("textDocument/publishDiagnostics");
+    let items = diag["params"]["diagnostics"].as_array().unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0]["severity"], 1);
+    assert_eq!(items[0]["range"]["start"]["line"], 1);
+    assert!(items[0]["message"]
+        .as_str()
+        .unwrap()
+        .contains("unclosed marker for ID=orphan"));
+}
+
+#[test]
+fn lsp_clean_document_has_no_diagnostics() {
+    let mut client = Client::open(DOC);
+    let diag = client.expect("textDocument/publishDiagnostics");
+    assert_eq!(diag["params"]["diagnostics"], json!([]));
+}
+
+#[test]
+fn lsp_code_lenses_on_start_markers() {
+    let mut client = Client::open(DOC);
+    let lenses = client.doc("textDocument/codeLens");
+    let lenses = lenses.as_array().unwrap();
+    assert_eq!(lenses.len(), 3);
+    assert_eq!(lenses[0]["range"]["start"]["line"], 1);
+    assert_eq!(lenses[0]["command"]["title"], "Toggle section");
+    assert_eq!(lenses[0]["command"]["arguments"], json!([URI, "debug"]));
+    assert_eq!(lenses[2]["command"]["title"], "Activate db:postgres");
+    assert_eq!(lenses[2]["command"]["command"], "togl.activateVariant");
+}
+
+#[test]
+fn lsp_code_actions_return_minimal_edits() {
+    let mut client = Client::open(DOC);
+    // Cursor inside the debug section.
+    let actions = client.at("textDocument/codeAction", 2, 0);
+    assert_eq!(
+        titles(&actions),
+        ["Toggle section debug", "Remove markers of debug"]
+    );
+    let edits = &actions[0]["edit"]["changes"][URI];
+    assert_eq!(
+        edits,
+        &json!([{
+            "range": {
+                "start": { "line": 2, "character": 0 },
+                "end": { "line": 3, "character": 0 }
+            },
+            "newText": "# print('debug')\n"
+        }])
+    );
+
+    // The remove action deletes just the two marker lines.
+    let edits = actions[1]["edit"]["changes"][URI].as_array().unwrap();
+    assert_eq!(edits.len(), 2);
+    assert!(edits.iter().all(|e| e["newText"] == ""));
+}
+
+#[test]
+fn lsp_activate_action_touches_only_variant_bodies() {
+    let mut client = Client::open(DOC);
+    let actions = client.at("textDocument/codeAction", 8, 0);
+    assert_eq!(actions[0]["title"], "Activate db:postgres");
+    let edits = actions[0]["edit"]["changes"][URI].as_array().unwrap();
+    let lines: Vec<u64> = edits
+        .iter()
+        .map(|e| e["range"]["start"]["line"].as_u64().unwrap())
+        .collect();
+    assert_eq!(lines, [5, 8]);
+    assert_eq!(edits[0]["newText"], "# db = 'sqlite'\n");
+    assert_eq!(edits[1]["newText"], "db = 'postgres'\n");
+}
+
+#[test]
+fn lsp_execute_command_requests_apply_edit() {
+    let mut client = Client::open(DOC);
+    client.next_id += 1;
+    let id = client.next_id;
+    client.send(json!({
+        "jsonrpc": "2.0", "id": id, "method": "workspace/executeCommand",
+        "params": { "command": "togl.toggleSection", "arguments": [URI, "debug"] }
+    }));
+    let apply = client.expect("workspace/applyEdit");
+    let edits = apply["params"]["edit"]["changes"][URI].as_array().unwrap();
+    assert_eq!(edits.len(), 1);
+    assert_eq!(edits[0]["newText"], "# print('debug')\n");
+}
+
+#[test]
+fn lsp_folding_ranges_and_symbols() {
+    let mut client = Client::open(DOC);
+    let folds = client.doc("textDocument/foldingRange");
+    assert_eq!(folds[0]["startLine"], 1);
+    assert_eq!(folds[0]["endLine"], 3);
+    assert_eq!(folds.as_array().unwrap().len(), 3);
+
+    let symbols = client.doc("textDocument/documentSymbol");
+    let symbols = symbols.as_array().unwrap();
+    assert_eq!(symbols.len(), 2);
+    assert_eq!(symbols[0]["name"], "debug");
+    assert_eq!(symbols[0]["detail"], "uncommented");
+    assert_eq!(symbols[1]["name"], "db");
+    let children: Vec<&str> = symbols[1]["children"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|c| c["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(children, ["db:sqlite", "db:postgres"]);
+}
+
+#[test]
+fn lsp_completes_section_ids_on_marker_lines() {
+    let text = format!("{DOC}# toggle:end ID=d\n");
+    let mut client = Client::open(&text);
+    let items = client.at("textDocument/completion", 10, 17);
+    let labels: Vec<&str> = items
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|i| i["label"].as_str().unwrap())
+        .collect();
+    assert_eq!(labels, ["db:postgres", "db:sqlite", "debug"]);
+    assert_eq!(items[2]["detail"], "Debug output");
+    assert_eq!(items[2]["textEdit"]["range"]["start"]["character"], 16);
+
+    // Outside a marker line there is nothing to complete.
+    assert_eq!(client.at("textDocument/completion", 0, 3), Value::Null);
+}
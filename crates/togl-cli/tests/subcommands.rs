@@ -15,11 +15,24 @@ use tempfile::TempDir;
 
 #[allow(deprecated)]
 fn cmd() -> Command {
-    Command::cargo_bin("toggle").unwrap()
+    let mut c = Command::cargo_bin("toggle").unwrap();
+    // Keep runs from writing .toggle/history.jsonl into the crate directory.
+    c.env("TOGGLE_NO_HISTORY", "1");
+    c
 }
 
 const SECTION_FILE: &str = "# toggle:start ID=feat\nprint(\"hi\")\n# toggle:end ID=feat\nafter\n";
 
+/// A fresh directory holding `a.py` and `b.py`, each with `SECTION_FILE`.
+/// Write-parity tests build one per form, since the journal lives in the cwd.
+fn section_dir() -> TempDir {
+    let dir = TempDir::new().unwrap();
+    for name in ["a.py", "b.py"] {
+        fs::write(dir.path().join(name), SECTION_FILE).unwrap();
+    }
+    dir
+}
+
 /// Run `args` and return captured stdout as a String.
 fn stdout_of(args: &[&str]) -> String {
     let out = cmd().args(args).assert().get_output().stdout.clone();
@@ -141,6 +154,61 @@ fn remove_default_mode_write_parity() {
     );
 }
 
+#[test]
+fn toggle_safety_flags_write_parity() {
+    assert_write_parity(
+        "# @generated\n# toggle:start ID=feat\nx = 1\n# toggle:end ID=feat\n",
+        "g.py",
+        &["toggle", "--allow-generated", "-S", "feat"],
+        &["--allow-generated", "-S", "feat"],
+    );
+}
+
+#[test]
+fn toggle_root_write_parity() {
+    assert_write_parity(
+        SECTION_FILE,
+        "root.py",
+        &["toggle", "--root", "/", "-S", "feat"],
+        &["--root", "/", "-S", "feat"],
+    );
+}
+
+#[test]
+fn toggle_preserve_mtime_write_parity() {
+    assert_write_parity(
+        SECTION_FILE,
+        "mtime.py",
+        &["toggle", "--preserve-mtime", "-S", "feat"],
+        &["--preserve-mtime", "-S", "feat"],
+    );
+}
+
+#[test]
+fn toggle_backup_mode_write_parity() {
+    assert_write_parity(
+        SECTION_FILE,
+        "bk.py",
+        &[
+            "toggle",
+            "--backup-mode",
+            "numbered",
+            "--backup-keep",
+            "1",
+            "-S",
+            "feat",
+        ],
+        &[
+            "--backup-mode",
+            "numbered",
+            "--backup-keep",
+            "1",
+            "-S",
+            "feat",
+        ],
+    );
+}
+
 #[test]
 fn remove_explicit_mode_write_parity() {
     assert_write_parity(
@@ -256,33 +324,454 @@ fn toggle_atomic_recursive_write_parity() {
     // Atomic multi-file mode reaches the real file-mutation + journal + backup
     // path through the subcommand bridge. Each run is isolated in its own cwd
     // (the journal lives in cwd) so they don't collide.
+    let sub_dir = section_dir();
+    cmd()
+        .current_dir(sub_dir.path())
+        .args(["toggle", ".", "-S", "feat", "--atomic", "-R"])
+        .assert()
+        .success();
+
+    let legacy_dir = section_dir();
+    cmd()
+        .current_dir(legacy_dir.path())
+        .args([".", "-S", "feat", "--atomic", "-R"])
+        .assert()
+        .success();
+
+    for name in ["a.py", "b.py"] {
+        assert_eq!(
+            fs::read(sub_dir.path().join(name)).unwrap(),
+            fs::read(legacy_dir.path().join(name)).unwrap(),
+            "atomic parity differs for {name}"
+        );
+    }
+}
+
+#[test]
+fn remove_atomic_recursive_write_parity() {
+    let sub_dir = section_dir();
+    cmd()
+        .current_dir(sub_dir.path())
+        .args(["remove", ".", "-S", "feat", "--atomic", "-R"])
+        .assert()
+        .success();
+
+    let legacy_dir = section_dir();
+    cmd()
+        .current_dir(legacy_dir.path())
+        .args(["--remove", "-S", "feat", "--atomic", "-R", "."])
+        .assert()
+        .success();
+
+    for name in ["a.py", "b.py"] {
+        assert_eq!(
+            fs::read(sub_dir.path().join(name)).unwrap(),
+            fs::read(legacy_dir.path().join(name)).unwrap(),
+            "atomic remove parity differs for {name}"
+        );
+    }
+}
+
+#[test]
+fn ui_requires_a_terminal() {
+    // Under captured stdio the UI refuses to start instead of drawing into a pipe.
+    let dir = TempDir::new().unwrap();
+    let a = dir.path().join("a.py");
+    fs::write(&a, SECTION_FILE).unwrap();
+    cmd()
+        .args(["ui", dir.path().to_str().unwrap()])
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("needs an interactive terminal"));
+    assert_eq!(fs::read_to_string(&a).unwrap(), SECTION_FILE);
+}
+
+#[test]
+fn ui_flag_rejects_preselected_sections() {
+    let dir = TempDir::new().unwrap();
+    let a = dir.path().join("a.py");
+    fs::write(&a, SECTION_FILE).unwrap();
+    cmd()
+        .args(["--ui", "-S", "feat", a.to_str().unwrap()])
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("does not take -S"));
+}
+
+#[test]
+fn rename_recursive_write_parity() {
+    let sub_dir = section_dir();
+    cmd()
+        .current_dir(sub_dir.path())
+        .args(["rename", "feat", "feature", "-R", "."])
+        .assert()
+        .success();
+
+    let legacy_dir = section_dir();
+    cmd()
+        .current_dir(legacy_dir.path())
+        .args(["--rename", "feature", "-S", "feat", "-R", "."])
+        .assert()
+        .success();
+
+    for name in ["a.py", "b.py"] {
+        let renamed = fs::read_to_string(sub_dir.path().join(name)).unwrap();
+        assert_eq!(
+            renamed,
+            "# toggle:start ID=feature\nprint(\"hi\")\n# toggle:end ID=feature\nafter\n"
+        );
+        assert_eq!(
+            renamed,
+            fs::read_to_string(legacy_dir.path().join(name)).unwrap(),
+            "rename parity differs for {name}"
+        );
+    }
+}
+
+#[test]
+fn rename_refuses_duplicate_and_writes_nothing() {
+    let dir = TempDir::new().unwrap();
+    let a = dir.path().join("a.py");
+    let b = dir.path().join("b.py");
+    let clash = format!("{SECTION_FILE}# toggle:start ID=feature\nx\n# toggle:end ID=feature\n");
+    fs::write(&a, SECTION_FILE).unwrap();
+    fs::write(&b, &clash).unwrap();
+    cmd()
+        .current_dir(dir.path())
+        .args(["rename", "feat", "feature", "-R", "."])
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains(
+            "Section ID 'feature' already exists",
+        ));
+    // The batch is planned in full before anything is written.
+    assert_eq!(fs::read_to_string(&a).unwrap(), SECTION_FILE);
+    assert_eq!(fs::read_to_string(&b).unwrap(), clash);
+}
+
+#[test]
+fn rename_refuses_undecodable_file_and_writes_nothing() {
+    let dir = TempDir::new().unwrap();
+    let a = dir.path().join("a.py");
+    let b = dir.path().join("b.py");
+    let latin1 = b"# toggle:start ID=feat\nname = '\xe9'\n# toggle:end ID=feat\n";
+    fs::write(&a, SECTION_FILE).unwrap();
+    fs::write(&b, latin1).unwrap();
+    for args in [&["a.py", "b.py"][..], &["-R", "."][..]] {
+        cmd()
+            .current_dir(dir.path())
+            .args(["rename", "feat", "feature"])
+            .args(args)
+            .assert()
+            .code(2)
+            .stderr(predicate::str::contains("could not decode"));
+        assert_eq!(fs::read_to_string(&a).unwrap(), SECTION_FILE);
+        assert_eq!(fs::read(&b).unwrap(), latin1);
+    }
+}
+
+#[test]
+fn rename_unknown_id_fails() {
+    let dir = TempDir::new().unwrap();
+    let a = dir.path().join("a.py");
+    fs::write(&a, SECTION_FILE).unwrap();
+    cmd()
+        .args(["rename", "nope", "other", a.to_str().unwrap()])
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("no section or group 'nope' found"));
+}
+
+#[test]
+fn variant_add_write_parity() {
+    let sub_dir = section_dir();
+    cmd()
+        .current_dir(sub_dir.path())
+        .args(["variant", "add", "feat:new", "--from", "feat", "-R", "."])
+        .assert()
+        .success();
+
+    let legacy_dir = section_dir();
+    cmd()
+        .current_dir(legacy_dir.path())
+        .args(["--add-variant", "feat:new", "-S", "feat", "-R", "."])
+        .assert()
+        .success();
+
+    for name in ["a.py", "b.py"] {
+        let added = fs::read_to_string(sub_dir.path().join(name)).unwrap();
+        assert_eq!(
+            added,
+            "# toggle:start ID=feat:original\nprint(\"hi\")\n# toggle:end ID=feat:original\n\
+             # toggle:start ID=feat:new\n# print(\"hi\")\n# toggle:end ID=feat:new\nafter\n"
+        );
+        assert_eq!(
+            added,
+            fs::read_to_string(legacy_dir.path().join(name)).unwrap(),
+            "variant add parity differs for {name}"
+        );
+    }
+}
+
+#[test]
+fn variant_add_and_drop_report_missing_file_as_io_error() {
+    let dir = TempDir::new().unwrap();
+    let a = dir.path().join("a.py");
+    let group = "# toggle:start ID=m:a\nx = 1\n# toggle:end ID=m:a\n";
+    fs::write(&a, group).unwrap();
+    for args in [
+        &["variant", "add", "m:b", "--from", "m:a"][..],
+        &["variant", "drop", "m:a"][..],
+    ] {
+        cmd()
+            .current_dir(dir.path())
+            .args(args)
+            .args(["a.py", "missing.py"])
+            .assert()
+            .code(2)
+            .stderr(predicate::str::contains("could not read 'missing.py'"));
+        assert_eq!(fs::read_to_string(&a).unwrap(), group);
+    }
+}
+
+#[test]
+fn variant_drop_warns_about_what_is_left() {
+    let dir = TempDir::new().unwrap();
+    let a = dir.path().join("a.py");
+    fs::write(
+        &a,
+        "# toggle:start ID=m:a\nx = 1\n# toggle:end ID=m:a\n# toggle:start ID=m:b\n# x = 2\n# toggle:end ID=m:b\n",
+    )
+    .unwrap();
+    cmd()
+        .args(["variant", "drop", "m:a", a.to_str().unwrap()])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("only one variant left (b)"))
+        .stderr(predicate::str::contains("no variant is active"));
+    assert_eq!(
+        fs::read_to_string(&a).unwrap(),
+        "# toggle:start ID=m:b\n# x = 2\n# toggle:end ID=m:b\n"
+    );
+}
+
+#[test]
+fn variant_drop_refuses_invalid_group_and_writes_nothing() {
+    let dir = TempDir::new().unwrap();
+    let a = dir.path().join("a.py");
+    // m:a never closes, so the group still fails validation after the drop.
+    let broken =
+        "# toggle:start ID=m:a\nx = 1\n# toggle:start ID=m:b\n# x = 2\n# toggle:end ID=m:b\n";
+    fs::write(&a, broken).unwrap();
+    cmd()
+        .args(["variant", "drop", "m:b", a.to_str().unwrap()])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("unclosed marker for ID=m:a"))
+        .stderr(predicate::str::contains("nothing was written"));
+    assert_eq!(fs::read_to_string(&a).unwrap(), broken);
+
+    cmd()
+        .args(["variant", "drop", "m", a.to_str().unwrap()])
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("is not a variant of group 'm'"));
+}
+
+#[test]
+fn insert_by_pattern_recursive_parity() {
     fn build_dir() -> TempDir {
         let dir = TempDir::new().unwrap();
-        for name in ["a.py", "b.py"] {
-            fs::write(dir.path().join(name), SECTION_FILE).unwrap();
-        }
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::write(
+            dir.path().join("a.py"),
+            "def f():\n    print('DEBUG a')\n    print('DEBUG b')\n    return 1\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("sub/b.py"), "x = 1\nprint('DEBUG c')\n").unwrap();
+        fs::write(dir.path().join("sub/c.py"), "y = 2\n").unwrap();
         dir
     }
 
     let sub_dir = build_dir();
     cmd()
         .current_dir(sub_dir.path())
-        .args(["toggle", ".", "-S", "feat", "--atomic", "-R"])
+        .args([
+            "insert",
+            "-S",
+            "debug",
+            "--match",
+            r"print\(.*DEBUG",
+            "-R",
+            ".",
+        ])
         .assert()
         .success();
 
     let legacy_dir = build_dir();
     cmd()
         .current_dir(legacy_dir.path())
-        .args([".", "-S", "feat", "--atomic", "-R"])
+        .args([
+            "--insert",
+            "-S",
+            "debug",
+            "--match",
+            r"print\(.*DEBUG",
+            "-R",
+            ".",
+        ])
         .assert()
         .success();
 
-    for name in ["a.py", "b.py"] {
+    assert_eq!(
+        fs::read_to_string(sub_dir.path().join("a.py")).unwrap(),
+        "def f():\n    # toggle:start ID=debug\n    print('DEBUG a')\n    print('DEBUG b')\n    # toggle:end ID=debug\n    return 1\n"
+    );
+    for name in ["a.py", "sub/b.py", "sub/c.py"] {
         assert_eq!(
-            fs::read(sub_dir.path().join(name)).unwrap(),
-            fs::read(legacy_dir.path().join(name)).unwrap(),
-            "atomic parity differs for {name}"
+            fs::read_to_string(sub_dir.path().join(name)).unwrap(),
+            fs::read_to_string(legacy_dir.path().join(name)).unwrap(),
+            "insert parity differs for {name}"
         );
     }
+    assert_eq!(
+        fs::read_to_string(sub_dir.path().join("sub/c.py")).unwrap(),
+        "y = 2\n"
+    );
+}
+
+#[test]
+fn insert_between_anchors_wraps_every_block_once() {
+    let dir = TempDir::new().unwrap();
+    let a = dir.path().join("a.py");
+    let b = dir.path().join("b.py");
+    fs::write(&a, "# BEGIN\nx\n# END\n").unwrap();
+    fs::write(&b, "# BEGIN\nx\n# END\n# BEGIN\ny\n# END\n").unwrap();
+    let insert = [
+        "insert", "-S", "blk", "--from", "/BEGIN/", "--to", "/END/", "a.py", "b.py",
+    ];
+    cmd()
+        .current_dir(dir.path())
+        .args(insert)
+        .assert()
+        .success();
+    let once = "# toggle:start ID=blk\n# BEGIN\nx\n# END\n# toggle:end ID=blk\n";
+    let twice = format!("{once}# toggle:start ID=blk\n# BEGIN\ny\n# END\n# toggle:end ID=blk\n");
+    assert_eq!(fs::read_to_string(&a).unwrap(), once);
+    assert_eq!(fs::read_to_string(&b).unwrap(), twice);
+
+    // Running it again leaves both files alone.
+    cmd()
+        .current_dir(dir.path())
+        .args(insert)
+        .arg("-v")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("is already in a.py"));
+    assert_eq!(fs::read_to_string(&a).unwrap(), once);
+    assert_eq!(fs::read_to_string(&b).unwrap(), twice);
+
+    // One toggle flips every block.
+    cmd()
+        .current_dir(dir.path())
+        .args(["toggle", "-S", "blk", "b.py"])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(&b).unwrap(),
+        "# toggle:start ID=blk\n# BEGIN\n# x\n# END\n# toggle:end ID=blk\n\
+         # toggle:start ID=blk\n# BEGIN\n# y\n# END\n# toggle:end ID=blk\n"
+    );
+}
+
+#[test]
+fn plan_then_apply_writes_exactly_the_plan() {
+    let dir = TempDir::new().unwrap();
+    let a = dir.path().join("a.py");
+    let b = dir.path().join("b.py");
+    fs::write(&a, SECTION_FILE).unwrap();
+    fs::write(&b, SECTION_FILE).unwrap();
+    cmd()
+        .current_dir(dir.path())
+        .args([
+            "plan",
+            "-o",
+            "plan.json",
+            "--diff",
+            "toggle",
+            "-S",
+            "feat",
+            "--force",
+            "on",
+            "a.py",
+            "b.py",
+        ])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("nothing was written"));
+    assert_eq!(fs::read_to_string(&a).unwrap(), SECTION_FILE);
+
+    let plan: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(dir.path().join("plan.json")).unwrap()).unwrap();
+    assert_eq!(plan["force"], "on");
+    let files = plan["files"].as_array().unwrap();
+    assert_eq!(files.len(), 2);
+    assert_eq!(files[0]["operations"][0]["op"], "toggle_section");
+    assert!(files[0]["diff"]
+        .as_str()
+        .unwrap()
+        .contains("+# print(\"hi\")"));
+
+    cmd()
+        .current_dir(dir.path())
+        .args(["apply", "plan.json"])
+        .assert()
+        .success();
+    let expected = "# toggle:start ID=feat\n# print(\"hi\")\n# toggle:end ID=feat\nafter\n";
+    assert_eq!(fs::read_to_string(&a).unwrap(), expected);
+    assert_eq!(fs::read_to_string(&b).unwrap(), expected);
+}
+
+#[test]
+fn apply_refuses_stale_plan_and_writes_nothing() {
+    let dir = TempDir::new().unwrap();
+    let a = dir.path().join("a.py");
+    let b = dir.path().join("b.py");
+    fs::write(&a, SECTION_FILE).unwrap();
+    fs::write(&b, SECTION_FILE).unwrap();
+    cmd()
+        .current_dir(dir.path())
+        .args([
+            "plan",
+            "-o",
+            "plan.json",
+            "rename",
+            "feat",
+            "feature",
+            "-R",
+            ".",
+        ])
+        .assert()
+        .success();
+    let edited = format!("{SECTION_FILE}more\n");
+    fs::write(&b, &edited).unwrap();
+
+    cmd()
+        .current_dir(dir.path())
+        .args(["apply", "plan.json"])
+        .assert()
+        .code(5)
+        .stderr(predicate::str::contains(
+            "b.py' does not have the expected content",
+        ))
+        .stderr(predicate::str::contains("nothing was written"));
+    assert_eq!(fs::read_to_string(&a).unwrap(), SECTION_FILE);
+    assert_eq!(fs::read_to_string(&b).unwrap(), edited);
+
+    cmd()
+        .current_dir(dir.path())
+        .args(["plan", "-o", "-", "scan", "."])
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("togl plan takes a toggle"));
 }
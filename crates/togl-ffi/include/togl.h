@@ -27,6 +27,14 @@
 
 #define TOGL_ERR_INVALID_ARGUMENT -5
 
+#define TOGL_ERR_SECTION_NOT_FOUND -6
+
+#define TOGL_ERR_AMBIGUOUS_GROUP -7
+
+#define TOGL_ERR_UNCLOSED_MARKER -8
+
+#define TOGL_ERR_UNSUPPORTED_LANGUAGE -9
+
 /**
  * A line range `[start, end]`, 1-based inclusive. Frozen POD — never change layout.
  */
@@ -84,8 +92,22 @@ int togl_toggle_comments(const char *content,
                          int force_mode,
                          char **out_result);
 
+/**
+ * Toggle line-comment markers on the lines a `-l` spec selects, e.g.
+ * `10:20,30:40`, `/def main/:+3`, `/BEGIN/:/END/` or `-5:$`. A selection
+ * past the end of `content` is an invalid argument.
+ */
+int togl_toggle_line_spec(const char *content,
+                          const char *spec,
+                          int force_mode,
+                          const char *comment_marker,
+                          char **out_result);
+
 /**
  * Toggle the comment state of a named `toggle:start`/`toggle:end` section.
+ *
+ * Any library failure is reported as `TOGL_ERR_OPERATION`, as it was before
+ * the finer-grained codes existed.
  */
 int togl_find_and_toggle_section(const char *content,
                                  const char *section_id,
@@ -94,6 +116,8 @@ int togl_find_and_toggle_section(const char *content,
 
 /**
  * Activate one variant of a group, commenting out its siblings.
+ *
+ * Like `togl_find_and_toggle_section`, failures stay `TOGL_ERR_OPERATION`.
  */
 int togl_activate_variant(const char *content,
                           const char *group,
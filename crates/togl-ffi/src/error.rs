@@ -1,11 +1,34 @@
 //! Stable C error codes. Values are part of the ABI and never change meaning.
 
+use togl_lib::exit_codes::ToglError;
+
 pub const TOGL_OK: i32 = 0;
 pub const TOGL_ERR_NULL_POINTER: i32 = -1;
 pub const TOGL_ERR_INVALID_UTF8: i32 = -2;
 pub const TOGL_ERR_PANIC: i32 = -3;
 pub const TOGL_ERR_OPERATION: i32 = -4;
 pub const TOGL_ERR_INVALID_ARGUMENT: i32 = -5;
+pub const TOGL_ERR_SECTION_NOT_FOUND: i32 = -6;
+pub const TOGL_ERR_AMBIGUOUS_GROUP: i32 = -7;
+pub const TOGL_ERR_UNCLOSED_MARKER: i32 = -8;
+pub const TOGL_ERR_UNSUPPORTED_LANGUAGE: i32 = -9;
+
+/// The status code a failed library call reports.
+pub fn code(err: &ToglError) -> i32 {
+    match err {
+        ToglError::SectionNotFound { .. } => TOGL_ERR_SECTION_NOT_FOUND,
+        ToglError::AmbiguousGroup { .. } => TOGL_ERR_AMBIGUOUS_GROUP,
+        ToglError::UnclosedMarker { .. } => TOGL_ERR_UNCLOSED_MARKER,
+        ToglError::UnsupportedLanguage { .. } => TOGL_ERR_UNSUPPORTED_LANGUAGE,
+        ToglError::InvalidSectionId { .. }
+        | ToglError::InvalidDescription { .. }
+        | ToglError::NotAVariant { .. }
+        | ToglError::InvalidRange { .. }
+        | ToglError::InvalidPattern { .. }
+        | ToglError::LineOutOfRange { .. } => TOGL_ERR_INVALID_ARGUMENT,
+        _ => TOGL_ERR_OPERATION,
+    }
+}
 
 pub fn message(code: i32) -> &'static str {
     match code {
@@ -15,6 +38,10 @@ pub fn message(code: i32) -> &'static str {
         TOGL_ERR_PANIC => "internal panic",
         TOGL_ERR_OPERATION => "operation failed",
         TOGL_ERR_INVALID_ARGUMENT => "invalid argument",
+        TOGL_ERR_SECTION_NOT_FOUND => "section or variant not found",
+        TOGL_ERR_AMBIGUOUS_GROUP => "group has several variants; name one",
+        TOGL_ERR_UNCLOSED_MARKER => "section start marker has no end marker",
+        TOGL_ERR_UNSUPPORTED_LANGUAGE => "unsupported language",
         _ => "unknown error",
     }
 }
@@ -40,7 +40,7 @@ pub extern "C" fn togl_error_message(code: c_int) -> *const c_char {
     use std::collections::HashMap;
     static MSGS: OnceLock<HashMap<i32, CString>> = OnceLock::new();
     let map = MSGS.get_or_init(|| {
-        [0, -1, -2, -3, -4, -5, i32::MIN]
+        [0, -1, -2, -3, -4, -5, -6, -7, -8, -9, i32::MIN]
             .into_iter()
             .map(|c| (c, CString::new(error::message(c)).unwrap()))
             .collect()
@@ -123,6 +123,42 @@ mod tests {
         assert_eq!(rc, -5);
     }
 
+    #[test]
+    fn toggle_line_spec_resolves_anchors() {
+        let content = CString::new("a\ndef main():\n    run()\nb\n").unwrap();
+        let spec = CString::new("/def main/:+1,$").unwrap();
+        let mut out: *mut c_char = std::ptr::null_mut();
+        let rc = togl_toggle_line_spec(
+            content.as_ptr(),
+            spec.as_ptr(),
+            1,
+            std::ptr::null(),
+            &mut out,
+        );
+        assert_eq!(rc, 0);
+        let s = unsafe { CStr::from_ptr(out) }.to_str().unwrap().to_owned();
+        assert_eq!(s, "a\n# def main():\n    # run()\n# b\n");
+        togl_string_free(out);
+    }
+
+    #[test]
+    fn toggle_line_spec_bad_or_out_of_range_errors() {
+        let content = CString::new("a\n").unwrap();
+        let mut out: *mut c_char = std::ptr::null_mut();
+        for spec in ["/zzz/", "1:5", "x"] {
+            let spec = CString::new(spec).unwrap();
+            let rc = togl_toggle_line_spec(
+                content.as_ptr(),
+                spec.as_ptr(),
+                0,
+                std::ptr::null(),
+                &mut out,
+            );
+            assert_eq!(rc, -5);
+            assert!(out.is_null());
+        }
+    }
+
     const VARIANTS: &str = "# toggle:start ID=db:sqlite\nimport sqlite3\n# toggle:end ID=db:sqlite\n# toggle:start ID=db:postgres\n# import psycopg2\n# toggle:end ID=db:postgres\n";
 
     #[test]
@@ -153,6 +189,33 @@ mod tests {
         togl_string_free(out);
     }
 
+    #[test]
+    fn activate_missing_variant_reports_operation_error() {
+        let content = CString::new(VARIANTS).unwrap();
+        let group = CString::new("db").unwrap();
+        let variant = CString::new("mysql").unwrap();
+        let mut out: *mut c_char = std::ptr::null_mut();
+        let rc = togl_activate_variant(
+            content.as_ptr(),
+            group.as_ptr(),
+            variant.as_ptr(),
+            std::ptr::null(),
+            &mut out,
+        );
+        assert_eq!(rc, -4);
+        assert!(out.is_null());
+    }
+
+    #[test]
+    fn find_and_toggle_unclosed_section_errors() {
+        let content = CString::new("# toggle:start ID=foo\nx\n").unwrap();
+        let id = CString::new("foo").unwrap();
+        let mut out: *mut c_char = std::ptr::null_mut();
+        let rc =
+            togl_find_and_toggle_section(content.as_ptr(), id.as_ptr(), std::ptr::null(), &mut out);
+        assert_eq!(rc, -4);
+    }
+
     #[test]
     fn discover_sections_returns_json_array() {
         let content = CString::new("# toggle:start ID=foo\nx\n# toggle:end ID=foo\n").unwrap();
@@ -175,6 +238,9 @@ mod tests {
         assert_eq!(rc, 0);
         let json = unsafe { CStr::from_ptr(out) }.to_str().unwrap().to_owned();
         assert!(json.contains("memory.py"), "got: {json}");
+        let v: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(v[0]["state"], "uncommented");
+        assert_eq!(v[0]["line_counts"]["live"], 1);
         togl_string_free(out);
     }
 
@@ -1,11 +1,14 @@
 //! Transform functions: content in → new content out.
 
-use crate::error::{TOGL_ERR_INVALID_ARGUMENT, TOGL_ERR_OPERATION};
+use crate::error::{self, TOGL_ERR_INVALID_ARGUMENT, TOGL_ERR_OPERATION};
 use crate::mem::{borrow_str, borrow_str_or, guard, out_string};
 use std::os::raw::{c_char, c_int};
 use togl_lib::core::{
-    activate_variant, find_and_toggle_section, toggle_comments, CommentStyle, LineRange,
+    activate_variant, find_and_toggle_section, toggle_comments, toggle_comments_with_marker,
+    CommentStyle, LineRange,
 };
+use togl_lib::exit_codes::ToglError;
+use togl_lib::select::LineSpec;
 
 /// A line range `[start, end]`, 1-based inclusive. Frozen POD — never change layout.
 #[repr(C)]
@@ -57,7 +60,41 @@ pub extern "C" fn togl_toggle_comments(
     })
 }
 
+/// Toggle line-comment markers on the lines a `-l` spec selects, e.g.
+/// `10:20,30:40`, `/def main/:+3`, `/BEGIN/:/END/` or `-5:$`. A selection
+/// past the end of `content` is an invalid argument.
+#[no_mangle]
+pub extern "C" fn togl_toggle_line_spec(
+    content: *const c_char,
+    spec: *const c_char,
+    force_mode: c_int,
+    comment_marker: *const c_char,
+    out_result: *mut *mut c_char,
+) -> c_int {
+    guard(|| {
+        let content = borrow_str(content)?;
+        let spec = borrow_str(spec)?;
+        let force = force_str(force_mode)?;
+        let marker = borrow_str_or(comment_marker, "#")?;
+        let ranges = LineSpec::parse(spec)
+            .and_then(|spec| spec.resolve(content))
+            .map_err(|e| error::code(&e))?;
+        let lines = content.lines().count();
+        if let Some(r) = ranges.iter().find(|r| r.end > lines) {
+            return Err(error::code(&ToglError::LineOutOfRange {
+                line: r.end,
+                lines,
+            }));
+        }
+        let result = toggle_comments_with_marker(content, &ranges, force, marker);
+        out_string(result, out_result)
+    })
+}
+
 /// Toggle the comment state of a named `toggle:start`/`toggle:end` section.
+///
+/// Any library failure is reported as `TOGL_ERR_OPERATION`, as it was before
+/// the finer-grained codes existed.
 #[no_mangle]
 pub extern "C" fn togl_find_and_toggle_section(
     content: *const c_char,
@@ -78,6 +115,8 @@ pub extern "C" fn togl_find_and_toggle_section(
 }
 
 /// Activate one variant of a group, commenting out its siblings.
+///
+/// Like `togl_find_and_toggle_section`, failures stay `TOGL_ERR_OPERATION`.
 #[no_mangle]
 pub extern "C" fn togl_activate_variant(
     content: *const c_char,
@@ -17,6 +17,12 @@ int main(void) {
     assert(strstr(out, "# a") != NULL);
     togl_string_free(out);
 
+    /* transform by spec: the last line */
+    rc = togl_toggle_line_spec("a\nb\n", "$", 1, NULL, &out);
+    assert(rc == 0);
+    assert(strstr(out, "# b") != NULL);
+    togl_string_free(out);
+
     /* introspection: JSON array */
     char *json = NULL;
     rc = togl_discover_sections("# toggle:start ID=foo\nx\n# toggle:end ID=foo\n", &json);
@@ -0,0 +1,504 @@
+// Per-file backups for `--backup` / `--backup-mode` and `togl backups`.
+//
+// Names follow GNU `cp --backup`: a simple backup is `<file><suffix>` and is
+// overwritten by the next one, a numbered backup is `<file>.~N~`. Timestamped
+// backups use the same shape with a UTC time, `<file>.~20261018T153012Z~`.
+// With a backup directory, backups go to the file's path relative to a base
+// directory under it instead of next to the file, so `togl backups` can map
+// them back to their originals.
+
+use crate::platform;
+use crate::walk;
+use serde::Serialize;
+use std::ffi::OsString;
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+use tempfile::NamedTempFile;
+use walkdir::WalkDir;
+
+/// Suffix for simple backups when none is given (GNU's default).
+pub const DEFAULT_SUFFIX: &str = "~";
+
+/// How the next backup of a file is named.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BackupMode {
+    /// `<file><suffix>`, replacing the previous backup.
+    #[default]
+    Simple,
+    /// `<file>.~N~`, one more than the highest existing number.
+    Numbered,
+    /// Numbered if the file already has numbered backups, else simple.
+    Existing,
+    /// `<file>.~YYYYMMDDTHHMMSSZ~` (UTC).
+    Timestamp,
+}
+
+/// Where and how backups are taken.
+#[derive(Debug, Clone)]
+pub struct BackupOptions {
+    pub mode: BackupMode,
+    /// Suffix for simple backups.
+    pub suffix: String,
+    /// Put backups under this directory instead of next to each file.
+    pub dir: Option<PathBuf>,
+    /// Files under `base` keep their relative path inside `dir`; others
+    /// keep their absolute one.
+    pub base: PathBuf,
+    /// Keep at most this many numbered or timestamped backups per file,
+    /// removing the oldest after each new one.
+    pub keep: Option<usize>,
+}
+
+impl Default for BackupOptions {
+    fn default() -> Self {
+        Self {
+            mode: BackupMode::Simple,
+            suffix: DEFAULT_SUFFIX.to_string(),
+            dir: None,
+            base: PathBuf::from("."),
+            keep: None,
+        }
+    }
+}
+
+/// The naming scheme a backup file was found under.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum BackupKind {
+    Simple,
+    Numbered { number: u64 },
+    Timestamp { stamp: String },
+}
+
+impl BackupKind {
+    /// Simple backups are overwritten in place, so retention ignores them.
+    pub fn is_versioned(&self) -> bool {
+        !matches!(self, BackupKind::Simple)
+    }
+
+    /// Tiebreak for backups with the same modification time.
+    fn rank(&self) -> (u8, u64, &str) {
+        match self {
+            BackupKind::Simple => (0, 0, ""),
+            BackupKind::Numbered { number } => (1, *number, ""),
+            BackupKind::Timestamp { stamp } => (2, 0, stamp),
+        }
+    }
+}
+
+/// A backup found on disk.
+#[derive(Debug, Clone, Serialize)]
+pub struct BackupFile {
+    /// The file this is a backup of.
+    pub original: PathBuf,
+    /// The backup itself.
+    pub path: PathBuf,
+    #[serde(flatten)]
+    pub kind: BackupKind,
+    pub size: u64,
+    #[serde(skip)]
+    pub modified: Option<SystemTime>,
+}
+
+/// The path the next backup of `path` will be written to.
+pub fn next_backup_path(path: &Path, opts: &BackupOptions) -> io::Result<PathBuf> {
+    Ok(plan(path, opts)?.0)
+}
+
+/// The next backup's path, and whether it is a simple (overwritable) one.
+fn plan(path: &Path, opts: &BackupOptions) -> io::Result<(PathBuf, bool)> {
+    let (dir, name) = location(path, opts)?;
+    let mode = match opts.mode {
+        BackupMode::Existing if highest_number(&dir, &name)?.is_some() => BackupMode::Numbered,
+        BackupMode::Existing => BackupMode::Simple,
+        mode => mode,
+    };
+    let simple = mode == BackupMode::Simple;
+    let path = match mode {
+        BackupMode::Numbered => {
+            let next = highest_number(&dir, &name)?.unwrap_or(0) + 1;
+            dir.join(versioned_name(&name, &next.to_string()))
+        }
+        BackupMode::Timestamp => {
+            let stamp = utc_stamp(SystemTime::now());
+            let mut candidate = dir.join(versioned_name(&name, &stamp));
+            // Several backups within one second get `-2`, `-3`, ...
+            let mut n = 2;
+            while candidate.symlink_metadata().is_ok() {
+                candidate = dir.join(versioned_name(&name, &format!("{}-{}", stamp, n)));
+                n += 1;
+            }
+            candidate
+        }
+        _ => {
+            let mut simple = name;
+            simple.push(&opts.suffix);
+            dir.join(simple)
+        }
+    };
+    Ok((path, simple))
+}
+
+/// Back up `path` per `opts` and apply the retention limit. Returns the new
+/// backup's path.
+pub fn create(path: &Path, opts: &BackupOptions) -> io::Result<PathBuf> {
+    let (dst, simple) = plan(path, opts)?;
+    if let Some(parent) = dst.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    if simple {
+        std::fs::copy(path, &dst)?;
+    } else {
+        // Never overwrite an existing versioned backup.
+        platform::copy_durable(path, &dst)?;
+    }
+    if let Some(keep) = opts.keep {
+        for old in prunable(&list(path, opts)?, keep) {
+            std::fs::remove_file(&old.path)?;
+        }
+    }
+    Ok(dst)
+}
+
+/// Every backup of the file `path`, oldest first.
+pub fn list(path: &Path, opts: &BackupOptions) -> io::Result<Vec<BackupFile>> {
+    let (dir, name) = location(path, opts)?;
+    let mut found = Vec::new();
+    let entries = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(found),
+        Err(e) => return Err(e),
+    };
+    for entry in entries {
+        let entry = entry?;
+        let Some(file_name) = entry.file_name().to_str().map(str::to_string) else {
+            continue;
+        };
+        match parse_name(&file_name, &opts.suffix) {
+            Some((original, kind)) if name.to_str() == Some(original.as_str()) => {
+                found.push(backup_file(path.to_path_buf(), entry.path(), kind)?);
+            }
+            _ => {}
+        }
+    }
+    sort(&mut found);
+    Ok(found)
+}
+
+/// Every backup of a file under the directory `root`, grouped by original
+/// and oldest first within each.
+pub fn list_tree(root: &Path, opts: &BackupOptions) -> io::Result<Vec<BackupFile>> {
+    let search = match &opts.dir {
+        None => root.to_path_buf(),
+        Some(backup_dir) => mirrored(backup_dir, &std::path::absolute(root)?, opts)?,
+    };
+    let mut found = Vec::new();
+    if !search.is_dir() {
+        return Ok(found);
+    }
+    // Skip the same directories as a recursive toggle, which never backs up
+    // anything inside them.
+    let walker = WalkDir::new(&search).into_iter().filter_entry(|e| {
+        e.depth() == 0
+            || !e.file_type().is_dir()
+            || !e
+                .file_name()
+                .to_str()
+                .is_some_and(|n| walk::should_skip_dir(n, true))
+    });
+    for entry in walker {
+        let entry = entry.map_err(io::Error::other)?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let Some(file_name) = entry.file_name().to_str() else {
+            continue;
+        };
+        let Some((original, kind)) = parse_name(file_name, &opts.suffix) else {
+            continue;
+        };
+        let rel_dir = entry
+            .path()
+            .parent()
+            .and_then(|p| p.strip_prefix(&search).ok())
+            .unwrap_or(Path::new(""));
+        let original = tidy(root.join(rel_dir).join(original));
+        found.push(backup_file(
+            original,
+            tidy(entry.path().to_path_buf()),
+            kind,
+        )?);
+    }
+    sort(&mut found);
+    Ok(found)
+}
+
+/// The backups to delete so that each original keeps at most `keep`
+/// numbered or timestamped backups (simple backups are never selected).
+/// `backups` must be sorted as [`list`] and [`list_tree`] return them.
+pub fn prunable(backups: &[BackupFile], keep: usize) -> Vec<BackupFile> {
+    let mut doomed = Vec::new();
+    let mut start = 0;
+    while start < backups.len() {
+        let original = &backups[start].original;
+        let end = backups[start..]
+            .iter()
+            .position(|b| &b.original != original)
+            .map_or(backups.len(), |n| start + n);
+        let versioned: Vec<&BackupFile> = backups[start..end]
+            .iter()
+            .filter(|b| b.kind.is_versioned())
+            .collect();
+        let excess = versioned.len().saturating_sub(keep);
+        doomed.extend(versioned[..excess].iter().map(|b| (*b).clone()));
+        start = end;
+    }
+    doomed
+}
+
+/// Replace the backup's original with the backup's content (temp file +
+/// rename, keeping the backup's permissions). The backup itself is kept.
+pub fn restore(backup: &BackupFile) -> io::Result<()> {
+    let bytes = std::fs::read(&backup.path)?;
+    let dir = match backup.original.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+    std::fs::create_dir_all(dir)?;
+    let mut tmp = NamedTempFile::new_in(dir)?;
+    tmp.write_all(&bytes)?;
+    tmp.as_file()
+        .set_permissions(std::fs::metadata(&backup.path)?.permissions())?;
+    platform::durable_sync(tmp.as_file())?;
+    tmp.persist(&backup.original).map_err(|e| e.error)?;
+    Ok(())
+}
+
+/// The directory backups of `path` live in, and the file name they start with.
+fn location(path: &Path, opts: &BackupOptions) -> io::Result<(PathBuf, OsString)> {
+    let abs = normalize(&std::path::absolute(path)?);
+    let name = abs.file_name().map(OsString::from).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("cannot back up '{}': no file name", path.display()),
+        )
+    })?;
+    let dir = match &opts.dir {
+        None => match path.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+            _ => PathBuf::from("."),
+        },
+        Some(backup_dir) => mirrored(backup_dir, abs.parent().unwrap_or(Path::new("/")), opts)?,
+    };
+    Ok((dir, name))
+}
+
+/// Where the absolute directory `dir` is mirrored inside `backup_dir`: its
+/// path relative to the base, or its full path when outside the base.
+fn mirrored(backup_dir: &Path, dir: &Path, opts: &BackupOptions) -> io::Result<PathBuf> {
+    let dir = normalize(dir);
+    let base = normalize(&std::path::absolute(&opts.base)?);
+    let rel: PathBuf = match dir.strip_prefix(&base) {
+        Ok(rel) => rel.to_path_buf(),
+        Err(_) => dir
+            .components()
+            .filter(|c| matches!(c, Component::Normal(_)))
+            .collect(),
+    };
+    Ok(backup_dir.join(rel))
+}
+
+/// Drop a leading `./` (from walking the current directory).
+fn tidy(path: PathBuf) -> PathBuf {
+    match path.strip_prefix(".") {
+        Ok(rest) => rest.to_path_buf(),
+        Err(_) => path,
+    }
+}
+
+/// Resolve `.` and `..` lexically (symlinks are not followed).
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+/// `<name>.~<tag>~`
+fn versioned_name(name: &OsString, tag: &str) -> OsString {
+    let mut out = name.clone();
+    out.push(format!(".~{}~", tag));
+    out
+}
+
+/// Highest N among the existing `<name>.~N~` files in `dir`.
+fn highest_number(dir: &Path, name: &OsString) -> io::Result<Option<u64>> {
+    let Some(name) = name.to_str() else {
+        return Ok(None);
+    };
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let mut highest = None;
+    for entry in entries {
+        let entry = entry?;
+        if let Some((original, BackupKind::Numbered { number })) =
+            entry.file_name().to_str().and_then(|n| parse_name(n, ""))
+        {
+            if original == name {
+                highest = highest.max(Some(number));
+            }
+        }
+    }
+    Ok(highest)
+}
+
+/// Split a backup file name into its original's name and its kind.
+fn parse_name(file_name: &str, suffix: &str) -> Option<(String, BackupKind)> {
+    if let Some(inner) = file_name.strip_suffix('~') {
+        if let Some((original, tag)) = inner.rsplit_once(".~") {
+            if !original.is_empty() {
+                if !tag.is_empty() && tag.bytes().all(|b| b.is_ascii_digit()) {
+                    if let Ok(number) = tag.parse() {
+                        return Some((original.to_string(), BackupKind::Numbered { number }));
+                    }
+                }
+                if is_stamp(tag) {
+                    return Some((
+                        original.to_string(),
+                        BackupKind::Timestamp {
+                            stamp: tag.to_string(),
+                        },
+                    ));
+                }
+            }
+        }
+    }
+    match file_name.strip_suffix(suffix) {
+        Some(original) if !suffix.is_empty() && !original.is_empty() => {
+            Some((original.to_string(), BackupKind::Simple))
+        }
+        _ => None,
+    }
+}
+
+/// `YYYYMMDDTHHMMSSZ`, optionally followed by `-N`.
+fn is_stamp(tag: &str) -> bool {
+    let (stamp, counter) = match tag.split_once('-') {
+        Some((s, n)) => (s, Some(n)),
+        None => (tag, None),
+    };
+    let b = stamp.as_bytes();
+    b.len() == 16
+        && b[..8].iter().all(u8::is_ascii_digit)
+        && b[8] == b'T'
+        && b[9..15].iter().all(u8::is_ascii_digit)
+        && b[15] == b'Z'
+        && counter.is_none_or(|n| !n.is_empty() && n.bytes().all(|c| c.is_ascii_digit()))
+}
+
+fn backup_file(original: PathBuf, path: PathBuf, kind: BackupKind) -> io::Result<BackupFile> {
+    let meta = std::fs::metadata(&path)?;
+    Ok(BackupFile {
+        original,
+        path,
+        kind,
+        size: meta.len(),
+        modified: meta.modified().ok(),
+    })
+}
+
+/// Group by original, then oldest first.
+fn sort(backups: &mut [BackupFile]) {
+    backups.sort_by(|a, b| {
+        a.original
+            .cmp(&b.original)
+            .then(a.modified.cmp(&b.modified))
+            .then_with(|| a.kind.rank().cmp(&b.kind.rank()))
+    });
+}
+
+/// Format `time` as `YYYYMMDDTHHMMSSZ` (UTC).
+fn utc_stamp(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+    let (year, month, day) = civil_from_days(days as i64);
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// Days since 1970-01-01 to a proleptic Gregorian (year, month, day), per
+/// Howard Hinnant's `civil_from_days`.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_utc_stamp() {
+        let t = SystemTime::UNIX_EPOCH + Duration::from_secs(1_792_337_412);
+        assert_eq!(utc_stamp(t), "20261018T153012Z");
+        let leap_day = SystemTime::UNIX_EPOCH + Duration::from_secs(951_782_400);
+        assert_eq!(utc_stamp(leap_day), "20000229T000000Z");
+        assert_eq!(utc_stamp(SystemTime::UNIX_EPOCH), "19700101T000000Z");
+    }
+
+    #[test]
+    fn test_parse_name() {
+        assert_eq!(
+            parse_name("a.py.~12~", "~"),
+            Some(("a.py".into(), BackupKind::Numbered { number: 12 }))
+        );
+        assert_eq!(
+            parse_name("a.py.~20261018T153012Z-2~", "~"),
+            Some((
+                "a.py".into(),
+                BackupKind::Timestamp {
+                    stamp: "20261018T153012Z-2".into()
+                }
+            ))
+        );
+        assert_eq!(
+            parse_name("a.py.bak", ".bak"),
+            Some(("a.py".into(), BackupKind::Simple))
+        );
+        assert_eq!(
+            parse_name("a.py~", "~"),
+            Some(("a.py".into(), BackupKind::Simple))
+        );
+        assert_eq!(parse_name("a.py", ".bak"), None);
+        assert_eq!(parse_name(".bak", ".bak"), None);
+    }
+}
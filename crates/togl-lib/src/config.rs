@@ -2,12 +2,14 @@
 
 use serde::Deserialize;
 use std::collections::HashMap;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
 #[derive(Debug, Deserialize, Default)]
 pub struct ToggleConfig {
     pub global: Option<GlobalConfig>,
     pub language: Option<HashMap<String, LanguageConfig>>,
+    pub encoding: Option<EncodingConfig>,
 }
 
 #[derive(Debug, Deserialize, Default)]
@@ -17,6 +19,17 @@ pub struct GlobalConfig {
     pub single_line_delimiter: Option<String>,
     pub multi_line_delimiter_start: Option<String>,
     pub multi_line_delimiter_end: Option<String>,
+    /// Default file encoding label (e.g. "auto", "latin-1") when `--encoding`
+    /// is not given.
+    pub encoding: Option<String>,
+    /// Default `--backup-strategy` for atomic mode ("hardlink", "copy",
+    /// "reflink-if-possible" or "auto").
+    pub backup_strategy: Option<String>,
+    /// Default `--max-file-size` (e.g. "10M"; "0" or "none" for no limit).
+    pub max_file_size: Option<String>,
+    /// Default `--root`: refuse writes outside this directory. A relative
+    /// path is resolved against the config file's directory.
+    pub root: Option<PathBuf>,
 }
 
 #[derive(Debug, Deserialize, Default)]
@@ -26,6 +39,38 @@ pub struct LanguageConfig {
     pub multi_line_delimiter_end: Option<String>,
 }
 
+/// `[encoding]` table: auto-detection fallbacks and per-path overrides.
+#[derive(Debug, Deserialize, Default)]
+pub struct EncodingConfig {
+    /// Legacy encodings tried in order by `--encoding auto` when a file has no
+    /// BOM and is not valid UTF-8.
+    pub fallback: Option<Vec<String>>,
+    /// `[[encoding.path]]` rules; the first rule whose glob matches wins.
+    #[serde(default, rename = "path")]
+    pub paths: Vec<EncodingPathRule>,
+}
+
+/// One `[[encoding.path]]` rule, e.g. `glob = "legacy/**/*.py"`, `encoding = "latin-1"`.
+#[derive(Debug, Deserialize)]
+pub struct EncodingPathRule {
+    pub glob: String,
+    pub encoding: String,
+    /// `glob`, compiled once by [`ToggleConfig::validate`] or on first use.
+    #[serde(skip)]
+    matcher: OnceLock<globset::GlobMatcher>,
+}
+
+impl EncodingPathRule {
+    /// The compiled glob, or None if it does not parse.
+    fn matcher(&self) -> Option<&globset::GlobMatcher> {
+        if let Some(matcher) = self.matcher.get() {
+            return Some(matcher);
+        }
+        let compiled = globset::Glob::new(&self.glob).ok()?.compile_matcher();
+        Some(self.matcher.get_or_init(|| compiled))
+    }
+}
+
 impl ToggleConfig {
     /// Load a toggle config from a TOML file.
     pub fn load(path: &Path) -> anyhow::Result<Self> {
@@ -35,9 +80,104 @@ impl ToggleConfig {
         let config: ToggleConfig = toml::from_str(&content).map_err(|e| {
             anyhow::anyhow!("Failed to parse config file '{}': {}", path.display(), e)
         })?;
+        config
+            .validate()
+            .map_err(|e| anyhow::anyhow!("Invalid config file '{}': {}", path.display(), e))?;
         Ok(config)
     }
 
+    /// Check values that TOML parsing alone cannot: encoding labels, backup
+    /// strategies, sizes and globs.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if let Some(label) = self.global.as_ref().and_then(|g| g.encoding.as_deref()) {
+            if !crate::io::is_valid_encoding(label) {
+                anyhow::bail!("unsupported encoding '{}' in [global]", label);
+            }
+        }
+        if let Some(name) = self
+            .global
+            .as_ref()
+            .and_then(|g| g.backup_strategy.as_deref())
+        {
+            if crate::io::BackupStrategy::parse(name).is_none() {
+                anyhow::bail!(
+                    "unknown backup_strategy '{}' in [global]: expected hardlink, copy, \
+                     reflink-if-possible or auto",
+                    name
+                );
+            }
+        }
+        if let Some(size) = self
+            .global
+            .as_ref()
+            .and_then(|g| g.max_file_size.as_deref())
+        {
+            crate::preflight::parse_size(size)
+                .map_err(|e| anyhow::anyhow!("{} for max_file_size in [global]", e))?;
+        }
+        if let Some(enc) = &self.encoding {
+            for label in enc.fallback.iter().flatten() {
+                if !crate::io::is_valid_encoding(label) || label.eq_ignore_ascii_case("auto") {
+                    anyhow::bail!("unsupported fallback encoding '{}' in [encoding]", label);
+                }
+            }
+            for rule in &enc.paths {
+                let glob = globset::Glob::new(&rule.glob).map_err(|e| {
+                    anyhow::anyhow!("invalid glob '{}' in [[encoding.path]]: {}", rule.glob, e)
+                })?;
+                let _ = rule.matcher.set(glob.compile_matcher());
+                if !crate::io::is_valid_encoding(&rule.encoding) {
+                    anyhow::bail!(
+                        "unsupported encoding '{}' for glob '{}' in [[encoding.path]]",
+                        rule.encoding,
+                        rule.glob
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the encoding label configured for `path`: the first matching
+    /// `[[encoding.path]]` rule, else `[global] encoding`. Globs are matched
+    /// against the path as given (with any leading `./` removed).
+    pub fn encoding_for_path(&self, path: &Path) -> Option<&str> {
+        let candidate = path.strip_prefix(".").unwrap_or(path);
+        let by_glob = self.encoding.as_ref().and_then(|enc| {
+            enc.paths.iter().find_map(|rule| {
+                rule.matcher()?
+                    .is_match(candidate)
+                    .then_some(rule.encoding.as_str())
+            })
+        });
+        by_glob.or_else(|| self.global.as_ref().and_then(|g| g.encoding.as_deref()))
+    }
+
+    /// Get the configured atomic-mode backup strategy, if any.
+    pub fn backup_strategy(&self) -> Option<crate::io::BackupStrategy> {
+        self.global
+            .as_ref()
+            .and_then(|g| g.backup_strategy.as_deref())
+            .and_then(crate::io::BackupStrategy::parse)
+    }
+
+    /// Get the configured file size limit: None if unset, Some(None) if
+    /// explicitly unlimited.
+    pub fn max_file_size(&self) -> Option<Option<u64>> {
+        self.global
+            .as_ref()
+            .and_then(|g| g.max_file_size.as_deref())
+            .and_then(|s| crate::preflight::parse_size(s).ok())
+    }
+
+    /// Get the `--encoding auto` fallback list (empty means the built-in default).
+    pub fn fallback_encodings(&self) -> &[String] {
+        self.encoding
+            .as_ref()
+            .and_then(|e| e.fallback.as_deref())
+            .unwrap_or(&[])
+    }
+
     /// Get the single-line comment delimiter for a given language name.
     /// Returns None if no language-specific override is configured.
     pub fn get_language_delimiter(&self, lang: &str) -> Option<&str> {
@@ -0,0 +1,142 @@
+// Root confinement: keep every file a run writes inside one directory tree.
+//
+// Paths handed to togl by scripts may escape the project through `..` or
+// symlinks. A `Root` resolves where each write would really land and refuses
+// any location outside the root with an `OutsideRootError`.
+
+use crate::exit_codes::OutsideRootError;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Symlink chains longer than this are treated as loops (Linux's MAXSYMLINKS).
+const MAX_SYMLINK_DEPTH: usize = 40;
+
+/// What a confined path is about to be used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteKind {
+    /// A file being toggled, inserted into or removed from.
+    Target,
+    /// A `--temp-suffix` temp file next to a target.
+    TempFile,
+    /// A `--backup` copy or an atomic-mode backup.
+    Backup,
+    /// The atomic-mode journal and its lock file.
+    Journal,
+    /// The `.toggle/history.jsonl` store in the CWD.
+    History,
+}
+
+impl WriteKind {
+    /// Short machine-readable name, used in JSON output.
+    pub fn code(self) -> &'static str {
+        match self {
+            WriteKind::Target => "target",
+            WriteKind::TempFile => "temp-file",
+            WriteKind::Backup => "backup",
+            WriteKind::Journal => "journal",
+            WriteKind::History => "history",
+        }
+    }
+}
+
+impl fmt::Display for WriteKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            WriteKind::Target => "file",
+            WriteKind::TempFile => "temp file",
+            WriteKind::Backup => "backup",
+            WriteKind::Journal => "journal",
+            WriteKind::History => "history store",
+        })
+    }
+}
+
+/// A canonicalized directory that writes must stay inside.
+#[derive(Debug, Clone)]
+pub struct Root {
+    dir: PathBuf,
+}
+
+impl Root {
+    /// Canonicalize `dir`, which must be an existing directory.
+    pub fn new(dir: &Path) -> io::Result<Self> {
+        let canonical = std::fs::canonicalize(dir)?;
+        if !canonical.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "not a directory",
+            ));
+        }
+        Ok(Self { dir: canonical })
+    }
+
+    /// The canonical root directory.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Fail with an [`OutsideRootError`] (inside an `io::Error`) unless
+    /// writing `path` stays inside the root. Both the directory entry that
+    /// would be replaced and the file a symlink there points to must be
+    /// inside: a plain write replaces the entry, `--no-dereference` and
+    /// atomic mode write through to the target.
+    pub fn check(&self, path: &Path, kind: WriteKind) -> io::Result<()> {
+        for resolved in [entry_location(path)?, final_location(path)?] {
+            if !resolved.starts_with(&self.dir) {
+                return Err(io::Error::other(OutsideRootError {
+                    kind,
+                    path: path.to_path_buf(),
+                    resolved,
+                    root: self.dir.clone(),
+                }));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Where `path` points once every symlink (including `path` itself) is
+/// followed and `..` is resolved. Works for paths that do not exist yet and
+/// for dangling symlinks.
+pub fn final_location(path: &Path) -> io::Result<PathBuf> {
+    resolve(path, 0)
+}
+
+/// The directory entry `path` names: its parent fully resolved, its last
+/// component kept as is (so a symlink there is not followed).
+pub fn entry_location(path: &Path) -> io::Result<PathBuf> {
+    entry(&std::path::absolute(path)?, 0)
+}
+
+fn resolve(path: &Path, depth: usize) -> io::Result<PathBuf> {
+    if depth > MAX_SYMLINK_DEPTH {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("too many levels of symbolic links: '{}'", path.display()),
+        ));
+    }
+    match std::fs::canonicalize(path) {
+        Ok(p) => return Ok(p),
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        Err(_) => {}
+    }
+    let abs = std::path::absolute(path)?;
+    let located = entry(&abs, depth)?;
+    // A dangling symlink: follow it to wherever it would create the file.
+    match std::fs::read_link(&located) {
+        Ok(target) => {
+            let dir = located.parent().unwrap_or(Path::new("/"));
+            resolve(&dir.join(target), depth + 1)
+        }
+        Err(_) => Ok(located),
+    }
+}
+
+fn entry(abs: &Path, depth: usize) -> io::Result<PathBuf> {
+    match (abs.parent(), abs.file_name()) {
+        (Some(parent), Some(name)) => Ok(resolve(parent, depth)?.join(name)),
+        // `/` or a path ending in `..`: only canonicalization can resolve it.
+        _ => std::fs::canonicalize(abs),
+    }
+}
@@ -1,10 +1,12 @@
 // Toggle algorithm implementation
 
-use anyhow::Result;
+use std::fmt;
 use std::path::Path;
 
 use crate::config::ToggleConfig;
-use crate::exit_codes::UsageError;
+use crate::exit_codes::ToglError;
+
+type Result<T> = std::result::Result<T, ToglError>;
 
 /// Returns the list of file extensions that toggle knows how to handle.
 pub fn supported_extensions() -> &'static [&'static str] {
@@ -40,7 +42,102 @@ pub struct ScanSectionInfo {
     pub start_line: usize,
     pub end_line: Option<usize>,
     pub description: Option<String>,
-    pub state: String,
+    pub state: SectionState,
+    pub line_counts: LineCounts,
+}
+
+impl ScanSectionInfo {
+    /// The state for display, with the live share when it is mixed, e.g.
+    /// `mixed (3/10 lines live)`.
+    pub fn state_label(&self) -> String {
+        match self.state {
+            SectionState::Mixed => format!(
+                "mixed ({}/{} lines live)",
+                self.line_counts.live,
+                self.line_counts.live + self.line_counts.commented
+            ),
+            state => state.to_string(),
+        }
+    }
+}
+
+/// Comment state of the lines between a section's markers. Serialized as
+/// the lowercase variant name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SectionState {
+    /// Every non-blank line is commented out.
+    Commented,
+    /// No non-blank line is commented out.
+    Uncommented,
+    /// Some lines are commented out and some are live.
+    Mixed,
+    /// The section has no non-blank lines.
+    Empty,
+    /// The section has no end marker.
+    Unknown,
+}
+
+impl SectionState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SectionState::Commented => "commented",
+            SectionState::Uncommented => "uncommented",
+            SectionState::Mixed => "mixed",
+            SectionState::Empty => "empty",
+            SectionState::Unknown => "unknown",
+        }
+    }
+}
+
+impl fmt::Display for SectionState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+/// How many lines of a section body are commented out, live, or blank.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+pub struct LineCounts {
+    pub commented: usize,
+    pub live: usize,
+    pub blank: usize,
+}
+
+impl LineCounts {
+    /// Count the lines of a section body.
+    pub fn of(lines: &[&str], comment_marker: &str) -> Self {
+        let mut counts = Self::default();
+        for line in lines {
+            let trimmed = line.trim_start();
+            if trimmed.is_empty() {
+                counts.blank += 1;
+            } else if trimmed.starts_with(comment_marker) {
+                counts.commented += 1;
+            } else {
+                counts.live += 1;
+            }
+        }
+        counts
+    }
+
+    /// The state these counts describe.
+    pub fn state(&self) -> SectionState {
+        match (self.commented, self.live) {
+            (0, 0) => SectionState::Empty,
+            (_, 0) => SectionState::Commented,
+            (0, _) => SectionState::Uncommented,
+            _ => SectionState::Mixed,
+        }
+    }
+}
+
+impl std::ops::AddAssign for LineCounts {
+    fn add_assign(&mut self, other: Self) {
+        self.commented += other.commented;
+        self.live += other.live;
+        self.blank += other.blank;
+    }
 }
 
 /// Extract the `desc="..."` value from a section marker line.
@@ -133,7 +230,8 @@ pub fn discover_sections(content: &str) -> Vec<SectionInfo> {
 }
 
 /// How `remove_section` strips a matched section.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum RemoveMode {
     /// Delete only the two marker lines; keep the body exactly as-is.
     Markers,
@@ -202,6 +300,205 @@ pub fn remove_section(
     (result, sections.len())
 }
 
+/// Rename the section `from` to `to` by rewriting the `ID=` of every start and
+/// end marker that matches it exactly (as toggling matches them). A bare group
+/// name renames the whole group, keeping variant suffixes: `db` -> `database`
+/// also turns `db:sqlite` into `database:sqlite`. Returns the rewritten content
+/// and the number of sections renamed; with none, `content` is returned as-is.
+///
+/// Errors if `to` is not a valid ID, would give a variant a second `:`, or is
+/// already used by a section that is not being renamed, either as the same ID
+/// or as a solo ID and a group name (`db` → `database` next to a solo
+/// `database`) that would merge into one group.
+pub fn rename_section(content: &str, from: &str, to: &str) -> Result<(String, usize)> {
+    use std::collections::BTreeSet;
+    check_section_id(to)?;
+    let renamed_id = |id: &str| -> Option<String> {
+        if id == from {
+            return Some(to.to_string());
+        }
+        match parse_id_parts(id) {
+            (group, Some(variant)) if group == from => Some(format!("{to}:{variant}")),
+            _ => None,
+        }
+    };
+
+    // Every ID with a start marker, closed or not.
+    let existing: BTreeSet<String> = content
+        .lines()
+        .filter(|line| line.contains("toggle:start"))
+        .filter_map(parse_section_id)
+        .collect();
+    for id in &existing {
+        let Some(new_id) = renamed_id(id) else {
+            continue;
+        };
+        if new_id != to && to.contains(':') {
+            return Err(ToglError::InvalidSectionId { id: new_id });
+        }
+        if id != &new_id && existing.contains(&new_id) && renamed_id(&new_id).is_none() {
+            return Err(ToglError::DuplicateSection { id: new_id });
+        }
+        // A solo ID and a group of the same name would read as one group.
+        let kept = existing.iter().filter(|other| renamed_id(other).is_none());
+        for other in kept {
+            let clash = match (parse_id_parts(&new_id), parse_id_parts(other)) {
+                ((group, Some(_)), (_, None)) => group == *other,
+                ((_, None), (group, Some(_))) => group == new_id,
+                _ => false,
+            };
+            if clash {
+                return Err(ToglError::DuplicateSection { id: to.to_string() });
+            }
+        }
+    }
+
+    let mut count = 0;
+    let lines: Vec<String> = content
+        .lines()
+        .map(|line| {
+            let Some(id) = parse_section_id(line) else {
+                return line.to_string();
+            };
+            let Some(new_id) = renamed_id(&id) else {
+                return line.to_string();
+            };
+            let is_start = line_matches_start(line, &id);
+            if !is_start && !line_matches_end(line, &id) {
+                return line.to_string();
+            }
+            if is_start {
+                count += 1;
+            }
+            replace_marker_id(line, &id, &new_id)
+        })
+        .collect();
+    if count == 0 {
+        return Ok((content.to_string(), 0));
+    }
+
+    let mut result = lines.join("\n");
+    if content.ends_with('\n') {
+        result.push('\n');
+    }
+    Ok((result, count))
+}
+
+/// Swap the ID of a marker line, whose current ID `old` follows its first
+/// `ID=` (as `parse_section_id` reads it), for `new`.
+fn replace_marker_id(line: &str, old: &str, new: &str) -> String {
+    let at = line.find("ID=").map_or(0, |i| i + 3);
+    format!("{}{}{}", &line[..at], new, &line[at + old.len()..])
+}
+
+/// Copy the section `from` as a new variant `to` (`group:variant`), placed
+/// right after it with its body commented out. A solo `from` first becomes a
+/// variant of the group itself: `group:<solo_as>`, where `solo_as` defaults to
+/// the solo's ID, or `original` when that is the group name. Returns the new
+/// content and the number of sections added (0, leaving `content` as-is, when
+/// `from` is not in it).
+///
+/// Errors if `to` is not a variant of `from`'s group, if `from` occurs more
+/// than once, or if `to` (or the renamed solo) already exists in the file.
+pub fn add_variant(
+    content: &str,
+    from: &str,
+    to: &str,
+    solo_as: Option<&str>,
+    desc: Option<&str>,
+    comment_prefix: &str,
+) -> Result<(String, usize)> {
+    check_section_id(to)?;
+    if let Some(d) = desc {
+        if d.contains('"') {
+            return Err(ToglError::InvalidDescription {
+                desc: d.to_string(),
+            });
+        }
+    }
+    let (from_group, from_variant) = parse_id_parts(from);
+    match parse_id_parts(to) {
+        (group, Some(variant)) if group == from_group && !variant.is_empty() => {}
+        _ => {
+            return Err(ToglError::NotAVariant {
+                id: to.to_string(),
+                group: from_group,
+            })
+        }
+    }
+
+    let sections: Vec<SectionInfo> = discover_sections(content)
+        .into_iter()
+        .filter(|s| s.id == from)
+        .collect();
+    let source = match sections.as_slice() {
+        [] => return Ok((content.to_string(), 0)),
+        [one] => one,
+        _ => {
+            return Err(ToglError::DuplicateSection {
+                id: from.to_string(),
+            })
+        }
+    };
+    // A solo source becomes a variant too, so the group keeps one shape.
+    let renamed = match from_variant {
+        Some(_) => None,
+        None => {
+            let variant = solo_as.unwrap_or(if from == from_group { "original" } else { from });
+            Some(format!("{from_group}:{variant}"))
+        }
+    };
+    let taken = |id: &str| content.lines().any(|line| line_matches_start(line, id));
+    for id in std::iter::once(to).chain(renamed.as_deref()) {
+        check_section_id(id)?;
+        if taken(id) {
+            return Err(ToglError::DuplicateSection { id: id.to_string() });
+        }
+    }
+    if renamed.as_deref() == Some(to) {
+        return Err(ToglError::DuplicateSection { id: to.to_string() });
+    }
+
+    let mut lines: Vec<String> = content.lines().map(String::from).collect();
+    let start = source.start_line - 1; // 0-based start marker
+    let end = source.end_line - 1; // 0-based end marker
+    let indent: String = lines[start]
+        .chars()
+        .take_while(|c| c.is_whitespace())
+        .collect();
+
+    let mut body = lines[start + 1..end].join("\n");
+    let body_lines = end - start - 1;
+    if body_lines > 0 {
+        body = toggle_comments_with_marker(
+            &body,
+            &[LineRange::new(1, body_lines)],
+            Some("on"),
+            comment_prefix,
+        );
+    }
+    let start_marker = match desc {
+        Some(d) => format!("{indent}{comment_prefix} toggle:start ID={to} desc=\"{d}\""),
+        None => format!("{indent}{comment_prefix} toggle:start ID={to}"),
+    };
+    let end_marker = format!("{indent}{comment_prefix} toggle:end ID={to}");
+    let block = std::iter::once(start_marker)
+        .chain(body.lines().map(String::from))
+        .chain(std::iter::once(end_marker));
+    lines.splice(end + 1..end + 1, block);
+
+    if let Some(new_id) = &renamed {
+        lines[start] = replace_marker_id(&lines[start], from, new_id);
+        lines[end] = replace_marker_id(&lines[end], from, new_id);
+    }
+
+    let mut result = lines.join("\n");
+    if content.ends_with('\n') {
+        result.push('\n');
+    }
+    Ok((result, 1))
+}
+
 /// Return all `SectionInfo` whose ID parses into the given group.
 /// `discover_variants(content, "db")` matches both `db` (solo) and `db:postgres` (variant).
 pub fn discover_variants(content: &str, group: &str) -> Vec<SectionInfo> {
@@ -211,6 +508,14 @@ pub fn discover_variants(content: &str, group: &str) -> Vec<SectionInfo> {
         .collect()
 }
 
+/// An ID must be one non-empty token that can sit inside `ID=...` unquoted.
+fn check_section_id(id: &str) -> Result<()> {
+    if id.is_empty() || id.contains(char::is_whitespace) || id.contains('"') {
+        return Err(ToglError::InvalidSectionId { id: id.to_string() });
+    }
+    Ok(())
+}
+
 /// Insert a `toggle:start`/`toggle:end` marker pair around the 1-based inclusive
 /// line range `[start, end]`. Markers inherit the leading whitespace of the
 /// `start` line and use `comment_prefix` (e.g. `"#"`, `"//"`). The body is left
@@ -226,36 +531,47 @@ pub fn insert_section(
     end: usize,
     comment_prefix: &str,
 ) -> Result<String> {
-    if id.is_empty() || id.contains(char::is_whitespace) || id.contains('"') {
-        return Err(UsageError(format!("Invalid section ID: '{}'", id)).into());
-    }
+    check_section_id(id)?;
     if let Some(d) = desc {
         if d.contains('"') {
-            return Err(UsageError("Section description must not contain '\"'".into()).into());
+            return Err(ToglError::InvalidDescription {
+                desc: d.to_string(),
+            });
         }
     }
     if start == 0 || end < start {
-        return Err(UsageError(format!("Invalid line range: {}:{}", start, end)).into());
+        return Err(ToglError::InvalidRange {
+            spec: format!("{start}:{end}"),
+            reason: "the range is empty".into(),
+        });
     }
 
-    let mut lines: Vec<String> = content.lines().map(String::from).collect();
-    if end > lines.len() {
-        return Err(UsageError(format!(
-            "Line range {}:{} exceeds file length ({} lines)",
-            start,
-            end,
-            lines.len()
-        ))
-        .into());
+    let line_count = content.lines().count();
+    if end > line_count {
+        return Err(ToglError::LineOutOfRange {
+            line: end,
+            lines: line_count,
+        });
     }
 
     // Duplicate-ID guard: refuse if any start marker already uses this ID.
-    for line in &lines {
-        if line_matches_start(line, id) {
-            return Err(UsageError(format!("Section ID '{}' already exists in file", id)).into());
-        }
+    if content.lines().any(|line| line_matches_start(line, id)) {
+        return Err(ToglError::DuplicateSection { id: id.to_string() });
     }
+    Ok(wrap_lines(content, id, desc, start, end, comment_prefix))
+}
 
+/// Wrap the in-bounds 1-based range `[start, end]` in markers for `id`,
+/// without [`insert_section`]'s checks.
+fn wrap_lines(
+    content: &str,
+    id: &str,
+    desc: Option<&str>,
+    start: usize,
+    end: usize,
+    comment_prefix: &str,
+) -> String {
+    let mut lines: Vec<String> = content.lines().map(String::from).collect();
     let indent: String = lines[start - 1]
         .chars()
         .take_while(|c| c.is_whitespace())
@@ -279,7 +595,128 @@ pub fn insert_section(
     if content.ends_with('\n') {
         result.push('\n');
     }
-    Ok(result)
+    result
+}
+
+/// How `--insert` finds the lines to wrap when they are not given as a range.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinePattern {
+    /// Every line matching the regex, or contiguous run of matching lines.
+    Match(String),
+    /// Each line matching `from` through the next line matching `to`, or
+    /// through the end of the file when `to` is `None`.
+    Between { from: String, to: Option<String> },
+}
+
+/// Compile a pattern, accepting both `regex` and `/regex/`.
+pub(crate) fn compile_pattern(spec: &str) -> Result<regex::Regex> {
+    let body = spec
+        .strip_prefix('/')
+        .and_then(|s| s.strip_suffix('/'))
+        .unwrap_or(spec);
+    regex::Regex::new(body).map_err(|e| ToglError::InvalidPattern {
+        pattern: spec.to_string(),
+        reason: e.to_string(),
+    })
+}
+
+/// Whether `line` matches `re`. Marker lines never match.
+pub(crate) fn pattern_matches(re: &regex::Regex, line: &str) -> bool {
+    re.is_match(line) && !line.contains("toggle:start") && !line.contains("toggle:end")
+}
+
+/// Resolve `pattern` to every block of lines it selects in `content`, in
+/// file order: each line or contiguous run of lines matching
+/// [`LinePattern::Match`], or each `from` ... `to` block of
+/// [`LinePattern::Between`] (a single block through the end of the file when
+/// `to` is `None`). Marker lines never match, so a block cannot start or end
+/// on one.
+pub fn find_pattern_ranges(content: &str, pattern: &LinePattern) -> Result<Vec<LineRange>> {
+    let lines: Vec<&str> = content.lines().collect();
+    let matches = pattern_matches;
+    let mut ranges: Vec<LineRange> = Vec::new();
+    match pattern {
+        LinePattern::Match(spec) => {
+            let re = compile_pattern(spec)?;
+            for (i, line) in lines.iter().enumerate() {
+                if !matches(&re, line) {
+                    continue;
+                }
+                match ranges.last_mut() {
+                    Some(run) if run.end == i => run.end = i + 1,
+                    _ => ranges.push(LineRange::new(i + 1, i + 1)),
+                }
+            }
+        }
+        LinePattern::Between { from, to } => {
+            let from_re = compile_pattern(from)?;
+            let to_re = to.as_deref().map(compile_pattern).transpose()?;
+            let mut next = 0;
+            while let Some(offset) = lines[next..].iter().position(|l| matches(&from_re, l)) {
+                let start = next + offset;
+                let end = match (&to_re, to) {
+                    (Some(re), Some(spec)) => {
+                        match lines[start + 1..].iter().position(|l| matches(re, l)) {
+                            Some(offset) => start + 1 + offset,
+                            None => {
+                                return Err(ToglError::InvalidPattern {
+                                    pattern: spec.clone(),
+                                    reason: format!("no line after line {} matches it", start + 1),
+                                })
+                            }
+                        }
+                    }
+                    _ => lines.len() - 1,
+                };
+                ranges.push(LineRange::new(start + 1, end + 1));
+                next = end + 1;
+            }
+        }
+    }
+    Ok(ranges)
+}
+
+/// [`insert_section`] around every block `pattern` selects (see
+/// [`find_pattern_ranges`]), each under the same `id`, so one toggle flips
+/// them all. A block already inside a section `id` is left as-is, so
+/// re-running an insert is a no-op. Returns the new content and the number
+/// of sections inserted.
+pub fn insert_section_matching(
+    content: &str,
+    id: &str,
+    desc: Option<&str>,
+    pattern: &LinePattern,
+    comment_prefix: &str,
+) -> Result<(String, usize)> {
+    // Still refuse a bad ID or desc, even in files the pattern misses.
+    check_section_id(id)?;
+    if let Some(d) = desc {
+        if d.contains('"') {
+            return Err(ToglError::InvalidDescription {
+                desc: d.to_string(),
+            });
+        }
+    }
+    let wrapped: Vec<SectionInfo> = discover_sections(content)
+        .into_iter()
+        .filter(|s| s.id == id)
+        .collect();
+    let ranges: Vec<LineRange> = find_pattern_ranges(content, pattern)?
+        .into_iter()
+        .filter(|r| {
+            !wrapped
+                .iter()
+                .any(|s| s.start_line < r.start && r.end < s.end_line)
+        })
+        .collect();
+
+    // Bottom-up, so the line numbers of the blocks above stay valid.
+    let mut result = content.to_string();
+    for range in ranges.iter().rev() {
+        result = wrap_lines(&result, id, desc, range.start, range.end, comment_prefix);
+    }
+    Ok((result, ranges.len()))
 }
 
 /// Scan file content for toggle:start / toggle:end markers.
@@ -319,12 +756,13 @@ pub fn scan_sections(path: &Path, content: &str) -> Vec<ScanSectionInfo> {
             }
 
             // Determine state of content between markers
-            let state = if let Some(end) = end_line {
+            let (state, line_counts) = if let Some(end) = end_line {
                 let content_start = i + 1;
                 let content_end = end - 1; // back to 0-based for the end marker line
-                detect_section_state(&lines[content_start..content_end], &comment_marker)
+                let counts = LineCounts::of(&lines[content_start..content_end], &comment_marker);
+                (counts.state(), counts)
             } else {
-                "unknown".to_string()
+                (SectionState::Unknown, LineCounts::default())
             };
 
             let (group, variant) = parse_id_parts(&id);
@@ -337,6 +775,7 @@ pub fn scan_sections(path: &Path, content: &str) -> Vec<ScanSectionInfo> {
                 end_line,
                 description,
                 state,
+                line_counts,
             });
 
             if let Some(end) = end_line {
@@ -352,29 +791,8 @@ pub fn scan_sections(path: &Path, content: &str) -> Vec<ScanSectionInfo> {
     sections
 }
 
-/// Detect whether section content is commented, uncommented, or mixed.
-fn detect_section_state(lines: &[&str], comment_marker: &str) -> String {
-    let non_empty: Vec<&&str> = lines.iter().filter(|l| !l.trim().is_empty()).collect();
-    if non_empty.is_empty() {
-        return "empty".to_string();
-    }
-
-    let commented_count = non_empty
-        .iter()
-        .filter(|l| l.trim_start().starts_with(comment_marker))
-        .count();
-
-    if commented_count == non_empty.len() {
-        "commented".to_string()
-    } else if commented_count == 0 {
-        "uncommented".to_string()
-    } else {
-        "mixed".to_string()
-    }
-}
-
 /// Line range representation
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub struct LineRange {
     pub start: usize,
     pub end: usize,
@@ -398,32 +816,35 @@ pub struct CommentStyle {
 /// Parse a line range specification.
 /// Supports formats: "start:end", "start:+count", "single_line"
 pub fn parse_line_range(range_spec: &str) -> Result<(usize, usize)> {
+    let invalid = |reason: String| ToglError::InvalidRange {
+        spec: range_spec.to_string(),
+        reason,
+    };
     if let Some((start, end)) = range_spec.split_once(':') {
         let start_line = start
             .parse::<usize>()
-            .map_err(|_| UsageError(format!("Invalid start line: {}", start)))?;
+            .map_err(|_| invalid(format!("invalid start line '{}'", start)))?;
 
         if start_line == 0 {
-            return Err(UsageError("Start line must be >= 1, got 0".into()).into());
+            return Err(invalid("start line must be >= 1".into()));
         }
 
         if let Some(stripped_end) = end.strip_prefix('+') {
             // Format: start:+count
             let count = stripped_end
                 .parse::<usize>()
-                .map_err(|_| UsageError(format!("Invalid line count: {}", stripped_end)))?;
+                .map_err(|_| invalid(format!("invalid line count '{}'", stripped_end)))?;
             Ok((start_line, start_line + count))
         } else {
             // Format: start:end
             let end_line = end
                 .parse::<usize>()
-                .map_err(|_| UsageError(format!("Invalid end line: {}", end)))?;
+                .map_err(|_| invalid(format!("invalid end line '{}'", end)))?;
             if end_line < start_line {
-                return Err(UsageError(format!(
-                    "End line {} is less than start line {}",
+                return Err(invalid(format!(
+                    "end line {} is less than start line {}",
                     end_line, start_line
-                ))
-                .into());
+                )));
             }
             Ok((start_line, end_line))
         }
@@ -431,9 +852,9 @@ pub fn parse_line_range(range_spec: &str) -> Result<(usize, usize)> {
         // Single line
         let line = range_spec
             .parse::<usize>()
-            .map_err(|_| UsageError(format!("Invalid line number: {}", range_spec)))?;
+            .map_err(|_| invalid("not a line number".into()))?;
         if line == 0 {
-            return Err(UsageError("Line number must be >= 1, got 0".into()).into());
+            return Err(invalid("line number must be >= 1".into()));
         }
         Ok((line, line))
     }
@@ -760,11 +1181,10 @@ pub fn get_comment_style(
             multi_line_start: Some("/*".to_string()),
             multi_line_end: Some("*/".to_string()),
         }),
-        _ => Err(UsageError(format!(
-            "Unsupported file extension: .{}; use --comment-style or --config with a [global] single_line_delimiter",
-            extension
-        ))
-        .into()),
+        _ => Err(ToglError::UnsupportedLanguage {
+            path: path.to_path_buf(),
+            extension: extension.to_string(),
+        }),
     }
 }
 
@@ -799,7 +1219,10 @@ pub fn find_and_toggle_section(
             let section_end = match section_end {
                 Some(end) => end,
                 None => {
-                    return Err(UsageError(format!("Unclosed section ID={}", section_id)).into());
+                    return Err(ToglError::UnclosedMarker {
+                        id: section_id.to_string(),
+                        line: i + 1,
+                    });
                 }
             };
 
@@ -868,14 +1291,15 @@ pub fn toggle_variant_group(
 ) -> Result<String> {
     let variants = discover_variants(content, group);
     if variants.is_empty() {
-        return Err(UsageError(format!("no section or group '{group}' found")).into());
+        return Err(ToglError::SectionNotFound {
+            id: group.to_string(),
+        });
     }
     if force.is_none() && variants.len() >= 3 {
-        return Err(UsageError(format!(
-            "group '{group}' has {} variants; specify one with -S {group}:<name>",
-            variants.len()
-        ))
-        .into());
+        return Err(ToglError::AmbiguousGroup {
+            group: group.to_string(),
+            count: variants.len(),
+        });
     }
 
     let mut lines: Vec<String> = content.lines().map(String::from).collect();
@@ -906,7 +1330,9 @@ pub struct GroupSummary {
     pub section_type: SectionType,
     pub variant_count: usize,
     pub file_count: usize,
-    pub state: String,
+    pub state: SectionState,
+    /// Totals over every section of the group.
+    pub line_counts: LineCounts,
     pub variants: Vec<String>,
 }
 
@@ -933,12 +1359,16 @@ pub fn summarize_scan(sections: &[ScanSectionInfo]) -> Vec<GroupSummary> {
             };
 
             let files: BTreeSet<&String> = items.iter().map(|s| &s.file).collect();
-            let states: BTreeSet<&String> = items.iter().map(|s| &s.state).collect();
+            let states: BTreeSet<SectionState> = items.iter().map(|s| s.state).collect();
             let state = if states.len() == 1 {
-                states.into_iter().next().unwrap().clone()
+                states.into_iter().next().unwrap()
             } else {
-                "mixed".to_string()
+                SectionState::Mixed
             };
+            let mut line_counts = LineCounts::default();
+            for s in &items {
+                line_counts += s.line_counts;
+            }
 
             GroupSummary {
                 group,
@@ -946,6 +1376,7 @@ pub fn summarize_scan(sections: &[ScanSectionInfo]) -> Vec<GroupSummary> {
                 variant_count: variants.len(),
                 file_count: files.len(),
                 state,
+                line_counts,
                 variants,
             }
         })
@@ -958,7 +1389,8 @@ pub struct ScanJsonFile {
     pub path: String,
     pub start: usize,
     pub end: Option<usize>,
-    pub state: String,
+    pub state: SectionState,
+    pub line_counts: LineCounts,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub desc: Option<String>,
 }
@@ -967,7 +1399,7 @@ pub struct ScanJsonFile {
 #[derive(Debug, Clone, serde::Serialize)]
 pub struct ScanJsonVariant {
     pub id: String,
-    pub state: String,
+    pub state: SectionState,
     pub files: Vec<ScanJsonFile>,
 }
 
@@ -1022,7 +1454,8 @@ pub fn build_scan_json(sections: &[ScanSectionInfo]) -> ScanJsonRoot {
                     path: s.file.clone(),
                     start: s.start_line,
                     end: s.end_line,
-                    state: s.state.clone(),
+                    state: s.state,
+                    line_counts: s.line_counts,
                     desc: s.description.clone(),
                 })
                 .collect();
@@ -1039,14 +1472,15 @@ pub fn build_scan_json(sections: &[ScanSectionInfo]) -> ScanJsonRoot {
             let variants = by_id
                 .into_iter()
                 .map(|(id, recs)| {
-                    let state = recs[0].state.clone();
+                    let state = recs[0].state;
                     let files = recs
                         .iter()
                         .map(|s| ScanJsonFile {
                             path: s.file.clone(),
                             start: s.start_line,
                             end: s.end_line,
-                            state: s.state.clone(),
+                            state: s.state,
+                            line_counts: s.line_counts,
                             desc: s.description.clone(),
                         })
                         .collect();
@@ -1080,6 +1514,9 @@ pub struct CheckIssue {
     pub group: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub file: Option<String>,
+    /// 1-based line of the marker at fault, when there is one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
     pub message: String,
 }
 
@@ -1100,20 +1537,27 @@ pub fn validate_sections(
                     level: CheckLevel::Err,
                     group: s.group.clone(),
                     file: Some(path.display().to_string()),
+                    line: Some(s.start_line),
                     message: format!("unclosed marker for ID={}", s.id),
                 });
             }
         }
-        let mut counts: HashMap<&str, usize> = HashMap::new();
+        // Each ID's occurrence count and the line of its second occurrence.
+        let mut counts: HashMap<&str, (usize, usize)> = HashMap::new();
         for s in sections {
-            *counts.entry(s.id.as_str()).or_insert(0) += 1;
+            let entry = counts.entry(s.id.as_str()).or_insert((0, 0));
+            entry.0 += 1;
+            if entry.0 == 2 {
+                entry.1 = s.start_line;
+            }
         }
-        for (id, n) in counts {
+        for (id, (n, line)) in counts {
             if n > 1 {
                 issues.push(CheckIssue {
                     level: CheckLevel::Err,
                     group: parse_id_parts(id).0,
                     file: Some(path.display().to_string()),
+                    line: Some(line),
                     message: format!("duplicate section ID '{id}' ({n} occurrences)"),
                 });
             }
@@ -1130,6 +1574,7 @@ pub fn validate_sections(
                 level: CheckLevel::Warn,
                 group: sum.group.clone(),
                 file: None,
+                line: None,
                 message: format!("{} variants, expected 2 (pair check)", sum.variant_count),
             });
         }
@@ -1151,6 +1596,7 @@ pub fn validate_sections(
                         level: CheckLevel::Warn,
                         group: sum.group.clone(),
                         file: Some(path.display().to_string()),
+                        line: None,
                         message: format!(
                             "missing variant(s): {}",
                             missing
@@ -1178,7 +1624,7 @@ pub fn activate_variant(
     let target_id = format!("{group}:{variant}");
     let variants = discover_variants(content, group);
     if !variants.iter().any(|s| s.id == target_id) {
-        return Err(UsageError(format!("variant '{target_id}' not found")).into());
+        return Err(ToglError::SectionNotFound { id: target_id });
     }
 
     let mut lines: Vec<String> = content.lines().map(String::from).collect();
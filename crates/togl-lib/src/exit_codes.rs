@@ -1,6 +1,9 @@
+use crate::confine::WriteKind;
 use std::fmt;
+use std::io;
+use std::path::PathBuf;
 
-/// Typed error for bad CLI input / range errors (maps to ExitCode::Usage).
+/// Typed error for bad CLI input (maps to ExitCode::Usage).
 /// Use this instead of bare `anyhow!()` for usage errors so that
 /// `classify_error` can downcast instead of matching on message strings.
 #[derive(Debug)]
@@ -14,6 +17,170 @@ impl fmt::Display for UsageError {
 
 impl std::error::Error for UsageError {}
 
+/// A file's content no longer matches the SHA-256 it had when it was read (or
+/// the one `--expect-sha256` named), so writing would clobber someone else's
+/// change. Maps to `ExitCode::ContentChanged`.
+#[derive(Debug)]
+pub struct ContentChangedError {
+    pub path: PathBuf,
+    pub expected_sha256: String,
+    /// None when the file no longer exists.
+    pub actual_sha256: Option<String>,
+}
+
+impl fmt::Display for ContentChangedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "'{}' does not have the expected content (expected sha256 {}, found {})",
+            self.path.display(),
+            self.expected_sha256,
+            self.actual_sha256.as_deref().unwrap_or("no file")
+        )
+    }
+}
+
+impl std::error::Error for ContentChangedError {}
+
+/// A write would land outside the `--root` directory, directly or through
+/// `..` or a symlink. Maps to `ExitCode::OutsideRoot`.
+#[derive(Debug)]
+pub struct OutsideRootError {
+    pub kind: WriteKind,
+    /// The path as togl was about to write it.
+    pub path: PathBuf,
+    /// Where that write would actually land.
+    pub resolved: PathBuf,
+    pub root: PathBuf,
+}
+
+impl fmt::Display for OutsideRootError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "refusing to write {} '{}'",
+            self.kind,
+            self.path.display()
+        )?;
+        if self.resolved != self.path {
+            write!(f, " (resolves to '{}')", self.resolved.display())?;
+        }
+        write!(f, ": outside the root '{}'", self.root.display())
+    }
+}
+
+impl std::error::Error for OutsideRootError {}
+
+/// Why a library operation failed, for callers that need to tell failures
+/// apart without parsing messages. Core transforms return it directly; the
+/// higher-level pipeline returns it inside `anyhow::Error`, where
+/// `downcast_ref::<ToglError>()` finds it.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ToglError {
+    /// No section, group or variant with this ID exists in the content.
+    SectionNotFound { id: String },
+    /// A group with three or more variants was toggled without naming one.
+    AmbiguousGroup { group: String, count: usize },
+    /// No comment style is known for the file's extension.
+    UnsupportedLanguage { path: PathBuf, extension: String },
+    /// Multi-line mode was asked for a language without block comments.
+    NoBlockComments { path: PathBuf },
+    /// The start marker on `line` (1-based) has no matching end marker.
+    UnclosedMarker { id: String, line: usize },
+    /// A section ID that is empty or contains whitespace or `"`.
+    InvalidSectionId { id: String },
+    /// A section description containing `"`.
+    InvalidDescription { desc: String },
+    /// A section with this ID already exists in the file.
+    DuplicateSection { id: String },
+    /// `id` was expected to be a `group:variant` ID in `group`.
+    NotAVariant { id: String, group: String },
+    /// A line pattern that does not compile, or that selects no single block.
+    InvalidPattern { pattern: String, reason: String },
+    /// A line range that does not parse, or ends before it starts.
+    InvalidRange { spec: String, reason: String },
+    /// A line past the end of the content.
+    LineOutOfRange { line: usize, lines: usize },
+    /// A directory was given without `-R`.
+    IsDirectory { path: PathBuf },
+    /// Reading `path` failed.
+    Io { path: PathBuf, source: io::Error },
+    /// `path` is not valid text in the encoding it was read as.
+    Encoding { path: PathBuf, source: io::Error },
+}
+
+impl ToglError {
+    /// The CLI exit code for this error.
+    pub fn exit_code(&self) -> ExitCode {
+        match self {
+            ToglError::Io { .. } | ToglError::Encoding { .. } => ExitCode::IoError,
+            _ => ExitCode::Usage,
+        }
+    }
+}
+
+impl fmt::Display for ToglError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ToglError::SectionNotFound { id } => write!(f, "no section or group '{id}' found"),
+            ToglError::AmbiguousGroup { group, count } => write!(
+                f,
+                "group '{group}' has {count} variants; specify one with -S {group}:<name>"
+            ),
+            ToglError::UnsupportedLanguage { extension, .. } => write!(
+                f,
+                "Unsupported file extension: .{extension}; use --comment-style or --config with a [global] single_line_delimiter"
+            ),
+            ToglError::NoBlockComments { path } => write!(
+                f,
+                "Multi-line comments not supported for {}",
+                path.display()
+            ),
+            ToglError::UnclosedMarker { id, line } => {
+                write!(f, "Unclosed section ID={id} (start marker on line {line})")
+            }
+            ToglError::InvalidSectionId { id } => write!(f, "Invalid section ID: '{id}'"),
+            ToglError::InvalidDescription { .. } => {
+                f.write_str("Section description must not contain '\"'")
+            }
+            ToglError::DuplicateSection { id } => {
+                write!(f, "Section ID '{id}' already exists in file")
+            }
+            ToglError::NotAVariant { id, group } => {
+                write!(f, "'{id}' is not a variant of group '{group}' (expected {group}:<name>)")
+            }
+            ToglError::InvalidPattern { pattern, reason } => {
+                write!(f, "Invalid pattern '{pattern}': {reason}")
+            }
+            ToglError::InvalidRange { spec, reason } => {
+                write!(f, "Invalid line range '{spec}': {reason}")
+            }
+            ToglError::LineOutOfRange { line, lines } => {
+                write!(f, "Line {line} is out of range (file has {lines} lines)")
+            }
+            ToglError::IsDirectory { path } => write!(
+                f,
+                "'{}' is a directory; use -R/--recursive to process directories",
+                path.display()
+            ),
+            ToglError::Io { path, .. } => write!(f, "could not read '{}'", path.display()),
+            ToglError::Encoding { path, .. } => {
+                write!(f, "could not decode '{}'", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ToglError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ToglError::Io { source, .. } | ToglError::Encoding { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
 /// Exit codes per Phase 0 PRD §0.8
 #[derive(Debug, Clone, Copy)]
 pub enum ExitCode {
@@ -28,17 +195,23 @@ pub enum ExitCode {
     /// EC04: Internal panic (reserved for future panic hook, not yet wired)
     #[allow(dead_code)]
     Internal = 4,
+    /// EC05: File changed between read and write (or --expect-sha256 mismatch)
+    ContentChanged = 5,
+    /// EC06: A write would land outside --root
+    OutsideRoot = 6,
 }
 
 impl ExitCode {
     /// Map to sysexits.h values for --posix-exit
     pub fn posix(self) -> i32 {
         match self {
-            Self::Success => 0,      // EX_OK
-            Self::Usage => 64,       // EX_USAGE
-            Self::IoError => 74,     // EX_IOERR
-            Self::ToggleError => 70, // EX_SOFTWARE
-            Self::Internal => 71,    // EX_OSERR
+            Self::Success => 0,         // EX_OK
+            Self::Usage => 64,          // EX_USAGE
+            Self::IoError => 74,        // EX_IOERR
+            Self::ToggleError => 70,    // EX_SOFTWARE
+            Self::Internal => 71,       // EX_OSERR
+            Self::ContentChanged => 75, // EX_TEMPFAIL
+            Self::OutsideRoot => 77,    // EX_NOPERM
         }
     }
 
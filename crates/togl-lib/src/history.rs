@@ -0,0 +1,419 @@
+// Operation history for `togl undo` / `togl history`.
+//
+// Every mutating run appends one JSON line to `.toggle/history.jsonl`
+// (relative to the CWD, like the atomic journal). A record holds, per file,
+// the SHA-256 of the on-disk bytes before and after the write plus a minimal
+// line-based reverse patch that turns the post-image back into the pre-image.
+// Undo verifies the current bytes still hash to the post-image before
+// applying anything, so edits made after the operation are never clobbered.
+
+use crate::journal;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use tempfile::NamedTempFile;
+
+/// Directory (relative to CWD) holding the history store.
+pub const HISTORY_DIR: &str = ".toggle";
+
+/// Name of the history file inside `HISTORY_DIR`.
+pub const HISTORY_FILENAME: &str = "history.jsonl";
+
+/// One hunk of a reverse patch, applied against the post-image's lines.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PatchHunk {
+    /// 0-based index of the first post-image line the hunk replaces.
+    pub at: usize,
+    /// Number of post-image lines to drop.
+    pub remove: usize,
+    /// Pre-image text to put back (terminators included).
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub insert: String,
+    /// Hex-encoded pre-image bytes, used instead of `insert` when they are
+    /// not valid UTF-8 (e.g. latin-1 or UTF-16 files).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub insert_hex: Option<String>,
+}
+
+impl PatchHunk {
+    fn insert_bytes(&self) -> io::Result<Vec<u8>> {
+        match &self.insert_hex {
+            Some(hex) => decode_hex(hex),
+            None => Ok(self.insert.as_bytes().to_vec()),
+        }
+    }
+}
+
+/// A single file touched by a recorded operation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileChange {
+    /// Absolute path of the file that was written.
+    pub path: PathBuf,
+    /// SHA-256 of the file's bytes before the operation.
+    pub pre_sha256: String,
+    /// SHA-256 of the file's bytes after the operation.
+    pub post_sha256: String,
+    /// Hunks turning the post-image back into the pre-image.
+    pub reverse_patch: Vec<PatchHunk>,
+}
+
+impl FileChange {
+    /// Build a change record from the exact bytes before and after a write.
+    pub fn new(path: &Path, pre: &[u8], post: &[u8]) -> Self {
+        let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        Self {
+            path,
+            pre_sha256: journal::sha256_hex(pre),
+            post_sha256: journal::sha256_hex(post),
+            reverse_patch: reverse_patch(pre, post),
+        }
+    }
+}
+
+/// One line of `history.jsonl`: everything a single run wrote.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryRecord {
+    /// Monotonic record number (1-based) within the store.
+    pub id: u64,
+    /// Timestamp of the operation.
+    pub created_at: String,
+    /// The command line that produced the change.
+    pub command: String,
+    /// Files written by the run, in write order.
+    pub files: Vec<FileChange>,
+    /// Set once the record has been reverted by `togl undo`.
+    #[serde(default)]
+    pub undone: bool,
+}
+
+/// A file whose current content no longer matches what a record expects.
+#[derive(Debug, Clone, Serialize)]
+pub struct UndoConflict {
+    /// Record the conflicting file belongs to.
+    pub record_id: u64,
+    pub path: PathBuf,
+    pub expected_sha256: String,
+    /// Hash of the current bytes, or None if the file is missing.
+    pub actual_sha256: Option<String>,
+}
+
+/// Undo refused because files changed after the operation being reverted.
+#[derive(Debug)]
+pub struct ConflictError(pub Vec<UndoConflict>);
+
+impl fmt::Display for ConflictError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "cannot undo: {} file(s) changed since the operation was recorded",
+            self.0.len()
+        )?;
+        for c in &self.0 {
+            match &c.actual_sha256 {
+                Some(actual) => write!(
+                    f,
+                    "\n  #{} {}: expected sha256 {}, found {}",
+                    c.record_id,
+                    c.path.display(),
+                    short_hash(&c.expected_sha256),
+                    short_hash(actual)
+                )?,
+                None => write!(
+                    f,
+                    "\n  #{} {}: file is missing",
+                    c.record_id,
+                    c.path.display()
+                )?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConflictError {}
+
+fn short_hash(hash: &str) -> &str {
+    &hash[..hash.len().min(12)]
+}
+
+/// The outcome of planning an undo: final contents to write, per file.
+#[derive(Debug)]
+pub struct UndoPlan {
+    /// Ids of the records being reverted, newest first.
+    pub record_ids: Vec<u64>,
+    /// Restored bytes per path, in first-seen order.
+    pub restores: Vec<(PathBuf, Vec<u8>)>,
+}
+
+/// Path of the history store under `dir`.
+pub fn history_path(dir: &Path) -> PathBuf {
+    dir.join(HISTORY_DIR).join(HISTORY_FILENAME)
+}
+
+/// Read all records. A missing store is an empty history.
+pub fn read_history(path: &Path) -> io::Result<Vec<HistoryRecord>> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(c) => c,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut records = Vec::new();
+    for (idx, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: HistoryRecord = serde_json::from_str(line).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "History file is corrupted at line {} ({}): {}",
+                    idx + 1,
+                    e,
+                    path.display()
+                ),
+            )
+        })?;
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// Append a record for `files` and return its id. Creates the store on first use.
+pub fn append_record(path: &Path, command: &str, files: Vec<FileChange>) -> io::Result<u64> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let id = last_record_id(path)?.map(|id| id + 1).unwrap_or(1);
+    let record = HistoryRecord {
+        id,
+        created_at: journal::chrono_lite_now(),
+        command: command.to_string(),
+        files,
+        undone: false,
+    };
+    let mut line = serde_json::to_string(&record)
+        .map_err(|e| io::Error::other(format!("Failed to serialize history record: {}", e)))?;
+    line.push('\n');
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    file.write_all(line.as_bytes())?;
+    Ok(id)
+}
+
+/// Id of the newest record, parsing only the last line of the store.
+fn last_record_id(path: &Path) -> io::Result<Option<u64>> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(c) => c,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    match content.lines().rev().find(|l| !l.trim().is_empty()) {
+        Some(line) => {
+            let record: HistoryRecord = serde_json::from_str(line).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("History file is corrupted ({}): {}", e, path.display()),
+                )
+            })?;
+            Ok(Some(record.id))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Flag the given records as undone, rewriting the store atomically.
+pub fn mark_undone(path: &Path, ids: &[u64]) -> io::Result<()> {
+    let mut records = read_history(path)?;
+    for record in &mut records {
+        if ids.contains(&record.id) {
+            record.undone = true;
+        }
+    }
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let mut tmp = NamedTempFile::new_in(dir)?;
+    for record in &records {
+        let line = serde_json::to_string(record)
+            .map_err(|e| io::Error::other(format!("Failed to serialize history record: {}", e)))?;
+        tmp.write_all(line.as_bytes())?;
+        tmp.write_all(b"\n")?;
+    }
+    tmp.persist(path).map_err(|e| e.error)?;
+    Ok(())
+}
+
+/// Plan reverting the `count` most recent records that are not yet undone.
+///
+/// Records are unwound newest first, chaining through files touched more than
+/// once. Every file must currently hash to the post-image its newest record
+/// expects; otherwise a `ConflictError` listing every mismatch is returned and
+/// nothing should be written.
+pub fn plan_undo(records: &[HistoryRecord], count: usize) -> anyhow::Result<UndoPlan> {
+    let targets: Vec<&HistoryRecord> = records
+        .iter()
+        .rev()
+        .filter(|r| !r.undone)
+        .take(count)
+        .collect();
+    if targets.is_empty() {
+        anyhow::bail!("nothing to undo");
+    }
+
+    let mut current: HashMap<PathBuf, Option<Vec<u8>>> = HashMap::new();
+    let mut order: Vec<PathBuf> = Vec::new();
+    let mut conflicts = Vec::new();
+    let mut conflicted: HashSet<PathBuf> = HashSet::new();
+
+    for record in &targets {
+        for change in record.files.iter().rev() {
+            if conflicted.contains(&change.path) {
+                continue;
+            }
+            if !current.contains_key(&change.path) {
+                let bytes = match std::fs::read(&change.path) {
+                    Ok(b) => Some(b),
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+                    Err(e) => return Err(e.into()),
+                };
+                current.insert(change.path.clone(), bytes);
+                order.push(change.path.clone());
+            }
+            let entry = current.get_mut(&change.path).expect("inserted above");
+            let actual = entry.as_deref().map(journal::sha256_hex);
+            if actual.as_deref() != Some(change.post_sha256.as_str()) {
+                conflicts.push(UndoConflict {
+                    record_id: record.id,
+                    path: change.path.clone(),
+                    expected_sha256: change.post_sha256.clone(),
+                    actual_sha256: actual,
+                });
+                // Keep checking the remaining files so the report is complete.
+                conflicted.insert(change.path.clone());
+                continue;
+            }
+            let post = entry.as_deref().unwrap_or_default();
+            let pre = apply_patch(post, &change.reverse_patch)?;
+            if journal::sha256_hex(&pre) != change.pre_sha256 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "History record #{} does not reproduce the original content of '{}'",
+                        record.id,
+                        change.path.display()
+                    ),
+                )
+                .into());
+            }
+            *entry = Some(pre);
+        }
+    }
+
+    if !conflicts.is_empty() {
+        return Err(ConflictError(conflicts).into());
+    }
+
+    let restores = order
+        .into_iter()
+        .map(|p| {
+            let bytes = current.remove(&p).flatten().unwrap_or_default();
+            (p, bytes)
+        })
+        .collect();
+    Ok(UndoPlan {
+        record_ids: targets.iter().map(|r| r.id).collect(),
+        restores,
+    })
+}
+
+/// Split bytes into lines, each keeping its `\n` terminator.
+fn split_lines(data: &[u8]) -> Vec<&[u8]> {
+    data.split_inclusive(|&b| b == b'\n').collect()
+}
+
+/// Compute the hunks that turn `post` back into `pre`.
+pub fn reverse_patch(pre: &[u8], post: &[u8]) -> Vec<PatchHunk> {
+    let old = split_lines(post);
+    let new = split_lines(pre);
+    let ops = similar::capture_diff_slices(similar::Algorithm::Myers, &old, &new);
+
+    let mut hunks: Vec<(usize, usize, Vec<u8>)> = Vec::new();
+    for op in ops {
+        let (tag, old_range, new_range) = op.as_tag_tuple();
+        if tag == similar::DiffTag::Equal {
+            continue;
+        }
+        let insert: Vec<u8> = new[new_range].concat();
+        match hunks.last_mut() {
+            // Adjacent delete + insert collapse into one replacement hunk.
+            Some((at, remove, bytes)) if *at + *remove == old_range.start => {
+                *remove += old_range.len();
+                bytes.extend_from_slice(&insert);
+            }
+            _ => hunks.push((old_range.start, old_range.len(), insert)),
+        }
+    }
+
+    hunks
+        .into_iter()
+        .map(|(at, remove, bytes)| match String::from_utf8(bytes) {
+            Ok(text) => PatchHunk {
+                at,
+                remove,
+                insert: text,
+                insert_hex: None,
+            },
+            Err(e) => PatchHunk {
+                at,
+                remove,
+                insert: String::new(),
+                insert_hex: Some(encode_hex(e.as_bytes())),
+            },
+        })
+        .collect()
+}
+
+/// Apply a reverse patch produced by `reverse_patch` to the post-image.
+pub fn apply_patch(post: &[u8], hunks: &[PatchHunk]) -> io::Result<Vec<u8>> {
+    let lines = split_lines(post);
+    let mut out = Vec::with_capacity(post.len());
+    let mut cursor = 0usize;
+    for hunk in hunks {
+        if hunk.at < cursor || hunk.at + hunk.remove > lines.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "reverse patch does not fit the current file",
+            ));
+        }
+        for line in &lines[cursor..hunk.at] {
+            out.extend_from_slice(line);
+        }
+        out.extend_from_slice(&hunk.insert_bytes()?);
+        cursor = hunk.at + hunk.remove;
+    }
+    for line in &lines[cursor..] {
+        out.extend_from_slice(line);
+    }
+    Ok(out)
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_hex(hex: &str) -> io::Result<Vec<u8>> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid hex in reverse patch");
+    if !hex.len().is_multiple_of(2) {
+        return Err(invalid());
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .and_then(|s| u8::from_str_radix(s, 16).ok())
+                .ok_or_else(invalid)
+        })
+        .collect()
+}
@@ -1,6 +1,8 @@
 // File I/O operations for the Toggle CLI
 
-use crate::journal::{self, Journal, JournalEntry, JOURNAL_FILENAME, LOCK_FILENAME};
+use crate::confine;
+use crate::exit_codes::ContentChangedError;
+use crate::journal::{self, BackupMethod, Journal, JournalEntry, JOURNAL_FILENAME, LOCK_FILENAME};
 use crate::platform;
 use similar::TextDiff;
 use std::fs::File;
@@ -8,6 +10,7 @@ use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tempfile::NamedTempFile;
 
 /// Read file content as UTF-8.
@@ -56,6 +59,161 @@ pub fn read_stdin_encoded(encoding: &str) -> io::Result<String> {
     Ok(decoded.into_owned())
 }
 
+/// Pseudo-label for `--encoding auto`: honor a byte-order mark, else accept
+/// valid UTF-8, else try the configured fallback encodings in order.
+pub const AUTO_ENCODING: &str = "auto";
+
+/// Legacy encodings tried by `auto` when no fallback list is configured.
+pub const DEFAULT_FALLBACK_ENCODINGS: &[&str] = &["windows-1252"];
+
+/// How a file's bytes were decoded, so the result can be written back in the
+/// same encoding with the same byte-order mark.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileEncoding {
+    /// Encoding Standard name (e.g. "UTF-8", "UTF-16LE", "windows-1252").
+    pub name: &'static str,
+    /// Whether the input started with a byte-order mark.
+    pub bom: bool,
+}
+
+impl FileEncoding {
+    /// Plain UTF-8 without a BOM.
+    pub fn utf8() -> Self {
+        Self {
+            name: encoding_rs::UTF_8.name(),
+            bom: false,
+        }
+    }
+
+    /// The byte-order mark to emit before the content (empty if none).
+    fn bom_bytes(&self) -> &'static [u8] {
+        if !self.bom {
+            return &[];
+        }
+        match self.name {
+            "UTF-8" => &[0xEF, 0xBB, 0xBF],
+            "UTF-16LE" => &[0xFF, 0xFE],
+            "UTF-16BE" => &[0xFE, 0xFF],
+            _ => &[],
+        }
+    }
+}
+
+/// Decode raw bytes according to an encoding label.
+///
+/// A UTF-8, UTF-16LE or UTF-16BE byte-order mark always wins and is stripped
+/// from the text (it is recorded in the returned [`FileEncoding`] instead).
+/// With the `auto` label and no BOM, valid UTF-8 is accepted as-is, otherwise
+/// each of `fallbacks` is tried in order ([`DEFAULT_FALLBACK_ENCODINGS`] when
+/// empty). Any other label decodes strictly in that encoding.
+pub fn decode_bytes(
+    bytes: &[u8],
+    encoding: &str,
+    fallbacks: &[String],
+) -> io::Result<(String, FileEncoding)> {
+    if let Some((enc, bom_len)) = encoding_rs::Encoding::for_bom(bytes) {
+        let text = enc
+            .decode_without_bom_handling_and_without_replacement(&bytes[bom_len..])
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Failed to decode content as {} (after BOM)", enc.name()),
+                )
+            })?;
+        let detected = FileEncoding {
+            name: enc.name(),
+            bom: true,
+        };
+        return Ok((text.into_owned(), detected));
+    }
+
+    if encoding.eq_ignore_ascii_case(AUTO_ENCODING) {
+        if let Ok(text) = std::str::from_utf8(bytes) {
+            return Ok((text.to_string(), FileEncoding::utf8()));
+        }
+        let defaults: Vec<String>;
+        let candidates = if fallbacks.is_empty() {
+            defaults = DEFAULT_FALLBACK_ENCODINGS
+                .iter()
+                .map(|s| s.to_string())
+                .collect();
+            &defaults
+        } else {
+            fallbacks
+        };
+        for label in candidates {
+            let enc = resolve_encoding(label)?;
+            if let Some(text) = enc.decode_without_bom_handling_and_without_replacement(bytes) {
+                let detected = FileEncoding {
+                    name: enc.name(),
+                    bom: false,
+                };
+                return Ok((text.into_owned(), detected));
+            }
+        }
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Content is not valid UTF-8 and did not decode as any fallback encoding ({})",
+                candidates.join(", ")
+            ),
+        ));
+    }
+
+    let enc = resolve_encoding(encoding)?;
+    let text = enc
+        .decode_without_bom_handling_and_without_replacement(bytes)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Failed to decode content as {}", encoding),
+            )
+        })?;
+    Ok((
+        text.into_owned(),
+        FileEncoding {
+            name: enc.name(),
+            bom: false,
+        },
+    ))
+}
+
+/// Read a file and decode it per [`decode_bytes`], returning the text and the
+/// encoding it was stored in.
+pub fn read_file_detect(
+    path: &Path,
+    encoding: &str,
+    fallbacks: &[String],
+) -> io::Result<(String, FileEncoding)> {
+    let bytes = std::fs::read(path)?;
+    decode_bytes(&bytes, encoding, fallbacks)
+}
+
+/// Read all of stdin and decode it per [`decode_bytes`].
+/// The filter-mode analog of [`read_file_detect`].
+pub fn read_stdin_detect(
+    encoding: &str,
+    fallbacks: &[String],
+) -> io::Result<(String, FileEncoding)> {
+    let mut bytes = Vec::new();
+    io::stdin().read_to_end(&mut bytes)?;
+    decode_bytes(&bytes, encoding, fallbacks)
+}
+
+/// Encode text back into the encoding it was read in, re-emitting its BOM.
+pub fn encode_text(content: &str, encoding: &FileEncoding) -> io::Result<Vec<u8>> {
+    let mut bytes = encoding.bom_bytes().to_vec();
+    bytes.extend(encode_string(content, encoding.name)?);
+    Ok(bytes)
+}
+
+/// Encode `content` per `encoding` and write it to stdout.
+/// The filter-mode analog of [`write_file_as`].
+pub fn write_stdout_as(content: &str, encoding: &FileEncoding) -> io::Result<()> {
+    let bytes = encode_text(content, encoding)?;
+    io::stdout().write_all(&bytes)
+}
+
 /// Encode `content` with the specified encoding and write it to stdout.
 /// The filter-mode analog of [`write_file_encoded`].
 pub fn write_stdout_encoded(content: &str, encoding: &str) -> io::Result<()> {
@@ -89,7 +247,7 @@ fn resolve_encoding(label: &str) -> io::Result<&'static encoding_rs::Encoding> {
 
 /// Check if an encoding label is valid/supported.
 pub fn is_valid_encoding(label: &str) -> bool {
-    if label.eq_ignore_ascii_case("utf-8") {
+    if label.eq_ignore_ascii_case("utf-8") || label.eq_ignore_ascii_case(AUTO_ENCODING) {
         return true;
     }
     resolve_encoding(label).is_ok()
@@ -101,6 +259,14 @@ fn encode_string(content: &str, encoding: &str) -> io::Result<Vec<u8>> {
         return Ok(content.as_bytes().to_vec());
     }
     let enc = resolve_encoding(encoding)?;
+    // encoding_rs never encodes *into* UTF-16 (its output encoding is UTF-8),
+    // so produce the code units by hand.
+    if enc == encoding_rs::UTF_16LE {
+        return Ok(content.encode_utf16().flat_map(u16::to_le_bytes).collect());
+    }
+    if enc == encoding_rs::UTF_16BE {
+        return Ok(content.encode_utf16().flat_map(u16::to_be_bytes).collect());
+    }
     let (encoded, _, had_errors) = enc.encode(content);
     if had_errors {
         return Err(io::Error::new(
@@ -162,11 +328,82 @@ pub fn write_file_encoded(
     write_bytes_impl(path, &bytes, temp_suffix, no_dereference)
 }
 
+/// Write file in a previously detected encoding (re-emitting its BOM), with
+/// symlink support.
+pub fn write_file_as(
+    path: &Path,
+    content: &str,
+    temp_suffix: Option<&str>,
+    no_dereference: bool,
+    encoding: &FileEncoding,
+) -> io::Result<()> {
+    write_file_as_checked(
+        path,
+        content,
+        temp_suffix,
+        no_dereference,
+        encoding,
+        None,
+        false,
+    )
+}
+
+/// Like [`write_file_as`], but if `expected_sha256` is given, re-hash the
+/// file just before the rename and fail with a [`ContentChangedError`] (the
+/// temp file is discarded) if it no longer matches, i.e. another process
+/// modified it since it was read. With `preserve_mtime` the replaced file
+/// keeps its access and modification times.
+pub fn write_file_as_checked(
+    path: &Path,
+    content: &str,
+    temp_suffix: Option<&str>,
+    no_dereference: bool,
+    encoding: &FileEncoding,
+    expected_sha256: Option<&str>,
+    preserve_mtime: bool,
+) -> io::Result<()> {
+    let bytes = encode_text(content, encoding)?;
+    write_bytes_checked(
+        path,
+        &bytes,
+        temp_suffix,
+        no_dereference,
+        expected_sha256,
+        preserve_mtime,
+    )
+}
+
+/// Fail with a [`ContentChangedError`] unless `path` hashes to `expected_sha256`.
+pub fn verify_unchanged(path: &Path, expected_sha256: &str) -> io::Result<()> {
+    let actual = match journal::sha256_file(path) {
+        Ok(h) if h == expected_sha256 => return Ok(()),
+        Ok(h) => Some(h),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e),
+    };
+    Err(io::Error::other(ContentChangedError {
+        path: path.to_path_buf(),
+        expected_sha256: expected_sha256.to_string(),
+        actual_sha256: actual,
+    }))
+}
+
 fn write_bytes_impl(
     path: &Path,
     bytes: &[u8],
     temp_suffix: Option<&str>,
     no_dereference: bool,
+) -> io::Result<()> {
+    write_bytes_checked(path, bytes, temp_suffix, no_dereference, None, false)
+}
+
+fn write_bytes_checked(
+    path: &Path,
+    bytes: &[u8],
+    temp_suffix: Option<&str>,
+    no_dereference: bool,
+    expected_sha256: Option<&str>,
+    preserve_mtime: bool,
 ) -> io::Result<()> {
     let write_path = if no_dereference && is_symlink(path) {
         resolve_symlink(path)?
@@ -174,6 +411,13 @@ fn write_bytes_impl(
         path.to_path_buf()
     };
     let dir = write_path.parent().unwrap_or(Path::new("."));
+    // The replacement takes over the original's owner, xattrs and mode.
+    let keep_metadata = |file: &File| -> io::Result<()> {
+        if write_path.exists() {
+            platform::copy_metadata(&write_path, file, preserve_mtime)?;
+        }
+        Ok(())
+    };
 
     if let Some(suffix) = temp_suffix {
         // Use explicit temp file name: file.py.tmp (append suffix, not replace extension)
@@ -183,13 +427,27 @@ fn write_bytes_impl(
         let temp_path = std::path::PathBuf::from(temp_name);
         let mut file = File::create(&temp_path)?;
         file.write_all(bytes)?;
-        file.sync_all()?;
+        if let Err(e) = keep_metadata(&file).and_then(|()| file.sync_all()) {
+            let _ = std::fs::remove_file(&temp_path);
+            return Err(e);
+        }
+        if let Some(expected) = expected_sha256 {
+            if let Err(e) = verify_unchanged(&write_path, expected) {
+                let _ = std::fs::remove_file(&temp_path);
+                return Err(e);
+            }
+        }
         std::fs::rename(&temp_path, &write_path)?;
     } else {
         // Use tempfile crate for safe atomic write
         let mut tmp = NamedTempFile::new_in(dir)?;
         tmp.write_all(bytes)?;
+        keep_metadata(tmp.as_file())?;
         tmp.as_file().sync_all()?;
+        // On mismatch, dropping `tmp` removes the temp file.
+        if let Some(expected) = expected_sha256 {
+            verify_unchanged(&write_path, expected)?;
+        }
         tmp.persist(&write_path).map_err(|e| e.error)?;
     }
 
@@ -199,16 +457,57 @@ fn write_bytes_impl(
 /// Print a unified diff between original and modified content.
 /// No-ops if content is identical.
 pub fn print_diff(path: &Path, original: &str, modified: &str) {
+    print!("{}", unified_diff(path, original, modified));
+}
+
+/// The unified diff `print_diff` prints; empty when nothing changed.
+pub fn unified_diff(path: &Path, original: &str, modified: &str) -> String {
     if original == modified {
-        return;
+        return String::new();
     }
     let diff = TextDiff::from_lines(original, modified);
     let path_str = path.display().to_string();
-    print!(
-        "{}",
-        diff.unified_diff()
-            .header(&format!("a/{}", path_str), &format!("b/{}", path_str))
-    );
+    diff.unified_diff()
+        .header(&format!("a/{}", path_str), &format!("b/{}", path_str))
+        .to_string()
+}
+
+/// A run of whole lines to replace: `original` lines `start..end` (0-based,
+/// end exclusive; `start == end` inserts) become `new_text`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineEdit {
+    pub start: usize,
+    pub end: usize,
+    /// The replacement lines, each with its line terminator.
+    pub new_text: String,
+}
+
+/// The changed line runs between `original` and `modified`, in order, so an
+/// editor can apply them without replacing the whole buffer.
+pub fn line_edits(original: &str, modified: &str) -> Vec<LineEdit> {
+    let diff = TextDiff::from_lines(original, modified);
+    let new_lines = diff.new_slices();
+    let mut edits: Vec<LineEdit> = Vec::new();
+    for op in diff.ops() {
+        let (tag, old, new) = op.as_tag_tuple();
+        if tag == similar::DiffTag::Equal {
+            continue;
+        }
+        let text: String = new_lines[new].concat();
+        match edits.last_mut() {
+            // A delete directly followed by an insert is one replacement.
+            Some(last) if last.end == old.start => {
+                last.end = old.end;
+                last.new_text.push_str(&text);
+            }
+            _ => edits.push(LineEdit {
+                start: old.start,
+                end: old.end,
+                new_text: text,
+            }),
+        }
+    }
+    edits
 }
 
 /// Create a backup copy of a file by appending the given extension.
@@ -236,6 +535,111 @@ pub fn normalize_eol(content: &str, eol: &str) -> String {
     }
 }
 
+/// Apply the `--eol` policy to transformed content.
+/// - "preserve": restore each line's original terminator (see [`restore_line_endings`])
+/// - "lf" / "crlf": normalize every line ending (see [`normalize_eol`])
+pub fn apply_eol(original: &str, modified: &str, eol: &str) -> String {
+    match eol {
+        "lf" | "crlf" => normalize_eol(modified, eol),
+        _ => restore_line_endings(original, modified),
+    }
+}
+
+/// Split content into `(body, terminator)` pairs, where the terminator is
+/// `"\r\n"`, `"\n"`, or `""` (only for a final line without a newline).
+fn split_line_endings(content: &str) -> Vec<(&str, &str)> {
+    let mut out = Vec::new();
+    let mut rest = content;
+    while !rest.is_empty() {
+        match rest.find('\n') {
+            Some(pos) => {
+                let line = &rest[..pos];
+                match line.strip_suffix('\r') {
+                    Some(body) => out.push((body, "\r\n")),
+                    None => out.push((line, "\n")),
+                }
+                rest = &rest[pos + 1..];
+            }
+            None => {
+                out.push((rest, ""));
+                rest = "";
+            }
+        }
+    }
+    out
+}
+
+/// Re-attach the original per-line terminators to `modified`.
+///
+/// The core transforms split on `lines()` and rejoin with `"\n"`, which turns
+/// CRLF (and mixed) files into LF. This aligns the modified lines against the
+/// original ones (a line diff on the bodies) and gives every line the
+/// terminator of the original line it came from, so untouched lines stay
+/// byte-identical. Lines with no original counterpart (e.g. inserted markers)
+/// inherit the nearest preceding terminator. Whether the file ends with a
+/// newline is taken from `modified`, which the transforms already keep in
+/// step with the original.
+pub fn restore_line_endings(original: &str, modified: &str) -> String {
+    if original == modified {
+        return modified.to_string();
+    }
+    let old = split_line_endings(original);
+    let new = split_line_endings(modified);
+    let old_bodies: Vec<&str> = old.iter().map(|(b, _)| *b).collect();
+    let new_bodies: Vec<&str> = new.iter().map(|(b, _)| *b).collect();
+
+    // For each modified line, the original terminator it maps to (if any).
+    let mut mapped: Vec<Option<&str>> = vec![None; new.len()];
+    let ops = similar::capture_diff_slices(similar::Algorithm::Myers, &old_bodies, &new_bodies);
+    for op in ops {
+        match op {
+            similar::DiffOp::Equal {
+                old_index,
+                new_index,
+                len,
+            } => {
+                for k in 0..len {
+                    mapped[new_index + k] = Some(old[old_index + k].1);
+                }
+            }
+            similar::DiffOp::Replace {
+                old_index,
+                old_len,
+                new_index,
+                new_len,
+            } => {
+                for k in 0..old_len.min(new_len) {
+                    mapped[new_index + k] = Some(old[old_index + k].1);
+                }
+            }
+            similar::DiffOp::Delete { .. } | similar::DiffOp::Insert { .. } => {}
+        }
+    }
+
+    let fallback = old
+        .iter()
+        .map(|(_, t)| *t)
+        .find(|t| !t.is_empty())
+        .unwrap_or("\n");
+    let ends_with_newline = modified.ends_with('\n');
+    let mut result = String::with_capacity(modified.len() + new.len());
+    let mut previous = fallback;
+    for (i, (body, _)) in new.iter().enumerate() {
+        result.push_str(body);
+        let is_last = i + 1 == new.len();
+        if is_last && !ends_with_newline {
+            break;
+        }
+        let term = match mapped[i] {
+            Some(t) if !t.is_empty() => t,
+            _ => previous,
+        };
+        result.push_str(term);
+        previous = term;
+    }
+    result
+}
+
 /// Function to detect if a file has UTF-8 BOM
 pub fn has_utf8_bom(content: &[u8]) -> bool {
     content.starts_with(&[0xEF, 0xBB, 0xBF])
@@ -300,6 +704,184 @@ pub struct StagedWrite {
     pub content_sha256: String,
     /// Original file permissions to copy to temp before rename.
     pub original_permissions: Option<std::fs::Permissions>,
+    /// SHA-256 hex digest of the target's content at staging time.
+    pub original_sha256: Option<String>,
+}
+
+/// Initial and maximum delay between lock attempts while waiting.
+const LOCK_BACKOFF_START: Duration = Duration::from_millis(10);
+const LOCK_BACKOFF_MAX: Duration = Duration::from_millis(500);
+
+/// Acquire the exclusive lock on `lock_path`, retrying with exponential
+/// backoff until `timeout` elapses (zero means a single attempt).
+///
+/// The lock is an OS advisory lock, so it is released automatically when its
+/// holder exits: a lock file left behind by a dead process never blocks, and
+/// is simply reclaimed. On success the holder metadata (PID, start time,
+/// command) is written into the file for other waiters to report.
+fn acquire_lock(
+    lock_path: &Path,
+    timeout: Duration,
+    interrupted: &AtomicBool,
+) -> io::Result<fd_lock::RwLock<File>> {
+    let deadline = Instant::now() + timeout;
+    let mut delay = LOCK_BACKOFF_START;
+    loop {
+        // Never truncate on open: the file holds the current holder's metadata.
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(lock_path)?;
+        let mut lock = fd_lock::RwLock::new(file);
+        let previous = journal::read_lock_holder(lock_path);
+        let acquired = match lock.try_write() {
+            // The previous holder removes the lock file on success. If it did
+            // so while we were opening it, we locked an orphaned inode that
+            // nobody else can see; back off and retry on the new file, still
+            // bounded by the deadline.
+            Ok(guard) if !platform::same_file(&guard, lock_path) => false,
+            Ok(mut guard) => {
+                if let Some(prev) = previous {
+                    if prev.pid != std::process::id() {
+                        eprintln!(
+                            "Warning: reclaimed stale lock left by PID {} ({}).",
+                            prev.pid, prev.command
+                        );
+                    }
+                }
+                let json = serde_json::to_string(&journal::LockHolder::current()).map_err(|e| {
+                    io::Error::other(format!("Failed to serialize lock holder: {}", e))
+                })?;
+                guard.set_len(0)?;
+                guard.write_all(json.as_bytes())?;
+                // Keep the lock held for as long as the RwLock (and its file
+                // descriptor) lives; dropping the guard would unlock it.
+                std::mem::forget(guard);
+                true
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => false,
+            Err(e) => return Err(e),
+        };
+        if acquired {
+            return Ok(lock);
+        }
+
+        let now = Instant::now();
+        if now >= deadline || interrupted.load(Ordering::Relaxed) {
+            return Err(lock_busy_error(lock_path, timeout));
+        }
+        std::thread::sleep(delay.min(deadline - now));
+        delay = (delay * 2).min(LOCK_BACKOFF_MAX);
+    }
+}
+
+/// Build the error for a lock that could not be acquired, naming the holder.
+fn lock_busy_error(lock_path: &Path, timeout: Duration) -> io::Error {
+    let holder = match journal::read_lock_holder(lock_path) {
+        Some(h) => {
+            let state = match platform::process_alive(h.pid) {
+                Some(false) => ", no longer running",
+                _ => "",
+            };
+            format!(
+                " Held by PID {}{} (started {}): {}.",
+                h.pid, state, h.started_at, h.command
+            )
+        }
+        None => String::new(),
+    };
+    let (kind, waited) = if timeout.is_zero() {
+        (io::ErrorKind::WouldBlock, String::new())
+    } else {
+        (
+            io::ErrorKind::TimedOut,
+            format!(" Gave up after waiting {:?}.", timeout),
+        )
+    };
+    io::Error::new(
+        kind,
+        format!(
+            "Another atomic operation is already in progress in this directory.{}{} \
+             Retry with --lock-timeout to wait for it.",
+            holder, waited
+        ),
+    )
+}
+
+/// How atomic mode backs up each target before replacing it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BackupStrategy {
+    /// Hard link only; fails on filesystems without hard links.
+    Hardlink,
+    /// Full copy with its own inode.
+    Copy,
+    /// Copy-on-write clone, falling back to a full copy.
+    ReflinkIfPossible,
+    /// Reflink, then hard link, then full copy: whichever works first.
+    #[default]
+    Auto,
+}
+
+impl BackupStrategy {
+    /// Parse a strategy name as used by `--backup-strategy` and the config.
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "hardlink" => Some(BackupStrategy::Hardlink),
+            "copy" => Some(BackupStrategy::Copy),
+            "reflink-if-possible" => Some(BackupStrategy::ReflinkIfPossible),
+            "auto" => Some(BackupStrategy::Auto),
+            _ => None,
+        }
+    }
+
+    /// Methods to try, in order.
+    fn methods(self) -> &'static [BackupMethod] {
+        match self {
+            BackupStrategy::Hardlink => &[BackupMethod::Hardlink],
+            BackupStrategy::Copy => &[BackupMethod::Copy],
+            BackupStrategy::ReflinkIfPossible => &[BackupMethod::Reflink, BackupMethod::Copy],
+            BackupStrategy::Auto => &[
+                BackupMethod::Reflink,
+                BackupMethod::Hardlink,
+                BackupMethod::Copy,
+            ],
+        }
+    }
+}
+
+/// Back up `src` to `dst` using the first method of `strategy` that works.
+/// Returns the method used, for recording in the journal. If every method
+/// fails, the last error is returned.
+pub fn create_atomic_backup(
+    ops: &dyn FileOps,
+    strategy: BackupStrategy,
+    src: &Path,
+    dst: &Path,
+) -> io::Result<BackupMethod> {
+    let mut last_err = None;
+    for &method in strategy.methods() {
+        let result = match method {
+            BackupMethod::Hardlink => ops.hard_link(src, dst),
+            BackupMethod::Reflink => ops.reflink(src, dst),
+            BackupMethod::Copy => ops.copy(src, dst),
+        };
+        match result {
+            Ok(()) => return Ok(method),
+            // Never fall back over a file we did not create.
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => return Err(e),
+            Err(e) => last_err = Some(e),
+        }
+    }
+    Err(last_err.unwrap_or_else(|| io::Error::other("no backup method available")))
+}
+
+/// Where atomic mode backs up `target`: `<target>.toggle-atomic-backup`.
+pub fn atomic_backup_path(target: &Path) -> PathBuf {
+    let mut bp = target.as_os_str().to_os_string();
+    bp.push(ATOMIC_BACKUP_EXT);
+    PathBuf::from(bp)
 }
 
 /// Manages a two-phase atomic commit of multiple file writes.
@@ -309,43 +891,38 @@ pub struct AtomicBatch {
     lock_path: PathBuf,
     _lock: Option<fd_lock::RwLock<File>>,
     backup_enabled: bool,
+    backup_strategy: BackupStrategy,
+    root: Option<confine::Root>,
+    preserve_mtime: bool,
     interrupted: Arc<AtomicBool>,
 }
 
 impl AtomicBatch {
     /// Create a new atomic batch. Acquires the lock file immediately.
     /// `targets` is used to determine the journal directory.
-    /// `backup_enabled` controls whether hard-link backups are created.
+    /// `backup_enabled` controls whether backups are created.
     /// `interrupted` is an AtomicBool set by signal handlers.
     pub fn new(
         targets: &[PathBuf],
         backup_enabled: bool,
         interrupted: Arc<AtomicBool>,
+    ) -> io::Result<Self> {
+        Self::with_lock_timeout(targets, backup_enabled, interrupted, Duration::ZERO)
+    }
+
+    /// Like `new`, but waits up to `lock_timeout` (with backoff) for another
+    /// process to release the lock instead of failing immediately.
+    pub fn with_lock_timeout(
+        targets: &[PathBuf],
+        backup_enabled: bool,
+        interrupted: Arc<AtomicBool>,
+        lock_timeout: Duration,
     ) -> io::Result<Self> {
         let dir = journal::journal_dir(targets)?;
         let lock_path = dir.join(LOCK_FILENAME);
         let journal_path = dir.join(JOURNAL_FILENAME);
 
-        // Acquire exclusive lock.
-        // We keep the RwLock (and its write guard implicitly via try_write)
-        // alive for the lifetime of the batch by storing the RwLock itself.
-        let lock_file = File::create(&lock_path)?;
-        let mut lock = fd_lock::RwLock::new(lock_file);
-        // Test that we can acquire the lock; this will fail if another
-        // atomic operation is running. The write guard is dropped immediately,
-        // but the underlying file descriptor (held by the RwLock) keeps the
-        // advisory lock on some platforms. We re-acquire below.
-        {
-            let _guard = lock.try_write().map_err(|_| {
-                io::Error::new(
-                    io::ErrorKind::WouldBlock,
-                    "Another atomic operation is already in progress in this directory. \
-                     Wait for it to complete or remove .toggle-atomic.lock if the previous \
-                     process crashed.",
-                )
-            })?;
-            // Guard dropped here but we keep the RwLock (and its fd) alive
-        }
+        let lock = acquire_lock(&lock_path, lock_timeout, &interrupted)?;
 
         Ok(Self {
             staged: Vec::new(),
@@ -353,28 +930,81 @@ impl AtomicBatch {
             lock_path,
             _lock: Some(lock),
             backup_enabled,
+            backup_strategy: BackupStrategy::default(),
+            root: None,
+            preserve_mtime: false,
             interrupted,
         })
     }
 
+    /// Choose how backups are taken (default: `BackupStrategy::Auto`).
+    pub fn with_backup_strategy(mut self, strategy: BackupStrategy) -> Self {
+        self.backup_strategy = strategy;
+        self
+    }
+
+    /// Refuse to stage a target, or to take a backup, outside `root`. The
+    /// journal and lock are created by the constructor, so callers check
+    /// those themselves first.
+    pub fn with_root(mut self, root: confine::Root) -> Self {
+        self.root = Some(root);
+        self
+    }
+
+    /// Keep each target's access and modification times across the commit.
+    pub fn with_preserve_mtime(mut self, preserve: bool) -> Self {
+        self.preserve_mtime = preserve;
+        self
+    }
+
     /// Stage a single file write: write content to a temp file in the same
     /// directory as the target, fsync it, then release the fd.
-    pub fn stage(&mut self, target_path: &Path, content: &[u8], _encoding: &str) -> io::Result<()> {
+    pub fn stage(&mut self, target_path: &Path, content: &[u8], encoding: &str) -> io::Result<()> {
+        self.stage_expecting(target_path, content, encoding, None)
+    }
+
+    /// Like `stage`, but take `expected_sha256` (the hash of the target when
+    /// its content was read) as the original instead of hashing it now.
+    /// `commit` re-checks every target against its original hash before the
+    /// first rename, so an edit made since the read aborts the batch.
+    pub fn stage_expecting(
+        &mut self,
+        target_path: &Path,
+        content: &[u8],
+        _encoding: &str,
+        expected_sha256: Option<&str>,
+    ) -> io::Result<()> {
+        // The journal records absolute paths so recovery works from any CWD.
+        let target_path = &std::path::absolute(target_path)?;
+        if let Some(root) = &self.root {
+            root.check(target_path, confine::WriteKind::Target)?;
+            if self.backup_enabled {
+                root.check(&atomic_backup_path(target_path), confine::WriteKind::Backup)?;
+            }
+        }
+        if let (Some(expected), false) = (expected_sha256, target_path.exists()) {
+            // Deleted since it was read.
+            return verify_unchanged(target_path, expected);
+        }
         let target_dir = target_path.parent().unwrap_or(Path::new("."));
         let mut tmp = NamedTempFile::new_in(target_dir)?;
         let encoded = content.to_vec();
         tmp.write_all(&encoded)?;
-        platform::durable_sync(tmp.as_file())?;
 
-        // Copy permissions from original file if it exists
-        let original_permissions = if target_path.exists() {
+        // Copy owner, xattrs and permissions from the original file if it exists
+        let (original_permissions, original_sha256) = if target_path.exists() {
             let meta = std::fs::metadata(target_path)?;
             let perms = meta.permissions();
-            tmp.as_file().set_permissions(perms.clone()).ok();
-            Some(perms)
+            platform::copy_metadata(target_path, tmp.as_file(), self.preserve_mtime)?;
+            let original = match expected_sha256 {
+                Some(h) => h.to_string(),
+                None => journal::sha256_file(target_path)?,
+            };
+            (Some(perms), Some(original))
         } else {
-            None
+            (None, None)
         };
+        platform::durable_sync(tmp.as_file())?;
 
         let content_sha256 = journal::sha256_hex(&encoded);
 
@@ -391,6 +1021,7 @@ impl AtomicBatch {
             target_path: target_path.to_path_buf(),
             content_sha256,
             original_permissions,
+            original_sha256,
         });
 
         Ok(())
@@ -412,8 +1043,19 @@ impl AtomicBatch {
     /// Returns Ok(()) if all renames succeed. On failure, attempts rollback
     /// if backups are enabled.
     pub fn commit(self) -> io::Result<()> {
+        self.commit_staged()
+    }
+
+    /// Like [`commit`](Self::commit), then run `after` while the lock is
+    /// still held, so bookkeeping tied to the write (marking history records
+    /// undone, say) cannot interleave with another batch.
+    pub fn commit_then(self, after: impl FnOnce() -> io::Result<()>) -> io::Result<()> {
+        self.commit_staged()?;
+        after()
+    }
+
+    fn commit_staged(&self) -> io::Result<()> {
         if self.staged.is_empty() {
-            self.cleanup_lock();
             return Ok(());
         }
 
@@ -422,46 +1064,69 @@ impl AtomicBatch {
         // Build journal entries
         let mut journal_entries: Vec<JournalEntry> = Vec::with_capacity(self.staged.len());
         for sw in &self.staged {
-            let backup_path = if self.backup_enabled {
-                let mut bp = sw.target_path.as_os_str().to_os_string();
-                bp.push(ATOMIC_BACKUP_EXT);
-                Some(PathBuf::from(bp))
-            } else {
-                None
-            };
+            let backup_path = self
+                .backup_enabled
+                .then(|| atomic_backup_path(&sw.target_path));
             journal_entries.push(JournalEntry {
                 target_path: sw.target_path.clone(),
                 temp_path: sw.temp_path.clone(),
                 backup_path,
+                backup_method: None,
                 content_sha256: sw.content_sha256.clone(),
                 rename_completed: false,
+                original_sha256: sw.original_sha256.clone(),
             });
         }
 
         let mut j = Journal::new(journal_entries, self.backup_enabled);
 
-        // Persist journal in Staged state
+        // Persist journal in Staged state, then point every other directory
+        // of the batch (e.g. a bind-mounted one) at it
         journal::persist_journal(&j, &self.journal_path)?;
+        if let Err(e) = journal::write_journal_refs(&j, &self.journal_path) {
+            self.rollback_staged(&j);
+            return Err(e);
+        }
 
-        // Create hard-link backups if enabled
+        // Create backups if enabled, recording how each one was taken so
+        // recovery can tell whether it shares an inode with the original.
         if self.backup_enabled {
-            for entry in &j.entries {
+            for entry in &mut j.entries {
                 if let Some(ref backup_path) = entry.backup_path {
                     if entry.target_path.exists() {
-                        if let Err(e) = std::fs::hard_link(&entry.target_path, backup_path) {
-                            eprintln!(
-                                "Error: failed to create backup for '{}': {}",
-                                entry.target_path.display(),
-                                e
-                            );
-                            self.rollback_staged(&j);
-                            return Err(e);
+                        match create_atomic_backup(
+                            &RealFileOps,
+                            self.backup_strategy,
+                            &entry.target_path,
+                            backup_path,
+                        ) {
+                            Ok(method) => entry.backup_method = Some(method),
+                            Err(e) => {
+                                eprintln!(
+                                    "Error: failed to create backup for '{}': {}",
+                                    entry.target_path.display(),
+                                    e
+                                );
+                                self.rollback_staged(&j);
+                                return Err(e);
+                            }
                         }
                     }
                 }
             }
         }
 
+        // Last check before anything is replaced: a file edited since it was
+        // staged aborts the whole batch, with nothing renamed yet.
+        for sw in &self.staged {
+            if let Some(ref expected) = sw.original_sha256 {
+                if let Err(e) = verify_unchanged(&sw.target_path, expected) {
+                    self.rollback_staged(&j);
+                    return Err(e);
+                }
+            }
+        }
+
         // Transition to Committing
         j.transition_to_committing();
         journal::persist_journal(&j, &self.journal_path)?;
@@ -533,8 +1198,8 @@ impl AtomicBatch {
             }
         }
 
-        // Delete journal
-        journal::delete_journal(&self.journal_path)?;
+        // Delete journal and its pointers
+        journal::finish_journal(&j, &self.journal_path)?;
 
         // Clean up atomic backup files
         if self.backup_enabled {
@@ -545,7 +1210,6 @@ impl AtomicBatch {
             }
         }
 
-        self.cleanup_lock();
         Ok(())
     }
 
@@ -561,22 +1225,20 @@ impl AtomicBatch {
                 }
             }
         }
-        let _ = journal::delete_journal(&self.journal_path);
-        self.cleanup_lock();
-    }
-
-    /// Clean up the lock file.
-    fn cleanup_lock(&self) {
-        let _ = std::fs::remove_file(&self.lock_path);
+        let _ = journal::finish_journal(journal, &self.journal_path);
     }
 }
 
 impl Drop for AtomicBatch {
     fn drop(&mut self) {
-        // If we're being dropped without commit() having cleaned up,
-        // the lock file should still be removed.
+        // Remove the lock file exactly once, while the lock is still held
+        // (the `_lock` field is dropped after this runs). Removing it after
+        // unlocking could delete a file another process has since locked.
+        // Waiters still blocked on the removed inode notice via
+        // `platform::same_file` and retry on a fresh file.
         // Note: staged temp files are NOT cleaned up on drop since we called
         // keep() on them. The journal (if written) provides recovery info.
+        let _ = std::fs::remove_file(&self.lock_path);
     }
 }
 
@@ -585,6 +1247,10 @@ impl Drop for AtomicBatch {
 pub trait FileOps {
     fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
     fn hard_link(&self, src: &Path, dst: &Path) -> io::Result<()>;
+    /// Copy `src` to a new file `dst` with its own inode.
+    fn copy(&self, src: &Path, dst: &Path) -> io::Result<()>;
+    /// Copy-on-write clone `src` to a new file `dst`.
+    fn reflink(&self, src: &Path, dst: &Path) -> io::Result<()>;
     fn remove_file(&self, path: &Path) -> io::Result<()>;
     fn sync_dir(&self, path: &Path) -> io::Result<()>;
 }
@@ -601,6 +1267,14 @@ impl FileOps for RealFileOps {
         std::fs::hard_link(src, dst)
     }
 
+    fn copy(&self, src: &Path, dst: &Path) -> io::Result<()> {
+        platform::copy_durable(src, dst)
+    }
+
+    fn reflink(&self, src: &Path, dst: &Path) -> io::Result<()> {
+        platform::reflink(src, dst)
+    }
+
     fn remove_file(&self, path: &Path) -> io::Result<()> {
         std::fs::remove_file(path)
     }
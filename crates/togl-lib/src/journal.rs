@@ -15,6 +15,10 @@ use tempfile::NamedTempFile;
 /// Name of the journal file placed in CWD (or fallback directory).
 pub const JOURNAL_FILENAME: &str = ".toggle-atomic.journal";
 
+/// Name of the file left in each directory of a batch that points to the
+/// journal, so `--recover` finds it from any of the batch's directories.
+pub const JOURNAL_REF_FILENAME: &str = ".toggle-atomic.journal-ref";
+
 /// Name of the lock file for concurrent execution prevention.
 pub const LOCK_FILENAME: &str = ".toggle-atomic.lock";
 
@@ -28,6 +32,29 @@ pub enum JournalStatus {
     Committing,
 }
 
+/// How an entry's backup was actually taken (see `io::BackupStrategy`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackupMethod {
+    /// Hard link to the original inode. Cheap, but an in-place edit of the
+    /// original by another tool also changes the backup.
+    Hardlink,
+    /// Copy-on-write clone with its own inode.
+    Reflink,
+    /// Full byte copy with its own inode.
+    Copy,
+}
+
+impl BackupMethod {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BackupMethod::Hardlink => "hardlink",
+            BackupMethod::Reflink => "reflink",
+            BackupMethod::Copy => "copy",
+        }
+    }
+}
+
 /// A single file entry in the journal.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JournalEntry {
@@ -35,13 +62,34 @@ pub struct JournalEntry {
     pub target_path: PathBuf,
     /// Path to the staged temp file containing the new content.
     pub temp_path: PathBuf,
-    /// Path to the backup (hard-link) of the original file.
+    /// Path to the backup of the original file.
     /// None if --no-backup was used.
     pub backup_path: Option<PathBuf>,
+    /// How the backup was taken. None until the backup exists, for new files,
+    /// and for journals written by older versions (which always hard-linked).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup_method: Option<BackupMethod>,
     /// SHA-256 hex digest of the temp file content for integrity verification.
     pub content_sha256: String,
     /// Whether this entry's rename (temp -> target) has completed.
     pub rename_completed: bool,
+    /// SHA-256 hex digest of the target before the batch, used to verify the
+    /// backup. None for new files and journals written by older versions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub original_sha256: Option<String>,
+}
+
+/// Targets of a batch that live on one filesystem. Each target is staged and
+/// backed up in its own directory, so every rename stays within its group's
+/// filesystem; the journal records the groups so recovery can visit them all.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StagingGroup {
+    /// Device ID of the filesystem (None where the platform has no such ID).
+    pub device: Option<u64>,
+    /// Deepest directory containing every target of the group.
+    pub dir: PathBuf,
+    /// Indices into `Journal::entries`.
+    pub entries: Vec<usize>,
 }
 
 /// The write-ahead journal persisted to disk.
@@ -57,18 +105,24 @@ pub struct Journal {
     pub backup_enabled: bool,
     /// Per-file entries tracking the state of each staged write.
     pub entries: Vec<JournalEntry>,
+    /// Per-filesystem grouping of `entries`. Empty for journals written by
+    /// older versions, which kept everything relative to the journal's CWD.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<StagingGroup>,
 }
 
 impl Journal {
     /// Create a new journal in Staged state.
     pub fn new(entries: Vec<JournalEntry>, backup_enabled: bool) -> Self {
         let now = chrono_lite_now();
+        let groups = staging_groups(&entries);
         Self {
             version: 1,
             status: JournalStatus::Staged,
             created_at: now,
             backup_enabled,
             entries,
+            groups,
         }
     }
 
@@ -85,6 +139,36 @@ impl Journal {
     }
 }
 
+/// Metadata the lock holder writes into the lock file, so a blocked process
+/// can report who holds the lock.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockHolder {
+    /// PID of the process holding the lock.
+    pub pid: u32,
+    /// Timestamp of lock acquisition.
+    pub started_at: String,
+    /// Command line of the holding process.
+    pub command: String,
+}
+
+impl LockHolder {
+    /// Describe the current process.
+    pub fn current() -> Self {
+        Self {
+            pid: std::process::id(),
+            started_at: chrono_lite_now(),
+            command: std::env::args().collect::<Vec<_>>().join(" "),
+        }
+    }
+}
+
+/// Read the holder metadata from a lock file. Returns None if the file is
+/// missing, empty or unreadable (e.g. written by an older version).
+pub fn read_lock_holder(lock_path: &Path) -> Option<LockHolder> {
+    let content = std::fs::read_to_string(lock_path).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
 /// Compute SHA-256 hex digest of a byte slice.
 pub fn sha256_hex(data: &[u8]) -> String {
     let mut hasher = Sha256::new();
@@ -98,6 +182,39 @@ pub fn sha256_file(path: &Path) -> io::Result<String> {
     Ok(sha256_hex(&data))
 }
 
+/// Group entries by the filesystem their target directory lives on.
+pub fn staging_groups(entries: &[JournalEntry]) -> Vec<StagingGroup> {
+    let mut groups: Vec<StagingGroup> = Vec::new();
+    for (idx, entry) in entries.iter().enumerate() {
+        let parent = match entry.target_path.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p,
+            _ => Path::new("."),
+        };
+        let device = platform::device_id(parent);
+        match groups.iter_mut().find(|g| g.device == device) {
+            Some(group) => {
+                group.dir = common_ancestor(&group.dir, parent);
+                group.entries.push(idx);
+            }
+            None => groups.push(StagingGroup {
+                device,
+                dir: parent.to_path_buf(),
+                entries: vec![idx],
+            }),
+        }
+    }
+    groups
+}
+
+/// Longest common leading path of `a` and `b`.
+fn common_ancestor(a: &Path, b: &Path) -> PathBuf {
+    a.components()
+        .zip(b.components())
+        .take_while(|(x, y)| x == y)
+        .map(|(x, _)| x)
+        .collect()
+}
+
 /// Determine the journal directory. Prefers CWD; falls back to the first
 /// target file's parent if CWD is not writable.
 pub fn journal_dir(targets: &[PathBuf]) -> io::Result<PathBuf> {
@@ -182,6 +299,83 @@ pub fn delete_journal(journal_path: &Path) -> io::Result<()> {
     }
 }
 
+/// Directories (other than the journal's) that get a pointer to the journal:
+/// the parent of every target, which staging already requires to be writable.
+fn journal_ref_dirs(journal: &Journal, journal_path: &Path) -> Vec<PathBuf> {
+    let journal_dir = journal_path.parent().unwrap_or(Path::new("."));
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    for entry in &journal.entries {
+        if let Some(parent) = entry.target_path.parent() {
+            if parent != journal_dir && !dirs.iter().any(|d| d == parent) {
+                dirs.push(parent.to_path_buf());
+            }
+        }
+    }
+    dirs
+}
+
+/// Leave a pointer to the journal in every directory of the batch, so
+/// recovery can be started from any of them. Pointers are only a search aid
+/// and are not fsynced.
+pub fn write_journal_refs(journal: &Journal, journal_path: &Path) -> io::Result<()> {
+    let target = std::path::absolute(journal_path)?;
+    for dir in journal_ref_dirs(journal, journal_path) {
+        let mut tmp = NamedTempFile::new_in(&dir)?;
+        tmp.write_all(target.as_os_str().as_encoded_bytes())?;
+        tmp.persist(dir.join(JOURNAL_REF_FILENAME))
+            .map_err(|e| e.error)?;
+    }
+    Ok(())
+}
+
+/// Remove the journal together with the pointers that lead to it. A pointer
+/// since overwritten by another batch is left alone.
+pub fn finish_journal(journal: &Journal, journal_path: &Path) -> io::Result<()> {
+    let ours = std::path::absolute(journal_path)?;
+    for dir in journal_ref_dirs(journal, journal_path) {
+        let ref_path = dir.join(JOURNAL_REF_FILENAME);
+        if read_journal_ref(&ref_path).as_deref() == Some(ours.as_path()) {
+            let _ = std::fs::remove_file(&ref_path);
+        }
+    }
+    delete_journal(journal_path)
+}
+
+fn read_journal_ref(ref_path: &Path) -> Option<PathBuf> {
+    let content = std::fs::read_to_string(ref_path).ok()?;
+    Some(PathBuf::from(content.trim_end()))
+}
+
+/// Find the journal of an interrupted batch. Looks in each start directory
+/// (or a file's directory) for the journal itself or a pointer to it, but
+/// not in their ancestors, so another project's journal higher up is never
+/// picked. Pointers whose journal is gone are ignored, and a start that
+/// cannot be resolved is skipped.
+pub fn locate_journal(starts: &[PathBuf]) -> Option<PathBuf> {
+    for start in starts {
+        let Ok(start) = std::path::absolute(start) else {
+            continue;
+        };
+        let Some(dir) = (if start.is_dir() {
+            Some(start.as_path())
+        } else {
+            start.parent()
+        }) else {
+            continue;
+        };
+        let candidate = dir.join(JOURNAL_FILENAME);
+        if candidate.exists() {
+            return Some(candidate);
+        }
+        if let Some(target) = read_journal_ref(&dir.join(JOURNAL_REF_FILENAME)) {
+            if target.exists() {
+                return Some(target);
+            }
+        }
+    }
+    None
+}
+
 /// Recover from a Staged journal: delete all temp files, delete journal.
 /// No originals were touched, so this is always safe.
 pub fn recover_staged(journal: &Journal, journal_path: &Path) -> io::Result<()> {
@@ -197,7 +391,7 @@ pub fn recover_staged(journal: &Journal, journal_path: &Path) -> io::Result<()>
             }
         }
     }
-    delete_journal(journal_path)?;
+    finish_journal(journal, journal_path)?;
     eprintln!("Recovery complete. No original files were modified.");
     Ok(())
 }
@@ -241,7 +435,7 @@ pub fn recover_rollback(journal: &Journal, journal_path: &Path) -> io::Result<()
                 let _ = std::fs::remove_file(&entry.temp_path);
             }
         }
-        delete_journal(journal_path)?;
+        finish_journal(journal, journal_path)?;
         return Err(io::Error::other(
             "Rollback impossible without backups. See output above for affected files.",
         ));
@@ -252,7 +446,15 @@ pub fn recover_rollback(journal: &Journal, journal_path: &Path) -> io::Result<()
     for entry in completed.iter().rev() {
         if let Some(ref backup_path) = entry.backup_path {
             if backup_path.exists() {
-                if let Err(e) = platform::rename_with_retry(backup_path, &entry.target_path) {
+                if let Some(reason) = backup_mismatch(entry, backup_path) {
+                    errors.push(format!(
+                        "Refusing to restore '{}' from backup '{}': {}",
+                        entry.target_path.display(),
+                        backup_path.display(),
+                        reason
+                    ));
+                } else if let Err(e) = platform::rename_with_retry(backup_path, &entry.target_path)
+                {
                     errors.push(format!(
                         "Failed to restore '{}' from backup '{}': {}",
                         entry.target_path.display(),
@@ -298,11 +500,32 @@ pub fn recover_rollback(journal: &Journal, journal_path: &Path) -> io::Result<()
         }
     }
 
-    delete_journal(journal_path)?;
+    finish_journal(journal, journal_path)?;
     eprintln!("Rollback complete. All files restored to pre-operation state.");
     Ok(())
 }
 
+/// Why a backup no longer holds the original content, if it doesn't.
+/// Entries without a recorded original hash (older journals) are trusted.
+fn backup_mismatch(entry: &JournalEntry, backup_path: &Path) -> Option<String> {
+    let expected = entry.original_sha256.as_ref()?;
+    match sha256_file(backup_path) {
+        Ok(actual) if &actual == expected => None,
+        Ok(_) => Some(match entry.backup_method {
+            // Journals from older versions always hard-linked.
+            Some(BackupMethod::Hardlink) | None => "backup content changed since it was taken \
+                 (a hard-link backup shares its inode with the original, so an in-place \
+                 edit by another tool also changes it)"
+                .to_string(),
+            Some(method) => format!(
+                "{} backup content changed since it was taken",
+                method.as_str()
+            ),
+        }),
+        Err(e) => Some(format!("cannot read backup: {}", e)),
+    }
+}
+
 /// Forward recovery: complete the interrupted commit by renaming remaining
 /// temp files to their targets.
 pub fn recover_forward(journal: &Journal, journal_path: &Path) -> io::Result<()> {
@@ -323,7 +546,7 @@ pub fn recover_forward(journal: &Journal, journal_path: &Path) -> io::Result<()>
                 let _ = std::fs::remove_file(backup_path);
             }
         }
-        delete_journal(journal_path)?;
+        finish_journal(journal, journal_path)?;
         return Ok(());
     }
 
@@ -410,11 +633,36 @@ pub fn recover_forward(journal: &Journal, journal_path: &Path) -> io::Result<()>
             let _ = std::fs::remove_file(backup_path);
         }
     }
-    delete_journal(journal_path)?;
+    finish_journal(journal, journal_path)?;
     eprintln!("Forward recovery complete. All files updated.");
     Ok(())
 }
 
+/// Refuse to recover while a group's directory sits on a different
+/// filesystem than when the batch ran (e.g. a bind mount that is not mounted
+/// yet): its paths would resolve to the wrong files.
+fn check_groups_mounted(journal: &Journal) -> io::Result<()> {
+    for group in &journal.groups {
+        let Some(recorded) = group.device else {
+            continue;
+        };
+        let current = platform::device_id(&group.dir);
+        if current != Some(recorded) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "Files under '{}' were on device {} but it is now {}. \
+                     Remount the filesystem there, then retry the recovery.",
+                    group.dir.display(),
+                    recorded,
+                    current.map_or_else(|| "missing".to_string(), |d| d.to_string())
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
 /// Perform recovery based on journal state and user flags.
 pub fn perform_recovery(journal_path: &Path, forward: bool) -> io::Result<()> {
     let journal = match read_journal(journal_path)? {
@@ -425,6 +673,8 @@ pub fn perform_recovery(journal_path: &Path, forward: bool) -> io::Result<()> {
         }
     };
 
+    check_groups_mounted(&journal)?;
+
     match journal.status {
         JournalStatus::Staged => {
             if forward {
@@ -445,8 +695,101 @@ pub fn perform_recovery(journal_path: &Path, forward: bool) -> io::Result<()> {
     }
 }
 
+/// On-disk state of one journal entry, as checked by `verify_journal`.
+#[derive(Debug, Clone, Serialize)]
+pub struct EntryCheck {
+    pub target_path: PathBuf,
+    pub rename_completed: bool,
+    /// Whether the staged temp file still exists.
+    pub temp_exists: bool,
+    /// Whether the temp file hashes to `content_sha256` (false if missing).
+    pub temp_ok: bool,
+    /// Whether the target already holds the staged content.
+    pub target_updated: bool,
+    /// Whether the backup exists; None when backups were disabled.
+    pub backup_exists: Option<bool>,
+    /// Whether the backup hashes to `original_sha256`; None when either is
+    /// unavailable.
+    pub backup_ok: Option<bool>,
+}
+
+/// Result of checking a whole journal against disk.
+#[derive(Debug, Clone, Serialize)]
+pub struct JournalVerification {
+    pub status: JournalStatus,
+    pub entries: Vec<EntryCheck>,
+    /// `--recover` can restore every original.
+    pub rollback_safe: bool,
+    /// `--recover --recover-forward` can complete every write.
+    pub forward_safe: bool,
+}
+
+impl JournalVerification {
+    /// The recovery to run: "rollback", "forward", or "manual" when neither
+    /// is safe.
+    pub fn recommendation(&self) -> &'static str {
+        if self.rollback_safe {
+            "rollback"
+        } else if self.forward_safe {
+            "forward"
+        } else {
+            "manual"
+        }
+    }
+}
+
+/// Check every journal entry's temp file, target and backup against disk so
+/// an operator can choose between rollback and roll-forward.
+pub fn verify_journal(journal: &Journal) -> JournalVerification {
+    let hash_of = |path: &Path| sha256_file(path).ok();
+    let entries: Vec<EntryCheck> = journal
+        .entries
+        .iter()
+        .map(|entry| {
+            let temp_hash = hash_of(&entry.temp_path);
+            let target_hash = hash_of(&entry.target_path);
+            let backup_exists = entry.backup_path.as_ref().map(|p| p.exists());
+            let backup_ok = match (&entry.backup_path, &entry.original_sha256) {
+                (Some(path), Some(expected)) => Some(hash_of(path).as_ref() == Some(expected)),
+                _ => None,
+            };
+            EntryCheck {
+                target_path: entry.target_path.clone(),
+                rename_completed: entry.rename_completed,
+                temp_exists: entry.temp_path.exists(),
+                temp_ok: temp_hash.as_ref() == Some(&entry.content_sha256),
+                target_updated: target_hash.as_ref() == Some(&entry.content_sha256),
+                backup_exists,
+                backup_ok,
+            }
+        })
+        .collect();
+
+    // Rollback restores only entries flagged `rename_completed` (from their
+    // backups), so an updated target whose flag was never persisted would be
+    // silently left with the new content.
+    let rollback_safe = match journal.status {
+        JournalStatus::Staged => entries.iter().all(|e| !e.target_updated),
+        JournalStatus::Committing => entries.iter().all(|e| {
+            if e.rename_completed {
+                e.backup_exists == Some(true) && e.backup_ok != Some(false)
+            } else {
+                !e.target_updated
+            }
+        }),
+    };
+    let forward_safe = entries.iter().all(|e| e.target_updated || e.temp_ok);
+
+    JournalVerification {
+        status: journal.status,
+        entries,
+        rollback_safe,
+        forward_safe,
+    }
+}
+
 /// Simple ISO 8601 timestamp without external chrono dependency.
-fn chrono_lite_now() -> String {
+pub(crate) fn chrono_lite_now() -> String {
     use std::time::SystemTime;
     match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
         Ok(d) => format!("{}s-since-epoch", d.as_secs()),
@@ -480,6 +823,8 @@ mod tests {
                 backup_path: Some(PathBuf::from("/tmp/test.py.bak")),
                 content_sha256: "abc123".to_string(),
                 rename_completed: false,
+                original_sha256: None,
+                backup_method: None,
             }],
             true,
         );
@@ -524,6 +869,8 @@ mod tests {
                 backup_path: None,
                 content_sha256: "xxx".to_string(),
                 rename_completed: false,
+                original_sha256: None,
+                backup_method: None,
             }],
             false,
         );
@@ -554,6 +901,8 @@ mod tests {
                 backup_path: Some(PathBuf::from("/tmp/café/données.py.bak")),
                 content_sha256: "abc".to_string(),
                 rename_completed: false,
+                original_sha256: None,
+                backup_method: None,
             }],
             true,
         );
@@ -565,4 +914,211 @@ mod tests {
             PathBuf::from("/tmp/café/données.py")
         );
     }
+
+    /// Lay out one committing entry on disk: target with `target`, temp with
+    /// `temp` (None = missing), backup with the original content.
+    fn committing_entry(
+        dir: &Path,
+        target: &str,
+        temp: Option<&str>,
+        renamed: bool,
+    ) -> JournalEntry {
+        let target_path = dir.join("a.py");
+        let temp_path = dir.join(".tmp-a");
+        let backup_path = dir.join("a.py.toggle-atomic-backup");
+        std::fs::write(&target_path, target).unwrap();
+        if let Some(t) = temp {
+            std::fs::write(&temp_path, t).unwrap();
+        }
+        std::fs::write(&backup_path, "old\n").unwrap();
+        JournalEntry {
+            target_path,
+            temp_path,
+            backup_path: Some(backup_path),
+            content_sha256: sha256_hex(b"new\n"),
+            rename_completed: renamed,
+            original_sha256: Some(sha256_hex(b"old\n")),
+            backup_method: None,
+        }
+    }
+
+    #[test]
+    fn test_verify_journal_pending_entry_is_safe_both_ways() {
+        let dir = TempDir::new().unwrap();
+        let mut journal = Journal::new(
+            vec![committing_entry(dir.path(), "old\n", Some("new\n"), false)],
+            true,
+        );
+        journal.transition_to_committing();
+        let v = verify_journal(&journal);
+        assert!(v.entries[0].temp_ok);
+        assert!(!v.entries[0].target_updated);
+        assert!(v.rollback_safe);
+        assert!(v.forward_safe);
+        assert_eq!(v.recommendation(), "rollback");
+    }
+
+    #[test]
+    fn test_verify_journal_unflagged_rename_blocks_rollback() {
+        let dir = TempDir::new().unwrap();
+        // Renamed on disk but the completion flag was never persisted.
+        let mut journal = Journal::new(
+            vec![committing_entry(dir.path(), "new\n", None, false)],
+            true,
+        );
+        journal.transition_to_committing();
+        let v = verify_journal(&journal);
+        assert!(v.entries[0].target_updated);
+        assert!(!v.rollback_safe);
+        assert!(v.forward_safe);
+        assert_eq!(v.recommendation(), "forward");
+    }
+
+    #[test]
+    fn test_verify_journal_corrupt_backup_and_temp_is_manual() {
+        let dir = TempDir::new().unwrap();
+        let mut entry = committing_entry(dir.path(), "old\n", Some("garbage\n"), true);
+        std::fs::write(entry.backup_path.as_ref().unwrap(), "tampered\n").unwrap();
+        entry.rename_completed = true;
+        let mut journal = Journal::new(vec![entry], true);
+        journal.transition_to_committing();
+        let v = verify_journal(&journal);
+        assert_eq!(v.entries[0].backup_ok, Some(false));
+        assert!(v.entries[0].temp_exists);
+        assert!(!v.entries[0].temp_ok);
+        assert_eq!(v.recommendation(), "manual");
+    }
+
+    #[test]
+    fn test_recover_rollback_refuses_tampered_backup() {
+        let dir = TempDir::new().unwrap();
+        let mut entry = committing_entry(dir.path(), "new\n", None, true);
+        entry.backup_method = Some(BackupMethod::Hardlink);
+        std::fs::write(entry.backup_path.as_ref().unwrap(), "edited in place\n").unwrap();
+        let journal_path = dir.path().join(JOURNAL_FILENAME);
+        let mut journal = Journal::new(vec![entry], true);
+        journal.transition_to_committing();
+        persist_journal(&journal, &journal_path).unwrap();
+
+        assert!(recover_rollback(&journal, &journal_path).is_err());
+        // Target left alone, journal kept for manual recovery.
+        let target = std::fs::read_to_string(dir.path().join("a.py")).unwrap();
+        assert_eq!(target, "new\n");
+        assert!(journal_path.exists());
+    }
+
+    #[test]
+    fn test_backup_method_round_trips_and_defaults() {
+        let dir = TempDir::new().unwrap();
+        let journal_path = dir.path().join(JOURNAL_FILENAME);
+        let mut entry = committing_entry(dir.path(), "new\n", None, true);
+        entry.backup_method = Some(BackupMethod::Copy);
+        persist_journal(&Journal::new(vec![entry], true), &journal_path).unwrap();
+        let raw = std::fs::read_to_string(&journal_path).unwrap();
+        assert!(raw.contains("\"backup_method\": \"copy\""));
+        let loaded = read_journal(&journal_path).unwrap().unwrap();
+        assert_eq!(loaded.entries[0].backup_method, Some(BackupMethod::Copy));
+
+        // Journals written before the field existed still load.
+        let legacy = raw.replace("\"backup_method\": \"copy\",", "");
+        std::fs::write(&journal_path, legacy).unwrap();
+        let loaded = read_journal(&journal_path).unwrap().unwrap();
+        assert_eq!(loaded.entries[0].backup_method, None);
+    }
+
+    fn staged_entry(target_path: PathBuf) -> JournalEntry {
+        JournalEntry {
+            temp_path: target_path.with_extension("tmp"),
+            target_path,
+            backup_path: None,
+            backup_method: None,
+            content_sha256: "xxx".to_string(),
+            rename_completed: false,
+            original_sha256: None,
+        }
+    }
+
+    #[test]
+    fn test_staging_groups_share_one_filesystem() {
+        let dir = TempDir::new().unwrap();
+        let checkout = dir.path().join("checkout/src");
+        let config = dir.path().join("config");
+        std::fs::create_dir_all(&checkout).unwrap();
+        std::fs::create_dir_all(&config).unwrap();
+
+        let journal = Journal::new(
+            vec![
+                staged_entry(checkout.join("a.py")),
+                staged_entry(config.join("b.toml")),
+            ],
+            false,
+        );
+        assert_eq!(journal.groups.len(), 1);
+        assert_eq!(journal.groups[0].dir, dir.path());
+        assert_eq!(journal.groups[0].entries, vec![0, 1]);
+    }
+
+    #[test]
+    fn test_journal_refs_locate_and_finish() {
+        let dir = TempDir::new().unwrap();
+        let checkout = dir.path().join("checkout");
+        let config = dir.path().join("config");
+        std::fs::create_dir_all(&checkout).unwrap();
+        std::fs::create_dir_all(&config).unwrap();
+        let journal_path = checkout.join(JOURNAL_FILENAME);
+        let journal = Journal::new(
+            vec![
+                staged_entry(checkout.join("a.py")),
+                staged_entry(config.join("b.toml")),
+            ],
+            false,
+        );
+        persist_journal(&journal, &journal_path).unwrap();
+        write_journal_refs(&journal, &journal_path).unwrap();
+
+        // No pointer next to the journal itself; one in the other directory.
+        assert!(!checkout.join(JOURNAL_REF_FILENAME).exists());
+        assert!(config.join(JOURNAL_REF_FILENAME).exists());
+        assert_eq!(
+            locate_journal(&[config.join("b.toml")]),
+            Some(journal_path.clone())
+        );
+        assert_eq!(
+            locate_journal(std::slice::from_ref(&checkout)),
+            Some(journal_path.clone())
+        );
+        assert_eq!(locate_journal(&[dir.path().to_path_buf()]), None);
+        // Neither the journal nor a pointer is looked for in ancestors.
+        let nested = config.join("nested");
+        std::fs::create_dir_all(&nested).unwrap();
+        assert_eq!(locate_journal(&[checkout.join("sub/a.py")]), None);
+        assert_eq!(locate_journal(std::slice::from_ref(&nested)), None);
+        // A start that fails to resolve does not end the search.
+        assert_eq!(
+            locate_journal(&[PathBuf::new(), config.clone()]),
+            Some(journal_path.clone())
+        );
+
+        finish_journal(&journal, &journal_path).unwrap();
+        assert!(!journal_path.exists());
+        assert!(!config.join(JOURNAL_REF_FILENAME).exists());
+        assert_eq!(locate_journal(&[config]), None);
+    }
+
+    #[test]
+    fn test_recovery_refuses_when_group_filesystem_changed() {
+        let dir = TempDir::new().unwrap();
+        let journal_path = dir.path().join(JOURNAL_FILENAME);
+        let temp = dir.path().join("a.tmp");
+        std::fs::write(&temp, "staged").unwrap();
+        let mut journal = Journal::new(vec![staged_entry(dir.path().join("a.py"))], false);
+        // As if a different filesystem was mounted here when the batch ran.
+        journal.groups[0].device = Some(u64::MAX);
+        persist_journal(&journal, &journal_path).unwrap();
+
+        let err = perform_recovery(&journal_path, false).unwrap_err();
+        assert!(err.to_string().contains("Remount"));
+        assert!(temp.exists());
+        assert!(journal_path.exists());
+    }
 }
@@ -1,7 +1,14 @@
+pub mod backup;
 pub mod config;
+pub mod confine;
 pub mod core;
 pub mod exit_codes;
+pub mod history;
 pub mod io;
 pub mod journal;
+pub mod planfile;
 pub mod platform;
+pub mod preflight;
+pub mod select;
+pub mod session;
 pub mod walk;
@@ -0,0 +1,153 @@
+// Saved plans for `togl plan` / `togl apply`.
+//
+// A plan file records, for every file a run would change, the SHA-256 of the
+// bytes it was planned against, the operations to run on it and the SHA-256
+// of the bytes they produce (optionally with the unified diff, for review).
+// Applying it re-reads each file, refuses if any pre-image hash differs, and
+// replays the operations; a post-image that does not match the recorded hash
+// (a different config or comment style, say) is refused as well. Only then
+// is the batch committed, so what gets written is exactly what was reviewed.
+
+use crate::exit_codes::ContentChangedError;
+use crate::io;
+use crate::journal;
+use crate::session::{FilePlan, Force, Operation, Toggler};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Schema version written to new plan files.
+pub const PLAN_VERSION: u32 = 1;
+
+/// One file of a saved plan.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedFile {
+    /// The path as the planning run named it.
+    pub path: PathBuf,
+    /// SHA-256 of the file's bytes when the plan was made.
+    pub pre_sha256: String,
+    /// SHA-256 of the bytes applying the plan writes.
+    pub post_sha256: String,
+    /// Operations to run on the file, in order.
+    pub operations: Vec<Operation>,
+    /// Unified diff of the change, when requested; informational only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diff: Option<String>,
+}
+
+/// A reviewed set of changes, applied all or nothing by `togl apply`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlanFile {
+    /// Schema version for forward compatibility.
+    pub version: u32,
+    /// Timestamp of plan creation.
+    pub created_at: String,
+    /// The command line that made the plan.
+    pub command: String,
+    /// The `--force` state the operations were planned with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub force: Option<Force>,
+    /// Every file the plan changes, in planning order.
+    pub files: Vec<PlannedFile>,
+}
+
+/// The SHA-256 of the bytes writing `file` would produce.
+fn post_sha256(file: &FilePlan) -> Result<String> {
+    let encoded = io::encode_text(&file.modified, &file.encoding)
+        .with_context(|| format!("Failed to encode content for {}", file.path.display()))?;
+    Ok(journal::sha256_hex(&encoded))
+}
+
+impl PlanFile {
+    /// Record the changed files of `files`, planned with `force`.
+    pub fn new(files: &[FilePlan], force: Option<Force>, with_diff: bool) -> Result<Self> {
+        let files = files
+            .iter()
+            .filter(|f| f.is_changed())
+            .map(|f| {
+                Ok(PlannedFile {
+                    path: f.path.clone(),
+                    pre_sha256: f.sha256.clone(),
+                    post_sha256: post_sha256(f)?,
+                    operations: f.outcomes.iter().map(|o| o.operation.clone()).collect(),
+                    diff: with_diff.then(|| io::unified_diff(&f.path, &f.original, &f.modified)),
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            version: PLAN_VERSION,
+            created_at: journal::chrono_lite_now(),
+            command: std::env::args().collect::<Vec<_>>().join(" "),
+            force,
+            files,
+        })
+    }
+
+    /// Read a plan file, refusing versions this build does not know.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read plan {}", path.display()))?;
+        let plan: Self = serde_json::from_str(&text)
+            .with_context(|| format!("Failed to parse plan {}", path.display()))?;
+        if plan.version != PLAN_VERSION {
+            anyhow::bail!(
+                "plan {} has version {}; this togl reads version {}",
+                path.display(),
+                plan.version,
+                PLAN_VERSION
+            );
+        }
+        Ok(plan)
+    }
+
+    /// The plan as pretty-printed JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("plan serializes to JSON")
+    }
+
+    /// Files whose current content differs from the planned pre-image.
+    pub fn stale(&self) -> Vec<ContentChangedError> {
+        self.files
+            .iter()
+            .filter_map(|f| {
+                let actual = std::fs::read(&f.path)
+                    .ok()
+                    .map(|bytes| journal::sha256_hex(&bytes));
+                (actual.as_deref() != Some(f.pre_sha256.as_str())).then(|| ContentChangedError {
+                    path: f.path.clone(),
+                    expected_sha256: f.pre_sha256.clone(),
+                    actual_sha256: actual,
+                })
+            })
+            .collect()
+    }
+
+    /// Re-plan every file with `toggler` (built with [`PlanFile::force`]),
+    /// checking both hashes. Nothing is written.
+    pub fn replay(&self, toggler: &Toggler) -> Result<Vec<FilePlan>> {
+        if let Some(changed) = self.stale().into_iter().next() {
+            return Err(anyhow::Error::new(changed).context("the plan is out of date"));
+        }
+        self.files
+            .iter()
+            .map(|planned| {
+                let path = &planned.path;
+                let source = toggler.read(path)?;
+                let file = toggler
+                    .plan_source(path, source, &planned.operations)
+                    .with_context(|| format!("Failed to compute changes for {}", path.display()))?;
+                let actual = post_sha256(&file)?;
+                if actual != planned.post_sha256 {
+                    anyhow::bail!(
+                        "replaying the plan on {} gives a different result (expected sha256 {}, \
+                         got {}); re-run `togl plan` with the same config and options",
+                        path.display(),
+                        planned.post_sha256,
+                        actual
+                    );
+                }
+                Ok(file)
+            })
+            .collect()
+    }
+}
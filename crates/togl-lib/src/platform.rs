@@ -77,8 +77,277 @@ pub fn rename_with_retry(from: &Path, to: &Path) -> io::Result<()> {
     std::fs::rename(from, to)
 }
 
+/// Create `dst` as a copy-on-write clone of `src` (Linux `FICLONE`, e.g. on
+/// Btrfs and XFS). The clone shares extents but not the inode, so later
+/// in-place edits to either file do not affect the other. Fails with
+/// `Unsupported` (or the filesystem's own error) when cloning is unavailable;
+/// `dst` is never left behind on failure.
+#[cfg(target_os = "linux")]
+pub fn reflink(src: &Path, dst: &Path) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+    // _IOW(0x94, 9, int) from <linux/fs.h>.
+    const FICLONE: u32 = 0x4004_9409;
+    let source = File::open(src)?;
+    let dest = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(dst)?;
+    let ret = unsafe { libc::ioctl(dest.as_raw_fd(), FICLONE as _, source.as_raw_fd()) };
+    let result = if ret == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        dest.set_permissions(source.metadata()?.permissions())
+            .and_then(|()| durable_sync(&dest))
+    };
+    if result.is_err() {
+        drop(dest);
+        let _ = std::fs::remove_file(dst);
+    }
+    result
+}
+
+/// Create `dst` as a copy-on-write clone of `src` using `clonefile` (APFS).
+#[cfg(target_os = "macos")]
+pub fn reflink(src: &Path, dst: &Path) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    let to_c = |p: &Path| {
+        CString::new(p.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+    };
+    let (src_c, dst_c) = (to_c(src)?, to_c(dst)?);
+    let ret = unsafe { libc::clonefile(src_c.as_ptr(), dst_c.as_ptr(), 0) };
+    if ret == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    durable_sync(&File::open(dst)?)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn reflink(_src: &Path, _dst: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "reflink copies are not supported on this platform",
+    ))
+}
+
+/// Copy `src` to a new file `dst` (which must not exist), preserving
+/// permissions, and fsync it. Unlike a hard link the copy has its own inode.
+pub fn copy_durable(src: &Path, dst: &Path) -> io::Result<()> {
+    let mut source = File::open(src)?;
+    let mut dest = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(dst)?;
+    let result = io::copy(&mut source, &mut dest)
+        .and_then(|_| dest.set_permissions(source.metadata()?.permissions()))
+        .and_then(|()| durable_sync(&dest));
+    if result.is_err() {
+        drop(dest);
+        let _ = std::fs::remove_file(dst);
+    }
+    result
+}
+
 /// Resolve symlinks to their canonical target path.
 /// Atomic operations should operate on the real file, not the symlink entry.
 pub fn resolve_symlinks(path: &Path) -> io::Result<std::path::PathBuf> {
     std::fs::canonicalize(path)
 }
+
+/// Whether a process with the given PID is still running.
+/// Returns None when liveness cannot be determined on this platform.
+#[cfg(unix)]
+pub fn process_alive(pid: u32) -> Option<bool> {
+    let pid = libc::pid_t::try_from(pid).ok()?;
+    if pid <= 0 {
+        return Some(false);
+    }
+    // Signal 0 performs the permission/existence checks without sending anything.
+    let ret = unsafe { libc::kill(pid, 0) };
+    if ret == 0 {
+        return Some(true);
+    }
+    match io::Error::last_os_error().raw_os_error() {
+        Some(libc::ESRCH) => Some(false),
+        // EPERM: the process exists but belongs to someone else.
+        Some(libc::EPERM) => Some(true),
+        _ => None,
+    }
+}
+
+#[cfg(not(unix))]
+pub fn process_alive(_pid: u32) -> Option<bool> {
+    None
+}
+
+/// Device (filesystem) ID of `path`. Returns None when unavailable, in which
+/// case callers treat every path as living on the same filesystem.
+#[cfg(unix)]
+pub fn device_id(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    std::fs::metadata(path).ok().map(|m| m.dev())
+}
+
+#[cfg(not(unix))]
+pub fn device_id(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// Whether `path` still names the same file as the open handle `file`.
+/// Used after locking to detect a lock file that was removed and recreated
+/// by the previous holder while we were waiting on the old inode.
+#[cfg(unix)]
+pub fn same_file(file: &File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (file.metadata(), std::fs::metadata(path)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+pub fn same_file(_file: &File, path: &Path) -> bool {
+    // Windows does not allow deleting a file with an open handle by default,
+    // so the lock file cannot be swapped out from under a waiter.
+    path.exists()
+}
+
+/// Carry the metadata of `src` over to `dst`, the new file about to be
+/// renamed over it, so that replacing a file does not change who owns it or
+/// who may read it: owner and group, extended attributes (where Linux keeps
+/// POSIX ACLs and SELinux labels), permissions and, with `times`, the access
+/// and modification times. Owner and xattrs are best effort: only root can
+/// give a file away, and not every filesystem accepts every xattr namespace.
+pub fn copy_metadata(src: &Path, dst: &File, times: bool) -> io::Result<()> {
+    let meta = std::fs::metadata(src)?;
+    // Changing the owner clears set-user-ID bits, and setting an ACL rewrites
+    // the group bits, so both go before the permissions.
+    copy_owner(&meta, dst);
+    copy_xattrs(src, dst);
+    dst.set_permissions(meta.permissions())?;
+    if times {
+        let file_times = std::fs::FileTimes::new()
+            .set_accessed(meta.accessed()?)
+            .set_modified(meta.modified()?);
+        dst.set_times(file_times)?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn copy_owner(meta: &std::fs::Metadata, dst: &File) {
+    use std::os::unix::fs::MetadataExt;
+    let Ok(current) = dst.metadata() else {
+        return;
+    };
+    if (current.uid(), current.gid()) == (meta.uid(), meta.gid()) {
+        return;
+    }
+    // Without privileges the owner cannot change, but the group can still be
+    // set to any group the caller belongs to.
+    if std::os::unix::fs::fchown(dst, Some(meta.uid()), Some(meta.gid())).is_err() {
+        let _ = std::os::unix::fs::fchown(dst, None, Some(meta.gid()));
+    }
+}
+
+#[cfg(not(unix))]
+fn copy_owner(_meta: &std::fs::Metadata, _dst: &File) {}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn copy_xattrs(src: &Path, dst: &File) {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    let Ok(src_c) = CString::new(src.as_os_str().as_bytes()) else {
+        return;
+    };
+    let Some(names) = xattr::list(&src_c) else {
+        return;
+    };
+    for name in names.split(|&b| b == 0).filter(|n| !n.is_empty()) {
+        let Ok(name_c) = CString::new(name) else {
+            continue;
+        };
+        if let Some(value) = xattr::get(&src_c, &name_c) {
+            xattr::set(dst, &name_c, &value);
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn copy_xattrs(_src: &Path, _dst: &File) {}
+
+/// Thin wrappers over the xattr syscalls, whose signatures differ between
+/// Linux and macOS. Each queries the size first and gives up on any error.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+mod xattr {
+    use std::ffi::CStr;
+    use std::fs::File;
+    use std::os::unix::io::AsRawFd;
+
+    #[cfg(target_os = "linux")]
+    unsafe fn list_raw(path: &CStr, buf: *mut libc::c_char, size: usize) -> isize {
+        libc::listxattr(path.as_ptr(), buf, size)
+    }
+
+    #[cfg(target_os = "macos")]
+    unsafe fn list_raw(path: &CStr, buf: *mut libc::c_char, size: usize) -> isize {
+        libc::listxattr(path.as_ptr(), buf, size, 0)
+    }
+
+    #[cfg(target_os = "linux")]
+    unsafe fn get_raw(path: &CStr, name: &CStr, buf: *mut libc::c_void, size: usize) -> isize {
+        libc::getxattr(path.as_ptr(), name.as_ptr(), buf, size)
+    }
+
+    #[cfg(target_os = "macos")]
+    unsafe fn get_raw(path: &CStr, name: &CStr, buf: *mut libc::c_void, size: usize) -> isize {
+        libc::getxattr(path.as_ptr(), name.as_ptr(), buf, size, 0, 0)
+    }
+
+    #[cfg(target_os = "linux")]
+    unsafe fn set_raw(fd: libc::c_int, name: &CStr, value: &[u8]) -> libc::c_int {
+        libc::fsetxattr(fd, name.as_ptr(), value.as_ptr().cast(), value.len(), 0)
+    }
+
+    #[cfg(target_os = "macos")]
+    unsafe fn set_raw(fd: libc::c_int, name: &CStr, value: &[u8]) -> libc::c_int {
+        libc::fsetxattr(fd, name.as_ptr(), value.as_ptr().cast(), value.len(), 0, 0)
+    }
+
+    /// The NUL-separated attribute names of `path`.
+    pub(super) fn list(path: &CStr) -> Option<Vec<u8>> {
+        let size = unsafe { list_raw(path, std::ptr::null_mut(), 0) };
+        let mut buf = vec![0u8; usize::try_from(size).ok().filter(|&n| n > 0)?];
+        let len = unsafe { list_raw(path, buf.as_mut_ptr().cast(), buf.len()) };
+        buf.truncate(usize::try_from(len).ok()?);
+        Some(buf)
+    }
+
+    pub(super) fn get(path: &CStr, name: &CStr) -> Option<Vec<u8>> {
+        let size = unsafe { get_raw(path, name, std::ptr::null_mut(), 0) };
+        let mut buf = vec![0u8; usize::try_from(size).ok()?];
+        let len = unsafe { get_raw(path, name, buf.as_mut_ptr().cast(), buf.len()) };
+        buf.truncate(usize::try_from(len).ok()?);
+        Some(buf)
+    }
+
+    pub(super) fn set(file: &File, name: &CStr, value: &[u8]) {
+        unsafe { set_raw(file.as_raw_fd(), name, value) };
+    }
+}
+
+/// Number of hard links to the directory entry `path` (a symlink there is
+/// not followed), or None when unknown on this platform. Replacing a file
+/// through a rename gives the entry a new inode, so every other link to the
+/// old one keeps the old content.
+#[cfg(unix)]
+pub fn link_count(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    std::fs::symlink_metadata(path).ok().map(|m| m.nlink())
+}
+
+#[cfg(not(unix))]
+pub fn link_count(_path: &Path) -> Option<u64> {
+    None
+}
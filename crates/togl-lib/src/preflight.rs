@@ -0,0 +1,190 @@
+// Preflight safety checks run before a file is modified.
+//
+// Rejects files that are almost certainly not meant to be toggled: binary
+// data, generated code, files with unresolved merge conflicts, and files too
+// large to read whole. Each check can be overridden individually.
+
+use serde::Serialize;
+use std::fmt;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Default `--max-file-size`: 10 MiB.
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 10 * 1024 * 1024;
+
+/// Only the first bytes are searched for NULs, as git does.
+const BINARY_SNIFF_LEN: usize = 8000;
+
+/// Only the first lines are searched for a generated-code header.
+const GENERATED_HEADER_LINES: usize = 10;
+
+/// Header markers used by code generators (Go's `Code generated ... DO NOT
+/// EDIT.`, Meta's `@generated`, and the many tools that copy either).
+const GENERATED_MARKERS: &[&str] = &["@generated", "DO NOT EDIT"];
+
+/// Which checks to run.
+#[derive(Debug, Clone, Copy)]
+pub struct PreflightOptions {
+    pub allow_binary: bool,
+    pub allow_generated: bool,
+    pub allow_conflicts: bool,
+    /// Largest file to process, in bytes. None disables the limit.
+    pub max_file_size: Option<u64>,
+}
+
+impl Default for PreflightOptions {
+    fn default() -> Self {
+        Self {
+            allow_binary: false,
+            allow_generated: false,
+            allow_conflicts: false,
+            max_file_size: Some(DEFAULT_MAX_FILE_SIZE),
+        }
+    }
+}
+
+/// Why a file failed preflight. Serialized as `{"reason": "generated", ...}`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "reason", rename_all = "kebab-case")]
+pub enum SkipReason {
+    /// A NUL byte in the first 8000 bytes.
+    Binary,
+    /// A generated-code marker in the file header.
+    Generated { marker: String, line: usize },
+    /// A git conflict marker (`<<<<<<<`).
+    ConflictMarkers { line: usize },
+    /// Larger than the size limit.
+    TooLarge { size: u64, limit: u64 },
+}
+
+impl SkipReason {
+    /// Short machine-readable name, matching the serialized `reason`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            SkipReason::Binary => "binary",
+            SkipReason::Generated { .. } => "generated",
+            SkipReason::ConflictMarkers { .. } => "conflict-markers",
+            SkipReason::TooLarge { .. } => "too-large",
+        }
+    }
+
+    /// The flag that overrides this check.
+    pub fn override_flag(&self) -> &'static str {
+        match self {
+            SkipReason::Binary => "--allow-binary",
+            SkipReason::Generated { .. } => "--allow-generated",
+            SkipReason::ConflictMarkers { .. } => "--allow-conflicts",
+            SkipReason::TooLarge { .. } => "--max-file-size",
+        }
+    }
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SkipReason::Binary => f.write_str("binary file (contains NUL bytes)"),
+            SkipReason::Generated { marker, line } => {
+                write!(f, "generated file ('{}' on line {})", marker, line)
+            }
+            SkipReason::ConflictMarkers { line } => {
+                write!(f, "unresolved merge conflict (marker on line {})", line)
+            }
+            SkipReason::TooLarge { size, limit } => {
+                write!(f, "file is {} bytes, over the {} byte limit", size, limit)
+            }
+        }
+    }
+}
+
+/// Check a file on disk. The size limit is checked from metadata first, so
+/// an oversized file is never read.
+pub fn check_file(path: &Path, opts: &PreflightOptions) -> io::Result<Option<SkipReason>> {
+    let size = std::fs::metadata(path)?.len();
+    if let Some(limit) = opts.max_file_size {
+        if size > limit {
+            return Ok(Some(SkipReason::TooLarge { size, limit }));
+        }
+    }
+    let mut bytes = Vec::with_capacity(size as usize);
+    std::fs::File::open(path)?.read_to_end(&mut bytes)?;
+    Ok(check_bytes(&bytes, opts))
+}
+
+/// Check file content (the size limit is not applied here).
+pub fn check_bytes(bytes: &[u8], opts: &PreflightOptions) -> Option<SkipReason> {
+    if !opts.allow_binary && is_binary(bytes) {
+        return Some(SkipReason::Binary);
+    }
+    let text = String::from_utf8_lossy(bytes);
+    if !opts.allow_generated {
+        for (idx, line) in text.lines().take(GENERATED_HEADER_LINES).enumerate() {
+            if let Some(marker) = GENERATED_MARKERS.iter().find(|m| line.contains(*m)) {
+                return Some(SkipReason::Generated {
+                    marker: marker.to_string(),
+                    line: idx + 1,
+                });
+            }
+        }
+    }
+    if !opts.allow_conflicts {
+        if let Some(line) = conflict_marker_line(&text) {
+            return Some(SkipReason::ConflictMarkers { line });
+        }
+    }
+    None
+}
+
+/// A NUL byte near the start means binary, unless the file announces itself
+/// as UTF-16/32 with a BOM (where NUL bytes are normal).
+fn is_binary(bytes: &[u8]) -> bool {
+    let wide_bom = bytes.starts_with(&[0xFF, 0xFE]) || bytes.starts_with(&[0xFE, 0xFF]);
+    !wide_bom && bytes.iter().take(BINARY_SNIFF_LEN).any(|&b| b == 0)
+}
+
+/// 1-based line of the first `<<<<<<<` that is later closed by `>>>>>>>`.
+fn conflict_marker_line(text: &str) -> Option<usize> {
+    let mut open = None;
+    for (idx, line) in text.lines().enumerate() {
+        if open.is_none() && is_marker(line, "<<<<<<<") {
+            open = Some(idx + 1);
+        } else if open.is_some() && is_marker(line, ">>>>>>>") {
+            return open;
+        }
+    }
+    None
+}
+
+/// `line` is the marker alone or followed by a space (and a ref name).
+fn is_marker(line: &str, marker: &str) -> bool {
+    line.strip_prefix(marker)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(' '))
+}
+
+/// Parse a size such as `10M`, `512K`, `1G` or a plain byte count (binary
+/// units; a trailing `B`/`iB` is accepted). `0` and `none` disable the limit.
+pub fn parse_size(s: &str) -> Result<Option<u64>, String> {
+    let t = s.trim();
+    if t.eq_ignore_ascii_case("none") {
+        return Ok(None);
+    }
+    let upper = t.to_ascii_uppercase();
+    let stripped = upper
+        .strip_suffix("IB")
+        .or_else(|| upper.strip_suffix('B'))
+        .unwrap_or(&upper);
+    let split = stripped
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(stripped.len());
+    let (digits, unit) = stripped.split_at(split);
+    let invalid = || format!("invalid size '{}': expected e.g. 10M, 512K or 1048576", s);
+    let value: u64 = digits.parse().map_err(|_| invalid())?;
+    let multiplier: u64 = match unit {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        _ => return Err(invalid()),
+    };
+    let bytes = value.checked_mul(multiplier).ok_or_else(invalid)?;
+    Ok((bytes > 0).then_some(bytes))
+}
@@ -0,0 +1,440 @@
+//! The `-l` line-selection grammar, resolved against a file's content so an
+//! ad-hoc selection survives small edits to the file.
+//!
+//! A spec is a comma-separated list of `A`, `A:B` or `A:+N`, where each end
+//! is a line number, `$` (the last line), `-N` (counted from the end, `-1`
+//! being the last line) or `/regex/` (the first matching line; as an end, the
+//! first match after the start). The end may also be `block`: the end of
+//! the code block that starts on the start line (see [`block_end`]).
+//! `10:20,30:40`, `/def main/:+3`, `/BEGIN/:/END/`, `-5:$` and `42:block`
+//! are all valid.
+//!
+//! [`Locations`] reads the other common way to name lines: the `path:line`
+//! records that grep, compilers and linters print.
+
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+
+use crate::core::{compile_pattern, merge_ranges, pattern_matches, LineRange};
+use crate::exit_codes::ToglError;
+
+type Result<T> = std::result::Result<T, ToglError>;
+
+/// One end of a range.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Anchor {
+    /// A 1-based line number.
+    Line(usize),
+    /// `$`: the last line.
+    Last,
+    /// `-N`: the Nth line from the end.
+    FromEnd(usize),
+    /// `/regex/`, kept with its slashes for error messages.
+    Pattern(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum End {
+    Anchor(Anchor),
+    /// `+N`: N lines after the start.
+    Count(usize),
+    /// `block`: the last line of the block that starts on the start line.
+    Block,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Item {
+    start: Anchor,
+    end: Option<End>,
+}
+
+/// A parsed `-l` spec (see the module docs for the grammar).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineSpec {
+    spec: String,
+    items: Vec<Item>,
+}
+
+impl LineSpec {
+    /// Parse `spec`. Syntax errors, including a regex that does not compile,
+    /// are reported here; whether the lines exist is only known on
+    /// [`resolve`](Self::resolve).
+    pub fn parse(spec: &str) -> Result<Self> {
+        let invalid = |reason: String| ToglError::InvalidRange {
+            spec: spec.to_string(),
+            reason,
+        };
+        let mut items = Vec::new();
+        for item in split_outside_patterns(spec, ',') {
+            if item.is_empty() && spec.contains(',') {
+                return Err(invalid("empty entry in the list".into()));
+            }
+            let parts = split_outside_patterns(item, ':');
+            let item = match parts.as_slice() {
+                [single] => {
+                    let start = parse_anchor(single).map_err(|reason| {
+                        invalid(reason.unwrap_or_else(|| "not a line number".into()))
+                    })?;
+                    if start == Anchor::Line(0) {
+                        return Err(invalid("line number must be >= 1".into()));
+                    }
+                    Item { start, end: None }
+                }
+                [start, end] => {
+                    let start = parse_anchor(start).map_err(|reason| {
+                        invalid(reason.unwrap_or_else(|| format!("invalid start line '{start}'")))
+                    })?;
+                    if start == Anchor::Line(0) {
+                        return Err(invalid("start line must be >= 1".into()));
+                    }
+                    let end = if *end == "block" {
+                        End::Block
+                    } else if let Some(count) = end.strip_prefix('+') {
+                        End::Count(
+                            count
+                                .parse()
+                                .map_err(|_| invalid(format!("invalid line count '{count}'")))?,
+                        )
+                    } else {
+                        End::Anchor(parse_anchor(end).map_err(|reason| {
+                            invalid(reason.unwrap_or_else(|| format!("invalid end line '{end}'")))
+                        })?)
+                    };
+                    if let (Anchor::Line(s), End::Anchor(Anchor::Line(e))) = (&start, &end) {
+                        if e < s {
+                            return Err(invalid(format!(
+                                "end line {e} is less than start line {s}"
+                            )));
+                        }
+                    }
+                    Item {
+                        start,
+                        end: Some(end),
+                    }
+                }
+                _ => return Err(invalid("too many ':'".into())),
+            };
+            for anchor in [Some(&item.start), item.end.as_ref().and_then(End::anchor)]
+                .into_iter()
+                .flatten()
+            {
+                if let Anchor::Pattern(p) = anchor {
+                    compile_pattern(p)?;
+                }
+            }
+            items.push(item);
+        }
+        Ok(Self {
+            spec: spec.to_string(),
+            items,
+        })
+    }
+
+    /// The spec as written.
+    pub fn as_str(&self) -> &str {
+        &self.spec
+    }
+
+    /// The 1-based ranges this spec selects in `content`, in spec order.
+    /// Numbered lines are not checked against the length of `content`; that
+    /// is up to the caller, as for [`crate::core::parse_line_range`].
+    pub fn resolve(&self, content: &str) -> Result<Vec<LineRange>> {
+        let lines: Vec<&str> = content.lines().collect();
+        self.items
+            .iter()
+            .map(|item| {
+                let start = self.locate(&item.start, &lines, 0)?;
+                let end = match &item.end {
+                    None => start,
+                    Some(End::Count(n)) => start + n,
+                    Some(End::Block) if start == 0 || start > lines.len() => {
+                        return Err(ToglError::LineOutOfRange {
+                            line: start,
+                            lines: lines.len(),
+                        })
+                    }
+                    Some(End::Block) => block_end(&lines, start),
+                    Some(End::Anchor(anchor)) => self.locate(anchor, &lines, start)?,
+                };
+                if end < start {
+                    return Err(ToglError::InvalidRange {
+                        spec: self.spec.clone(),
+                        reason: format!("end line {end} is less than start line {start}"),
+                    });
+                }
+                Ok(LineRange::new(start, end))
+            })
+            .collect()
+    }
+
+    /// The 1-based line `anchor` points at; a pattern is searched for in the
+    /// lines after the first `after`.
+    fn locate(&self, anchor: &Anchor, lines: &[&str], after: usize) -> Result<usize> {
+        let invalid = |reason: String| ToglError::InvalidRange {
+            spec: self.spec.clone(),
+            reason,
+        };
+        match anchor {
+            Anchor::Line(n) => Ok(*n),
+            Anchor::Last if lines.is_empty() => Err(invalid("'$' in an empty file".into())),
+            Anchor::Last => Ok(lines.len()),
+            Anchor::FromEnd(n) if *n == 0 || *n > lines.len() => Err(invalid(format!(
+                "-{n} is outside the file ({} lines)",
+                lines.len()
+            ))),
+            Anchor::FromEnd(n) => Ok(lines.len() + 1 - n),
+            Anchor::Pattern(p) => {
+                let re = compile_pattern(p)?;
+                lines
+                    .iter()
+                    .enumerate()
+                    .skip(after)
+                    .find(|(_, line)| pattern_matches(&re, line))
+                    .map(|(i, _)| i + 1)
+                    .ok_or_else(|| ToglError::InvalidPattern {
+                        pattern: p.clone(),
+                        reason: if after == 0 {
+                            "no line matches it".into()
+                        } else {
+                            format!("no line after line {after} matches it")
+                        },
+                    })
+            }
+        }
+    }
+}
+
+impl End {
+    fn anchor(&self) -> Option<&Anchor> {
+        match self {
+            End::Anchor(a) => Some(a),
+            End::Count(_) | End::Block => None,
+        }
+    }
+}
+
+impl FromStr for LineSpec {
+    type Err = ToglError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s)
+    }
+}
+
+impl fmt::Display for LineSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.spec)
+    }
+}
+
+/// Serialized as the spec string, e.g. in saved plans.
+impl serde::Serialize for LineSpec {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.spec)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for LineSpec {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        let spec = <String as serde::Deserialize>::deserialize(deserializer)?;
+        Self::parse(&spec).map_err(serde::de::Error::custom)
+    }
+}
+
+/// The 1-based last line of the code block starting on line `start` of
+/// `lines`.
+///
+/// Brackets left open on the start line (or a `{` opening the next line, for
+/// brace-on-its-own-line styles) extend the block to the line that closes
+/// them. Unless that was a `{ ... }` body, the block then takes in every
+/// following line indented deeper than the start line, skipping blank lines;
+/// this covers Python functions and YAML mappings, including a `def` whose
+/// parameters span several lines. Trailing blank lines are not included.
+pub fn block_end(lines: &[&str], start: usize) -> usize {
+    let indent = |line: &str| line.len() - line.trim_start().len();
+    let base = indent(lines[start - 1]);
+
+    // Match brackets from the start line, or from a `{` on its own line.
+    let mut opener = start - 1;
+    if bracket_depth(lines[opener]).0 <= 0 {
+        let next = (start..lines.len()).find(|&i| !lines[i].trim().is_empty());
+        if let Some(i) = next {
+            if lines[i].trim_start().starts_with('{') && indent(lines[i]) <= base {
+                opener = i;
+            }
+        }
+    }
+    let mut end = start;
+    let mut depth = 0i64;
+    let mut braced = false;
+    for (i, line) in lines.iter().enumerate().skip(opener) {
+        let (delta, opens_brace) = bracket_depth(line);
+        depth += delta;
+        end = i + 1;
+        if depth <= 0 {
+            break;
+        }
+        braced |= opens_brace;
+    }
+    if braced {
+        return end;
+    }
+
+    for (i, line) in lines.iter().enumerate().skip(end) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if indent(line) <= base {
+            break;
+        }
+        end = i + 1;
+    }
+    end
+}
+
+/// The net bracket depth change of `line`, ignoring string literals and a
+/// trailing `//` or `#` comment, and whether a `{` is still open at its end.
+fn bracket_depth(line: &str) -> (i64, bool) {
+    let mut depth = 0i64;
+    // Brackets opened on this line and not yet closed.
+    let mut open: Vec<char> = Vec::new();
+    let mut quote: Option<char> = None;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(_), '\\') => {
+                chars.next();
+            }
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'') if is_lifetime(chars.clone()) => {}
+            (None, '"' | '\'' | '`') => quote = Some(c),
+            (None, '#') => break,
+            (None, '/') if chars.peek() == Some(&'/') => break,
+            (None, '(' | '[' | '{') => {
+                depth += 1;
+                open.push(c);
+            }
+            (None, ')' | ']' | '}') => {
+                depth -= 1;
+                open.pop();
+            }
+            _ => {}
+        }
+    }
+    (depth, open.contains(&'{'))
+}
+
+/// Whether a `'` followed by `rest` is a Rust lifetime (`'a`, `'static`)
+/// rather than an opening quote: an identifier not closed by another `'`.
+fn is_lifetime(mut rest: impl Iterator<Item = char>) -> bool {
+    let mut ident = 0;
+    for c in rest.by_ref() {
+        if c.is_alphanumeric() || c == '_' {
+            ident += 1;
+        } else {
+            return ident > 0 && c != '\'';
+        }
+    }
+    ident > 0
+}
+
+/// Parse one end. `Err(None)` means "not a line"; the caller words it.
+fn parse_anchor(token: &str) -> std::result::Result<Anchor, Option<String>> {
+    if token == "$" {
+        return Ok(Anchor::Last);
+    }
+    if token.len() >= 2 && token.starts_with('/') && token.ends_with('/') {
+        if token.len() == 2 {
+            return Err(Some("empty pattern '//'".into()));
+        }
+        return Ok(Anchor::Pattern(token.to_string()));
+    }
+    if let Some(n) = token.strip_prefix('-') {
+        return match n.parse::<usize>() {
+            Ok(0) => Err(Some("'-0' is not a line; '-1' is the last line".into())),
+            Ok(n) => Ok(Anchor::FromEnd(n)),
+            Err(_) => Err(None),
+        };
+    }
+    token.parse().map(Anchor::Line).map_err(|_| None)
+}
+
+/// Split `s` on `sep`, ignoring separators inside `/regex/` anchors (where
+/// `\/` does not close the pattern).
+fn split_outside_patterns(s: &str, sep: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut in_pattern = false;
+    let mut escaped = false;
+    let mut from = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_pattern => escaped = true,
+            '/' => in_pattern = !in_pattern,
+            c if c == sep && !in_pattern => {
+                parts.push(&s[from..i]);
+                from = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&s[from..]);
+    parts
+}
+
+/// `path:line[:col]` and `path:start-end` records, as printed by `grep -n`,
+/// `rg --vimgrep` and most compilers and linters, grouped per file.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Locations {
+    /// Each file in the order first seen, with its merged line ranges.
+    pub files: Vec<(PathBuf, Vec<LineRange>)>,
+    /// 1-based numbers of the input lines that held no location (compiler
+    /// notes, context lines and the like).
+    pub unparsed: Vec<usize>,
+}
+
+impl Locations {
+    /// Parse one record per line. Anything after the line (or column) is
+    /// ignored, so `src/a.py:12:5: E501 line too long` is line 12 of
+    /// `src/a.py`; `./a.py` and `a.py` are the same file.
+    pub fn parse(text: &str) -> Self {
+        let record = regex::Regex::new(r"^(.+?):(\d+)(?:-(\d+))?(?::\d+)?(?::|\s|$)")
+            .expect("location pattern compiles");
+        let mut files: Vec<(PathBuf, Vec<LineRange>)> = Vec::new();
+        let mut unparsed = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let parsed = record.captures(line.trim_end()).and_then(|c| {
+                let start: usize = c[2].parse().ok()?;
+                let end: usize = match c.get(3) {
+                    Some(end) => end.as_str().parse().ok()?,
+                    None => start,
+                };
+                let path: PathBuf = Path::new(&c[1])
+                    .components()
+                    .filter(|c| *c != Component::CurDir)
+                    .collect();
+                (start >= 1 && end >= start && !path.as_os_str().is_empty())
+                    .then(|| (path, LineRange::new(start, end)))
+            });
+            match parsed {
+                Some((path, range)) => match files.iter_mut().find(|(p, _)| *p == path) {
+                    Some((_, ranges)) => ranges.push(range),
+                    None => files.push((path, vec![range])),
+                },
+                None if line.trim().is_empty() => {}
+                None => unparsed.push(i + 1),
+            }
+        }
+        for (_, ranges) in &mut files {
+            *ranges = merge_ranges(ranges);
+        }
+        Self { files, unparsed }
+    }
+}
@@ -0,0 +1,1088 @@
+// High-level toggle pipeline: read files, plan every change in memory, then
+// write the plan back, file by file or as one atomic batch.
+//
+// A `Toggler` holds the settings a run resolves once (config, comment style,
+// encoding, backups, root, ...). `plan` turns typed `Operation`s into a
+// `Plan` of per-file changes without touching disk; `apply` writes it. The
+// `toggle` binary is a thin wrapper that builds a `Toggler` from its flags.
+
+use crate::backup::{self, BackupOptions};
+use crate::config::ToggleConfig;
+use crate::confine::{self, Root};
+use crate::core::{self, CommentStyle, LineRange, RemoveMode};
+use crate::exit_codes::{ContentChangedError, OutsideRootError, ToglError};
+use crate::history;
+use crate::io::{self, BackupStrategy, FileEncoding};
+use crate::journal;
+use crate::platform;
+use crate::preflight::{self, PreflightOptions, SkipReason};
+use crate::select::LineSpec;
+use crate::walk;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Set sections to a fixed state instead of inverting them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Force {
+    /// Comment out (disable) the targeted lines.
+    On,
+    /// Uncomment (enable) the targeted lines.
+    Off,
+}
+
+impl Force {
+    /// The `--force` value this stands for.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Force::On => "on",
+            Force::Off => "off",
+        }
+    }
+}
+
+/// How line ranges are commented out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Mode {
+    /// Line comments (the extension decides the delimiter).
+    #[default]
+    Auto,
+    /// Line comments.
+    Single,
+    /// Block comments around each range.
+    Multi,
+}
+
+impl Mode {
+    /// Parse a `--mode` value: "auto", "single" or "multi".
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "auto" => Some(Mode::Auto),
+            "single" => Some(Mode::Single),
+            "multi" => Some(Mode::Multi),
+            _ => None,
+        }
+    }
+
+    /// The `--mode` value this stands for.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Mode::Auto => "auto",
+            Mode::Single => "single",
+            Mode::Multi => "multi",
+        }
+    }
+}
+
+/// Line endings of written files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Eol {
+    /// Keep each line's original ending.
+    #[default]
+    Preserve,
+    Lf,
+    Crlf,
+}
+
+impl Eol {
+    /// Parse an `--eol` value: "preserve", "lf" or "crlf".
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "preserve" => Some(Eol::Preserve),
+            "lf" => Some(Eol::Lf),
+            "crlf" => Some(Eol::Crlf),
+            _ => None,
+        }
+    }
+
+    /// The `--eol` value this stands for.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Eol::Preserve => "preserve",
+            Eol::Lf => "lf",
+            Eol::Crlf => "crlf",
+        }
+    }
+}
+
+/// One change to apply to every planned file. Serialized as
+/// `{"op": "toggle_section", "id": ...}` in saved plans.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Operation {
+    /// Comment or uncomment line ranges (1-based, inclusive). With `to_end`
+    /// the last range runs to the end of the file.
+    ToggleLines {
+        ranges: Vec<LineRange>,
+        to_end: bool,
+    },
+    /// [`Operation::ToggleLines`] on the ranges `-l` specs select in each
+    /// file (`$`, `-N`, `/regex/` anchors and lists; see [`crate::select`]).
+    ToggleLineSpecs { specs: Vec<LineSpec>, to_end: bool },
+    /// Invert a section, flip a two-variant group, or (with a force state)
+    /// set every variant of the group.
+    ToggleSection { id: String },
+    /// Uncomment `group:variant` and comment out every other variant.
+    Activate { group: String, variant: String },
+    /// Wrap a line range in new section markers. With `to_end` the range
+    /// runs to the end of the file.
+    Insert {
+        id: String,
+        desc: Option<String>,
+        range: LineRange,
+        to_end: bool,
+    },
+    /// Wrap the block of lines `pattern` selects in new section markers.
+    InsertMatching {
+        id: String,
+        desc: Option<String>,
+        pattern: core::LinePattern,
+    },
+    /// Delete every section whose ID is exactly `id`.
+    Remove { id: String, mode: RemoveMode },
+    /// Rename the section `from` (or every variant of the group `from`) to
+    /// `to`, rewriting its start and end markers.
+    Rename { from: String, to: String },
+    /// Copy the section `from` right after itself as the commented-out
+    /// variant `to`. A solo `from` first becomes `group:<solo_as>` (see
+    /// [`core::add_variant`]).
+    AddVariant {
+        from: String,
+        to: String,
+        solo_as: Option<String>,
+        desc: Option<String>,
+    },
+}
+
+impl Operation {
+    /// What `-S id` means: activate `group:variant`, otherwise toggle the
+    /// section or group `id`.
+    pub fn section(id: &str) -> Self {
+        match core::parse_id_parts(id) {
+            (group, Some(variant)) => Operation::Activate { group, variant },
+            (_, None) => Operation::ToggleSection { id: id.to_string() },
+        }
+    }
+
+    /// The section ID this operation targets, if any.
+    pub fn section_id(&self) -> Option<String> {
+        match self {
+            Operation::ToggleLines { .. } | Operation::ToggleLineSpecs { .. } => None,
+            Operation::ToggleSection { id }
+            | Operation::Insert { id, .. }
+            | Operation::InsertMatching { id, .. }
+            | Operation::Remove { id, .. }
+            | Operation::Rename { from: id, .. }
+            | Operation::AddVariant { from: id, .. } => Some(id.clone()),
+            Operation::Activate { group, variant } => Some(format!("{group}:{variant}")),
+        }
+    }
+}
+
+/// What one operation did to one file.
+#[derive(Debug, Clone)]
+pub struct Outcome {
+    pub operation: Operation,
+    /// Lines that differ before and after this operation.
+    pub lines_changed: usize,
+    /// The `desc=` of the toggled section, if it has one.
+    pub desc: Option<String>,
+    /// Sections deleted by [`Operation::Remove`].
+    pub removed: usize,
+    /// Sections renamed by [`Operation::Rename`].
+    pub renamed: usize,
+    /// Sections added by [`Operation::AddVariant`] or
+    /// [`Operation::InsertMatching`].
+    pub added: usize,
+}
+
+/// A file's decoded text plus the encoding it must be written back in.
+#[derive(Debug, Clone)]
+pub struct Source {
+    pub content: String,
+    pub encoding: FileEncoding,
+    /// True when the encoding was auto-detected rather than named.
+    pub detected: bool,
+    /// SHA-256 of the bytes read, re-checked just before the file is replaced.
+    pub sha256: String,
+}
+
+/// The planned change to one file. Nothing is written until it is applied.
+#[derive(Debug, Clone)]
+pub struct FilePlan {
+    pub path: PathBuf,
+    pub original: String,
+    pub modified: String,
+    pub encoding: FileEncoding,
+    /// True when the encoding was auto-detected rather than named.
+    pub detected: bool,
+    /// Hash of the file as read; writing it fails if the file changed since.
+    pub sha256: String,
+    /// One entry per operation, in order.
+    pub outcomes: Vec<Outcome>,
+}
+
+impl FilePlan {
+    /// Whether applying the plan would change the file.
+    pub fn is_changed(&self) -> bool {
+        self.original != self.modified
+    }
+
+    /// Lines that differ between the original and the planned content.
+    pub fn lines_changed(&self) -> usize {
+        count_changed_lines(&self.original, &self.modified)
+    }
+}
+
+/// A file that failed preflight.
+#[derive(Debug, Clone)]
+pub struct Skipped {
+    pub path: PathBuf,
+    pub reason: SkipReason,
+    /// Named explicitly (refused) rather than found by a walk (skipped).
+    pub explicit: bool,
+}
+
+/// Every file's planned change, plus the files preflight left out.
+#[derive(Debug, Clone, Default)]
+pub struct Plan {
+    pub files: Vec<FilePlan>,
+    pub skipped: Vec<Skipped>,
+}
+
+impl Plan {
+    /// The files whose content would change.
+    pub fn changed(&self) -> impl Iterator<Item = &FilePlan> {
+        self.files.iter().filter(|f| f.is_changed())
+    }
+}
+
+/// One file written by [`Toggler::write`] or [`Toggler::apply`].
+#[derive(Debug, Clone)]
+pub struct Written {
+    pub path: PathBuf,
+    /// The backup taken first (not set for atomic batches, whose backups
+    /// are removed once the commit succeeds).
+    pub backup: Option<PathBuf>,
+    /// The change as recorded in the history log, with `with_history`.
+    pub history: Option<history::FileChange>,
+}
+
+/// An atomic batch with every changed file staged, from
+/// [`Toggler::stage`].
+pub struct Staged {
+    batch: Option<io::AtomicBatch>,
+    written: Vec<Written>,
+}
+
+impl Staged {
+    /// The files that [`commit`](Self::commit) will replace.
+    pub fn files(&self) -> impl Iterator<Item = &Path> {
+        self.written.iter().map(|w| w.path.as_path())
+    }
+
+    /// Move every staged file into place as one unit. An interrupted
+    /// commit leaves a journal that recovery can roll back or complete.
+    pub fn commit(self) -> Result<Vec<Written>> {
+        if let Some(batch) = self.batch {
+            batch
+                .commit()
+                .map_err(|e| batch_error("Atomic commit failed".to_string(), e))?;
+        }
+        Ok(self.written)
+    }
+}
+
+/// Builder for a toggle run: how files are read, transformed and written.
+#[derive(Debug)]
+pub struct Toggler {
+    config: Option<ToggleConfig>,
+    mode: Mode,
+    force: Option<Force>,
+    eol: Eol,
+    encoding: Option<String>,
+    comment_style: Option<CommentStyle>,
+    expect_sha256: Option<String>,
+    preflight: PreflightOptions,
+    root: Option<Root>,
+    temp_suffix: Option<String>,
+    no_dereference: bool,
+    preserve_mtime: bool,
+    backup: Option<BackupOptions>,
+    atomic: bool,
+    atomic_backup: bool,
+    backup_strategy: BackupStrategy,
+    lock_timeout: Duration,
+    interrupted: Option<Arc<AtomicBool>>,
+    history: bool,
+}
+
+impl Default for Toggler {
+    fn default() -> Self {
+        Self {
+            config: None,
+            mode: Mode::default(),
+            force: None,
+            eol: Eol::default(),
+            encoding: None,
+            comment_style: None,
+            expect_sha256: None,
+            preflight: PreflightOptions::default(),
+            root: None,
+            temp_suffix: None,
+            no_dereference: false,
+            preserve_mtime: false,
+            backup: None,
+            atomic: false,
+            atomic_backup: true,
+            backup_strategy: BackupStrategy::default(),
+            lock_timeout: Duration::ZERO,
+            interrupted: None,
+            history: false,
+        }
+    }
+}
+
+impl Toggler {
+    /// Defaults: auto comment mode, preserved line endings, UTF-8, default
+    /// preflight checks, plain (non-atomic) writes without backups.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use a loaded config for comment delimiters and encoding rules.
+    pub fn with_config(mut self, config: ToggleConfig) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Comment mode ([`Mode::Multi`] uses block comments for line ranges).
+    pub fn with_mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Set sections and lines to a fixed state instead of inverting them.
+    pub fn with_force(mut self, force: Option<Force>) -> Self {
+        self.force = force;
+        self
+    }
+
+    /// Line endings of written files.
+    pub fn with_eol(mut self, eol: Eol) -> Self {
+        self.eol = eol;
+        self
+    }
+
+    /// Decode every file with this encoding label (including "auto"),
+    /// overriding the config's `[[encoding.path]]` rules.
+    pub fn with_encoding(mut self, label: &str) -> Self {
+        self.encoding = Some(label.to_string());
+        self
+    }
+
+    /// Use this comment style for every file instead of looking it up by
+    /// extension.
+    pub fn with_comment_style(mut self, style: CommentStyle) -> Self {
+        self.comment_style = Some(style);
+        self
+    }
+
+    /// Refuse to process any input that does not hash to `sha256`.
+    pub fn with_expect_sha256(mut self, sha256: &str) -> Self {
+        self.expect_sha256 = Some(sha256.to_string());
+        self
+    }
+
+    /// Which files preflight refuses (binary, generated, ...).
+    pub fn with_preflight(mut self, preflight: PreflightOptions) -> Self {
+        self.preflight = preflight;
+        self
+    }
+
+    /// Refuse every write (targets, temp files, backups, journal) outside `root`.
+    pub fn with_root(mut self, root: Root) -> Self {
+        self.root = Some(root);
+        self
+    }
+
+    /// Write through `<file>.<suffix>` instead of a random temp file.
+    pub fn with_temp_suffix(mut self, suffix: &str) -> Self {
+        self.temp_suffix = Some(suffix.to_string());
+        self
+    }
+
+    /// Write through a symlink to its target instead of replacing the link.
+    pub fn with_no_dereference(mut self, no_dereference: bool) -> Self {
+        self.no_dereference = no_dereference;
+        self
+    }
+
+    /// Keep each written file's access and modification times.
+    pub fn with_preserve_mtime(mut self, preserve: bool) -> Self {
+        self.preserve_mtime = preserve;
+        self
+    }
+
+    /// Back up each file before a plain write replaces it.
+    pub fn with_backup(mut self, backup: BackupOptions) -> Self {
+        self.backup = Some(backup);
+        self
+    }
+
+    /// Make [`apply`](Self::apply) commit every file as one atomic batch.
+    /// `backup` keeps journal backups for rollback (recommended).
+    pub fn with_atomic(mut self, atomic: bool, backup: bool) -> Self {
+        self.atomic = atomic;
+        self.atomic_backup = backup;
+        self
+    }
+
+    /// How atomic batches back up files before replacing them.
+    pub fn with_backup_strategy(mut self, strategy: BackupStrategy) -> Self {
+        self.backup_strategy = strategy;
+        self
+    }
+
+    /// How long an atomic batch waits for a competing lock holder.
+    pub fn with_lock_timeout(mut self, timeout: Duration) -> Self {
+        self.lock_timeout = timeout;
+        self
+    }
+
+    /// A flag (set by signal handlers) that stops an atomic commit between
+    /// renames, leaving a recoverable journal.
+    pub fn with_interrupt(mut self, interrupted: Arc<AtomicBool>) -> Self {
+        self.interrupted = Some(interrupted);
+        self
+    }
+
+    /// Return each write's [`history::FileChange`] for the undo log.
+    pub fn with_history(mut self, history: bool) -> Self {
+        self.history = history;
+        self
+    }
+
+    /// The config given to [`with_config`](Self::with_config).
+    pub fn config(&self) -> Option<&ToggleConfig> {
+        self.config.as_ref()
+    }
+
+    /// Whether plain writes back up files first.
+    pub fn backs_up(&self) -> bool {
+        self.backup.is_some()
+    }
+
+    /// The fixed state sections and lines are set to, if any.
+    pub fn force(&self) -> Option<Force> {
+        self.force
+    }
+
+    /// The encoding label for `path` (or for stdin, with None): the explicit
+    /// encoding, else the config's rule for the path, else its global
+    /// default, else UTF-8.
+    pub fn encoding_label(&self, path: Option<&Path>) -> &str {
+        if let Some(label) = &self.encoding {
+            return label;
+        }
+        let config = self.config.as_ref();
+        let by_path = path.and_then(|p| config?.encoding_for_path(p));
+        by_path
+            .or_else(|| config?.global.as_ref()?.encoding.as_deref())
+            .unwrap_or("utf-8")
+    }
+
+    /// Read and decode `path`, failing with a [`ContentChangedError`] if it
+    /// does not match the expected hash.
+    pub fn read(&self, path: &Path) -> Result<Source> {
+        let label = self.encoding_label(Some(path));
+        let bytes = std::fs::read(path).map_err(|source| ToglError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let sha256 = journal::sha256_hex(&bytes);
+        if let Some(expected) = &self.expect_sha256 {
+            if &sha256 != expected {
+                return Err(anyhow::Error::new(ContentChangedError {
+                    path: path.to_path_buf(),
+                    expected_sha256: expected.clone(),
+                    actual_sha256: Some(sha256),
+                })
+                .context("input does not match --expect-sha256"));
+            }
+        }
+        let (content, encoding) =
+            io::decode_bytes(&bytes, label, self.fallbacks()).map_err(|source| {
+                ToglError::Encoding {
+                    path: path.to_path_buf(),
+                    source,
+                }
+            })?;
+        Ok(Source {
+            content,
+            encoding,
+            detected: label.eq_ignore_ascii_case(io::AUTO_ENCODING),
+            sha256,
+        })
+    }
+
+    /// Read and decode stdin (filter mode).
+    pub fn read_stdin(&self) -> Result<(String, FileEncoding)> {
+        Ok(io::read_stdin_detect(
+            self.encoding_label(None),
+            self.fallbacks(),
+        )?)
+    }
+
+    fn fallbacks(&self) -> &[String] {
+        self.config
+            .as_ref()
+            .map(|c| c.fallback_encodings())
+            .unwrap_or(&[])
+    }
+
+    /// The comment style for `path`: the override, else by extension (with
+    /// config overrides).
+    pub fn comment_style(&self, path: &Path) -> Result<CommentStyle> {
+        match &self.comment_style {
+            Some(style) => Ok(style.clone()),
+            None => Ok(core::get_comment_style(
+                path,
+                self.mode.as_str(),
+                self.config.as_ref(),
+            )?),
+        }
+    }
+
+    /// Split `files` into those that pass preflight and those that don't.
+    /// Unreadable paths pass through so reading them reports the error.
+    /// Files in `named` (given explicitly) are refused rather than skipped.
+    pub fn preflight(
+        &self,
+        files: Vec<PathBuf>,
+        named: &[PathBuf],
+    ) -> (Vec<PathBuf>, Vec<Skipped>) {
+        let mut kept = Vec::with_capacity(files.len());
+        let mut skipped = Vec::new();
+        for path in files {
+            match preflight::check_file(&path, &self.preflight) {
+                Ok(Some(reason)) => {
+                    let explicit = named.contains(&path);
+                    skipped.push(Skipped {
+                        path,
+                        reason,
+                        explicit,
+                    });
+                }
+                Ok(None) | Err(_) => kept.push(path),
+            }
+        }
+        (kept, skipped)
+    }
+
+    /// The files `ops` apply to: `paths` (walked when `recursive`), minus
+    /// those that fail preflight. A recursive walk also drops files with no
+    /// comment style and, when `ops` target sections, files without them.
+    pub fn collect(
+        &self,
+        paths: &[PathBuf],
+        recursive: bool,
+        verbose: bool,
+        ops: &[Operation],
+    ) -> Result<(Vec<PathBuf>, Vec<Skipped>)> {
+        let walk_opts = walk::WalkOptions {
+            verbose,
+            skip_unsupported_extensions: false,
+            ..walk::WalkOptions::default()
+        };
+        let files = walk::collect_files(paths, recursive, &walk_opts)?;
+        // Preflight first: it never reads an oversized file whole.
+        let (files, skipped) = self.preflight(files, paths);
+        if !recursive {
+            return Ok((files, skipped));
+        }
+
+        let ids: Vec<String> = ops
+            .iter()
+            .filter(|op| {
+                matches!(
+                    op,
+                    Operation::ToggleSection { .. }
+                        | Operation::Activate { .. }
+                        | Operation::Rename { .. }
+                        | Operation::AddVariant { .. }
+                )
+            })
+            .filter_map(Operation::section_id)
+            .collect();
+        let files = files
+            .into_iter()
+            .filter(|path| ids.is_empty() || self.has_matching_sections(path, &ids))
+            .filter(|path| self.comment_style(path).is_ok())
+            .collect();
+        Ok((files, skipped))
+    }
+
+    /// Whether `path` has a section (or a variant of a group) in `ids`.
+    fn has_matching_sections(&self, path: &Path, ids: &[String]) -> bool {
+        let Ok(source) = self.read(path) else {
+            return false;
+        };
+        let found = core::discover_sections(&source.content);
+        ids.iter().any(|id| {
+            let (group, variant) = core::parse_id_parts(id);
+            found.iter().any(|s| match &variant {
+                Some(v) => s.id == format!("{group}:{v}"),
+                None => s.id == *id || core::parse_id_parts(&s.id).0 == group,
+            })
+        })
+    }
+
+    /// Collect the files under `paths` and plan `ops` on each. Fails on the
+    /// first file that cannot be read or transformed.
+    pub fn plan(&self, paths: &[PathBuf], recursive: bool, ops: &[Operation]) -> Result<Plan> {
+        let (files, skipped) = self.collect(paths, recursive, false, ops)?;
+        let files = files
+            .iter()
+            .map(|path| {
+                self.plan_file(path, ops)
+                    .with_context(|| format!("Failed to plan {}", path.display()))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Plan { files, skipped })
+    }
+
+    /// Read `path` and plan `ops` on it.
+    pub fn plan_file(&self, path: &Path, ops: &[Operation]) -> Result<FilePlan> {
+        let source = self.read(path)?;
+        self.plan_source(path, source, ops)
+    }
+
+    /// Plan `ops` on `path`, already read as `source`.
+    pub fn plan_source(&self, path: &Path, source: Source, ops: &[Operation]) -> Result<FilePlan> {
+        let (modified, outcomes) = self.transform(path, &source.content, ops)?;
+        Ok(FilePlan {
+            path: path.to_path_buf(),
+            original: source.content,
+            modified,
+            encoding: source.encoding,
+            detected: source.detected,
+            sha256: source.sha256,
+            outcomes,
+        })
+    }
+
+    /// Apply `ops` in order to `content`, using the comment style for
+    /// `path` (which need not exist).
+    pub fn transform(
+        &self,
+        path: &Path,
+        content: &str,
+        ops: &[Operation],
+    ) -> Result<(String, Vec<Outcome>)> {
+        let mut current = content.to_string();
+        let mut outcomes = Vec::with_capacity(ops.len());
+        for op in ops {
+            let mut outcome = Outcome {
+                operation: op.clone(),
+                lines_changed: 0,
+                desc: None,
+                removed: 0,
+                renamed: 0,
+                added: 0,
+            };
+            let next = match op {
+                Operation::ToggleLines { ranges, to_end } => {
+                    self.toggle_lines(path, &current, ranges, *to_end)?
+                }
+                Operation::ToggleLineSpecs { specs, to_end } => {
+                    let mut ranges = Vec::new();
+                    for spec in specs {
+                        ranges.extend(spec.resolve(&current)?);
+                    }
+                    self.toggle_lines(path, &current, &ranges, *to_end)?
+                }
+                Operation::ToggleSection { id } => {
+                    outcome.desc = section_desc(&current, id);
+                    self.toggle_section(path, &current, id)?
+                }
+                Operation::Activate { group, variant } => {
+                    outcome.desc = section_desc(&current, &format!("{group}:{variant}"));
+                    let style = self.comment_style(path)?;
+                    let toggled = core::activate_variant(&current, group, variant, &style)?;
+                    io::apply_eol(&current, &toggled, self.eol.as_str())
+                }
+                Operation::Insert {
+                    id,
+                    desc,
+                    range,
+                    to_end,
+                } => {
+                    let prefix = self.comment_style(path)?.single_line;
+                    let end = if *to_end {
+                        current.lines().count()
+                    } else {
+                        range.end
+                    };
+                    let inserted = core::insert_section(
+                        &current,
+                        id,
+                        desc.as_deref(),
+                        range.start,
+                        end,
+                        &prefix,
+                    )?;
+                    io::apply_eol(&current, &inserted, self.eol.as_str())
+                }
+                Operation::InsertMatching { id, desc, pattern } => {
+                    let prefix = self.comment_style(path)?.single_line;
+                    let (inserted, count) = core::insert_section_matching(
+                        &current,
+                        id,
+                        desc.as_deref(),
+                        pattern,
+                        &prefix,
+                    )?;
+                    outcome.added = count;
+                    if count == 0 {
+                        current.clone()
+                    } else {
+                        io::apply_eol(&current, &inserted, self.eol.as_str())
+                    }
+                }
+                Operation::Remove { id, mode } => {
+                    let style = self.comment_style(path)?;
+                    let (removed, count) = core::remove_section(&current, id, *mode, &style);
+                    outcome.removed = count;
+                    if count == 0 {
+                        current.clone()
+                    } else {
+                        io::apply_eol(&current, &removed, self.eol.as_str())
+                    }
+                }
+                Operation::Rename { from, to } => {
+                    let (renamed, count) = core::rename_section(&current, from, to)?;
+                    outcome.renamed = count;
+                    if count == 0 {
+                        current.clone()
+                    } else {
+                        io::apply_eol(&current, &renamed, self.eol.as_str())
+                    }
+                }
+                Operation::AddVariant {
+                    from,
+                    to,
+                    solo_as,
+                    desc,
+                } => {
+                    let prefix = self.comment_style(path)?.single_line;
+                    let (added, count) = core::add_variant(
+                        &current,
+                        from,
+                        to,
+                        solo_as.as_deref(),
+                        desc.as_deref(),
+                        &prefix,
+                    )?;
+                    outcome.added = count;
+                    if count == 0 {
+                        current.clone()
+                    } else {
+                        io::apply_eol(&current, &added, self.eol.as_str())
+                    }
+                }
+            };
+            outcome.lines_changed = count_changed_lines(&current, &next);
+            outcomes.push(outcome);
+            current = next;
+        }
+        Ok((current, outcomes))
+    }
+
+    fn toggle_lines(
+        &self,
+        path: &Path,
+        content: &str,
+        ranges: &[LineRange],
+        to_end: bool,
+    ) -> Result<String> {
+        let comment_style = self.comment_style(path)?;
+        let line_count = content.lines().count();
+
+        let mut ranges = ranges.to_vec();
+        if let Some(range) = ranges.iter().find(|r| r.start > line_count) {
+            return Err(ToglError::LineOutOfRange {
+                line: range.start,
+                lines: line_count,
+            }
+            .into());
+        }
+        if to_end {
+            if let Some(last) = ranges.last_mut() {
+                last.end = line_count;
+            }
+        }
+        if let Some(range) = ranges.iter().find(|r| r.end > line_count) {
+            return Err(ToglError::LineOutOfRange {
+                line: range.end,
+                lines: line_count,
+            }
+            .into());
+        }
+
+        let merged = core::merge_ranges(&ranges);
+        let force_mode = self.force.map(Force::as_str);
+        let toggled = if self.mode == Mode::Multi {
+            let (ms, me) = match (
+                &comment_style.multi_line_start,
+                &comment_style.multi_line_end,
+            ) {
+                (Some(s), Some(e)) => (s.as_str(), e.as_str()),
+                _ => {
+                    return Err(ToglError::NoBlockComments {
+                        path: path.to_path_buf(),
+                    }
+                    .into());
+                }
+            };
+            core::toggle_comments_multi(content, &merged, force_mode, ms, me)
+        } else {
+            core::toggle_comments_with_marker(
+                content,
+                &merged,
+                force_mode,
+                &comment_style.single_line,
+            )
+        };
+        Ok(io::apply_eol(content, &toggled, self.eol.as_str()))
+    }
+
+    /// Toggle a section or a whole group per PRD §0.13.3: solo invert,
+    /// pair-flip, force-all, or an error for 3+ variants.
+    fn toggle_section(&self, path: &Path, content: &str, id: &str) -> Result<String> {
+        let comment_style = self.comment_style(path)?;
+        let (group, _) = core::parse_id_parts(id);
+        let variants = core::discover_variants(content, &group);
+        // Several sections sharing one ID (as `insert --match` makes) toggle
+        // together, not as a group of variants.
+        let solo = variants.len() <= 1 || variants.iter().all(|s| s.id == id);
+        let force = self.force.map(|f| f.as_str().to_string());
+        let toggled = if solo && force.is_none() {
+            let mut lines: Vec<String> = content.lines().map(String::from).collect();
+            let result = core::find_and_toggle_section(&mut lines, id, &force, &comment_style)?;
+            if !result.modified {
+                return Ok(content.to_string());
+            }
+            let mut joined = lines.join("\n");
+            if content.ends_with('\n') {
+                joined.push('\n');
+            }
+            joined
+        } else {
+            core::toggle_variant_group(content, &group, &force, &comment_style)?
+        };
+        Ok(io::apply_eol(content, &toggled, self.eol.as_str()))
+    }
+
+    /// Write every changed file in `plan`: one by one, or as a single
+    /// atomic batch with [`with_atomic`](Self::with_atomic).
+    pub fn apply(&self, plan: &Plan) -> Result<Vec<Written>> {
+        let changed: Vec<&FilePlan> = plan.changed().collect();
+        if self.atomic {
+            return self.stage(plan)?.commit();
+        }
+        changed.into_iter().map(|file| self.write(file)).collect()
+    }
+
+    /// Write one file (whether or not it changed): check the root, take
+    /// the backup, then replace the file unless it changed since it was read.
+    pub fn write(&self, file: &FilePlan) -> Result<Written> {
+        let path = &file.path;
+        if let Some(root) = &self.root {
+            self.confine(root, path)?;
+        }
+        let backup = match &self.backup {
+            Some(opts) => Some(backup::create(path, opts)?),
+            None => None,
+        };
+        // Capture the exact on-disk bytes for the history log before overwriting.
+        let pre_image = if self.history {
+            Some(std::fs::read(path)?)
+        } else {
+            None
+        };
+        io::write_file_as_checked(
+            path,
+            &file.modified,
+            self.temp_suffix.as_deref(),
+            self.no_dereference,
+            &file.encoding,
+            Some(&file.sha256),
+            self.preserve_mtime,
+        )?;
+        let history = match pre_image {
+            Some(pre) => {
+                let post = io::encode_text(&file.modified, &file.encoding)?;
+                Some(history::FileChange::new(path, &pre, &post))
+            }
+            None => None,
+        };
+        Ok(Written {
+            path: path.clone(),
+            backup,
+            history,
+        })
+    }
+
+    /// Refuse a write to `path` (and its temp file and backup) that would
+    /// land outside `root`.
+    fn confine(&self, root: &Root, path: &Path) -> Result<()> {
+        self.confine_history(root)?;
+        root.check(path, confine::WriteKind::Target)?;
+        if let Some(suffix) = &self.temp_suffix {
+            let mut temp = path.as_os_str().to_os_string();
+            temp.push(".");
+            temp.push(suffix);
+            root.check(Path::new(&temp), confine::WriteKind::TempFile)?;
+        }
+        if let Some(opts) = &self.backup {
+            let next = backup::next_backup_path(path, opts)?;
+            root.check(&next, confine::WriteKind::Backup)?;
+        }
+        Ok(())
+    }
+
+    /// With history on, the store the run appends to (in the CWD) must be
+    /// inside the root as well.
+    fn confine_history(&self, root: &Root) -> Result<()> {
+        if self.history {
+            let cwd = std::env::current_dir().context("Failed to determine current directory")?;
+            root.check(&history::history_path(&cwd), confine::WriteKind::History)?;
+        }
+        Ok(())
+    }
+
+    /// Stage every changed file in `plan` through an `AtomicBatch`, ready
+    /// to [`commit`](Staged::commit) as one unit. Dropping the result
+    /// instead discards the staged files.
+    pub fn stage(&self, plan: &Plan) -> Result<Staged> {
+        let files: Vec<&FilePlan> = plan.changed().collect();
+        if files.is_empty() {
+            return Ok(Staged {
+                batch: None,
+                written: Vec::new(),
+            });
+        }
+        let targets: Vec<PathBuf> = files.iter().map(|f| f.path.clone()).collect();
+        if let Some(root) = &self.root {
+            // Checked before the batch exists: it creates the lock immediately,
+            // and a target refused mid-staging would strand the earlier temps.
+            self.confine_history(root)?;
+            let dir = journal::journal_dir(&targets)?;
+            root.check(
+                &dir.join(journal::JOURNAL_FILENAME),
+                confine::WriteKind::Journal,
+            )?;
+            root.check(
+                &dir.join(journal::LOCK_FILENAME),
+                confine::WriteKind::Journal,
+            )?;
+            for path in &targets {
+                root.check(path, confine::WriteKind::Target)?;
+                if self.atomic_backup {
+                    root.check(&io::atomic_backup_path(path), confine::WriteKind::Backup)?;
+                }
+            }
+        }
+        let mut batch = io::AtomicBatch::with_lock_timeout(
+            &targets,
+            self.atomic_backup,
+            self.interrupted.clone().unwrap_or_default(),
+            self.lock_timeout,
+        )
+        .map_err(|e| anyhow::anyhow!("Failed to initialize atomic batch: {}", e))?
+        .with_backup_strategy(self.backup_strategy)
+        .with_preserve_mtime(self.preserve_mtime);
+        if let Some(root) = &self.root {
+            batch = batch.with_root(root.clone());
+        }
+
+        let mut written = Vec::with_capacity(files.len());
+        for file in files {
+            let path = &file.path;
+            let encoded = io::encode_text(&file.modified, &file.encoding)
+                .with_context(|| format!("Failed to encode content for {}", path.display()))?;
+            let history = if self.history {
+                let pre = std::fs::read(path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?;
+                Some(history::FileChange::new(path, &pre, &encoded))
+            } else {
+                None
+            };
+            batch
+                .stage_expecting(path, &encoded, file.encoding.name, Some(&file.sha256))
+                .map_err(|e| batch_error(format!("Failed to stage '{}'", path.display()), e))?;
+            written.push(Written {
+                path: path.clone(),
+                backup: None,
+                history,
+            });
+        }
+
+        Ok(Staged {
+            batch: Some(batch),
+            written,
+        })
+    }
+
+    /// How many hard links the file a write to `path` replaces has, when
+    /// more than one: the other links keep the old content.
+    pub fn shared_links(&self, path: &Path) -> Option<u64> {
+        let entry = if self.no_dereference && !self.atomic && io::is_symlink(path) {
+            // The write goes to the symlink's target, not the link itself.
+            std::fs::canonicalize(path).ok()?
+        } else {
+            path.to_path_buf()
+        };
+        platform::link_count(&entry).filter(|&links| links > 1)
+    }
+}
+
+/// Wrap an atomic-batch error, keeping a `ContentChangedError` or
+/// `OutsideRootError` intact so it still maps to its exit code.
+fn batch_error(context: String, e: std::io::Error) -> anyhow::Error {
+    if e.get_ref()
+        .is_some_and(|inner| inner.is::<ContentChangedError>() || inner.is::<OutsideRootError>())
+    {
+        anyhow::Error::new(e).context(context)
+    } else {
+        anyhow::anyhow!("{}: {}", context, e)
+    }
+}
+
+/// The `desc=` of section `id` in `content`: the variant itself for
+/// `group:variant`, else the solo section or the group's first variant.
+fn section_desc(content: &str, id: &str) -> Option<String> {
+    let (group, variant) = core::parse_id_parts(id);
+    core::discover_variants(content, &group)
+        .into_iter()
+        .find(|s| match &variant {
+            Some(v) => s.id == format!("{group}:{v}"),
+            None => s.id == id || core::parse_id_parts(&s.id).1.is_some(),
+        })
+        .and_then(|s| s.desc)
+}
+
+/// Count the lines that differ between two strings, position by position.
+pub fn count_changed_lines(original: &str, modified: &str) -> usize {
+    let orig_lines: Vec<&str> = original.lines().collect();
+    let mod_lines: Vec<&str> = modified.lines().collect();
+    let max_len = orig_lines.len().max(mod_lines.len());
+    (0..max_len)
+        .filter(|&i| orig_lines.get(i).unwrap_or(&"") != mod_lines.get(i).unwrap_or(&""))
+        .count()
+}
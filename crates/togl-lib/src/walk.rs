@@ -5,7 +5,7 @@ use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
 
 use crate::core::supported_extensions;
-use crate::exit_codes::UsageError;
+use crate::exit_codes::ToglError;
 
 /// Configuration for directory walking
 pub struct WalkOptions {
@@ -41,7 +41,7 @@ const SKIP_DIRS: &[&str] = &[
 ];
 
 /// Returns true if the directory entry should be skipped.
-fn should_skip_dir(name: &str, skip_hidden: bool) -> bool {
+pub(crate) fn should_skip_dir(name: &str, skip_hidden: bool) -> bool {
     if skip_hidden && name.starts_with('.') {
         return true;
     }
@@ -78,11 +78,7 @@ pub fn collect_files(
             files.push(path.clone());
         } else if path.is_dir() {
             if !recursive {
-                return Err(UsageError(format!(
-                    "'{}' is a directory; use -R/--recursive to process directories",
-                    path.display()
-                ))
-                .into());
+                return Err(ToglError::IsDirectory { path: path.clone() }.into());
             }
             walk_directory(path, opts, &mut files)?;
         }
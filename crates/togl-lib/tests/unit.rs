@@ -1,6 +1,13 @@
 mod unit {
+    mod backup_tests;
     mod config_tests;
+    mod confine_tests;
     mod core_tests;
+    mod history_tests;
     mod io_tests;
+    mod planfile_tests;
+    mod preflight_tests;
+    mod select_tests;
+    mod session_tests;
     mod walk_tests;
 }
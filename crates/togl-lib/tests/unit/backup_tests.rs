@@ -0,0 +1,172 @@
+use std::fs;
+use std::path::Path;
+use togl_lib::backup::{
+    create, list, list_tree, next_backup_path, prunable, restore, BackupKind, BackupMode,
+    BackupOptions,
+};
+
+fn opts(mode: BackupMode) -> BackupOptions {
+    BackupOptions {
+        mode,
+        ..BackupOptions::default()
+    }
+}
+
+fn names(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn test_simple_backup_is_overwritten() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let file = dir.path().join("a.py");
+    fs::write(&file, "one\n").unwrap();
+    let o = BackupOptions {
+        suffix: ".bak".into(),
+        ..BackupOptions::default()
+    };
+    assert_eq!(create(&file, &o).unwrap(), dir.path().join("a.py.bak"));
+    fs::write(&file, "two\n").unwrap();
+    create(&file, &o).unwrap();
+    assert_eq!(names(dir.path()), ["a.py", "a.py.bak"]);
+    assert_eq!(
+        fs::read_to_string(dir.path().join("a.py.bak")).unwrap(),
+        "two\n"
+    );
+}
+
+#[test]
+fn test_numbered_backups_count_up() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let file = dir.path().join("a.py");
+    fs::write(&file, "x\n").unwrap();
+    let o = opts(BackupMode::Numbered);
+    for _ in 0..3 {
+        create(&file, &o).unwrap();
+    }
+    assert_eq!(
+        names(dir.path()),
+        ["a.py", "a.py.~1~", "a.py.~2~", "a.py.~3~"]
+    );
+    let kinds: Vec<BackupKind> = list(&file, &o)
+        .unwrap()
+        .into_iter()
+        .map(|b| b.kind)
+        .collect();
+    assert_eq!(
+        kinds,
+        (1..=3)
+            .map(|number| BackupKind::Numbered { number })
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_existing_mode_follows_what_is_there() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let file = dir.path().join("a.py");
+    fs::write(&file, "x\n").unwrap();
+    let existing = opts(BackupMode::Existing);
+    assert_eq!(
+        next_backup_path(&file, &existing).unwrap(),
+        dir.path().join("a.py~")
+    );
+    create(&file, &opts(BackupMode::Numbered)).unwrap();
+    assert_eq!(
+        next_backup_path(&file, &existing).unwrap(),
+        dir.path().join("a.py.~2~")
+    );
+}
+
+#[test]
+fn test_timestamp_backups_never_collide() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let file = dir.path().join("a.py");
+    fs::write(&file, "x\n").unwrap();
+    let o = opts(BackupMode::Timestamp);
+    let first = create(&file, &o).unwrap();
+    let second = create(&file, &o).unwrap();
+    assert_ne!(first, second);
+    let backups = list(&file, &o).unwrap();
+    assert_eq!(backups.len(), 2);
+    assert!(backups
+        .iter()
+        .all(|b| matches!(b.kind, BackupKind::Timestamp { .. })));
+}
+
+#[test]
+fn test_backup_dir_mirrors_relative_layout() {
+    let dir = tempfile::TempDir::new().unwrap();
+    fs::create_dir_all(dir.path().join("src/pkg")).unwrap();
+    let file = dir.path().join("src/pkg/a.py");
+    fs::write(&file, "x\n").unwrap();
+    let o = BackupOptions {
+        mode: BackupMode::Numbered,
+        dir: Some(dir.path().join("backups")),
+        base: dir.path().to_path_buf(),
+        ..BackupOptions::default()
+    };
+    let saved = create(&file, &o).unwrap();
+    assert_eq!(saved, dir.path().join("backups/src/pkg/a.py.~1~"));
+
+    let found = list_tree(&dir.path().join("src"), &o).unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].original, dir.path().join("src/pkg/a.py"));
+    assert_eq!(found[0].path, saved);
+}
+
+#[test]
+fn test_keep_prunes_oldest_versioned_backups() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let file = dir.path().join("a.py");
+    fs::write(&file, "x\n").unwrap();
+    fs::write(dir.path().join("a.py~"), "simple\n").unwrap();
+    let o = BackupOptions {
+        mode: BackupMode::Numbered,
+        keep: Some(2),
+        ..BackupOptions::default()
+    };
+    for _ in 0..4 {
+        create(&file, &o).unwrap();
+    }
+    // The simple backup does not count against the limit.
+    assert_eq!(names(dir.path()), ["a.py", "a.py.~3~", "a.py.~4~", "a.py~"]);
+}
+
+#[test]
+fn test_prunable_is_per_original() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let o = opts(BackupMode::Numbered);
+    for name in ["a.py", "b.py"] {
+        let file = dir.path().join(name);
+        fs::write(&file, "x\n").unwrap();
+        create(&file, &o).unwrap();
+        create(&file, &o).unwrap();
+    }
+    let all = list_tree(dir.path(), &o).unwrap();
+    assert_eq!(all.len(), 4);
+    let doomed: Vec<String> = prunable(&all, 1)
+        .iter()
+        .map(|b| b.path.file_name().unwrap().to_string_lossy().into_owned())
+        .collect();
+    assert_eq!(doomed, ["a.py.~1~", "b.py.~1~"]);
+}
+
+#[test]
+fn test_restore_puts_backup_content_back() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let file = dir.path().join("a.py");
+    fs::write(&file, "original\n").unwrap();
+    let o = opts(BackupMode::Numbered);
+    create(&file, &o).unwrap();
+    fs::write(&file, "changed\n").unwrap();
+    let backups = list(&file, &o).unwrap();
+    restore(backups.last().unwrap()).unwrap();
+    assert_eq!(fs::read_to_string(&file).unwrap(), "original\n");
+    assert!(dir.path().join("a.py.~1~").exists());
+}
@@ -89,3 +89,40 @@ fn test_parse_invalid_toml() {
     let result: Result<ToggleConfig, _> = toml::from_str("invalid [[[toml");
     assert!(result.is_err());
 }
+
+#[test]
+fn test_encoding_for_path_glob_then_global() {
+    let toml_str = r##"
+[global]
+encoding = "auto"
+
+[encoding]
+fallback = ["latin-1"]
+
+[[encoding.path]]
+glob = "legacy/**/*.py"
+encoding = "latin-1"
+"##;
+    let config: ToggleConfig = toml::from_str(toml_str).unwrap();
+    config.validate().unwrap();
+    assert_eq!(
+        config.encoding_for_path(std::path::Path::new("./legacy/a/b.py")),
+        Some("latin-1")
+    );
+    assert_eq!(
+        config.encoding_for_path(std::path::Path::new("src/main.py")),
+        Some("auto")
+    );
+    assert_eq!(config.fallback_encodings(), ["latin-1".to_string()]);
+}
+
+#[test]
+fn test_validate_rejects_bad_encoding_rule() {
+    let toml_str = r##"
+[[encoding.path]]
+glob = "*.py"
+encoding = "bogus-codec"
+"##;
+    let config: ToggleConfig = toml::from_str(toml_str).unwrap();
+    assert!(config.validate().is_err());
+}
@@ -0,0 +1,112 @@
+use std::fs;
+use std::io;
+use togl_lib::confine::{final_location, Root, WriteKind};
+use togl_lib::exit_codes::OutsideRootError;
+
+fn violation(err: &io::Error) -> &OutsideRootError {
+    err.get_ref()
+        .and_then(|e| e.downcast_ref::<OutsideRootError>())
+        .expect("expected an OutsideRootError")
+}
+
+#[test]
+fn test_paths_inside_root_pass() {
+    let dir = tempfile::TempDir::new().unwrap();
+    fs::create_dir(dir.path().join("src")).unwrap();
+    fs::write(dir.path().join("src/a.py"), "x\n").unwrap();
+    let root = Root::new(dir.path()).unwrap();
+    root.check(&dir.path().join("src/a.py"), WriteKind::Target)
+        .unwrap();
+    // Not created yet, and through a `..` that stays inside.
+    root.check(&dir.path().join("src/../src/a.py.bak"), WriteKind::Backup)
+        .unwrap();
+}
+
+#[test]
+fn test_dotdot_escape_is_refused() {
+    let dir = tempfile::TempDir::new().unwrap();
+    fs::create_dir(dir.path().join("proj")).unwrap();
+    fs::write(dir.path().join("out.py"), "x\n").unwrap();
+    let root = Root::new(&dir.path().join("proj")).unwrap();
+    let err = root
+        .check(&dir.path().join("proj/../out.py"), WriteKind::Target)
+        .unwrap_err();
+    let v = violation(&err);
+    assert_eq!(v.kind, WriteKind::Target);
+    assert_eq!(
+        v.resolved,
+        fs::canonicalize(dir.path()).unwrap().join("out.py")
+    );
+}
+
+#[test]
+fn test_root_must_be_a_directory() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let file = dir.path().join("f");
+    fs::write(&file, "").unwrap();
+    assert!(Root::new(&file).is_err());
+    assert!(Root::new(&dir.path().join("missing")).is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_symlink_escapes_are_refused() {
+    use std::os::unix::fs::symlink;
+    let dir = tempfile::TempDir::new().unwrap();
+    let proj = dir.path().join("proj");
+    fs::create_dir(&proj).unwrap();
+    fs::write(dir.path().join("out.py"), "x\n").unwrap();
+    symlink("../out.py", proj.join("link.py")).unwrap();
+    symlink("..", proj.join("up")).unwrap();
+    // A dangling link: the write would create the file outside.
+    symlink("../new.py", proj.join("dangling.py")).unwrap();
+    let root = Root::new(&proj).unwrap();
+
+    for (path, kind) in [
+        ("link.py", WriteKind::Target),
+        ("up/out.py", WriteKind::Target),
+        ("dangling.py", WriteKind::Backup),
+    ] {
+        let err = root.check(&proj.join(path), kind).unwrap_err();
+        assert_eq!(violation(&err).kind, kind, "{}", path);
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_symlink_outside_root_pointing_inside_is_refused() {
+    use std::os::unix::fs::symlink;
+    let dir = tempfile::TempDir::new().unwrap();
+    let proj = dir.path().join("proj");
+    fs::create_dir(&proj).unwrap();
+    fs::write(proj.join("a.py"), "x\n").unwrap();
+    symlink("proj/a.py", dir.path().join("link.py")).unwrap();
+    let root = Root::new(&proj).unwrap();
+    // A plain write would replace the link itself, outside the root.
+    assert!(root
+        .check(&dir.path().join("link.py"), WriteKind::Target)
+        .is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_final_location_follows_dangling_link() {
+    use std::os::unix::fs::symlink;
+    let dir = tempfile::TempDir::new().unwrap();
+    symlink("sub/new.py", dir.path().join("l.py")).unwrap();
+    fs::create_dir(dir.path().join("sub")).unwrap();
+    assert_eq!(
+        final_location(&dir.path().join("l.py")).unwrap(),
+        fs::canonicalize(dir.path()).unwrap().join("sub/new.py")
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_symlink_loop_is_an_error() {
+    use std::os::unix::fs::symlink;
+    let dir = tempfile::TempDir::new().unwrap();
+    symlink("b", dir.path().join("a")).unwrap();
+    symlink("a", dir.path().join("b")).unwrap();
+    assert!(final_location(&dir.path().join("a")).is_err());
+}
@@ -1,8 +1,11 @@
 use std::path::Path;
 use togl_lib::core::{
-    find_and_toggle_section, get_comment_style, insert_section, merge_ranges, parse_line_range,
-    scan_sections, supported_extensions, toggle_comments, CommentStyle, LineRange,
+    find_and_toggle_section, find_pattern_ranges, get_comment_style, insert_section,
+    insert_section_matching, merge_ranges, parse_line_range, scan_sections, supported_extensions,
+    toggle_comments, toggle_variant_group, CommentStyle, LineCounts, LinePattern, LineRange,
+    SectionState,
 };
+use togl_lib::exit_codes::{ExitCode, ToglError};
 
 // ── parse_line_range ──
 
@@ -273,7 +276,7 @@ fn test_scan_sections_finds_single_section() {
     assert_eq!(sections[0].start_line, 1);
     assert_eq!(sections[0].end_line, Some(3));
     assert_eq!(sections[0].description.as_deref(), Some("Debug output"));
-    assert_eq!(sections[0].state, "commented");
+    assert_eq!(sections[0].state, SectionState::Commented);
 }
 
 #[test]
@@ -290,9 +293,9 @@ active_line
     let sections = scan_sections(path, content);
     assert_eq!(sections.len(), 2);
     assert_eq!(sections[0].id, "alpha");
-    assert_eq!(sections[0].state, "commented");
+    assert_eq!(sections[0].state, SectionState::Commented);
     assert_eq!(sections[1].id, "beta");
-    assert_eq!(sections[1].state, "uncommented");
+    assert_eq!(sections[1].state, SectionState::Uncommented);
 }
 
 #[test]
@@ -305,7 +308,27 @@ uncommented
 ";
     let path = Path::new("test.py");
     let sections = scan_sections(path, content);
-    assert_eq!(sections[0].state, "mixed");
+    assert_eq!(sections[0].state, SectionState::Mixed);
+    assert_eq!(
+        sections[0].line_counts,
+        LineCounts {
+            commented: 1,
+            live: 1,
+            blank: 0
+        }
+    );
+    assert_eq!(sections[0].state_label(), "mixed (1/2 lines live)");
+}
+
+#[test]
+fn test_section_state_serializes_lowercase() {
+    let content = "# toggle:start ID=a\n\nx\n# toggle:end ID=a\n";
+    let sections = scan_sections(Path::new("test.py"), content);
+    assert_eq!(sections[0].line_counts.blank, 1);
+    let json = serde_json::to_value(&sections[0]).unwrap();
+    assert_eq!(json["state"], "uncommented");
+    assert_eq!(json["line_counts"]["live"], 1);
+    assert_eq!(SectionState::Mixed.to_string(), "mixed");
 }
 
 #[test]
@@ -316,7 +339,7 @@ fn test_scan_sections_unclosed_section() {
     assert_eq!(sections.len(), 1);
     assert_eq!(sections[0].id, "orphan");
     assert!(sections[0].end_line.is_none());
-    assert_eq!(sections[0].state, "unknown");
+    assert_eq!(sections[0].state, SectionState::Unknown);
 }
 
 #[test]
@@ -325,7 +348,7 @@ fn test_scan_sections_empty_section() {
     let path = Path::new("test.py");
     let sections = scan_sections(path, content);
     assert_eq!(sections.len(), 1);
-    assert_eq!(sections[0].state, "empty");
+    assert_eq!(sections[0].state, SectionState::Empty);
 }
 
 #[test]
@@ -339,7 +362,7 @@ fn test_scan_sections_javascript_comment_style() {
     let sections = scan_sections(path, content);
     assert_eq!(sections.len(), 1);
     assert_eq!(sections[0].id, "feature");
-    assert_eq!(sections[0].state, "commented");
+    assert_eq!(sections[0].state, SectionState::Commented);
 }
 
 // ── parse_id_parts ──
@@ -706,7 +729,7 @@ print("d")
             assert_eq!(section_type, &SectionType::Solo);
             assert_eq!(files.len(), 1);
             assert_eq!(files[0].path, "src/app.py");
-            assert_eq!(files[0].state, "uncommented");
+            assert_eq!(files[0].state, SectionState::Uncommented);
             assert_eq!(files[0].desc.as_deref(), Some("Debug output"));
         }
         _ => panic!("expected Solo"),
@@ -726,10 +749,10 @@ print("d")
             assert_eq!(section_type, &SectionType::Pair);
             assert_eq!(variants.len(), 2);
             let sqlite = variants.iter().find(|v| v.id == "db:sqlite").unwrap();
-            assert_eq!(sqlite.state, "uncommented");
+            assert_eq!(sqlite.state, SectionState::Uncommented);
             assert_eq!(sqlite.files.len(), 1);
             let postgres = variants.iter().find(|v| v.id == "db:postgres").unwrap();
-            assert_eq!(postgres.state, "commented");
+            assert_eq!(postgres.state, SectionState::Commented);
         }
         _ => panic!("expected Group"),
     }
@@ -874,3 +897,326 @@ fn test_remove_section_id_not_found_is_noop() {
     assert_eq!(n, 0);
     assert_eq!(out, REMOVE_FIXTURE);
 }
+
+// ── rename_section ──
+
+const RENAME_FIXTURE: &str = "\
+# toggle:start ID=db:sqlite desc=\"Local\"
+db = 'sqlite'
+# toggle:end ID=db:sqlite
+# toggle:start ID=db:postgres
+# db = 'postgres'
+# toggle:end ID=db:postgres
+# toggle:start ID=dbx
+x = 1
+# toggle:end ID=dbx
+";
+
+#[test]
+fn test_rename_section_exact_id() {
+    let (out, n) = togl_lib::core::rename_section(RENAME_FIXTURE, "dbx", "db_extra").unwrap();
+    assert_eq!(n, 1);
+    assert!(out.contains("# toggle:start ID=db_extra\n"));
+    assert!(out.contains("# toggle:end ID=db_extra\n"));
+    // The group `db` shares a prefix but is not touched.
+    assert!(out.contains("ID=db:sqlite"));
+}
+
+#[test]
+fn test_rename_section_group_keeps_variants() {
+    let (out, n) = togl_lib::core::rename_section(RENAME_FIXTURE, "db", "database").unwrap();
+    assert_eq!(n, 2);
+    assert!(out.contains("# toggle:start ID=database:sqlite desc=\"Local\"\n"));
+    assert!(out.contains("# toggle:end ID=database:postgres\n"));
+    assert!(out.contains("ID=dbx"), "an exact-match-only rename");
+    // Only marker lines change.
+    assert_eq!(out.lines().count(), RENAME_FIXTURE.lines().count());
+    assert!(out.contains("# db = 'postgres'"));
+}
+
+#[test]
+fn test_rename_section_single_variant() {
+    let (out, n) = togl_lib::core::rename_section(RENAME_FIXTURE, "db:postgres", "db:pg").unwrap();
+    assert_eq!(n, 1);
+    assert!(out.contains("ID=db:pg\n"));
+    assert!(out.contains("ID=db:sqlite"));
+}
+
+#[test]
+fn test_rename_section_refuses_duplicate_id() {
+    match togl_lib::core::rename_section(RENAME_FIXTURE, "db:postgres", "db:sqlite") {
+        Err(ToglError::DuplicateSection { id }) => assert_eq!(id, "db:sqlite"),
+        other => panic!("expected DuplicateSection, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_rename_section_refuses_merging_a_group_and_a_solo() {
+    let content =
+        format!("{RENAME_FIXTURE}# toggle:start ID=database\nx = 1\n# toggle:end ID=database\n");
+    match togl_lib::core::rename_section(&content, "db", "database") {
+        Err(ToglError::DuplicateSection { id }) => assert_eq!(id, "database"),
+        other => panic!("expected DuplicateSection, got {:?}", other),
+    }
+    // And the other way round: a solo renamed to an existing group's name.
+    match togl_lib::core::rename_section(&content, "database", "db") {
+        Err(ToglError::DuplicateSection { id }) => assert_eq!(id, "db"),
+        other => panic!("expected DuplicateSection, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_rename_section_refuses_variant_with_two_colons() {
+    assert!(matches!(
+        togl_lib::core::rename_section(RENAME_FIXTURE, "db", "store:db"),
+        Err(ToglError::InvalidSectionId { .. })
+    ));
+    assert!(matches!(
+        togl_lib::core::rename_section(RENAME_FIXTURE, "dbx", "has space"),
+        Err(ToglError::InvalidSectionId { .. })
+    ));
+}
+
+#[test]
+fn test_rename_section_not_found_is_noop() {
+    let (out, n) = togl_lib::core::rename_section(RENAME_FIXTURE, "missing", "x").unwrap();
+    assert_eq!(n, 0);
+    assert_eq!(out, RENAME_FIXTURE);
+}
+
+// ── add_variant ──
+
+#[test]
+fn test_add_variant_copies_commented_body_after_source() {
+    let (out, n) =
+        togl_lib::core::add_variant(RENAME_FIXTURE, "db:sqlite", "db:mysql", None, None, "#")
+            .unwrap();
+    assert_eq!(n, 1);
+    assert!(out.contains(
+        "# toggle:end ID=db:sqlite\n# toggle:start ID=db:mysql\n# db = 'sqlite'\n# toggle:end ID=db:mysql\n# toggle:start ID=db:postgres\n"
+    ));
+}
+
+#[test]
+fn test_add_variant_converts_solo_and_keeps_indent() {
+    let content =
+        "def f():\n    # toggle:start ID=cache\n    hit = True\n    # toggle:end ID=cache\n";
+    let (out, _) =
+        togl_lib::core::add_variant(content, "cache", "cache:redis", None, Some("Redis"), "#")
+            .unwrap();
+    assert_eq!(
+        out,
+        "def f():\n    # toggle:start ID=cache:original\n    hit = True\n    # toggle:end ID=cache:original\n    # toggle:start ID=cache:redis desc=\"Redis\"\n    # hit = True\n    # toggle:end ID=cache:redis\n"
+    );
+    let (out, _) =
+        togl_lib::core::add_variant(content, "cache", "cache:redis", Some("lru"), None, "#")
+            .unwrap();
+    assert!(out.contains("# toggle:start ID=cache:lru\n"));
+}
+
+#[test]
+fn test_add_variant_requires_variant_of_source_group() {
+    match togl_lib::core::add_variant(RENAME_FIXTURE, "db:sqlite", "cache:x", None, None, "#") {
+        Err(ToglError::NotAVariant { id, group }) => {
+            assert_eq!(id, "cache:x");
+            assert_eq!(group, "db");
+        }
+        other => panic!("expected NotAVariant, got {:?}", other),
+    }
+    assert!(matches!(
+        togl_lib::core::add_variant(RENAME_FIXTURE, "dbx", "dbx", None, None, "#"),
+        Err(ToglError::NotAVariant { .. })
+    ));
+}
+
+#[test]
+fn test_add_variant_refuses_existing_id() {
+    assert!(matches!(
+        togl_lib::core::add_variant(RENAME_FIXTURE, "db:sqlite", "db:postgres", None, None, "#"),
+        Err(ToglError::DuplicateSection { .. })
+    ));
+}
+
+#[test]
+fn test_add_variant_missing_source_is_noop() {
+    let (out, n) =
+        togl_lib::core::add_variant(RENAME_FIXTURE, "db:oracle", "db:x", None, None, "#").unwrap();
+    assert_eq!(n, 0);
+    assert_eq!(out, RENAME_FIXTURE);
+}
+
+// ── insert by pattern ──
+
+const PATTERN_FIXTURE: &str = "\
+def main():
+    print('DEBUG start')
+    print('DEBUG args')
+    run()
+# BEGIN extra
+extra()
+# END extra
+";
+
+#[test]
+fn test_find_pattern_ranges_contiguous_runs() {
+    let pattern = LinePattern::Match(r"print\(.*DEBUG".into());
+    assert_eq!(
+        find_pattern_ranges(PATTERN_FIXTURE, &pattern).unwrap(),
+        vec![LineRange::new(2, 3)]
+    );
+    let single = LinePattern::Match("run".into());
+    assert_eq!(
+        find_pattern_ranges(PATTERN_FIXTURE, &single).unwrap(),
+        vec![LineRange::new(4, 4)]
+    );
+    let none = LinePattern::Match("nothing".into());
+    assert!(find_pattern_ranges(PATTERN_FIXTURE, &none)
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn test_find_pattern_ranges_separate_blocks() {
+    assert_eq!(
+        find_pattern_ranges(PATTERN_FIXTURE, &LinePattern::Match("ext|DEBUG".into())).unwrap(),
+        vec![LineRange::new(2, 3), LineRange::new(5, 7)]
+    );
+    assert!(matches!(
+        find_pattern_ranges(PATTERN_FIXTURE, &LinePattern::Match("(".into())),
+        Err(ToglError::InvalidPattern { .. })
+    ));
+}
+
+#[test]
+fn test_find_pattern_ranges_between_anchors() {
+    let between = LinePattern::Between {
+        from: "/BEGIN/".into(),
+        to: Some("/END/".into()),
+    };
+    assert_eq!(
+        find_pattern_ranges(PATTERN_FIXTURE, &between).unwrap(),
+        vec![LineRange::new(5, 7)]
+    );
+    let twice = "# BEGIN\nx\n# END\ny\n# BEGIN\nz\n# END\n";
+    assert_eq!(
+        find_pattern_ranges(twice, &between).unwrap(),
+        vec![LineRange::new(1, 3), LineRange::new(5, 7)]
+    );
+    let to_end = LinePattern::Between {
+        from: "/run/".into(),
+        to: None,
+    };
+    assert_eq!(
+        find_pattern_ranges(PATTERN_FIXTURE, &to_end).unwrap(),
+        vec![LineRange::new(4, 7)]
+    );
+    let unclosed = LinePattern::Between {
+        from: "/END/".into(),
+        to: Some("/BEGIN/".into()),
+    };
+    assert!(matches!(
+        find_pattern_ranges(PATTERN_FIXTURE, &unclosed),
+        Err(ToglError::InvalidPattern { .. })
+    ));
+}
+
+#[test]
+fn test_insert_section_matching_skips_marker_lines() {
+    let pattern = LinePattern::Match("DEBUG".into());
+    let (out, n) = insert_section_matching(PATTERN_FIXTURE, "debug", None, &pattern, "#").unwrap();
+    assert_eq!(n, 1);
+    assert!(out.contains("    # toggle:start ID=debug\n    print('DEBUG start')\n"));
+    // Markers contain the ID; they must not extend the run or match again.
+    let again = LinePattern::Match("debug|DEBUG".into());
+    assert!(matches!(
+        insert_section_matching(&out, "other", None, &again, "#"),
+        Ok((_, 1))
+    ));
+    // Inserting the same ID again is a no-op.
+    assert_eq!(
+        insert_section_matching(&out, "debug", None, &pattern, "#").unwrap(),
+        (out.clone(), 0)
+    );
+}
+
+#[test]
+fn test_insert_section_matching_wraps_each_block_under_one_id() {
+    let pattern = LinePattern::Match("ext|DEBUG".into());
+    let (out, n) = insert_section_matching(PATTERN_FIXTURE, "dbg", None, &pattern, "#").unwrap();
+    assert_eq!(n, 2);
+    assert_eq!(
+        out,
+        "def main():\n    # toggle:start ID=dbg\n    print('DEBUG start')\n    print('DEBUG args')\n    \
+         # toggle:end ID=dbg\n    run()\n# toggle:start ID=dbg\n# BEGIN extra\nextra()\n# END extra\n\
+         # toggle:end ID=dbg\n"
+    );
+    assert_eq!(
+        insert_section_matching(&out, "dbg", None, &pattern, "#").unwrap(),
+        (out.clone(), 0)
+    );
+    // Only a block not yet wrapped is inserted on a re-run.
+    let grown = format!("{out}print('DEBUG late')\n");
+    let (again, n) = insert_section_matching(&grown, "dbg", None, &pattern, "#").unwrap();
+    assert_eq!(n, 1);
+    assert_eq!(
+        again,
+        format!("{out}# toggle:start ID=dbg\nprint('DEBUG late')\n# toggle:end ID=dbg\n")
+    );
+}
+
+// ── ToglError ──
+
+#[test]
+fn test_error_invalid_range_keeps_spec() {
+    match parse_line_range("5:3").unwrap_err() {
+        ToglError::InvalidRange { spec, .. } => assert_eq!(spec, "5:3"),
+        other => panic!("unexpected error: {other:?}"),
+    }
+}
+
+#[test]
+fn test_error_unclosed_marker_has_line() {
+    let mut lines: Vec<String> = ["x", "# toggle:start ID=foo", "y"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+    let result = find_and_toggle_section(&mut lines, "foo", &None, &comment_style_py());
+    assert!(matches!(result, Err(ToglError::UnclosedMarker { ref id, line: 2 }) if id == "foo"));
+}
+
+#[test]
+fn test_error_ambiguous_group_has_count() {
+    let content = "\
+# toggle:start ID=db:a
+a
+# toggle:end ID=db:a
+# toggle:start ID=db:b
+# b
+# toggle:end ID=db:b
+# toggle:start ID=db:c
+# c
+# toggle:end ID=db:c
+";
+    let err = toggle_variant_group(content, "db", &None, &comment_style_py()).unwrap_err();
+    assert!(matches!(err, ToglError::AmbiguousGroup { count: 3, .. }));
+    assert!(matches!(
+        toggle_variant_group(content, "cache", &None, &comment_style_py()).unwrap_err(),
+        ToglError::SectionNotFound { .. }
+    ));
+}
+
+#[test]
+fn test_error_unsupported_language_and_exit_codes() {
+    let err = get_comment_style(Path::new("notes.xyz"), "auto", None).unwrap_err();
+    assert!(
+        matches!(err, ToglError::UnsupportedLanguage { ref extension, .. } if extension == "xyz")
+    );
+    assert_eq!(err.exit_code().code(), ExitCode::Usage.code());
+
+    let io = ToglError::Io {
+        path: "a.py".into(),
+        source: std::io::Error::from(std::io::ErrorKind::NotFound),
+    };
+    assert_eq!(io.exit_code().code(), ExitCode::IoError.code());
+    assert_eq!(io.to_string(), "could not read 'a.py'");
+}
@@ -0,0 +1,151 @@
+use std::fs;
+use tempfile::TempDir;
+use togl_lib::history::{
+    append_record, apply_patch, history_path, mark_undone, plan_undo, read_history, reverse_patch,
+    ConflictError, FileChange,
+};
+
+#[test]
+fn test_reverse_patch_roundtrip() {
+    let pre = b"a\nb\n# c\nd\n";
+    let post = b"a\nb\nc\nd\ne\n";
+    let hunks = reverse_patch(pre, post);
+    assert_eq!(apply_patch(post, &hunks).unwrap(), pre);
+}
+
+#[test]
+fn test_reverse_patch_is_minimal() {
+    let pre = b"one\ntwo\nthree\n";
+    let post = b"one\n# two\nthree\n";
+    let hunks = reverse_patch(pre, post);
+    assert_eq!(hunks.len(), 1);
+    assert_eq!(hunks[0].at, 1);
+    assert_eq!(hunks[0].remove, 1);
+    assert_eq!(hunks[0].insert, "two\n");
+}
+
+#[test]
+fn test_reverse_patch_non_utf8_uses_hex() {
+    let pre = b"caf\xe9\n";
+    let post = b"# caf\xe9\n";
+    let hunks = reverse_patch(pre, post);
+    assert!(hunks[0].insert_hex.is_some());
+    assert_eq!(apply_patch(post, &hunks).unwrap(), pre);
+}
+
+#[test]
+fn test_reverse_patch_crlf_and_missing_final_newline() {
+    let pre = b"x\r\ny\r\nz";
+    let post = b"x\r\n# y\r\nz\n";
+    let hunks = reverse_patch(pre, post);
+    assert_eq!(apply_patch(post, &hunks).unwrap(), pre);
+}
+
+#[test]
+fn test_append_and_read_history() {
+    let dir = TempDir::new().unwrap();
+    let store = history_path(dir.path());
+    let file = dir.path().join("a.py");
+    fs::write(&file, "b\n").unwrap();
+
+    let id1 = append_record(
+        &store,
+        "togl toggle",
+        vec![FileChange::new(&file, b"a\n", b"b\n")],
+    )
+    .unwrap();
+    let id2 = append_record(&store, "togl remove", Vec::new()).unwrap();
+    assert_eq!((id1, id2), (1, 2));
+
+    let records = read_history(&store).unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].command, "togl toggle");
+    assert!(!records[0].undone);
+
+    mark_undone(&store, &[1]).unwrap();
+    let records = read_history(&store).unwrap();
+    assert!(records[0].undone);
+    assert!(!records[1].undone);
+}
+
+#[test]
+fn test_read_history_missing_is_empty() {
+    let dir = TempDir::new().unwrap();
+    assert!(read_history(&history_path(dir.path())).unwrap().is_empty());
+}
+
+#[test]
+fn test_plan_undo_chains_records() {
+    let dir = TempDir::new().unwrap();
+    let store = history_path(dir.path());
+    let file = dir.path().join("a.py");
+    fs::write(&file, "v3\n").unwrap();
+    append_record(
+        &store,
+        "first",
+        vec![FileChange::new(&file, b"v1\n", b"v2\n")],
+    )
+    .unwrap();
+    append_record(
+        &store,
+        "second",
+        vec![FileChange::new(&file, b"v2\n", b"v3\n")],
+    )
+    .unwrap();
+
+    let records = read_history(&store).unwrap();
+    let plan = plan_undo(&records, 2).unwrap();
+    assert_eq!(plan.record_ids, vec![2, 1]);
+    assert_eq!(plan.restores.len(), 1);
+    assert_eq!(plan.restores[0].1, b"v1\n");
+}
+
+#[test]
+fn test_plan_undo_skips_undone_records() {
+    let dir = TempDir::new().unwrap();
+    let store = history_path(dir.path());
+    let file = dir.path().join("a.py");
+    fs::write(&file, "v2\n").unwrap();
+    append_record(
+        &store,
+        "first",
+        vec![FileChange::new(&file, b"v1\n", b"v2\n")],
+    )
+    .unwrap();
+    append_record(
+        &store,
+        "second",
+        vec![FileChange::new(&file, b"v2\n", b"v3\n")],
+    )
+    .unwrap();
+    mark_undone(&store, &[2]).unwrap();
+
+    let plan = plan_undo(&read_history(&store).unwrap(), 1).unwrap();
+    assert_eq!(plan.record_ids, vec![1]);
+    assert_eq!(plan.restores[0].1, b"v1\n");
+}
+
+#[test]
+fn test_plan_undo_reports_conflicts() {
+    let dir = TempDir::new().unwrap();
+    let store = history_path(dir.path());
+    let file = dir.path().join("a.py");
+    fs::write(&file, "edited later\n").unwrap();
+    append_record(
+        &store,
+        "first",
+        vec![FileChange::new(&file, b"v1\n", b"v2\n")],
+    )
+    .unwrap();
+
+    let err = plan_undo(&read_history(&store).unwrap(), 1).unwrap_err();
+    let conflict = err.downcast_ref::<ConflictError>().unwrap();
+    assert_eq!(conflict.0.len(), 1);
+    assert_eq!(conflict.0[0].record_id, 1);
+    assert!(conflict.0[0].actual_sha256.is_some());
+}
+
+#[test]
+fn test_plan_undo_nothing_to_undo() {
+    assert!(plan_undo(&[], 1).is_err());
+}
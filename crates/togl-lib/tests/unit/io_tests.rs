@@ -1,4 +1,12 @@
-use togl_lib::io::{detect_protected_lines, is_symlink, normalize_eol, read_file_encoded};
+use std::io;
+use std::path::Path;
+use togl_lib::exit_codes::ContentChangedError;
+use togl_lib::io::{
+    apply_eol, create_atomic_backup, decode_bytes, detect_protected_lines, encode_text, is_symlink,
+    line_edits, normalize_eol, read_file_encoded, restore_line_endings, verify_unchanged,
+    write_file_as_checked, BackupStrategy, FileEncoding, FileOps, LineEdit, RealFileOps,
+};
+use togl_lib::journal::BackupMethod;
 
 #[test]
 fn test_detect_shebang() {
@@ -80,6 +88,63 @@ fn test_normalize_eol_crlf_no_double_convert() {
     assert_eq!(normalize_eol(content, "crlf"), "line1\r\nline2\r\n");
 }
 
+// ── restore_line_endings / apply_eol ──
+
+#[test]
+fn test_restore_line_endings_crlf_toggle() {
+    let original = "a\r\nb\r\nc\r\n";
+    let modified = "# a\nb\nc\n";
+    assert_eq!(
+        restore_line_endings(original, modified),
+        "# a\r\nb\r\nc\r\n"
+    );
+}
+
+#[test]
+fn test_restore_line_endings_mixed_untouched_lines_kept() {
+    let original = "a\r\nb\nc\r\nd\n";
+    let modified = "a\n# b\n# c\nd\n";
+    assert_eq!(
+        restore_line_endings(original, modified),
+        "a\r\n# b\n# c\r\nd\n"
+    );
+}
+
+#[test]
+fn test_restore_line_endings_no_final_newline() {
+    let original = "a\r\nb";
+    let modified = "a\n# b";
+    assert_eq!(restore_line_endings(original, modified), "a\r\n# b");
+}
+
+#[test]
+fn test_restore_line_endings_inserted_lines_inherit_neighbor() {
+    let original = "x\na\r\nb";
+    let modified = "x\n# start\na\nb\n# end";
+    assert_eq!(
+        restore_line_endings(original, modified),
+        "x\n# start\na\r\nb\r\n# end"
+    );
+}
+
+#[test]
+fn test_restore_line_endings_removed_lines() {
+    let original = "keep\r\n# start\r\nbody\n# end\r\ntail\n";
+    let modified = "keep\nbody\ntail\n";
+    assert_eq!(
+        restore_line_endings(original, modified),
+        "keep\r\nbody\ntail\n"
+    );
+}
+
+#[test]
+fn test_apply_eol_normalizes_for_lf_and_crlf() {
+    let original = "a\r\nb\n";
+    assert_eq!(apply_eol(original, "# a\nb\n", "lf"), "# a\nb\n");
+    assert_eq!(apply_eol(original, "# a\nb\n", "crlf"), "# a\r\nb\r\n");
+    assert_eq!(apply_eol(original, "# a\nb\n", "preserve"), "# a\r\nb\n");
+}
+
 // ── is_symlink ──
 
 #[cfg(unix)]
@@ -132,3 +197,344 @@ fn test_read_file_encoded_unsupported() {
     let result = read_file_encoded(&path, "bogus-codec");
     assert!(result.is_err());
 }
+
+// ── decode_bytes / encode_text ──
+
+#[test]
+fn test_decode_auto_plain_utf8() {
+    let (text, enc) = decode_bytes("héllo\n".as_bytes(), "auto", &[]).unwrap();
+    assert_eq!(text, "héllo\n");
+    assert_eq!(enc, FileEncoding::utf8());
+}
+
+#[test]
+fn test_decode_utf8_bom_stripped_and_restored() {
+    let bytes = [0xEF, 0xBB, 0xBF, b'a', b'\n'];
+    let (text, enc) = decode_bytes(&bytes, "utf-8", &[]).unwrap();
+    assert_eq!(text, "a\n");
+    assert!(enc.bom);
+    assert_eq!(encode_text("# a\n", &enc).unwrap(), b"\xEF\xBB\xBF# a\n");
+}
+
+#[test]
+fn test_decode_auto_utf16le_roundtrip() {
+    let mut bytes = vec![0xFF, 0xFE];
+    bytes.extend("a\nb\n".encode_utf16().flat_map(u16::to_le_bytes));
+    let (text, enc) = decode_bytes(&bytes, "auto", &[]).unwrap();
+    assert_eq!(text, "a\nb\n");
+    assert_eq!(enc.name, "UTF-16LE");
+    assert!(enc.bom);
+    assert_eq!(encode_text("a\nb\n", &enc).unwrap(), bytes);
+}
+
+#[test]
+fn test_decode_auto_utf16be_roundtrip() {
+    let mut bytes = vec![0xFE, 0xFF];
+    bytes.extend("x\n".encode_utf16().flat_map(u16::to_be_bytes));
+    let (text, enc) = decode_bytes(&bytes, "auto", &[]).unwrap();
+    assert_eq!(text, "x\n");
+    assert_eq!(enc.name, "UTF-16BE");
+    assert_eq!(encode_text("x\n", &enc).unwrap(), bytes);
+}
+
+#[test]
+fn test_decode_auto_falls_back_to_default_legacy() {
+    let (text, enc) = decode_bytes(&[0x63, 0x61, 0x66, 0xe9], "auto", &[]).unwrap();
+    assert_eq!(text, "caf\u{e9}");
+    assert_eq!(enc.name, "windows-1252");
+    assert!(!enc.bom);
+}
+
+#[test]
+fn test_decode_auto_uses_configured_fallbacks_in_order() {
+    // 0x82 0xA0 is "あ" in Shift_JIS.
+    let fallbacks = vec!["shift_jis".to_string(), "windows-1252".to_string()];
+    let (text, enc) = decode_bytes(&[0x82, 0xA0], "auto", &fallbacks).unwrap();
+    assert_eq!(text, "\u{3042}");
+    assert_eq!(enc.name, "Shift_JIS");
+}
+
+#[test]
+fn test_decode_explicit_utf8_rejects_invalid() {
+    assert!(decode_bytes(&[0xff, 0xfe, 0xfd], "utf-8", &[]).is_err());
+}
+
+/// Filesystem that supports neither hard links nor reflinks (like some FUSE
+/// and network mounts), delegating everything else to the real one.
+struct NoLinkFileOps;
+
+impl FileOps for NoLinkFileOps {
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        RealFileOps.rename(from, to)
+    }
+    fn hard_link(&self, _src: &Path, _dst: &Path) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "no hard links"))
+    }
+    fn copy(&self, src: &Path, dst: &Path) -> io::Result<()> {
+        RealFileOps.copy(src, dst)
+    }
+    fn reflink(&self, _src: &Path, _dst: &Path) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "no reflinks"))
+    }
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        RealFileOps.remove_file(path)
+    }
+    fn sync_dir(&self, path: &Path) -> io::Result<()> {
+        RealFileOps.sync_dir(path)
+    }
+}
+
+#[test]
+fn test_backup_strategy_parse() {
+    assert_eq!(
+        BackupStrategy::parse("hardlink"),
+        Some(BackupStrategy::Hardlink)
+    );
+    assert_eq!(BackupStrategy::parse("copy"), Some(BackupStrategy::Copy));
+    assert_eq!(
+        BackupStrategy::parse("reflink-if-possible"),
+        Some(BackupStrategy::ReflinkIfPossible)
+    );
+    assert_eq!(BackupStrategy::parse("auto"), Some(BackupStrategy::Auto));
+    assert_eq!(BackupStrategy::parse("symlink"), None);
+    assert_eq!(BackupStrategy::default(), BackupStrategy::Auto);
+}
+
+#[test]
+fn test_backup_auto_falls_back_to_copy() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let src = dir.path().join("a.py");
+    let dst = dir.path().join("a.py.bak");
+    std::fs::write(&src, "original\n").unwrap();
+
+    let method = create_atomic_backup(&NoLinkFileOps, BackupStrategy::Auto, &src, &dst).unwrap();
+    assert_eq!(method, BackupMethod::Copy);
+    assert_eq!(std::fs::read_to_string(&dst).unwrap(), "original\n");
+}
+
+#[test]
+fn test_backup_hardlink_strategy_does_not_fall_back() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let src = dir.path().join("a.py");
+    let dst = dir.path().join("a.py.bak");
+    std::fs::write(&src, "original\n").unwrap();
+
+    let err =
+        create_atomic_backup(&NoLinkFileOps, BackupStrategy::Hardlink, &src, &dst).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+    assert!(!dst.exists());
+}
+
+#[test]
+fn test_backup_copy_is_independent_of_original() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let src = dir.path().join("a.py");
+    let dst = dir.path().join("a.py.bak");
+    std::fs::write(&src, "original\n").unwrap();
+
+    let method = create_atomic_backup(&RealFileOps, BackupStrategy::Copy, &src, &dst).unwrap();
+    assert_eq!(method, BackupMethod::Copy);
+    // An in-place edit of the original must not reach the backup.
+    std::fs::write(&src, "edited\n").unwrap();
+    assert_eq!(std::fs::read_to_string(&dst).unwrap(), "original\n");
+}
+
+#[test]
+fn test_backup_never_overwrites_existing_file() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let src = dir.path().join("a.py");
+    let dst = dir.path().join("a.py.bak");
+    std::fs::write(&src, "original\n").unwrap();
+    std::fs::write(&dst, "someone else's\n").unwrap();
+
+    for strategy in [BackupStrategy::Auto, BackupStrategy::ReflinkIfPossible] {
+        let err = create_atomic_backup(&RealFileOps, strategy, &src, &dst).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+    }
+    assert_eq!(std::fs::read_to_string(&dst).unwrap(), "someone else's\n");
+}
+
+fn content_changed(err: &io::Error) -> &ContentChangedError {
+    err.get_ref()
+        .and_then(|e| e.downcast_ref::<ContentChangedError>())
+        .expect("expected a ContentChangedError")
+}
+
+#[test]
+fn test_checked_write_succeeds_when_unchanged() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = dir.path().join("a.py");
+    std::fs::write(&path, "old\n").unwrap();
+    let hash = togl_lib::journal::sha256_hex(b"old\n");
+
+    write_file_as_checked(
+        &path,
+        "new\n",
+        None,
+        false,
+        &FileEncoding::utf8(),
+        Some(&hash),
+        false,
+    )
+    .unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "new\n");
+}
+
+#[test]
+fn test_checked_write_refuses_concurrent_edit() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = dir.path().join("a.py");
+    std::fs::write(&path, "edited\n").unwrap();
+    let read_hash = togl_lib::journal::sha256_hex(b"old\n");
+
+    for suffix in [None, Some("tmp")] {
+        let err = write_file_as_checked(
+            &path,
+            "new\n",
+            suffix,
+            false,
+            &FileEncoding::utf8(),
+            Some(&read_hash),
+            false,
+        )
+        .unwrap_err();
+        let changed = content_changed(&err);
+        assert_eq!(changed.expected_sha256, read_hash);
+        assert_eq!(
+            changed.actual_sha256.as_deref(),
+            Some(togl_lib::journal::sha256_hex(b"edited\n").as_str())
+        );
+    }
+    // The edit survives and no temp file is left behind.
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "edited\n");
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+}
+
+#[test]
+fn test_verify_unchanged_reports_deleted_file() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let err = verify_unchanged(&dir.path().join("gone.py"), "00").unwrap_err();
+    assert_eq!(content_changed(&err).actual_sha256, None);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_write_keeps_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = dir.path().join("run.sh");
+    for suffix in [None, Some("tmp")] {
+        std::fs::write(&path, "old\n").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o751)).unwrap();
+        write_file_as_checked(
+            &path,
+            "new\n",
+            suffix,
+            false,
+            &FileEncoding::utf8(),
+            None,
+            false,
+        )
+        .unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o751, "suffix {:?}", suffix);
+    }
+}
+
+#[test]
+fn test_write_preserve_mtime() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = dir.path().join("a.py");
+    let old = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000_000);
+    for (suffix, preserve) in [(None, true), (Some("tmp"), true), (None, false)] {
+        std::fs::write(&path, "old\n").unwrap();
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(old).unwrap();
+        drop(file);
+
+        write_file_as_checked(
+            &path,
+            "new\n",
+            suffix,
+            false,
+            &FileEncoding::utf8(),
+            None,
+            preserve,
+        )
+        .unwrap();
+        let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+        assert_eq!(modified == old, preserve, "suffix {:?}", suffix);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new\n");
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_write_keeps_xattrs() {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = dir.path().join("a.py");
+    std::fs::write(&path, "old\n").unwrap();
+    let path_c = CString::new(path.as_os_str().as_bytes()).unwrap();
+    let name = c"user.togl.test";
+    let value = b"kept";
+    let ret = unsafe {
+        libc::setxattr(
+            path_c.as_ptr(),
+            name.as_ptr(),
+            value.as_ptr().cast(),
+            value.len(),
+            0,
+        )
+    };
+    if ret != 0 {
+        eprintln!("skipping: user xattrs not supported here");
+        return;
+    }
+
+    write_file_as_checked(
+        &path,
+        "new\n",
+        None,
+        false,
+        &FileEncoding::utf8(),
+        None,
+        false,
+    )
+    .unwrap();
+    let mut buf = [0u8; 16];
+    let len = unsafe {
+        libc::getxattr(
+            path_c.as_ptr(),
+            name.as_ptr(),
+            buf.as_mut_ptr().cast(),
+            buf.len(),
+        )
+    };
+    assert_eq!(len, value.len() as isize);
+    assert_eq!(&buf[..value.len()], value);
+}
+
+#[test]
+fn test_line_edits_are_minimal() {
+    let original = "a\nb\nc\nd\n";
+    let modified = "a\n# b\nc\nd\ne\n";
+    let edits = line_edits(original, modified);
+    assert_eq!(
+        edits,
+        vec![
+            LineEdit {
+                start: 1,
+                end: 2,
+                new_text: "# b\n".into()
+            },
+            LineEdit {
+                start: 4,
+                end: 4,
+                new_text: "e\n".into()
+            },
+        ]
+    );
+    assert!(line_edits(original, original).is_empty());
+}
@@ -0,0 +1,104 @@
+use std::fs;
+use togl_lib::core::{LinePattern, LineRange, RemoveMode};
+use togl_lib::exit_codes::ContentChangedError;
+use togl_lib::planfile::PlanFile;
+use togl_lib::select::LineSpec;
+use togl_lib::session::{Force, Operation, Toggler};
+
+const DEBUG: &str = "a\n# toggle:start ID=debug\nprint(1)\n# toggle:end ID=debug\n";
+
+#[test]
+fn test_operations_round_trip_through_json() {
+    let ops = vec![
+        Operation::ToggleLines {
+            ranges: vec![LineRange::new(1, 2)],
+            to_end: true,
+        },
+        Operation::ToggleLineSpecs {
+            specs: vec![LineSpec::parse("/def main/:block,-2:$").unwrap()],
+            to_end: false,
+        },
+        Operation::section("env:prod"),
+        Operation::InsertMatching {
+            id: "dbg".into(),
+            desc: Some("Debug".into()),
+            pattern: LinePattern::Between {
+                from: "BEGIN".into(),
+                to: None,
+            },
+        },
+        Operation::Remove {
+            id: "old".into(),
+            mode: RemoveMode::Commented,
+        },
+    ];
+    let json = serde_json::to_string(&ops).unwrap();
+    assert!(json.contains(r#""op":"toggle_line_specs","specs":["/def main/:block,-2:$"]"#));
+    assert!(json.contains(r#""mode":"commented""#));
+    let back: Vec<Operation> = serde_json::from_str(&json).unwrap();
+    assert_eq!(back, ops);
+
+    let bad = r#"{"op":"toggle_line_specs","specs":["0:1"],"to_end":false}"#;
+    assert!(serde_json::from_str::<Operation>(bad).is_err());
+}
+
+#[test]
+fn test_plan_replays_to_the_recorded_post_image() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("a.py");
+    fs::write(&path, DEBUG).unwrap();
+    let toggler = Toggler::new().with_force(Some(Force::On));
+    let ops = [Operation::section("debug")];
+    let plan = toggler
+        .plan(std::slice::from_ref(&path), false, &ops)
+        .unwrap();
+
+    let saved = PlanFile::new(&plan.files, toggler.force(), true).unwrap();
+    assert_eq!(saved.force, Some(Force::On));
+    assert_eq!(saved.files.len(), 1);
+    assert_eq!(saved.files[0].operations, ops);
+    assert!(saved.files[0]
+        .diff
+        .as_deref()
+        .unwrap()
+        .contains("+# print(1)"));
+
+    let reloaded: PlanFile = serde_json::from_str(&saved.to_json()).unwrap();
+    assert_eq!(reloaded, saved);
+    let replayed = reloaded.replay(&toggler).unwrap();
+    assert_eq!(replayed[0].modified, plan.files[0].modified);
+
+    // A different force gives a different post-image, so replay refuses.
+    let err = reloaded
+        .replay(&Toggler::new().with_force(Some(Force::Off)))
+        .unwrap_err();
+    assert!(err.to_string().contains("gives a different result"));
+}
+
+#[test]
+fn test_plan_refuses_changed_pre_image() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("a.py");
+    fs::write(&path, DEBUG).unwrap();
+    let toggler = Toggler::new();
+    let plan = toggler
+        .plan(
+            std::slice::from_ref(&path),
+            false,
+            &[Operation::section("debug")],
+        )
+        .unwrap();
+    let saved = PlanFile::new(&plan.files, None, false).unwrap();
+    assert!(saved.stale().is_empty());
+    assert!(saved.files[0].diff.is_none());
+
+    fs::write(&path, format!("{DEBUG}b\n")).unwrap();
+    let stale = saved.stale();
+    assert_eq!(stale.len(), 1);
+    assert_eq!(stale[0].expected_sha256, saved.files[0].pre_sha256);
+    let err = saved.replay(&toggler).unwrap_err();
+    assert!(err.downcast_ref::<ContentChangedError>().is_some());
+
+    fs::remove_file(&path).unwrap();
+    assert_eq!(saved.stale()[0].actual_sha256, None);
+}
@@ -0,0 +1,103 @@
+use std::io::Write;
+use togl_lib::preflight::{check_bytes, check_file, parse_size, PreflightOptions, SkipReason};
+
+fn opts() -> PreflightOptions {
+    PreflightOptions::default()
+}
+
+#[test]
+fn test_plain_text_passes() {
+    assert_eq!(check_bytes(b"x = 1\n# toggle:start ID=a\n", &opts()), None);
+}
+
+#[test]
+fn test_nul_byte_is_binary() {
+    assert_eq!(check_bytes(b"abc\0def", &opts()), Some(SkipReason::Binary));
+}
+
+#[test]
+fn test_utf16_bom_is_not_binary() {
+    let bytes = [0xFF, 0xFE, b'x', 0, b'\n', 0];
+    assert_eq!(check_bytes(&bytes, &opts()), None);
+}
+
+#[test]
+fn test_generated_marker_in_header() {
+    let src = b"// Code generated by protoc. DO NOT EDIT.\npackage x\n";
+    assert_eq!(
+        check_bytes(src, &opts()),
+        Some(SkipReason::Generated {
+            marker: "DO NOT EDIT".to_string(),
+            line: 1
+        })
+    );
+}
+
+#[test]
+fn test_generated_marker_past_header_is_ignored() {
+    let mut src = "x = 1\n".repeat(20);
+    src.push_str("# @generated\n");
+    assert_eq!(check_bytes(src.as_bytes(), &opts()), None);
+}
+
+#[test]
+fn test_conflict_markers_detected() {
+    let src = b"a\n<<<<<<< HEAD\nb\n=======\nc\n>>>>>>> main\n";
+    assert_eq!(
+        check_bytes(src, &opts()),
+        Some(SkipReason::ConflictMarkers { line: 2 })
+    );
+}
+
+#[test]
+fn test_unclosed_conflict_marker_is_not_a_conflict() {
+    assert_eq!(check_bytes(b"<<<<<<< HEAD\nb\n", &opts()), None);
+}
+
+#[test]
+fn test_allow_flags_override_checks() {
+    let o = PreflightOptions {
+        allow_binary: true,
+        allow_generated: true,
+        allow_conflicts: true,
+        max_file_size: None,
+    };
+    assert_eq!(check_bytes(b"@generated\0", &o), None);
+    assert_eq!(check_bytes(b"<<<<<<< a\n>>>>>>> b\n", &o), None);
+}
+
+#[test]
+fn test_check_file_too_large() {
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    file.write_all(&[b'x'; 100]).unwrap();
+    let o = PreflightOptions {
+        max_file_size: Some(10),
+        ..opts()
+    };
+    assert_eq!(
+        check_file(file.path(), &o).unwrap(),
+        Some(SkipReason::TooLarge {
+            size: 100,
+            limit: 10
+        })
+    );
+    assert_eq!(check_file(file.path(), &opts()).unwrap(), None);
+}
+
+#[test]
+fn test_parse_size() {
+    assert_eq!(parse_size("10M"), Ok(Some(10 << 20)));
+    assert_eq!(parse_size("512k"), Ok(Some(512 << 10)));
+    assert_eq!(parse_size("1GiB"), Ok(Some(1 << 30)));
+    assert_eq!(parse_size("4096"), Ok(Some(4096)));
+    assert_eq!(parse_size("0"), Ok(None));
+    assert_eq!(parse_size("none"), Ok(None));
+    assert!(parse_size("10X").is_err());
+    assert!(parse_size("").is_err());
+}
+
+#[test]
+fn test_skip_reason_serializes_with_reason_tag() {
+    let json = serde_json::to_string(&SkipReason::ConflictMarkers { line: 3 }).unwrap();
+    assert_eq!(json, r#"{"reason":"conflict-markers","line":3}"#);
+}
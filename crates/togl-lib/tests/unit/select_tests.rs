@@ -0,0 +1,169 @@
+use std::path::PathBuf;
+
+use togl_lib::core::LineRange;
+use togl_lib::exit_codes::ToglError;
+use togl_lib::select::{block_end, LineSpec, Locations};
+
+const CONTENT: &str = "\
+import os
+# BEGIN setup
+setup()
+# END setup
+def main():
+    run()
+    stop()
+print('done')
+";
+
+fn resolve(spec: &str) -> Vec<LineRange> {
+    LineSpec::parse(spec).unwrap().resolve(CONTENT).unwrap()
+}
+
+#[test]
+fn test_numeric_specs_match_parse_line_range() {
+    assert_eq!(resolve("2:4"), vec![LineRange::new(2, 4)]);
+    assert_eq!(resolve("2:+1"), vec![LineRange::new(2, 3)]);
+    assert_eq!(resolve("7"), vec![LineRange::new(7, 7)]);
+    // Bounds are the caller's job, as with parse_line_range.
+    assert_eq!(resolve("20:30"), vec![LineRange::new(20, 30)]);
+}
+
+#[test]
+fn test_lists_last_line_and_offsets_from_end() {
+    assert_eq!(
+        resolve("1:2,5:6"),
+        vec![LineRange::new(1, 2), LineRange::new(5, 6)]
+    );
+    assert_eq!(resolve("$"), vec![LineRange::new(8, 8)]);
+    assert_eq!(resolve("-3:$"), vec![LineRange::new(6, 8)]);
+    assert_eq!(resolve("5:-2"), vec![LineRange::new(5, 7)]);
+}
+
+#[test]
+fn test_regex_anchors() {
+    assert_eq!(resolve("/def main/:+2"), vec![LineRange::new(5, 7)]);
+    assert_eq!(resolve("/BEGIN/:/END/"), vec![LineRange::new(2, 4)]);
+    // An end pattern is searched for after the start only.
+    assert_eq!(resolve("/run/:/\\(\\)/"), vec![LineRange::new(6, 7)]);
+    // ':' and ',' inside a pattern do not split the spec.
+    assert_eq!(
+        resolve("/main\\(\\):/,1"),
+        vec![LineRange::new(5, 5), LineRange::new(1, 1)]
+    );
+}
+
+#[test]
+fn test_parse_errors() {
+    for spec in [
+        "", "abc", "1:abc", "1:+abc", "0", "0:5", "5:3", "1:2:3", "1,,2", "//", "-0",
+    ] {
+        assert!(
+            matches!(LineSpec::parse(spec), Err(ToglError::InvalidRange { .. })),
+            "{spec:?} should not parse"
+        );
+    }
+    assert!(matches!(
+        LineSpec::parse("/(/"),
+        Err(ToglError::InvalidPattern { .. })
+    ));
+}
+
+#[test]
+fn test_resolve_errors() {
+    let spec = LineSpec::parse("/nothing/:+1").unwrap();
+    match spec.resolve(CONTENT) {
+        Err(ToglError::InvalidPattern { pattern, reason }) => {
+            assert_eq!(pattern, "/nothing/");
+            assert_eq!(reason, "no line matches it");
+        }
+        other => panic!("expected InvalidPattern, got {:?}", other),
+    }
+    assert!(LineSpec::parse("-9:$").unwrap().resolve(CONTENT).is_err());
+    assert!(LineSpec::parse("$").unwrap().resolve("").is_err());
+    // Both ends resolve, but in the wrong order.
+    assert!(matches!(
+        LineSpec::parse("$:/BEGIN/").unwrap().resolve(CONTENT),
+        Err(ToglError::InvalidPattern { .. })
+    ));
+    assert!(matches!(
+        LineSpec::parse("-1:3").unwrap().resolve(CONTENT),
+        Err(ToglError::InvalidRange { .. })
+    ));
+}
+
+// ── blocks ──
+
+fn block(content: &str, start: usize) -> usize {
+    let lines: Vec<&str> = content.lines().collect();
+    block_end(&lines, start)
+}
+
+#[test]
+fn test_block_by_indentation() {
+    let py = "def main(\n    a,\n):\n    x = 1\n\n    return x\n\n\ndef other():\n    pass\n";
+    // Multi-line parameters, then the indented body; trailing blanks excluded.
+    assert_eq!(block(py, 1), 6);
+    assert_eq!(block(py, 9), 10);
+    let yaml = "a:\n  b: 1\n  c:\n    - d\nz: 2\n";
+    assert_eq!(block(yaml, 1), 4);
+    assert_eq!(block(yaml, 3), 4);
+    // A line with nothing nested under it is its own block.
+    assert_eq!(block(yaml, 2), 2);
+}
+
+#[test]
+fn test_block_by_braces() {
+    let c = "int f(int a)\n{\n    if (a) {\n        return 1;\n    } else {\n        return 2;\n    }\n}\nint g;\n";
+    assert_eq!(block(c, 1), 8, "brace on its own line");
+    assert_eq!(block(c, 3), 7, "if/else chain");
+    let js = "const x = {\n  a: \"}\", // }\n  b: '{',\n};\nlater();\n";
+    assert_eq!(
+        block(js, 1),
+        4,
+        "braces in strings and comments are ignored"
+    );
+    let rs = "fn f<'a>(x: &'a str) -> &'a str {\n    x\n}\nfn g() {}\n";
+    assert_eq!(block(rs, 1), 3, "lifetimes are not quotes");
+}
+
+#[test]
+fn test_block_spec() {
+    assert_eq!(resolve("/def main/:block"), vec![LineRange::new(5, 7)]);
+    assert_eq!(resolve("2:block"), vec![LineRange::new(2, 2)]);
+    assert!(matches!(
+        LineSpec::parse("20:block").unwrap().resolve(CONTENT),
+        Err(ToglError::LineOutOfRange { line: 20, .. })
+    ));
+}
+
+#[test]
+fn test_locations_from_grep_rg_and_compilers() {
+    let text = "\
+src/a.py:3:print('debug')
+./src/a.py:4:1:print('more')
+src/b.c:10:5: warning: unused variable 'x'
+    int x;
+        ^
+src/a.py:12-14
+
+";
+    let locations = Locations::parse(text);
+    assert_eq!(
+        locations.files,
+        vec![
+            (
+                PathBuf::from("src/a.py"),
+                vec![LineRange::new(3, 4), LineRange::new(12, 14)]
+            ),
+            (PathBuf::from("src/b.c"), vec![LineRange::new(10, 10)]),
+        ]
+    );
+    assert_eq!(locations.unparsed, vec![4, 5]);
+}
+
+#[test]
+fn test_locations_reject_bad_ranges() {
+    let locations = Locations::parse("a.py:0\na.py:5-2\nno location here\n");
+    assert!(locations.files.is_empty());
+    assert_eq!(locations.unparsed, vec![1, 2, 3]);
+}
@@ -0,0 +1,250 @@
+use std::fs;
+use std::path::Path;
+use togl_lib::backup::BackupOptions;
+use togl_lib::core::{LineRange, RemoveMode};
+use togl_lib::session::{Eol, Force, Mode, Operation, Plan, Toggler};
+
+const SECTIONS: &str = "\
+# toggle:start ID=debug desc=\"Debug output\"
+print('debug')
+# toggle:end ID=debug
+# toggle:start ID=env:dev
+url = 'dev'
+# toggle:end ID=env:dev
+# toggle:start ID=env:prod
+# url = 'prod'
+# toggle:end ID=env:prod
+";
+
+#[test]
+fn test_operation_section_maps_variants_to_activate() {
+    assert_eq!(
+        Operation::section("env:prod"),
+        Operation::Activate {
+            group: "env".into(),
+            variant: "prod".into()
+        }
+    );
+    assert_eq!(
+        Operation::section("debug"),
+        Operation::ToggleSection { id: "debug".into() }
+    );
+    assert_eq!(
+        Operation::section("env:prod").section_id().as_deref(),
+        Some("env:prod")
+    );
+}
+
+#[test]
+fn test_transform_toggle_lines() {
+    let ops = [Operation::ToggleLines {
+        ranges: vec![LineRange::new(2, 2)],
+        to_end: false,
+    }];
+    let (out, outcomes) = Toggler::new()
+        .transform(Path::new("a.py"), "a = 1\nb = 2\n", &ops)
+        .unwrap();
+    assert_eq!(out, "a = 1\n# b = 2\n");
+    assert_eq!(outcomes.len(), 1);
+    assert_eq!(outcomes[0].lines_changed, 1);
+}
+
+#[test]
+fn test_transform_section_records_desc() {
+    let ops = [Operation::section("debug")];
+    let (out, outcomes) = Toggler::new()
+        .transform(Path::new("a.py"), SECTIONS, &ops)
+        .unwrap();
+    assert!(out.contains("# print('debug')"));
+    assert_eq!(outcomes[0].desc.as_deref(), Some("Debug output"));
+}
+
+#[test]
+fn test_transform_activate_variant() {
+    let ops = [Operation::section("env:prod")];
+    let (out, _) = Toggler::new()
+        .transform(Path::new("a.py"), SECTIONS, &ops)
+        .unwrap();
+    assert!(out.contains("# url = 'dev'"));
+    assert!(out.contains("\nurl = 'prod'"));
+}
+
+#[test]
+fn test_transform_force_on_comments_every_variant() {
+    let ops = [Operation::section("env")];
+    let toggler = Toggler::new().with_force(Some(Force::On));
+    let (once, _) = toggler
+        .transform(Path::new("a.py"), SECTIONS, &ops)
+        .unwrap();
+    let (twice, _) = toggler.transform(Path::new("a.py"), &once, &ops).unwrap();
+    assert!(once.contains("# url = 'dev'"));
+    assert!(once.contains("# url = 'prod'"));
+    assert_eq!(once, twice);
+}
+
+#[test]
+fn test_mode_and_eol_parse_known_values_only() {
+    assert_eq!(Mode::parse("multi"), Some(Mode::Multi));
+    assert_eq!(Mode::parse("block"), None);
+    assert_eq!(Eol::parse("crlf"), Some(Eol::Crlf));
+    assert_eq!(Eol::parse("CRLF"), None);
+    let ops = [Operation::ToggleLines {
+        ranges: vec![LineRange::new(1, 1)],
+        to_end: false,
+    }];
+    let (out, _) = Toggler::new()
+        .with_eol(Eol::Crlf)
+        .transform(Path::new("a.py"), "x = 1\ny = 2\n", &ops)
+        .unwrap();
+    assert_eq!(out, "# x = 1\r\ny = 2\r\n");
+}
+
+#[test]
+fn test_transform_insert_then_remove() {
+    let insert = [Operation::Insert {
+        id: "new".into(),
+        desc: None,
+        range: LineRange::new(1, 1),
+        to_end: false,
+    }];
+    let toggler = Toggler::new();
+    let (inserted, _) = toggler
+        .transform(Path::new("a.py"), "x = 1\n", &insert)
+        .unwrap();
+    assert!(inserted.contains("toggle:start ID=new"));
+
+    let remove = [Operation::Remove {
+        id: "new".into(),
+        mode: RemoveMode::Markers,
+    }];
+    let (removed, outcomes) = toggler
+        .transform(Path::new("a.py"), &inserted, &remove)
+        .unwrap();
+    assert_eq!(removed, "x = 1\n");
+    assert_eq!(outcomes[0].removed, 1);
+}
+
+#[test]
+fn test_transform_rename_group() {
+    let ops = [Operation::Rename {
+        from: "env".into(),
+        to: "stage".into(),
+    }];
+    let (out, outcomes) = Toggler::new()
+        .transform(Path::new("a.py"), SECTIONS, &ops)
+        .unwrap();
+    assert_eq!(outcomes[0].renamed, 2);
+    assert!(out.contains("ID=stage:dev"));
+    assert!(out.contains("# toggle:end ID=stage:prod"));
+    assert!(out.contains("ID=debug"));
+}
+
+#[test]
+fn test_transform_add_variant_from_solo() {
+    let ops = [Operation::AddVariant {
+        from: "debug".into(),
+        to: "debug:trace".into(),
+        solo_as: None,
+        desc: None,
+    }];
+    let (out, outcomes) = Toggler::new()
+        .transform(Path::new("a.py"), SECTIONS, &ops)
+        .unwrap();
+    assert_eq!(outcomes[0].added, 1);
+    assert!(out.contains("# toggle:start ID=debug:original desc=\"Debug output\"\n"));
+    assert!(out.contains("# toggle:start ID=debug:trace\n# print('debug')\n"));
+}
+
+#[test]
+fn test_apply_writes_only_changed_files() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let changed = dir.path().join("a.py");
+    let untouched = dir.path().join("b.py");
+    fs::write(&changed, SECTIONS).unwrap();
+    fs::write(&untouched, "x = 1\n").unwrap();
+
+    let toggler = Toggler::new();
+    let ops = [Operation::section("debug")];
+    let plan = toggler
+        .plan(&[changed.clone(), untouched.clone()], false, &ops)
+        .unwrap();
+    assert_eq!(plan.files.len(), 2);
+    assert_eq!(plan.changed().count(), 1);
+    // Planning alone writes nothing.
+    assert_eq!(fs::read_to_string(&changed).unwrap(), SECTIONS);
+
+    let written = toggler.apply(&plan).unwrap();
+    assert_eq!(written.len(), 1);
+    assert_eq!(written[0].path, changed);
+    assert!(fs::read_to_string(&changed)
+        .unwrap()
+        .contains("# print('debug')"));
+}
+
+#[test]
+fn test_apply_atomic() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let a = dir.path().join("a.py");
+    let b = dir.path().join("b.py");
+    fs::write(&a, "a = 1\n").unwrap();
+    fs::write(&b, "b = 1\n").unwrap();
+
+    let toggler = Toggler::new().with_atomic(true, true);
+    let ops = [Operation::ToggleLines {
+        ranges: vec![LineRange::new(1, 1)],
+        to_end: false,
+    }];
+    let plan = toggler.plan(&[a.clone(), b.clone()], false, &ops).unwrap();
+    let staged = toggler.stage(&plan).unwrap();
+    assert_eq!(staged.files().count(), 2);
+    // Staged files are not in place until the commit.
+    assert_eq!(fs::read_to_string(&a).unwrap(), "a = 1\n");
+    staged.commit().unwrap();
+    assert_eq!(fs::read_to_string(&a).unwrap(), "# a = 1\n");
+    assert_eq!(fs::read_to_string(&b).unwrap(), "# b = 1\n");
+}
+
+#[test]
+fn test_apply_empty_plan() {
+    let toggler = Toggler::new().with_atomic(true, true);
+    assert!(toggler.apply(&Plan::default()).unwrap().is_empty());
+}
+
+#[test]
+fn test_write_takes_backup() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let file = dir.path().join("a.py");
+    fs::write(&file, "a = 1\n").unwrap();
+
+    let toggler = Toggler::new().with_backup(BackupOptions {
+        suffix: ".bak".into(),
+        ..BackupOptions::default()
+    });
+    let ops = [Operation::ToggleLines {
+        ranges: vec![LineRange::new(1, 1)],
+        to_end: false,
+    }];
+    let plan = toggler.plan_file(&file, &ops).unwrap();
+    let written = toggler.write(&plan).unwrap();
+    let backup = written.backup.unwrap();
+    assert_eq!(backup, dir.path().join("a.py.bak"));
+    assert_eq!(fs::read_to_string(backup).unwrap(), "a = 1\n");
+    assert_eq!(fs::read_to_string(&file).unwrap(), "# a = 1\n");
+}
+
+#[test]
+fn test_write_refuses_changed_file() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let file = dir.path().join("a.py");
+    fs::write(&file, "a = 1\n").unwrap();
+
+    let toggler = Toggler::new();
+    let ops = [Operation::ToggleLines {
+        ranges: vec![LineRange::new(1, 1)],
+        to_end: false,
+    }];
+    let plan = toggler.plan_file(&file, &ops).unwrap();
+    fs::write(&file, "a = 2\n").unwrap();
+    assert!(toggler.write(&plan).is_err());
+    assert_eq!(fs::read_to_string(&file).unwrap(), "a = 2\n");
+}